@@ -0,0 +1,182 @@
+use anyhow::bail;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{Data, DataStruct, DeriveInput, GenericParam, Lifetime, LifetimeDef, Lit, LitInt, Type};
+
+use crate::{
+    encoded_type,
+    spec::field_name,
+    struct_members,
+    util::{FieldAttributes, MessageAttributes, Result, WhereClauseBuilder},
+    FieldTags,
+};
+
+/// Implement `ProtoBorrow` and `ProtoStructBorrow` for a struct. The first lifetime parameter of
+/// the struct is the lifetime of the buffer that fields borrow from, and structs without one get a
+/// fresh lifetime so that they can still be used as fields of borrowed messages.
+pub fn derive_protoborrow(input: TokenStream2) -> Result<TokenStream2> {
+    let input: DeriveInput = syn::parse2(input)?;
+    let DeriveInput {
+        attrs,
+        ident,
+        generics,
+        data,
+        ..
+    } = &input;
+
+    let attrs = MessageAttributes::new(attrs)?;
+    let autoproto_path = &attrs.autoproto_path;
+
+    if attrs.transparent {
+        bail!("`ProtoBorrow` can't be derived for `transparent` messages");
+    }
+
+    let fields = match data {
+        Data::Struct(DataStruct { fields, .. }) => fields,
+        _ => bail!("`ProtoBorrow` can only be derived for structs"),
+    };
+
+    let members = struct_members(fields.iter())?;
+    let required = fields
+        .iter()
+        .map(|field| Ok(FieldAttributes::new(&field.attrs)?.required))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut impl_generics = generics.clone();
+    let lifetime = match generics.lifetimes().next() {
+        Some(def) => def.lifetime.clone(),
+        None => {
+            let lifetime = Lifetime::new("'__autoproto_buf", Span::call_site());
+
+            impl_generics.params.insert(
+                0,
+                GenericParam::Lifetime(LifetimeDef::new(lifetime.clone())),
+            );
+
+            lifetime
+        }
+    };
+
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+    let (_, ty_generics, _) = generics.split_for_impl();
+
+    let tag_lit = |tag: u32| -> Lit { LitInt::new(&tag.to_string(), Span::call_site()).into() };
+
+    let mut field_types = Vec::<Type>::new();
+    let mut arms = Vec::new();
+    let mut required_fields = Vec::new();
+    let mut unknown_fields = None;
+
+    for ((tags, member, ty), required) in members.iter().zip(required) {
+        let tag = match tags {
+            FieldTags::Unknown => {
+                unknown_fields = Some(member);
+
+                continue;
+            }
+            FieldTags::Oneof(_) => {
+                bail!("`oneof` fields can't be used when deriving `ProtoBorrow`");
+            }
+            FieldTags::Single(tag) => {
+                let tag = tag_lit(tag.get());
+
+                field_types.push((*ty).clone());
+                arms.push(quote!(#tag => &mut self.#member));
+
+                tag
+            }
+            FieldTags::Encoded(tag, encoding) => {
+                let tag = tag_lit(tag.get());
+                let ty = encoded_type(autoproto_path, ty, encoding);
+
+                arms.push(quote!(#tag => <#ty>::from_mut(&mut self.#member)));
+                field_types.push(ty);
+
+                tag
+            }
+            FieldTags::Group(tag) => {
+                let tag = tag_lit(tag.get());
+
+                field_types.push(syn::parse_quote!(#autoproto_path::Group<#ty, #tag>));
+                arms.push(
+                    quote!(#tag => #autoproto_path::Group::<_, #tag>::from_mut(&mut self.#member)),
+                );
+
+                tag
+            }
+        };
+
+        if required {
+            let name = field_name(member);
+
+            required_fields.push(quote!((#tag, #name)));
+        }
+    }
+
+    let required_fields = if required_fields.is_empty() {
+        None
+    } else {
+        Some(quote!(
+            const REQUIRED_FIELDS: &'static [(u32, &'static str)] = &[#(#required_fields),*];
+        ))
+    };
+
+    let unknown_fields_mut = unknown_fields.map(|member| {
+        quote!(
+            fn unknown_fields_mut(&mut self) -> Option<&mut #autoproto_path::UnknownFields> {
+                Some(&mut self.#member)
+            }
+        )
+    });
+
+    let field_borrowed_mut = if arms.is_empty() {
+        quote!(
+            let _ = tag;
+
+            None
+        )
+    } else {
+        quote!(
+            Some(match ::core::num::NonZeroU32::get(tag) {
+                #(#arms,)*
+                _ => { return None; }
+            })
+        )
+    };
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::borrow::ProtoBorrow<#lifetime>));
+
+    Ok(quote!(
+        impl #impl_generics #autoproto_path::borrow::ProtoStructBorrow<#lifetime>
+            for #ident #ty_generics #where_clause
+        {
+            #required_fields
+
+            fn field_borrowed_mut(
+                &mut self,
+                tag: ::core::num::NonZeroU32,
+            ) -> Option<&mut (dyn #autoproto_path::borrow::ProtoBorrow<#lifetime> + '_)> {
+                #field_borrowed_mut
+            }
+
+            #unknown_fields_mut
+        }
+
+        impl #impl_generics #autoproto_path::borrow::ProtoBorrow<#lifetime>
+            for #ident #ty_generics #where_clause
+        {
+            fn merge_borrowed(
+                &mut self,
+                wire_type: #autoproto_path::prost::encoding::WireType,
+                buf: &mut &#lifetime [u8],
+                ctx: #autoproto_path::prost::encoding::DecodeContext,
+            ) -> ::core::result::Result<(), #autoproto_path::prost::DecodeError> {
+                #autoproto_path::generic::protostructborrow::protoborrow_merge_borrowed(
+                    self, wire_type, buf, ctx,
+                )
+            }
+        }
+    ))
+}
@@ -0,0 +1,381 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Fields, FieldsNamed, FieldsUnnamed, Generics, Ident, ItemImpl, Lit, LitStr, Member, Path, Type,
+    Variant,
+};
+
+use crate::{
+    bounded_field_types,
+    spec::{enum_value_name, spec_fields, well_known_name},
+    struct_members,
+    util::{snake_case, Result, WhereClauseBuilder},
+    FieldTags,
+};
+
+/// Statements adding the fields of a message to `message`, and registering the types of those
+/// fields.
+fn fields_describe_and_register(
+    autoproto_path: &Path,
+    members: &[(FieldTags, Member, &Type)],
+) -> (TokenStream2, TokenStream2) {
+    spec_fields(autoproto_path, members)
+        .into_iter()
+        .map(|(name, tag, ty)| {
+            (
+                quote!(
+                    <#ty as #autoproto_path::ProtoDescriptor>::describe_field(
+                        file,
+                        &mut message,
+                        #name,
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                    );
+                ),
+                quote!(<#ty as #autoproto_path::ProtoDescriptor>::register_descriptor(file);),
+            )
+        })
+        .unzip()
+}
+
+/// The name of a type in its descriptor. Well-known types are named after the Rust type, since
+/// `ToProtoSpec::proto_name` gives the fully-qualified name for them.
+fn descriptor_name(
+    autoproto_path: &Path,
+    ident: &Ident,
+    well_known: Option<&LitStr>,
+) -> TokenStream2 {
+    match well_known {
+        Some(_) => {
+            let name = ident.to_string();
+
+            quote!(::std::string::String::from(#name))
+        }
+        None => quote!(<Self as #autoproto_path::ToProtoSpec>::proto_name()),
+    }
+}
+
+/// The items of a `ProtoDescriptor` impl for a type whose descriptor is added by `define`. Well-known
+/// types are defined in their own file in the `google.protobuf` package, which is added as a
+/// dependency, so they are referred to by their fully-qualified name.
+fn define_in_file(
+    autoproto_path: &Path,
+    ident: &Ident,
+    well_known: Option<&LitStr>,
+    ty: TokenStream2,
+    define: TokenStream2,
+) -> TokenStream2 {
+    match well_known {
+        Some(file_name) => {
+            let type_name = format!(".{}", well_known_name(ident));
+
+            quote!(
+                fn descriptor_type(
+                    _file: &#autoproto_path::FileDescriptor,
+                ) -> (
+                    #autoproto_path::descriptor::Type,
+                    ::core::option::Option<::std::string::String>,
+                ) {
+                    (
+                        #autoproto_path::descriptor::Type::#ty,
+                        ::core::option::Option::Some(::std::string::String::from(#type_name)),
+                    )
+                }
+
+                fn register_descriptor(file: &mut #autoproto_path::FileDescriptor) {
+                    file.define_dependency(#file_name, "google.protobuf", |file| {
+                        #define
+                    });
+                }
+            )
+        }
+        None => quote!(
+            fn register_descriptor(file: &mut #autoproto_path::FileDescriptor) {
+                #define
+            }
+        ),
+    }
+}
+
+pub fn message(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    members: &[(FieldTags, Member, &Type)],
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let (describe_fields, register_fields) = fields_describe_and_register(autoproto_path, members);
+    let name = descriptor_name(autoproto_path, ident, well_known);
+    let items = define_in_file(
+        autoproto_path,
+        ident,
+        well_known,
+        quote!(Message),
+        quote!(
+            let mut message = #autoproto_path::prost_types::DescriptorProto {
+                name: ::core::option::Option::Some(#name),
+                ..::core::default::Default::default()
+            };
+
+            #describe_fields
+
+            if file.define_message(message) {
+                #register_fields
+            }
+        ),
+    );
+
+    let where_clause_builder = WhereClauseBuilder::new(generics)
+        .with_field_types(bounded_field_types(autoproto_path, members));
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::ProtoDescriptor))
+        .with_bound(quote!(#autoproto_path::ProtoDescriptor));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoDescriptor for #ident #ty_generics #where_clause {
+            #items
+        }
+    )
+}
+
+pub fn unit(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    message(autoproto_path, ident, generics, &[], well_known)
+}
+
+pub fn newtype(
+    autoproto_path: &Path,
+    ident: &Ident,
+    field_ty: &Type,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> ItemImpl {
+    let where_clause = where_clause_builder.with_bound(quote!(#autoproto_path::ProtoDescriptor));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoDescriptor for #ident #ty_generics #where_clause {
+            fn descriptor_type(
+                file: &#autoproto_path::FileDescriptor,
+            ) -> (
+                #autoproto_path::descriptor::Type,
+                ::core::option::Option<::std::string::String>,
+            ) {
+                <#field_ty as #autoproto_path::ProtoDescriptor>::descriptor_type(file)
+            }
+
+            fn describe_field(
+                file: &#autoproto_path::FileDescriptor,
+                message: &mut #autoproto_path::prost_types::DescriptorProto,
+                name: &str,
+                tag: ::core::num::NonZeroU32,
+            ) {
+                <#field_ty as #autoproto_path::ProtoDescriptor>::describe_field(
+                    file,
+                    message,
+                    name,
+                    tag,
+                )
+            }
+
+            fn register_descriptor(file: &mut #autoproto_path::FileDescriptor) {
+                <#field_ty as #autoproto_path::ProtoDescriptor>::register_descriptor(file)
+            }
+        }
+    )
+}
+
+pub fn scalar_enum(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(&Ident, TokenStream2)],
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let values = variants.iter().map(|(variant, value)| {
+        let name = enum_value_name(ident, variant, well_known);
+
+        quote!(
+            #autoproto_path::prost_types::EnumValueDescriptorProto {
+                name: ::core::option::Option::Some(::std::string::String::from(#name)),
+                number: ::core::option::Option::Some(#value),
+                ..::core::default::Default::default()
+            }
+        )
+    });
+
+    let name = descriptor_name(autoproto_path, ident, well_known);
+    let items = define_in_file(
+        autoproto_path,
+        ident,
+        well_known,
+        quote!(Enum),
+        quote!(
+            file.define_enum(#autoproto_path::prost_types::EnumDescriptorProto {
+                name: ::core::option::Option::Some(#name),
+                value: ::std::vec![#(#values),*],
+                ..::core::default::Default::default()
+            });
+        ),
+    );
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoDescriptor for #ident #ty_generics #where_clause {
+            #items
+        }
+    )
+}
+
+pub fn oneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(Lit, &Variant)],
+    well_known: Option<&LitStr>,
+) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let mut describe_variants = vec![];
+    let mut register_variants = vec![];
+    let mut field_types = vec![];
+
+    for (tag, variant) in variants {
+        let variant_name = snake_case(&variant.ident.to_string());
+
+        let fields = match &variant.fields {
+            Fields::Named(FieldsNamed { named: fields, .. })
+            | Fields::Unnamed(FieldsUnnamed {
+                unnamed: fields, ..
+            }) => fields.iter().collect::<Vec<_>>(),
+            Fields::Unit => vec![],
+        };
+
+        let descriptor_type = match &fields[..] {
+            [] => {
+                register_variants.push(quote!(
+                    <() as #autoproto_path::ProtoDescriptor>::register_descriptor(file);
+                ));
+
+                quote!(<() as #autoproto_path::ProtoDescriptor>::descriptor_type(file))
+            }
+            [field] => {
+                let ty = &field.ty;
+
+                register_variants.push(quote!(
+                    <#ty as #autoproto_path::ProtoDescriptor>::register_descriptor(file);
+                ));
+                field_types.push((*ty).clone());
+
+                quote!(<#ty as #autoproto_path::ProtoDescriptor>::descriptor_type(file))
+            }
+            fields => {
+                // Variants with multiple fields are described as a message, which we name after
+                // the oneof and the variant, as in the `.proto` spec.
+                let members = struct_members(fields.iter().copied())?;
+                let (describe_fields, register_fields) =
+                    fields_describe_and_register(autoproto_path, &members);
+                let variant_ident = variant.ident.to_string();
+                let message_name = quote!(::std::format!(
+                    "{}{}",
+                    <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                    #variant_ident,
+                ));
+
+                register_variants.push(quote!(
+                    let mut message = #autoproto_path::prost_types::DescriptorProto {
+                        name: ::core::option::Option::Some(#message_name),
+                        ..::core::default::Default::default()
+                    };
+
+                    #describe_fields
+
+                    if file.define_message(message) {
+                        #register_fields
+                    }
+                ));
+                field_types.extend(bounded_field_types(autoproto_path, &members));
+
+                quote!((
+                    #autoproto_path::descriptor::Type::Message,
+                    ::core::option::Option::Some(file.type_name(&#message_name)),
+                ))
+            }
+        };
+
+        describe_variants.push(quote!(
+            let (ty, type_name) = #descriptor_type;
+            let mut field = #autoproto_path::descriptor::field(
+                #variant_name,
+                unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                #autoproto_path::descriptor::Label::Optional,
+                ty,
+                type_name,
+            );
+
+            field.oneof_index = ::core::option::Option::Some(oneof_index);
+            message.field.push(field);
+        ));
+    }
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::ProtoDescriptor))
+        .with_bound(quote!(#autoproto_path::ProtoDescriptor));
+
+    let name = descriptor_name(autoproto_path, ident, well_known);
+    let items = define_in_file(
+        autoproto_path,
+        ident,
+        well_known,
+        quote!(Message),
+        quote!(
+            let mut message = #autoproto_path::prost_types::DescriptorProto {
+                name: ::core::option::Option::Some(#name),
+                oneof_decl: ::std::vec![#autoproto_path::prost_types::OneofDescriptorProto {
+                    name: ::core::option::Option::Some(::std::string::String::from("value")),
+                    ..::core::default::Default::default()
+                }],
+                ..::core::default::Default::default()
+            };
+
+            <Self as #autoproto_path::ProtoOneofDescriptor>::describe_variants(
+                file,
+                &mut message,
+                0,
+            );
+
+            if file.define_message(message) {
+                <Self as #autoproto_path::ProtoOneofDescriptor>::register_variant_descriptors(
+                    file,
+                );
+            }
+        ),
+    );
+
+    Ok(quote!(
+        impl #impl_generics #autoproto_path::ProtoDescriptor for #ident #ty_generics #where_clause {
+            #items
+        }
+
+        impl #impl_generics #autoproto_path::ProtoOneofDescriptor for #ident #ty_generics #where_clause {
+            fn describe_variants(
+                file: &#autoproto_path::FileDescriptor,
+                message: &mut #autoproto_path::prost_types::DescriptorProto,
+                oneof_index: ::core::primitive::i32,
+            ) {
+                #({ #describe_variants })*
+            }
+
+            fn register_variant_descriptors(file: &mut #autoproto_path::FileDescriptor) {
+                #({ #register_variants })*
+            }
+        }
+    ))
+}
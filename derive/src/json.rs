@@ -0,0 +1,504 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    Field, Fields, FieldsNamed, FieldsUnnamed, Generics, Ident, ItemImpl, Lit, LitInt, Member,
+    Path, Type, Variant,
+};
+
+use crate::{
+    spec::{enum_value_name, field_name},
+    struct_members,
+    util::{snake_case, FieldAttributes, Result, WhereClauseBuilder},
+    FieldTags,
+};
+
+/// Convert a name from `snake_case` to `lowerCamelCase`, which is how fields are named in the
+/// proto3 JSON mapping.
+fn lower_camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut capitalize_next = false;
+
+    for c in name.chars() {
+        if c == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            out.extend(c.to_uppercase());
+            capitalize_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+/// The name a field is written with, along with every name that is accepted when parsing. Parsers
+/// accept both the JSON name and the original name of the field.
+fn json_names(name: String, json_name: Option<String>) -> (String, Vec<String>) {
+    let json_name = json_name.unwrap_or_else(|| lower_camel_case(&name));
+
+    if json_name == name {
+        (json_name, vec![name])
+    } else {
+        (json_name.clone(), vec![json_name, name])
+    }
+}
+
+/// The code to write and merge the fields of a message.
+struct JsonFields {
+    /// Statements inserting each field into `object`.
+    write: Vec<TokenStream2>,
+    /// Match arms merging `value` into the field named `name`.
+    merge_arms: Vec<TokenStream2>,
+    /// The types of the fields, other than `oneof`s.
+    field_types: Vec<Type>,
+    /// The `OneofField` types through which `oneof` fields are accessed.
+    oneof_types: Vec<Type>,
+}
+
+/// Generate the code for the fields of a message. `place` gives the expression to access a field
+/// from its index and member, such as `self.field`, and `reset` gives a closure resetting the field
+/// for a `null` value.
+fn json_fields<'a>(
+    autoproto_path: &Path,
+    fields: impl Iterator<Item = &'a Field> + Clone,
+    place: impl Fn(usize, &Member) -> TokenStream2,
+    reset: impl Fn(&Member) -> TokenStream2,
+) -> Result<JsonFields> {
+    let members = struct_members(fields.clone())?;
+
+    let mut out = JsonFields {
+        write: vec![],
+        merge_arms: vec![],
+        field_types: vec![],
+        oneof_types: vec![],
+    };
+    let mut oneof_arms = vec![];
+
+    for (i, ((tags, member, ty), field)) in members.iter().zip(fields).enumerate() {
+        let place = place(i, member);
+
+        match tags {
+            FieldTags::Unknown => {}
+            FieldTags::Single(_) | FieldTags::Encoded(..) | FieldTags::Group(_) => {
+                let json_name = FieldAttributes::new(&field.attrs)?.json_name;
+                let (json_name, accepted) = json_names(field_name(member), json_name);
+                let reset = reset(member);
+
+                out.write.push(quote!(
+                    #autoproto_path::json::write_field(&mut object, #json_name, &#place);
+                ));
+                out.merge_arms.push(quote!(
+                    #(#accepted)|* => {
+                        #autoproto_path::json::merge_field_with(&mut #place, value, #reset)
+                    }
+                ));
+                out.field_types.push((*ty).clone());
+            }
+            FieldTags::Oneof(tags) => {
+                // The variants of a `oneof` are written as fields of the containing message. The
+                // tag doesn't affect the JSON, so the first one is used.
+                let tag: Lit = LitInt::new(&tags[0].get().to_string(), Span::call_site()).into();
+                let oneof_ty: Type = syn::parse_quote!(#autoproto_path::OneofField<#ty, #tag>);
+
+                out.write.push(quote!(
+                    <#oneof_ty as #autoproto_path::ProtoOneofJson>::variants_to_json(
+                        <#oneof_ty>::from_ref(&#place),
+                        &mut object,
+                    );
+                ));
+                oneof_arms.push(quote!(
+                    name if <#oneof_ty as #autoproto_path::ProtoOneofJson>::has_variant(name) => {
+                        #autoproto_path::json::merge_variant(
+                            <#oneof_ty>::from_mut(&mut #place),
+                            name,
+                            value,
+                        )
+                    }
+                ));
+                out.oneof_types.push(oneof_ty);
+            }
+        }
+    }
+
+    out.merge_arms.extend(oneof_arms);
+
+    Ok(out)
+}
+
+/// An expression converting the fields to a JSON object.
+fn fields_to_json(autoproto_path: &Path, write: &[TokenStream2]) -> TokenStream2 {
+    if write.is_empty() {
+        quote!(#autoproto_path::serde_json::Value::Object(
+            #autoproto_path::serde_json::Map::new()
+        ))
+    } else {
+        quote!({
+            let mut object = #autoproto_path::serde_json::Map::new();
+            #(#write)*
+            #autoproto_path::serde_json::Value::Object(object)
+        })
+    }
+}
+
+/// An expression merging the JSON object `value` into the fields.
+fn merge_fields(autoproto_path: &Path, merge_arms: &[TokenStream2]) -> TokenStream2 {
+    quote!(
+        #autoproto_path::json::merge_object(value, |name, value| match name {
+            #(#merge_arms)*
+            name => ::core::result::Result::Err(
+                #autoproto_path::json::JsonError::unknown_field(name),
+            ),
+        })
+    )
+}
+
+pub fn message<'a>(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    fields: impl Iterator<Item = &'a Field> + Clone,
+) -> Result<ItemImpl> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let JsonFields {
+        write,
+        merge_arms,
+        field_types,
+        oneof_types,
+    } = json_fields(
+        autoproto_path,
+        fields,
+        |_, member| quote!(self.#member),
+        // Fields are reset from the default message, since fields such as `SystemTime` have no
+        // default of their own.
+        |member| {
+            quote!(|field| ::core::mem::swap(
+                field,
+                &mut <Self as ::core::default::Default>::default().#member,
+            ))
+        },
+    )?;
+
+    let to_json = fields_to_json(autoproto_path, &write);
+    let merge = merge_fields(autoproto_path, &merge_arms);
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::ProtoJson))
+        .with_types_bound(oneof_types, quote!(#autoproto_path::ProtoOneofJson))
+        .with_bound(quote!(#autoproto_path::ProtoJson + ::core::default::Default));
+
+    Ok(syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoJson for #ident #ty_generics #where_clause {
+            fn to_json_value(&self) -> #autoproto_path::serde_json::Value {
+                #to_json
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                #merge
+            }
+        }
+    ))
+}
+
+/// Messages without fields are written in the same way as `google.protobuf.Empty`.
+pub fn unit(autoproto_path: &Path, ident: &Ident, generics: &Generics) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoJson for #ident #ty_generics #where_clause {
+            fn to_json_value(&self) -> #autoproto_path::serde_json::Value {
+                <() as #autoproto_path::ProtoJson>::to_json_value(&())
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                <() as #autoproto_path::ProtoJson>::merge_json_value(&mut (), value)
+            }
+        }
+    )
+}
+
+pub fn newtype(
+    autoproto_path: &Path,
+    ident: &Ident,
+    field: &Member,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> ItemImpl {
+    let where_clause = where_clause_builder.with_bound(quote!(#autoproto_path::ProtoJson));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoJson for #ident #ty_generics #where_clause {
+            fn to_json_value(&self) -> #autoproto_path::serde_json::Value {
+                #autoproto_path::ProtoJson::to_json_value(&self.#field)
+            }
+
+            fn is_json_default(&self) -> bool {
+                #autoproto_path::ProtoJson::is_json_default(&self.#field)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                #autoproto_path::ProtoJson::merge_json_value(&mut self.#field, value)
+            }
+        }
+    )
+}
+
+/// Enumerations are written as the names of their values, and either the name or the number is
+/// accepted when parsing.
+pub fn scalar_enum<'a>(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: impl Iterator<Item = &'a Ident>,
+    unknown: Option<&Ident>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let (names, variants): (Vec<_>, Vec<_>) = variants
+        .map(|variant| (enum_value_name(ident, variant, None), variant))
+        .unzip();
+
+    // Values that an `open` enumeration doesn't recognise are written as numbers.
+    let unknown_arm = unknown.map(|unknown| {
+        quote!(
+            #ident::#unknown(value) => {
+                return #autoproto_path::serde_json::Value::Number(::core::convert::From::from(
+                            ::core::clone::Clone::clone(value),
+                        ));
+            }
+        )
+    });
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoJson for #ident #ty_generics #where_clause {
+            fn to_json_value(&self) -> #autoproto_path::serde_json::Value {
+                let name = match self {
+                    #(#ident::#variants => #names,)*
+                    #unknown_arm
+                };
+
+                #autoproto_path::serde_json::Value::String(::std::string::String::from(name))
+            }
+
+            fn is_json_default(&self) -> bool {
+                #autoproto_path::IsDefault::is_default(self)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                let parsed = match &value {
+                    #autoproto_path::serde_json::Value::String(name) => match &name[..] {
+                        #(#names => ::core::option::Option::Some(#ident::#variants),)*
+                        _ => ::core::option::Option::None,
+                    },
+                    #autoproto_path::serde_json::Value::Number(number) => number
+                        .as_i64()
+                        .and_then(|number| {
+                            <Self as #autoproto_path::ProtoScalar>::from_value(
+                                #autoproto_path::Value::Int(::core::convert::From::from(number)),
+                            )
+                        }),
+                    other => {
+                        return ::core::result::Result::Err(
+                            #autoproto_path::json::JsonError::expected("an enum value", other),
+                        )
+                    }
+                };
+
+                *self = parsed.ok_or_else(|| {
+                    #autoproto_path::json::JsonError::new(::std::format!(
+                        "Unknown value {} for enum `{}`",
+                        value,
+                        ::core::stringify!(#ident),
+                    ))
+                })?;
+
+                ::core::result::Result::Ok(())
+            }
+        }
+    )
+}
+
+/// A `oneof` is written as an object with a single field for the current variant, or as fields of
+/// the containing message when it is used for an `#[autoproto(oneof)]` field.
+pub fn oneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(Lit, &Variant)],
+) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let mut names = vec![];
+    let mut write_arms = vec![];
+    let mut merge_arms = vec![];
+    let mut single_types = vec![];
+    let mut field_types = vec![];
+    let mut oneof_types = vec![];
+
+    for (_, variant) in variants {
+        let variant_ident = &variant.ident;
+        let json_name = FieldAttributes::new(&variant.attrs)?.json_name;
+        let (json_name, accepted) = json_names(snake_case(&variant_ident.to_string()), json_name);
+
+        let fields = match &variant.fields {
+            Fields::Named(FieldsNamed { named: fields, .. })
+            | Fields::Unnamed(FieldsUnnamed {
+                unnamed: fields, ..
+            }) => fields.iter().collect::<Vec<_>>(),
+            Fields::Unit => vec![],
+        };
+
+        let bindings = (0..fields.len())
+            .map(|i| Ident::new(&format!("__field_{}", i), Span::call_site()))
+            .collect::<Vec<_>>();
+        let defaults = fields
+            .iter()
+            .map(|_| quote!(::core::default::Default::default()))
+            .collect::<Vec<_>>();
+        let (pattern, default) = match &variant.fields {
+            Fields::Named(_) => {
+                let field_idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+
+                (
+                    quote!(#ident::#variant_ident { #(#field_idents: #bindings),* }),
+                    quote!(#ident::#variant_ident { #(#field_idents: #defaults),* }),
+                )
+            }
+            Fields::Unnamed(_) => (
+                quote!(#ident::#variant_ident(#(#bindings),*)),
+                quote!(#ident::#variant_ident(#(#defaults),*)),
+            ),
+            Fields::Unit => (
+                quote!(#ident::#variant_ident),
+                quote!(#ident::#variant_ident),
+            ),
+        };
+
+        let (to_json, merge) = match &fields[..] {
+            // Variants without fields are `google.protobuf.Empty`.
+            [] => (
+                quote!(<() as #autoproto_path::ProtoJson>::to_json_value(&())),
+                quote!(<() as #autoproto_path::ProtoJson>::merge_json_value(&mut (), value)),
+            ),
+            [field] => {
+                single_types.push(field.ty.clone());
+
+                (
+                    quote!(#autoproto_path::ProtoJson::to_json_value(__field_0)),
+                    quote!(#autoproto_path::ProtoJson::merge_json_value(__field_0, value)),
+                )
+            }
+            // Variants with multiple fields are written as a message.
+            fields => {
+                let variant_fields = json_fields(
+                    autoproto_path,
+                    fields.iter().copied(),
+                    |i, _| {
+                        let binding = &bindings[i];
+
+                        quote!((*#binding))
+                    },
+                    |_| quote!(|field| *field = ::core::default::Default::default()),
+                )?;
+
+                field_types.extend(variant_fields.field_types);
+                oneof_types.extend(variant_fields.oneof_types);
+
+                (
+                    fields_to_json(autoproto_path, &variant_fields.write),
+                    merge_fields(autoproto_path, &variant_fields.merge_arms),
+                )
+            }
+        };
+
+        write_arms.push(quote!(
+            #pattern => {
+                object.insert(::std::string::String::from(#json_name), #to_json);
+            }
+        ));
+        merge_arms.push(quote!(
+            #(#accepted)|* => match self {
+                #pattern => #merge,
+                _ => {
+                    *self = #default;
+
+                    <Self as #autoproto_path::ProtoOneofJson>::merge_variant_json(self, name, value)
+                }
+            },
+        ));
+        names.extend(accepted);
+    }
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(
+            #autoproto_path::ProtoJson + ::core::default::Default
+        ))
+        .with_types_bound(
+            single_types,
+            quote!(#autoproto_path::ProtoJson + ::core::default::Default),
+        )
+        .with_types_bound(oneof_types, quote!(#autoproto_path::ProtoOneofJson))
+        .with_bound(quote!(#autoproto_path::ProtoJson));
+
+    Ok(quote!(
+        impl #impl_generics #autoproto_path::ProtoJson for #ident #ty_generics #where_clause {
+            fn to_json_value(&self) -> #autoproto_path::serde_json::Value {
+                #autoproto_path::json::oneof_to_json(self)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                #autoproto_path::json::merge_oneof(self, value)
+            }
+        }
+
+        impl #impl_generics #autoproto_path::ProtoOneofJson for #ident #ty_generics #where_clause {
+            fn has_variant(name: &str) -> bool {
+                ::core::matches!(name, #(#names)|*)
+            }
+
+            fn variants_to_json(
+                &self,
+                object: &mut #autoproto_path::serde_json::Map<
+                    ::std::string::String,
+                    #autoproto_path::serde_json::Value,
+                >,
+            ) {
+                match self {
+                    #(#write_arms)*
+                }
+            }
+
+            fn merge_variant_json(
+                &mut self,
+                name: &str,
+                value: #autoproto_path::serde_json::Value,
+            ) -> ::core::result::Result<(), #autoproto_path::json::JsonError> {
+                match name {
+                    #(#merge_arms)*
+                    name => ::core::result::Result::Err(
+                        #autoproto_path::json::JsonError::unknown_field(name),
+                    ),
+                }
+            }
+        }
+    ))
+}
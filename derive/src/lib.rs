@@ -6,17 +6,25 @@ use anyhow::{anyhow, bail};
 use proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::{quote, ToTokens};
-use std::{iter, num::NonZeroU32};
+use std::{convert::TryFrom, iter, num::NonZeroU32};
 use syn::{
     punctuated::Punctuated, Arm, Attribute, Block, Data, DataEnum, DataStruct, DeriveInput, Expr,
     ExprMatch, Field, Fields, FieldsNamed, FieldsUnnamed, GenericParam, Generics, Ident, ItemConst,
-    ItemImpl, ItemStruct, Lit, LitInt, Member, Path, Stmt, Token, Type, TypePath,
+    ItemImpl, ItemStruct, Lit, LitInt, Member, Path, Stmt, Token, Type, TypePath, Variant,
 };
 
+mod borrow;
+mod descriptor;
+mod json;
 mod newtype;
+mod proto_file;
+mod service;
+mod spec;
+mod text;
 mod util;
+mod verify;
 
-use util::{FieldAttributes, MessageAttributes, Result, WhereClauseBuilder};
+use util::{FieldAttributes, FieldEncoding, MessageAttributes, Result, WhereClauseBuilder};
 
 #[proc_macro_derive(Message, attributes(autoproto))]
 pub fn derive_message(input: TokenStream) -> TokenStream {
@@ -30,6 +38,11 @@ pub fn derive_message(input: TokenStream) -> TokenStream {
     try_derive_message(input).unwrap().into()
 }
 
+#[proc_macro_attribute]
+pub fn service(attr: TokenStream, item: TokenStream) -> TokenStream {
+    service::service(attr.into(), item.into()).unwrap().into()
+}
+
 #[proc_macro_derive(Proto, attributes(autoproto))]
 pub fn derive_proto(input: TokenStream) -> TokenStream {
     try_derive_proto(input).unwrap().into()
@@ -40,6 +53,11 @@ pub fn derive_protoencode(input: TokenStream) -> TokenStream {
     try_derive_protoencode(input).unwrap().into()
 }
 
+#[proc_macro_derive(ProtoBorrow, attributes(autoproto))]
+pub fn derive_protoborrow(input: TokenStream) -> TokenStream {
+    borrow::derive_protoborrow(input.into()).unwrap().into()
+}
+
 #[proc_macro_derive(ProtoScalar, attributes(autoproto))]
 pub fn derive_protoscalar(input: TokenStream) -> TokenStream {
     try_derive_protoscalar(input).unwrap().into()
@@ -60,27 +78,70 @@ fn derive_protoscalar_enum(
 
     let autoproto_path = &attrs.autoproto_path;
 
-    let variant_constants = data
+    let unknown_variant = match data
         .variants
         .iter()
-        .enumerate()
-        .map::<Result<_>, _>(|(i, variant)| {
-            if variant.fields.is_empty() {
-                let variant_ident = &variant.ident;
-
-                let const_name = Ident::new(&format!("__TAG_{}", i), variant_ident.span().clone());
-
-                let item: ItemConst = syn::parse_quote!(
-                    const #const_name: ::core::primitive::i32 = #ident::#variant_ident as ::core::primitive::i32;
-                );
+        .filter(|variant| util::is_unknown_variant(&variant.attrs))
+        .collect::<Vec<_>>()[..]
+    {
+        [] if attrs.open => bail!("`open` enums must have a variant marked `unknown`"),
+        [] => None,
+        [variant] if attrs.open => match &variant.fields {
+            Fields::Unnamed(FieldsUnnamed { unnamed, .. }) if unnamed.len() == 1 => {
+                Some(&variant.ident)
+            }
+            _ => bail!("The `unknown` variant must have a single unnamed `i32` field"),
+        },
+        [_] => bail!("`unknown` variants can only be used in `open` enums"),
+        _ => bail!("Only one variant can be marked `unknown`"),
+    };
 
-                Ok((const_name, variant_ident, item))
-            } else {
+    // The discriminants of an enum with fields can't be read with `as`, so for `open` enums they
+    // are worked out in the same way as the compiler does instead.
+    let mut next_discriminant = quote!(0);
+    let variant_values = data
+        .variants
+        .iter()
+        .map(|variant| {
+            let discriminant = match &variant.discriminant {
+                Some((_, expr)) => quote!((#expr)),
+                None => next_discriminant.clone(),
+            };
+            next_discriminant = quote!(#discriminant + 1);
+
+            (variant, discriminant)
+        })
+        .filter(|(variant, _)| Some(&variant.ident) != unknown_variant)
+        .map(|(variant, discriminant)| {
+            if !variant.fields.is_empty() {
                 bail!("Cannot derive `protoscalar` for an enum with fields");
             }
+
+            let variant_ident = &variant.ident;
+            let value = if attrs.open {
+                quote!(#discriminant as ::core::primitive::i32)
+            } else {
+                quote!(#ident::#variant_ident as ::core::primitive::i32)
+            };
+
+            Ok((variant_ident, value))
         })
         .collect::<Result<Vec<_>>>()?;
 
+    let variant_constants = variant_values
+        .iter()
+        .enumerate()
+        .map(|(i, (variant_ident, value))| {
+            let const_name = Ident::new(&format!("__TAG_{}", i), variant_ident.span());
+
+            let item: ItemConst = syn::parse_quote!(
+                const #const_name: ::core::primitive::i32 = #value;
+            );
+
+            (const_name, *variant_ident, item)
+        })
+        .collect::<Vec<_>>();
+
     let constant_items = variant_constants
         .iter()
         .map(|(_, _, item)| item)
@@ -89,8 +150,52 @@ fn derive_protoscalar_enum(
     let match_arms = variant_constants
         .iter()
         .map::<Arm, _>(|(const_name, variant_ident, _)| syn::parse_quote!(#const_name => #ident :: #variant_ident))
+        .chain(iter::once(match unknown_variant {
+            Some(unknown) => syn::parse_quote!(other => #ident::#unknown(other)),
+            None => syn::parse_quote!(_ => return None),
+        }))
         .collect::<Punctuated<_, Token!(,)>>();
 
+    let as_i32 = |place: TokenStream2| match unknown_variant {
+        Some(unknown) => {
+            let (variants, values): (Vec<_>, Vec<_>) = variant_values.iter().cloned().unzip();
+
+            quote!(
+                (match #place {
+                    #(#ident::#variants => #values,)*
+                    #ident::#unknown(value) => ::core::clone::Clone::clone(value),
+                })
+            )
+        }
+        None => quote!((::core::clone::Clone::clone(#place) as ::core::primitive::i32)),
+    };
+
+    let self_as_i32 = as_i32(quote!(self));
+    let default_as_i32 = as_i32(quote!(&<Self as ::core::default::Default>::default()));
+
+    let well_known = attrs.well_known.as_ref();
+    let spec_impl = spec::scalar_enum(autoproto_path, ident, generics, &variant_values, well_known);
+    let descriptor_impl =
+        descriptor::scalar_enum(autoproto_path, ident, generics, &variant_values, well_known);
+    // Well-known types have their own JSON mappings, which are implemented by hand.
+    let json_impl = well_known.is_none().then(|| {
+        json::scalar_enum(
+            autoproto_path,
+            ident,
+            generics,
+            variant_values.iter().map(|(variant, _)| *variant),
+            unknown_variant,
+        )
+    });
+    let text_impl = text::scalar_enum(
+        autoproto_path,
+        ident,
+        generics,
+        variant_values.iter().map(|(variant, _)| *variant),
+        unknown_variant,
+        well_known,
+    );
+
     let (
         protoscalar_impl,
         is_default_impl,
@@ -109,21 +214,20 @@ fn derive_protoscalar_enum(
                     <::core::primitive::i32 as #autoproto_path::ProtoScalar>::DEFAULT_ENCODING;
 
                 fn from_value(other: #autoproto_path::Value) -> Option<Self> {
-                    ::core::debug_assert_eq!(<Self as ::core::default::Default>::default() as i32, 0);
+                    ::core::debug_assert_eq!(#default_as_i32, 0);
 
                     #(#constant_items)*
 
                     Some(match <::core::primitive::i32 as #autoproto_path::ProtoScalar>::from_value(other)? {
                         #match_arms,
-                        _ => return None,
                     })
                 }
 
                 fn to_value(&self) -> #autoproto_path::Value {
-                    ::core::debug_assert_eq!(<Self as ::core::default::Default>::default() as i32, 0);
+                    ::core::debug_assert_eq!(#default_as_i32, 0);
 
                     <::core::primitive::i32 as #autoproto_path::ProtoScalar>::to_value(
-                        &(::core::clone::Clone::clone(self) as ::core::primitive::i32)
+                        &#self_as_i32
                     )
                 }
             }
@@ -131,7 +235,7 @@ fn derive_protoscalar_enum(
         syn::parse_quote!(
             impl #impl_generics #autoproto_path::IsDefault for #ident #ty_generics #where_clause {
                 fn is_default(&self) -> ::core::primitive::bool {
-                    (::core::clone::Clone::clone(self) as ::core::primitive::i32) == 0
+                    #self_as_i32 == 0
                 }
             }
         ),
@@ -161,6 +265,31 @@ fn derive_protoscalar_enum(
                         tag,
                     )
                 }
+
+                fn encode_as_field_explicit(
+                    &self,
+                    tag: ::core::num::NonZeroU32,
+                    buf: &mut dyn #autoproto_path::prost::bytes::BufMut,
+                ) {
+                    <
+                        #autoproto_path::MappedInt::<Self>
+                        as #autoproto_path::ProtoEncode
+                    >::encode_as_field_explicit(
+                        &#autoproto_path::MappedInt::new(::core::clone::Clone::clone(self)),
+                        tag,
+                        buf,
+                    )
+                }
+
+                fn encoded_len_as_field_explicit(&self, tag: ::core::num::NonZeroU32) -> usize {
+                    <
+                        #autoproto_path::MappedInt::<Self>
+                        as #autoproto_path::ProtoEncode
+                    >::encoded_len_as_field_explicit(
+                        &#autoproto_path::MappedInt::new(::core::clone::Clone::clone(self)),
+                        tag,
+                    )
+                }
             }
         ),
         syn::parse_quote!(
@@ -243,6 +372,14 @@ fn derive_protoscalar_enum(
         #protoencoderepeated_impl
 
         #protomergerepeated_impl
+
+        #spec_impl
+
+        #descriptor_impl
+
+        #json_impl
+
+        #text_impl
     })
 }
 
@@ -352,6 +489,38 @@ fn derive_protoscalar_struct(
             &mut where_clause_builder,
         ),
     );
+    let spec_impl = spec::newtype(
+        autoproto_path,
+        ident,
+        &inner_field.ty,
+        &impl_generics,
+        &ty_generics,
+        &mut where_clause_builder,
+    );
+    let descriptor_impl = descriptor::newtype(
+        autoproto_path,
+        ident,
+        &inner_field.ty,
+        &impl_generics,
+        &ty_generics,
+        &mut where_clause_builder,
+    );
+    let json_impl = json::newtype(
+        autoproto_path,
+        ident,
+        &field,
+        &impl_generics,
+        &ty_generics,
+        &mut where_clause_builder,
+    );
+    let text_impl = text::newtype(
+        autoproto_path,
+        ident,
+        &field,
+        &impl_generics,
+        &ty_generics,
+        &mut where_clause_builder,
+    );
 
     Ok(quote! {
         #is_default_impl
@@ -365,6 +534,14 @@ fn derive_protoscalar_struct(
         #protoencoderepeated_impl
 
         #protomergerepeated_impl
+
+        #spec_impl
+
+        #descriptor_impl
+
+        #json_impl
+
+        #text_impl
     })
 }
 
@@ -466,6 +643,18 @@ fn try_derive_is_default(input: TokenStream) -> Result<TokenStream2> {
     }
 }
 
+enum FieldTags {
+    Single(NonZeroU32),
+    /// A field with `#[autoproto(encoding = "..")]`, `#[autoproto(fixed)]` or
+    /// `#[autoproto(packed = ..)]`, which is accessed through `WithEncoding`.
+    Encoded(NonZeroU32, FieldEncoding),
+    Oneof(Vec<NonZeroU32>),
+    /// A field with `#[autoproto(group)]`, which is accessed through `Group`.
+    Group(NonZeroU32),
+    /// The field marked `#[autoproto(unknown_fields)]`, which has no tag of its own.
+    Unknown,
+}
+
 enum DeriveMode {
     ImmutableOnly,
     ImmutableAndMutable,
@@ -575,6 +764,32 @@ fn try_derive_protoencode(input: TokenStream) -> Result<TokenStream2> {
                     #protoencode_impl
                 ))
             }
+            Data::Enum(data) => {
+                let variants = oneof_variants(data)?;
+
+                let protooneof_impl = try_derive_protooneof(
+                    autoproto_path,
+                    ident,
+                    generics,
+                    &variants,
+                    DeriveMode::ImmutableOnly,
+                )?;
+
+                let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+                let protoencode_impl = impl_protoencode_for_protooneof(
+                    autoproto_path,
+                    ident,
+                    &impl_generics,
+                    &ty_generics,
+                    &mut WhereClauseBuilder::new(generics),
+                );
+
+                Ok(quote!(
+                    #protooneof_impl
+                    #protoencode_impl
+                ))
+            }
             Data::Union(..) => {
                 bail!("Message can not be derived for an untagged union (try using `enum`)")
             }
@@ -595,13 +810,38 @@ fn try_derive_message(input: TokenStream) -> Result<TokenStream2> {
         data,
     } = &input;
 
-    match data {
-        Data::Struct(data) => try_derive_message_for_struct(attrs, ident, generics, data),
-        Data::Enum(data) => try_derive_oneof(attrs, ident, generics, data),
+    let message_impl = match data {
+        Data::Struct(data) => try_derive_message_for_struct(attrs, ident, generics, data)?,
+        Data::Enum(data) => try_derive_oneof(attrs, ident, generics, data)?,
         Data::Union(..) => {
             bail!("Message can not be derived for an untagged union (try using `enum`)")
         }
-    }
+    };
+    let type_url_impl = type_url_impl(&MessageAttributes::new(attrs)?, ident, generics);
+
+    Ok(quote!(
+        #message_impl
+
+        #type_url_impl
+    ))
+}
+
+fn type_url_impl(
+    attrs: &MessageAttributes,
+    ident: &Ident,
+    generics: &Generics,
+) -> Option<ItemImpl> {
+    let autoproto_path = &attrs.autoproto_path;
+    let type_url = attrs.type_url.as_ref()?;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Some(syn::parse_quote!(
+        impl #impl_generics #autoproto_path::registry::TypeUrl for #ident #ty_generics #where_clause {
+            fn type_url() -> &'static str {
+                #type_url
+            }
+        }
+    ))
 }
 
 fn try_derive_proto(input: TokenStream) -> Result<TokenStream2> {
@@ -616,18 +856,127 @@ fn try_derive_proto(input: TokenStream) -> Result<TokenStream2> {
 
     match data {
         Data::Struct(data) => try_derive_proto_for_struct(attrs, ident, generics, data),
-        Data::Enum(..) => todo!(),
+        Data::Enum(data) => try_derive_proto_for_enum(attrs, ident, generics, data),
         Data::Union(..) => {
             bail!("Message can not be derived for an untagged union (try using `enum`)")
         }
     }
 }
 
+/// The tag of each variant of an enum deriving `ProtoOneof`.
+fn oneof_variants(data: &DataEnum) -> Result<Vec<(Lit, &Variant)>> {
+    let mut explicitly_tagged = None::<bool>;
+
+    data.variants
+        .iter()
+        .enumerate()
+        .map(|(i, variant)| {
+            let attributes = FieldAttributes::new(&variant.attrs)?;
+
+            match (explicitly_tagged, &attributes.tag) {
+                (None, _) | (Some(true), Some(_)) | (Some(false), None) => {}
+                (Some(true), None) | (Some(false), Some(_)) => {
+                    return Err(anyhow!(
+                        "If `tag` is specified for one field it must be specified for all fields"
+                    ));
+                }
+            }
+
+            explicitly_tagged = Some(attributes.tag.is_some());
+
+            let tag = attributes
+                .tag
+                .unwrap_or_else(|| NonZeroU32::new(i as u32 + 1).unwrap());
+            let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+
+            Ok((tag, variant))
+        })
+        .collect()
+}
+
+/// The types of the fields of every variant, which need to be bounded in the where clause of
+/// impls for the enum.
+fn variant_field_types<'a>(variants: &'a [(Lit, &'a Variant)]) -> Vec<&'a Type> {
+    variants
+        .iter()
+        .flat_map(|(_, variant)| variant.fields.iter())
+        .map(|field| &field.ty)
+        .collect()
+}
+
 fn try_derive_oneof(
     attrs: &[Attribute],
     ident: &Ident,
     generics: &Generics,
     data: &DataEnum,
+) -> Result<TokenStream2> {
+    let attrs = MessageAttributes::new(attrs)?;
+
+    let autoproto_path = &attrs.autoproto_path;
+
+    let variants = oneof_variants(data)?;
+
+    let protooneof_impl = try_derive_protooneof(
+        autoproto_path,
+        ident,
+        generics,
+        &variants,
+        DeriveMode::ImmutableAndMutable,
+    )?;
+
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let where_clause_builder =
+        WhereClauseBuilder::new(generics).with_field_types(variant_field_types(&variants));
+
+    let message_where_clause = where_clause_builder
+        .with_field_bound(quote!(::core::marker::Send + ::core::marker::Sync))
+        .with_self_bound(quote!(
+            #autoproto_path::ProtoOneofMut
+                + #autoproto_path::Clear
+                + ::core::fmt::Debug
+                + ::core::marker::Send
+                + ::core::marker::Sync
+        ));
+    let message_impl = impl_message_for_protooneof(
+        autoproto_path,
+        ident,
+        &impl_generics,
+        &ty_generics,
+        Some(&message_where_clause),
+    );
+
+    let well_known = attrs.well_known.as_ref();
+    let spec_impl = spec::oneof(autoproto_path, ident, generics, &variants, well_known)?;
+    let descriptor_impl =
+        descriptor::oneof(autoproto_path, ident, generics, &variants, well_known)?;
+    let json_impl = well_known
+        .is_none()
+        .then(|| json::oneof(autoproto_path, ident, generics, &variants))
+        .transpose()?;
+    let text_impl = text::oneof(autoproto_path, ident, generics, &variants)?;
+
+    Ok(quote!(
+        #protooneof_impl
+
+        #message_impl
+
+        #spec_impl
+
+        #descriptor_impl
+
+        #json_impl
+
+        #text_impl
+    ))
+}
+
+fn try_derive_protooneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(Lit, &Variant)],
+    mode: DeriveMode,
 ) -> Result<TokenStream2> {
     fn make_variant_get_field_arm_with_fields<F, T, FIter>(
         autoproto_path: &Path,
@@ -1007,39 +1356,6 @@ fn try_derive_oneof(
         }
     }
 
-    let attrs = MessageAttributes::new(attrs)?;
-
-    let autoproto_path = &attrs.autoproto_path;
-
-    let mut explicitly_tagged = None::<bool>;
-
-    let variants = data
-        .variants
-        .iter()
-        .enumerate()
-        .map(|(i, variant)| {
-            let attributes = FieldAttributes::new(&variant.attrs)?;
-
-            match (explicitly_tagged, &attributes.tag) {
-                (None, _) | (Some(true), Some(_)) | (Some(false), None) => {}
-                (Some(true), None) | (Some(false), Some(_)) => {
-                    return Err(anyhow!(
-                        "If `tag` is specified for one field it must be specified for all fields"
-                    ));
-                }
-            }
-
-            explicitly_tagged = Some(attributes.tag.is_some());
-
-            let tag = attributes
-                .tag
-                .unwrap_or_else(|| NonZeroU32::new(i as u32 + 1).unwrap());
-            let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
-
-            Ok((tag, variant))
-        })
-        .collect::<Result<Vec<_>>>()?;
-
     let (impl_generics, ty_generics, _) = generics.split_for_impl();
 
     let variant_get_field: Vec<Arm> = variants
@@ -1071,19 +1387,8 @@ fn try_derive_oneof(
         ))
         .collect();
 
-    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(
-        data.variants
-            .iter()
-            .filter_map(|v| match &v.fields {
-                Fields::Named(FieldsNamed { named: fields, .. })
-                | Fields::Unnamed(FieldsUnnamed {
-                    unnamed: fields, ..
-                }) => Some(fields),
-                Fields::Unit => None,
-            })
-            .flatten()
-            .map(|field| &field.ty),
-    );
+    let where_clause_builder =
+        WhereClauseBuilder::new(generics).with_field_types(variant_field_types(variants));
 
     let get_variant = ExprMatch {
         attrs: vec![],
@@ -1101,28 +1406,33 @@ fn try_derive_oneof(
         arms: variant_exec_merge,
     };
 
-    let message_where_clause = where_clause_builder
-        .with_field_bound(quote!(::core::marker::Send + ::core::marker::Sync))
-        .with_self_bound(quote!(
-            #autoproto_path::ProtoOneof
-                + #autoproto_path::Clear
-                + ::core::fmt::Debug
-                + ::core::marker::Send
-                + ::core::marker::Sync
-        ));
-    let message_impl = impl_message_for_protooneof(
-        autoproto_path,
-        ident,
-        &impl_generics,
-        &ty_generics,
-        Some(&message_where_clause),
-    );
+    let protooneof_where_clause =
+        where_clause_builder.with_field_bound(quote!(#autoproto_path::ProtoEncode));
+    let protooneofmut_where_clause = where_clause_builder
+        .with_field_bound(quote!(
+            ::core::default::Default + #autoproto_path::Proto + #autoproto_path::Clear
+        ))
+        .with_self_bound(quote!(#autoproto_path::ProtoOneof));
 
-    let protooneof_where_clause = where_clause_builder.with_field_bound(quote!(
-        ::core::default::Default + #autoproto_path::Proto + #autoproto_path::Clear
-    ));
+    let mutable: Option<ItemImpl> = match mode {
+        DeriveMode::ImmutableOnly => None,
+        DeriveMode::ImmutableAndMutable => Some(syn::parse_quote!(
+            impl #impl_generics #autoproto_path::ProtoOneofMut for #ident #ty_generics
+            #protooneofmut_where_clause
+            {
+                fn exec_merge<__Func, __FuncOut>(&mut self, tag: ::core::num::NonZeroU32, __proto_arg_func: __Func) -> Option<__FuncOut>
+                where
+                    __Func: ::core::ops::FnOnce(&mut (dyn #autoproto_path::Proto + '_)) -> __FuncOut
+                {
+                    ::core::option::Option::<__FuncOut>::Some(#exec_merge)
+                }
+            }
+        )),
+    };
 
     Ok(quote!(
+        impl #impl_generics #autoproto_path::IsMessage for #ident #ty_generics #protooneof_where_clause {}
+
         impl #impl_generics #autoproto_path::ProtoOneof for #ident #ty_generics
         #protooneof_where_clause
         {
@@ -1132,21 +1442,227 @@ fn try_derive_oneof(
             {
                 #get_variant
             }
+        }
 
-            fn exec_merge<__Func, __FuncOut>(&mut self, tag: ::core::num::NonZeroU32, __proto_arg_func: __Func) -> Option<__FuncOut>
-            where
-                __Func: ::core::ops::FnOnce(&mut (dyn #autoproto_path::Proto + '_)) -> __FuncOut
-            {
-                ::core::option::Option::<__FuncOut>::Some(#exec_merge)
+        #mutable
+    ))
+}
+
+fn struct_members<'a>(
+    fields: impl Iterator<Item = &'a Field>,
+) -> Result<Vec<(FieldTags, Member, &'a Type)>> {
+    let mut explicitly_tagged = None::<bool>;
+    let mut has_unknown_fields = false;
+    // The unknown fields don't take up a tag, so implicit tags are counted separately from the
+    // index of the field.
+    let mut implicit_tag = 0;
+
+    fields
+        .enumerate()
+        .map(|(i, field)| {
+            let attributes = FieldAttributes::new(&field.attrs)?;
+
+            let member = field
+                .ident
+                .clone()
+                .map(Member::Named)
+                .unwrap_or_else(|| Member::Unnamed(i.into()));
+
+            if attributes.unknown_fields {
+                if has_unknown_fields {
+                    bail!("Only one field can be marked `unknown_fields`");
+                }
+
+                has_unknown_fields = true;
+
+                return Ok((FieldTags::Unknown, member, &field.ty));
+            }
+
+            match (explicitly_tagged, attributes.is_explicitly_tagged()) {
+                (None, _) | (Some(true), true) | (Some(false), false) => {}
+                (Some(true), false) | (Some(false), true) => {
+                    return Err(anyhow!(
+                        "If `tag` is specified for one field it must be specified for all fields \
+                         (`oneof` fields count as explicitly tagged)"
+                    ));
+                }
             }
+
+            explicitly_tagged = Some(attributes.is_explicitly_tagged());
+            implicit_tag += 1;
+
+            let tag = attributes
+                .tag
+                .unwrap_or_else(|| NonZeroU32::new(implicit_tag).unwrap());
+            let tags = match (attributes.oneof, attributes.encoding) {
+                (Some(tags), _) => FieldTags::Oneof(tags),
+                (None, Some(encoding)) => FieldTags::Encoded(tag, encoding),
+                (None, None) if attributes.group => FieldTags::Group(tag),
+                (None, None) => FieldTags::Single(tag),
+            };
+
+            Ok((tags, member, &field.ty))
+        })
+        .collect()
+}
+
+/// The `WithEncoding` type that a field with an overridden encoding is accessed through.
+fn encoded_type(autoproto_path: &Path, ty: &Type, encoding: &FieldEncoding) -> Type {
+    let kind = Ident::new(encoding.kind, Span::call_site());
+    let packing = Ident::new(encoding.packing, Span::call_site());
+
+    let kind = match encoding.default {
+        Some(default) => {
+            let default = proc_macro2::Literal::i128_suffixed(default);
+
+            quote!(
+                #autoproto_path::encoding::WithDefault<
+                    #autoproto_path::encoding::#kind,
+                    { #default },
+                >
+            )
         }
+        None => quote!(#autoproto_path::encoding::#kind),
+    };
 
-        impl #impl_generics #autoproto_path::IsMessage for #ident #ty_generics #protooneof_where_clause {}
+    syn::parse_quote!(
+        #autoproto_path::encoding::WithEncoding<
+            #ty,
+            #kind,
+            #autoproto_path::encoding::#packing,
+        >
+    )
+}
 
-        #message_impl
+/// Whether `default` is a valid value for a field of type `ty`, if it's a primitive. Other scalars,
+/// such as enums, are only checked when `Default::default` is called.
+fn default_fits(ty: &Type, default: i128) -> Option<bool> {
+    let ident = match ty {
+        Type::Path(TypePath { qself: None, path }) => path.get_ident()?,
+        _ => return None,
+    };
+
+    Some(match &ident.to_string()[..] {
+        "bool" => matches!(default, 0 | 1),
+        "u8" => u8::try_from(default).is_ok(),
+        "u16" => u16::try_from(default).is_ok(),
+        "u32" => u32::try_from(default).is_ok(),
+        "u64" | "usize" => u64::try_from(default).is_ok(),
+        "i8" => i8::try_from(default).is_ok(),
+        "i16" => i16::try_from(default).is_ok(),
+        "i32" => i32::try_from(default).is_ok(),
+        "i64" | "isize" => i64::try_from(default).is_ok(),
+        // Defaults are always integers, which floats can't be converted from.
+        "f32" | "f64" => false,
+        _ => return None,
+    })
+}
+
+/// A `Default` implementation for a struct with `#[autoproto(default = ..)]` fields, since these
+/// can't be expressed with `#[derive(Default)]`.
+fn impl_default_for_defaulted_fields(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    members: &[(FieldTags, Member, &Type)],
+) -> Option<ItemImpl> {
+    if !members.iter().any(|(tags, _, _)| {
+        matches!(
+            tags,
+            FieldTags::Encoded(
+                _,
+                FieldEncoding {
+                    default: Some(_),
+                    ..
+                }
+            )
+        )
+    }) {
+        return None;
+    }
+
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(
+        members
+            .iter()
+            .map(|(_, _, ty)| (*ty).clone())
+            .collect::<Vec<_>>(),
+    );
+    let where_clause = where_clause_builder.with_field_bound(quote!(::core::default::Default));
+
+    let fields: Punctuated<_, Token!(,)> = members
+        .iter()
+        .map(|(tags, member, ty)| match tags {
+            FieldTags::Encoded(
+                _,
+                FieldEncoding {
+                    default: Some(default),
+                    default_span,
+                    ..
+                },
+            ) => {
+                let msg = format!("`default` is not a valid value for `{}`", quote!(#ty));
+
+                if default_fits(ty, *default) == Some(false) {
+                    let error = syn::Error::new(*default_span, msg).to_compile_error();
+
+                    return quote!(#member: #error);
+                }
+
+                let default = proc_macro2::Literal::i128_suffixed(*default);
+
+                quote!(
+                    #member: <#ty as #autoproto_path::ProtoScalar>::from_value(
+                        #autoproto_path::Value::Int(#default),
+                    )
+                    .expect(#msg)
+                )
+            }
+            _ => quote!(#member: ::core::default::Default::default()),
+        })
+        .collect();
+
+    Some(syn::parse_quote!(
+        impl #impl_generics ::core::default::Default for #ident #ty_generics #where_clause {
+            fn default() -> Self {
+                Self { #fields }
+            }
+        }
     ))
 }
 
+/// The types that need to be bounded in the where clause of impls for a struct with these members.
+/// Oneof fields are accessed through `OneofField`, groups through `Group` and fields with an
+/// overridden encoding through `WithEncoding`, so those are the types that need to be bounded.
+fn bounded_field_types(autoproto_path: &Path, members: &[(FieldTags, Member, &Type)]) -> Vec<Type> {
+    members
+        .iter()
+        .flat_map(|(tags, _, ty)| -> Vec<Type> {
+            match tags {
+                FieldTags::Unknown => vec![],
+                FieldTags::Single(_) => vec![(*ty).clone()],
+                FieldTags::Encoded(_, encoding) => {
+                    vec![encoded_type(autoproto_path, ty, encoding)]
+                }
+                FieldTags::Group(tag) => {
+                    let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+
+                    vec![syn::parse_quote!(#autoproto_path::Group<#ty, #tag>)]
+                }
+                FieldTags::Oneof(tags) => tags
+                    .iter()
+                    .map(|tag| {
+                        let tag: Lit =
+                            LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+
+                        syn::parse_quote!(#autoproto_path::OneofField<#ty, #tag>)
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
 fn try_derive_protostruct<'a>(
     autoproto_path: &Path,
     fields: impl ExactSizeIterator<Item = &'a Field> + Clone,
@@ -1158,65 +1674,203 @@ fn try_derive_protostruct<'a>(
 
     let num_fields = fields.len();
 
-    let mut explicitly_tagged = None::<bool>;
-
-    let members: Result<Vec<(NonZeroU32, Member)>> = fields
+    let members = struct_members(fields.clone())?;
+    let required = fields
         .clone()
-        .enumerate()
-        .map(|(i, field)| {
-            let attributes = FieldAttributes::new(&field.attrs)?;
+        .map(|field| Ok(FieldAttributes::new(&field.attrs)?.required))
+        .collect::<Result<Vec<_>>>()?;
+    let skip_default = fields
+        .clone()
+        .map(|field| Ok(FieldAttributes::new(&field.attrs)?.skip_default))
+        .collect::<Result<Vec<_>>>()?;
 
-            match (explicitly_tagged, &attributes.tag) {
-                (None, _) | (Some(true), Some(_)) | (Some(false), None) => {}
-                (Some(true), None) | (Some(false), Some(_)) => {
-                    return Err(anyhow!(
-                        "If `tag` is specified for one field it must be specified for all fields"
-                    ));
-                }
-            }
+    let tag_lit =
+        |tag: &NonZeroU32| -> Lit { LitInt::new(&tag.get().to_string(), Span::call_site()).into() };
 
-            explicitly_tagged = Some(attributes.tag.is_some());
+    // `required` fields are accessed through `Required`, so that they're always encoded.
+    let wrap_required = |required: bool, place: TokenStream2, from: TokenStream2| {
+        if required {
+            quote!(#autoproto_path::Required::#from(#place))
+        } else {
+            place
+        }
+    };
 
-            Ok((
-                attributes
-                    .tag
-                    .unwrap_or_else(|| NonZeroU32::new(i as u32 + 1).unwrap()),
-                field
-                    .ident
-                    .clone()
-                    .map(Member::Named)
-                    .unwrap_or_else(|| Member::Unnamed(i.into())),
-            ))
-        })
-        .collect();
-    let members = members?;
+    let unknown_fields = members
+        .iter()
+        .find(|(tags, _, _)| matches!(tags, FieldTags::Unknown))
+        .map(|(_, member, _)| member);
 
+    // Unknown fields are always encoded after the known fields.
     let members_array: Punctuated<_, Token!(,)> = members
         .iter()
-        .map(|(tag, member)| {
-            let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+        .zip(required.iter().zip(&skip_default))
+        .filter(|((tags, _, _), _)| !matches!(tags, FieldTags::Unknown))
+        .map(|((tags, member, ty), (required, skip))| match tags {
+            FieldTags::Unknown => unreachable!(),
+            // `skip_default` fields are accessed through `SkipDefault`, so that they're left out
+            // when they're the default.
+            FieldTags::Single(tag) if *skip => {
+                let tag = tag_lit(tag);
+
+                quote!(
+                    (
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                        #autoproto_path::SkipDefault::from_ref(&self.#member)
+                            as &dyn #autoproto_path::ProtoEncode,
+                    )
+                )
+            }
+            FieldTags::Single(tag) => {
+                let tag = tag_lit(tag);
+                let place = wrap_required(*required, quote!(&self.#member), quote!(from_ref));
+
+                quote!(
+                    (
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                        #place as &dyn #autoproto_path::ProtoEncode,
+                    )
+                )
+            }
+            FieldTags::Encoded(tag, encoding) => {
+                let tag = tag_lit(tag);
+                let ty = encoded_type(autoproto_path, ty, encoding);
+                let place = wrap_required(
+                    *required,
+                    quote!(<#ty>::from_ref(&self.#member)),
+                    quote!(from_ref),
+                );
 
+                quote!(
+                    (
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                        #place as &dyn #autoproto_path::ProtoEncode,
+                    )
+                )
+            }
+            FieldTags::Group(tag) => {
+                let tag = tag_lit(tag);
+                let place = wrap_required(
+                    *required,
+                    quote!(#autoproto_path::Group::<_, #tag>::from_ref(&self.#member)),
+                    quote!(from_ref),
+                );
+
+                quote!(
+                    (
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                        #place as &dyn #autoproto_path::ProtoEncode,
+                    )
+                )
+            }
+            FieldTags::Oneof(tags) => {
+                // The active variant is encoded with its own tag, so which tag we pass here
+                // doesn't matter as long as it's one of the oneof's tags.
+                let tag = tag_lit(&tags[0]);
+
+                quote!(
+                    (
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                        #autoproto_path::OneofField::<_, #tag>::from_ref(&self.#member)
+                            as &dyn #autoproto_path::ProtoEncode,
+                    )
+                )
+            }
+        })
+        .chain(unknown_fields.map(|member| {
+            // Each unknown field is encoded with its own tag, so the tag passed here is ignored.
             quote!(
                 (
-                    unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                    unsafe { ::core::num::NonZeroU32::new_unchecked(1) },
                     &self.#member as &dyn #autoproto_path::ProtoEncode,
                 )
             )
-        })
+        }))
         .collect();
 
     let get_field_mut: Punctuated<_, Token!(,)> = members
-        .into_iter()
-        .map::<Arm, _>(|(tag, member)| {
-            let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+        .iter()
+        .zip(&required)
+        .flat_map(|((tags, member, ty), required)| -> Vec<Arm> {
+            match tags {
+                FieldTags::Unknown => vec![],
+                FieldTags::Single(tag) => {
+                    let tag = tag_lit(tag);
+                    let place =
+                        wrap_required(*required, quote!(&mut self.#member), quote!(from_mut));
+
+                    vec![syn::parse_quote!(#tag => #place)]
+                }
+                FieldTags::Encoded(tag, encoding) => {
+                    let tag = tag_lit(tag);
+                    let ty = encoded_type(autoproto_path, ty, encoding);
+                    let place = wrap_required(
+                        *required,
+                        quote!(<#ty>::from_mut(&mut self.#member)),
+                        quote!(from_mut),
+                    );
 
-            syn::parse_quote!(#tag => &mut self.#member)
+                    vec![syn::parse_quote!(#tag => #place)]
+                }
+                FieldTags::Group(tag) => {
+                    let tag = tag_lit(tag);
+                    let place = wrap_required(
+                        *required,
+                        quote!(#autoproto_path::Group::<_, #tag>::from_mut(&mut self.#member)),
+                        quote!(from_mut),
+                    );
+
+                    vec![syn::parse_quote!(#tag => #place)]
+                }
+                FieldTags::Oneof(tags) => tags
+                    .iter()
+                    .map(|tag| {
+                        let tag = tag_lit(tag);
+
+                        syn::parse_quote!(
+                            #tag => #autoproto_path::OneofField::<_, #tag>::from_mut(&mut self.#member)
+                        )
+                    })
+                    .collect(),
+            }
         })
         .chain(iter::once(syn::parse_quote!(_ => { return None; })))
         .collect();
 
-    let where_clause_builder =
-        WhereClauseBuilder::new(generics).with_field_types(fields.map(|f| &f.ty));
+    let required_fields: Punctuated<_, Token!(,)> = members
+        .iter()
+        .zip(&required)
+        .filter_map(|((tags, member, _), required)| match tags {
+            FieldTags::Single(tag) | FieldTags::Encoded(tag, _) | FieldTags::Group(tag)
+                if *required =>
+            {
+                let tag = tag_lit(tag);
+                let name = spec::field_name(member);
+
+                Some(quote!((#tag, #name)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let required_fields = if required_fields.is_empty() {
+        None
+    } else {
+        Some(quote!(
+            const REQUIRED_FIELDS: &'static [(u32, &'static str)] = &[#required_fields];
+        ))
+    };
+
+    let unknown_fields_mut = unknown_fields.map(|member| {
+        quote!(
+            fn unknown_fields_mut(&mut self) -> Option<&mut #autoproto_path::UnknownFields> {
+                Some(&mut self.#member)
+            }
+        )
+    });
+
+    let where_clause_builder = WhereClauseBuilder::new(generics)
+        .with_field_types(bounded_field_types(autoproto_path, &members));
 
     let protostruct_where_clause =
         where_clause_builder.with_field_bound(quote!(#autoproto_path::ProtoEncode));
@@ -1253,16 +1907,29 @@ fn try_derive_protostruct<'a>(
                         #get_field_mut
                     })
                 }
+
+                #unknown_fields_mut
             }
         }),
     };
 
+    let default_impl = match mode {
+        DeriveMode::ImmutableOnly => None,
+        DeriveMode::ImmutableAndMutable => {
+            impl_default_for_defaulted_fields(autoproto_path, ident, generics, &members)
+        }
+    };
+
     Ok(quote! {
-        impl #impl_generics #autoproto_path::IsMessage for #ident #ty_generics #protostruct_where_clause {}
+        impl #impl_generics #autoproto_path::IsMessage for #ident #ty_generics #protostruct_where_clause {
+            #required_fields
+        }
 
         #immut
 
         #mutable
+
+        #default_impl
     })
 }
 
@@ -1397,6 +2064,43 @@ fn try_derive_proto_for_struct(
     }
 }
 
+fn try_derive_proto_for_enum(
+    attrs: &[Attribute],
+    ident: &Ident,
+    generics: &Generics,
+    data: &DataEnum,
+) -> Result<TokenStream2> {
+    let attrs = MessageAttributes::new(attrs)?;
+
+    let autoproto_path = &attrs.autoproto_path;
+
+    let variants = oneof_variants(data)?;
+
+    let protooneof_impl = try_derive_protooneof(
+        autoproto_path,
+        ident,
+        generics,
+        &variants,
+        DeriveMode::ImmutableAndMutable,
+    )?;
+
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let proto_impl = impl_proto_for_protooneof(
+        autoproto_path,
+        ident,
+        &impl_generics,
+        &ty_generics,
+        &mut WhereClauseBuilder::new(generics),
+    );
+
+    Ok(quote!(
+        #protooneof_impl
+
+        #proto_impl
+    ))
+}
+
 fn try_derive_message_for_struct(
     attrs: &[Attribute],
     ident: &Ident,
@@ -1410,6 +2114,10 @@ fn try_derive_message_for_struct(
     let autoproto_path = &attrs.autoproto_path;
 
     if attrs.transparent {
+        if attrs.proto_file.is_some() {
+            bail!("`proto_file` cannot be used with `transparent` messages");
+        }
+
         let inner_field = match data {
             DataStruct {
                 fields: Fields::Named(FieldsNamed { named: fields, .. }),
@@ -1470,11 +2178,50 @@ fn try_derive_message_for_struct(
                 &mut where_clause_builder,
             ),
         );
+        let spec_impl = spec::newtype(
+            &attrs.autoproto_path,
+            ident,
+            &inner_field.ty,
+            &impl_generics,
+            &ty_generics,
+            &mut where_clause_builder,
+        );
+        let descriptor_impl = descriptor::newtype(
+            &attrs.autoproto_path,
+            ident,
+            &inner_field.ty,
+            &impl_generics,
+            &ty_generics,
+            &mut where_clause_builder,
+        );
+        let json_impl = json::newtype(
+            &attrs.autoproto_path,
+            ident,
+            &field,
+            &impl_generics,
+            &ty_generics,
+            &mut where_clause_builder,
+        );
+        let text_impl = text::newtype(
+            &attrs.autoproto_path,
+            ident,
+            &field,
+            &impl_generics,
+            &ty_generics,
+            &mut where_clause_builder,
+        );
 
         Ok(quote! {
             #protoencode_impl
             #proto_impl
             #message_impl
+            #spec_impl
+
+            #descriptor_impl
+
+            #json_impl
+
+            #text_impl
         })
     } else {
         match data {
@@ -1490,12 +2237,40 @@ fn try_derive_message_for_struct(
                 ..
             } => {
                 if fields.is_empty() {
-                    Ok(unit_proto_impl(
+                    let unit_impl = unit_proto_impl(
                         autoproto_path,
                         ident,
                         impl_generics,
                         ty_generics,
                         where_clause,
+                    );
+                    let spec_impl =
+                        spec::unit(autoproto_path, ident, generics, attrs.well_known.as_ref());
+                    let descriptor_impl = descriptor::unit(
+                        autoproto_path,
+                        ident,
+                        generics,
+                        attrs.well_known.as_ref(),
+                    );
+                    let json_impl = attrs
+                        .well_known
+                        .is_none()
+                        .then(|| json::unit(autoproto_path, ident, generics));
+                    let text_impl = text::unit(autoproto_path, ident, generics);
+                    let verify = verify::message(&attrs, ident, generics, &[])?;
+
+                    Ok(quote!(
+                        #unit_impl
+
+                        #spec_impl
+
+                        #descriptor_impl
+
+                        #json_impl
+
+                        #text_impl
+
+                        #verify
                     ))
                 } else {
                     let where_clause_builder = WhereClauseBuilder::new(generics)
@@ -1526,23 +2301,72 @@ fn try_derive_message_for_struct(
                         Some(&message_where_clause),
                     );
 
+                    let members = struct_members(fields.iter())?;
+                    let well_known = attrs.well_known.as_ref();
+                    let spec_impl =
+                        spec::message(autoproto_path, ident, generics, &members, well_known);
+                    let descriptor_impl =
+                        descriptor::message(autoproto_path, ident, generics, &members, well_known);
+                    let json_impl = well_known
+                        .is_none()
+                        .then(|| json::message(autoproto_path, ident, generics, fields.iter()))
+                        .transpose()?;
+                    let text_impl = text::message(autoproto_path, ident, generics, fields.iter())?;
+                    let verify = verify::message(&attrs, ident, generics, &members)?;
+
                     Ok(quote!(
                         #protostruct_impl
 
                         #message_impl
+
+                        #spec_impl
+
+                        #descriptor_impl
+
+                        #json_impl
+
+                        #text_impl
+
+                        #verify
                     ))
                 }
             }
             DataStruct {
                 fields: Fields::Unit,
                 ..
-            } => Ok(unit_proto_impl(
-                autoproto_path,
-                ident,
-                impl_generics,
-                ty_generics,
-                where_clause,
-            )),
+            } => {
+                let unit_impl = unit_proto_impl(
+                    autoproto_path,
+                    ident,
+                    impl_generics,
+                    ty_generics,
+                    where_clause,
+                );
+                let spec_impl =
+                    spec::unit(autoproto_path, ident, generics, attrs.well_known.as_ref());
+                let descriptor_impl =
+                    descriptor::unit(autoproto_path, ident, generics, attrs.well_known.as_ref());
+                let json_impl = attrs
+                    .well_known
+                    .is_none()
+                    .then(|| json::unit(autoproto_path, ident, generics));
+                let text_impl = text::unit(autoproto_path, ident, generics);
+                let verify = verify::message(&attrs, ident, generics, &[])?;
+
+                Ok(quote!(
+                    #unit_impl
+
+                    #spec_impl
+
+                    #descriptor_impl
+
+                    #json_impl
+
+                    #text_impl
+
+                    #verify
+                ))
+            }
         }
     }
 }
@@ -1612,6 +2436,20 @@ fn impl_message_for_protostruct(
                 #autoproto_path::generic::protostruct::message_merge_field(self, tag, wire_type, buf, ctx)
             }
 
+            fn merge<__Buffer: #autoproto_path::prost::bytes::Buf>(
+                &mut self,
+                buf: __Buffer,
+            ) -> Result<(), #autoproto_path::prost::DecodeError> {
+                #autoproto_path::generic::protostruct::message_merge(self, buf)
+            }
+
+            fn merge_length_delimited<__Buffer: #autoproto_path::prost::bytes::Buf>(
+                &mut self,
+                buf: __Buffer,
+            ) -> Result<(), #autoproto_path::prost::DecodeError> {
+                #autoproto_path::generic::protostruct::message_merge_length_delimited(self, buf)
+            }
+
             fn encoded_len(&self) -> usize {
                 #autoproto_path::generic::protostruct::message_encoded_len(self)
             }
@@ -1681,6 +2519,64 @@ fn impl_protoencode_for_protostruct(
     )
 }
 
+fn impl_proto_for_protooneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> TokenStream2 {
+    let protoencode_impl = impl_protoencode_for_protooneof(
+        autoproto_path,
+        ident,
+        impl_generics,
+        ty_generics,
+        where_clause_builder,
+    );
+    let proto_where_clause = where_clause_builder
+        .build()
+        .with_self_bound(quote!(#autoproto_path::ProtoOneofMut + #autoproto_path::ProtoEncode));
+
+    quote!(
+        impl #impl_generics #autoproto_path::Proto for #ident #ty_generics #proto_where_clause
+        {
+            fn merge_self(
+                &mut self,
+                wire_type: #autoproto_path::prost::encoding::WireType,
+                mut buf: &mut dyn #autoproto_path::prost::bytes::Buf,
+                ctx: #autoproto_path::prost::encoding::DecodeContext,
+            ) -> Result<(), #autoproto_path::prost::DecodeError> {
+                #autoproto_path::generic::protooneof::proto_merge_self(self, wire_type, &mut buf, ctx)
+            }
+        }
+
+        #protoencode_impl
+    )
+}
+
+fn impl_protoencode_for_protooneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> ItemImpl {
+    let where_clause = where_clause_builder.with_self_bound(quote!(#autoproto_path::ProtoOneof));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ProtoEncode for #ident #ty_generics #where_clause
+        {
+            fn encode_as_field(&self, tag: ::core::num::NonZeroU32, mut buf: &mut dyn #autoproto_path::prost::bytes::BufMut) {
+                #autoproto_path::generic::protooneof::protoencode_encode_as_field(self, tag, buf)
+            }
+
+            fn encoded_len_as_field(&self, tag: ::core::num::NonZeroU32) -> usize {
+                #autoproto_path::generic::protooneof::protoencode_encoded_len_as_field(self, tag)
+            }
+        }
+    )
+}
+
 fn unit_proto_impl(
     autoproto_path: &Path,
     ident: &Ident,
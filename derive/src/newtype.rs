@@ -24,6 +24,14 @@ pub fn protoencode(
             fn encoded_len_as_field(&self, tag: ::core::num::NonZeroU32) -> usize {
                 #autoproto_path::ProtoEncode::encoded_len_as_field(&self.#field, tag)
             }
+
+            fn encode_as_field_explicit(&self, tag: ::core::num::NonZeroU32, buf: &mut dyn #autoproto_path::bytes::BufMut) {
+                #autoproto_path::ProtoEncode::encode_as_field_explicit(&self.#field, tag, buf)
+            }
+
+            fn encoded_len_as_field_explicit(&self, tag: ::core::num::NonZeroU32) -> usize {
+                #autoproto_path::ProtoEncode::encoded_len_as_field_explicit(&self.#field, tag)
+            }
         }
     )
 }
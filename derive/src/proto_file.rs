@@ -0,0 +1,512 @@
+//! A minimal parser for `.proto` files, used to check derived types against a schema. This only
+//! keeps the information needed for that check - the messages, their fields and the names of the
+//! enumerations - and skips over everything else (options, services, extensions and so on).
+
+use anyhow::{anyhow, bail};
+use std::{iter::Peekable, str::CharIndices};
+
+use crate::util::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Label {
+    Single,
+    Optional,
+    Required,
+    Repeated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    /// A type built in to protobuf, like `uint32` or `string`.
+    Builtin(String),
+    /// A message or enumeration, resolved to its fully-qualified name (without the leading `.`) if
+    /// it is defined in the same file.
+    Named(String),
+    Map(Box<FieldType>, Box<FieldType>),
+    Group(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtoField {
+    pub name: String,
+    pub tag: u32,
+    pub label: Label,
+    pub ty: FieldType,
+    /// The name of the `oneof` that this field is part of, if any.
+    pub oneof: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProtoMessage {
+    pub name: String,
+    pub fields: Vec<ProtoField>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProtoFile {
+    pub package: Option<String>,
+    /// Every message in the file, including nested messages, by fully-qualified name.
+    pub messages: Vec<ProtoMessage>,
+    /// The fully-qualified names of every enumeration in the file.
+    pub enums: Vec<String>,
+}
+
+const BUILTIN_TYPES: &[&str] = &[
+    "double", "float", "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32",
+    "fixed64", "sfixed32", "sfixed64", "bool", "string", "bytes",
+];
+
+impl ProtoFile {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: Tokens::new(src).collect::<Result<Vec<_>>>()?,
+            pos: 0,
+            file: ProtoFile::default(),
+        };
+
+        parser.parse_file()?;
+
+        let mut file = parser.file;
+        file.resolve_types();
+
+        Ok(file)
+    }
+
+    pub fn message(&self, name: &str) -> Option<&ProtoMessage> {
+        let name = name.trim_start_matches('.');
+
+        self.messages.iter().find(|msg| msg.name == name)
+    }
+
+    pub fn is_enum(&self, name: &str) -> bool {
+        self.enums.iter().any(|e| e == name)
+    }
+
+    /// Resolve every named type to its fully-qualified name, following the protobuf scoping rules:
+    /// the name is looked up in the innermost scope first, then in each enclosing scope in turn.
+    fn resolve_types(&mut self) {
+        let known = self
+            .messages
+            .iter()
+            .map(|msg| msg.name.clone())
+            .chain(self.enums.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let resolve = |scope: &str, name: &str| -> String {
+            if let Some(absolute) = name.strip_prefix('.') {
+                return absolute.to_owned();
+            }
+
+            let mut scope = scope;
+
+            loop {
+                let candidate = if scope.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", scope, name)
+                };
+
+                if known.contains(&candidate) {
+                    return candidate;
+                }
+
+                match scope.rfind('.') {
+                    Some(i) => scope = &scope[..i],
+                    None if !scope.is_empty() => scope = "",
+                    None => return name.to_owned(),
+                }
+            }
+        };
+
+        fn resolve_ty(ty: &mut FieldType, scope: &str, resolve: &dyn Fn(&str, &str) -> String) {
+            match ty {
+                FieldType::Named(name) | FieldType::Group(name) => *name = resolve(scope, name),
+                FieldType::Map(key, val) => {
+                    resolve_ty(key, scope, resolve);
+                    resolve_ty(val, scope, resolve);
+                }
+                FieldType::Builtin(_) => {}
+            }
+        }
+
+        for msg in &mut self.messages {
+            for field in &mut msg.fields {
+                resolve_ty(&mut field.ty, &msg.name, &resolve);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(u64),
+    Str(String),
+    Symbol(char),
+}
+
+struct Tokens<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            chars: src.char_indices().peekable(),
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, start: usize, func: F) -> &'a str {
+        let mut end = self.chars.peek().map_or(self.src.len(), |&(i, _)| i);
+
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !func(c) {
+                break;
+            }
+
+            end = i + c.len_utf8();
+            self.chars.next();
+        }
+
+        &self.src[start..end]
+    }
+
+    fn skip_comments_and_whitespace(&mut self) -> Result<()> {
+        loop {
+            match self.chars.peek() {
+                Some(&(_, c)) if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                Some(&(i, '/')) => match self.src[i..].chars().nth(1) {
+                    Some('/') => {
+                        self.take_while(i, |c| c != '\n');
+                    }
+                    Some('*') => {
+                        let end = self.src[i + 2..]
+                            .find("*/")
+                            .ok_or_else(|| anyhow!("Unterminated comment"))?;
+
+                        while self.chars.peek().map(|&(j, _)| j < i + 2 + end + 2) == Some(true) {
+                            self.chars.next();
+                        }
+                    }
+                    _ => return Ok(()),
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.skip_comments_and_whitespace() {
+            return Some(Err(e));
+        }
+
+        let (start, c) = self.chars.next()?;
+
+        Some(Ok(match c {
+            c if c.is_ascii_alphabetic() || c == '_' => Token::Ident(
+                self.take_while(start, |c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                    .to_owned(),
+            ),
+            // Fully-qualified names start with a `.`
+            '.' if self.chars.peek().map(|&(_, c)| c.is_ascii_alphabetic()) == Some(true) => {
+                Token::Ident(
+                    self.take_while(start, |c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+                        .to_owned(),
+                )
+            }
+            c if c.is_ascii_digit() => {
+                let lit = self.take_while(start, |c| c.is_ascii_alphanumeric() || c == '.');
+
+                let parsed =
+                    if let Some(hex) = lit.strip_prefix("0x").or_else(|| lit.strip_prefix("0X")) {
+                        u64::from_str_radix(hex, 16).ok()
+                    } else if lit.len() > 1 && lit.starts_with('0') {
+                        u64::from_str_radix(&lit[1..], 8).ok()
+                    } else {
+                        lit.parse().ok()
+                    };
+
+                // Floats only appear in options, which we skip, so their value doesn't matter.
+                Token::Int(parsed.unwrap_or_default())
+            }
+            '"' | '\'' => {
+                let mut out = String::new();
+
+                loop {
+                    match self.chars.next() {
+                        Some((_, '\\')) => {
+                            if let Some((_, escaped)) = self.chars.next() {
+                                out.push(escaped);
+                            }
+                        }
+                        Some((_, end)) if end == c => break,
+                        Some((_, other)) => out.push(other),
+                        None => return Some(Err(anyhow!("Unterminated string literal"))),
+                    }
+                }
+
+                Token::Str(out)
+            }
+            other => Token::Symbol(other),
+        }))
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    file: ProtoFile,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of file"))?;
+
+        self.pos += 1;
+
+        Ok(token)
+    }
+
+    fn peek_symbol(&self, symbol: char) -> bool {
+        self.peek() == Some(&Token::Symbol(symbol))
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(ident) => Ok(ident),
+            other => bail!("Expected identifier, found {:?}", other),
+        }
+    }
+
+    fn int(&mut self) -> Result<u64> {
+        match self.next()? {
+            Token::Int(int) => Ok(int),
+            other => bail!("Expected integer, found {:?}", other),
+        }
+    }
+
+    fn symbol(&mut self, symbol: char) -> Result<()> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            other => bail!("Expected `{}`, found {:?}", symbol, other),
+        }
+    }
+
+    /// Skip everything up to and including the next `;` or balanced `{ ... }` block.
+    fn skip_statement(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+
+        loop {
+            match self.next()? {
+                Token::Symbol(';') if depth == 0 => return Ok(()),
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or_else(|| anyhow!("Unbalanced `}}`"))?;
+
+                    if depth == 0 {
+                        if self.peek_symbol(';') {
+                            self.pos += 1;
+                        }
+
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_file(&mut self) -> Result<()> {
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Symbol(';') => self.pos += 1,
+                Token::Ident(ident) => match &ident[..] {
+                    "package" => {
+                        self.pos += 1;
+                        self.file.package = Some(self.ident()?);
+                        self.symbol(';')?;
+                    }
+                    "message" => {
+                        self.pos += 1;
+                        let name = self.ident()?;
+                        let scope = self.file.package.clone().unwrap_or_default();
+                        self.parse_message(&scope, name)?;
+                    }
+                    "enum" => {
+                        self.pos += 1;
+                        let name = self.ident()?;
+                        let scope = self.file.package.clone().unwrap_or_default();
+                        self.file.enums.push(qualify(&scope, &name));
+                        self.skip_statement()?;
+                    }
+                    _ => self.skip_statement()?,
+                },
+                other => bail!("Unexpected {:?}", other),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_message(&mut self, scope: &str, name: String) -> Result<()> {
+        self.symbol('{')?;
+        self.parse_message_body_as(scope, &name)
+    }
+
+    /// Parse the body of a message or `oneof`, up to and including the closing `}`.
+    fn parse_message_body(&mut self, scope: &str, oneof: Option<&str>) -> Result<Vec<ProtoField>> {
+        let mut fields = vec![];
+
+        loop {
+            let ident = match self.next()? {
+                Token::Symbol('}') => return Ok(fields),
+                Token::Symbol(';') => continue,
+                Token::Ident(ident) => ident,
+                other => bail!("Unexpected {:?} in body of `{}`", other, scope),
+            };
+
+            match &ident[..] {
+                "message" if oneof.is_none() => {
+                    let name = self.ident()?;
+                    self.parse_message(scope, name)?;
+                }
+                "enum" if oneof.is_none() => {
+                    let name = self.ident()?;
+                    self.file.enums.push(qualify(scope, &name));
+                    self.skip_statement()?;
+                }
+                "oneof" if oneof.is_none() => {
+                    let name = self.ident()?;
+                    self.symbol('{')?;
+                    fields.extend(self.parse_message_body(scope, Some(&name))?);
+                }
+                "option" | "reserved" | "extensions" | "extend" => {
+                    self.skip_statement()?;
+                }
+                "repeated" | "optional" | "required" => {
+                    let label = match &ident[..] {
+                        "repeated" => Label::Repeated,
+                        "optional" => Label::Optional,
+                        _ => Label::Required,
+                    };
+                    let ty = self.ident()?;
+
+                    fields.push(self.parse_field(scope, oneof, label, ty)?);
+                }
+                _ => fields.push(self.parse_field(scope, oneof, Label::Single, ident)?),
+            }
+        }
+    }
+
+    fn parse_field(
+        &mut self,
+        scope: &str,
+        oneof: Option<&str>,
+        label: Label,
+        ty: String,
+    ) -> Result<ProtoField> {
+        let ty = if ty == "map" {
+            self.symbol('<')?;
+            let key = self.ident()?;
+            self.symbol(',')?;
+            let val = self.ident()?;
+            self.symbol('>')?;
+
+            FieldType::Map(Box::new(field_type(key)), Box::new(field_type(val)))
+        } else if ty == "group" {
+            FieldType::Group(String::new())
+        } else {
+            field_type(ty)
+        };
+
+        let name = self.ident()?;
+        self.symbol('=')?;
+        let tag = self.int()?;
+
+        if tag == 0 || tag > u32::MAX as u64 {
+            bail!("Invalid tag {} for field `{}`", tag, name);
+        }
+
+        if self.peek_symbol('[') {
+            while !self.peek_symbol(']') {
+                self.next()?;
+            }
+
+            self.pos += 1;
+        }
+
+        let ty = if let FieldType::Group(_) = ty {
+            // Groups declare a message of the same name as the group, and a field named after the
+            // group in lowercase.
+            self.symbol('{')?;
+            self.parse_message_body_as(scope, &name)?;
+
+            FieldType::Group(name.clone())
+        } else {
+            self.symbol(';')?;
+
+            ty
+        };
+
+        Ok(ProtoField {
+            name: if let FieldType::Group(_) = ty {
+                name.to_lowercase()
+            } else {
+                name
+            },
+            tag: tag as u32,
+            label,
+            ty,
+            oneof: oneof.map(String::from),
+        })
+    }
+
+    fn parse_message_body_as(&mut self, scope: &str, name: &str) -> Result<()> {
+        let name = qualify(scope, name);
+        let index = self.file.messages.len();
+
+        self.file.messages.push(ProtoMessage {
+            name: name.clone(),
+            fields: vec![],
+        });
+
+        let fields = self.parse_message_body(&name, None)?;
+        self.file.messages[index].fields = fields;
+
+        Ok(())
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn field_type(name: String) -> FieldType {
+    if BUILTIN_TYPES.contains(&&name[..]) {
+        FieldType::Builtin(name)
+    } else {
+        FieldType::Named(name)
+    }
+}
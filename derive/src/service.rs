@@ -0,0 +1,545 @@
+use crate::util::Result;
+use anyhow::bail;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, punctuated::Punctuated, FnArg, GenericArgument, Ident, ItemTrait, Lit, LitStr,
+    Meta, NestedMeta, Path, PathArguments, ReturnType, Token, TraitItem, TraitItemMethod, Type,
+    TypeParamBound,
+};
+
+struct ServiceAttributes {
+    /// The protobuf package of the service, which is part of the path of each method.
+    package: Option<LitStr>,
+    /// The path to the `autoproto` crate, for when it's re-exported or renamed.
+    autoproto_path: Path,
+}
+
+impl ServiceAttributes {
+    fn new(attr: TokenStream2) -> Result<Self> {
+        let mut package = None;
+        let mut autoproto_path = syn::parse_quote!(::autoproto);
+
+        for meta in Punctuated::<NestedMeta, Token![,]>::parse_terminated.parse2(attr)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(inner)) if inner.path.is_ident("package") => {
+                    package = match inner.lit {
+                        Lit::Str(lit) => Some(lit),
+                        _ => bail!("Invalid value for `package`"),
+                    };
+                }
+                NestedMeta::Meta(Meta::List(inner)) if inner.path.is_ident("path") => {
+                    autoproto_path = match inner.nested.iter().collect::<Vec<_>>()[..] {
+                        [NestedMeta::Meta(Meta::Path(path))] => path.clone(),
+                        _ => bail!("`path` requires exactly one argument"),
+                    };
+                }
+                _ => bail!("Unknown argument to `#[autoproto::service]`"),
+            }
+        }
+
+        Ok(Self {
+            package,
+            autoproto_path,
+        })
+    }
+}
+
+/// A method of a service trait, which takes `&self` and a single request and returns a
+/// `Result<Response, Status>`. A request of type `Streaming<T>` makes the method client-streaming,
+/// and a response of type `BoxStream<T>` makes it server-streaming.
+struct Method<'a> {
+    ident: &'a Ident,
+    doc: Vec<&'a syn::Attribute>,
+    /// The name of the method in the service, which is the method's name in `PascalCase`.
+    name: String,
+    request: &'a Type,
+    response: &'a Type,
+    client_streaming: bool,
+    server_streaming: bool,
+}
+
+/// If `ty` is a path ending in `name<T>`, return `T`.
+fn type_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    if segment.ident != name {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn pascal_case(ident: &str) -> String {
+    ident
+        .split('_')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars))
+                .into_iter()
+                .flatten()
+        })
+        .collect()
+}
+
+impl<'a> Method<'a> {
+    fn new(method: &'a TraitItemMethod) -> Result<Self> {
+        let sig = &method.sig;
+        let ident = &sig.ident;
+
+        if sig.asyncness.is_none() {
+            bail!("Service method `{}` must be `async`", ident);
+        }
+
+        if !sig.generics.params.is_empty() {
+            bail!("Service method `{}` can't be generic", ident);
+        }
+
+        let request = match sig.inputs.iter().collect::<Vec<_>>()[..] {
+            [FnArg::Receiver(receiver), FnArg::Typed(request)]
+                if receiver.reference.is_some() && receiver.mutability.is_none() =>
+            {
+                &*request.ty
+            }
+            _ => bail!(
+                "Service method `{}` must take `&self` and a single request",
+                ident
+            ),
+        };
+
+        let response = match &sig.output {
+            ReturnType::Type(_, ty) => type_argument(ty, "Result"),
+            ReturnType::Default => None,
+        };
+        let response = match response {
+            Some(response) => response,
+            None => bail!(
+                "Service method `{}` must return a `Result<_, tonic::Status>`",
+                ident
+            ),
+        };
+
+        let (request, client_streaming) = match type_argument(request, "Streaming") {
+            Some(inner) => (inner, true),
+            None => (request, false),
+        };
+        let (response, server_streaming) = match type_argument(response, "BoxStream") {
+            Some(inner) => (inner, true),
+            None => (response, false),
+        };
+
+        Ok(Self {
+            ident,
+            doc: method
+                .attrs
+                .iter()
+                .filter(|attr| attr.path.is_ident("doc"))
+                .collect(),
+            name: pascal_case(&ident.to_string()),
+            request,
+            response,
+            client_streaming,
+            server_streaming,
+        })
+    }
+
+    /// The arm of the server's `match` on the request path which handles this method.
+    fn server_arm(&self, autoproto: &Path, trait_ident: &Ident, path: &str) -> TokenStream2 {
+        let Self {
+            ident,
+            request,
+            response,
+            ..
+        } = self;
+        let svc = format_ident!("{}Svc", self.name);
+
+        let (service_trait, request_ty, response_stream, call) =
+            match (self.client_streaming, self.server_streaming) {
+                (false, false) => (quote!(UnaryService), quote!(#request), None, quote!(unary)),
+                (false, true) => (
+                    quote!(ServerStreamingService),
+                    quote!(#request),
+                    Some(quote!(type ResponseStream = #autoproto::grpc::BoxStream<#response>;)),
+                    quote!(server_streaming),
+                ),
+                (true, false) => (
+                    quote!(ClientStreamingService),
+                    quote!(#autoproto::grpc::Streaming<#request>),
+                    None,
+                    quote!(client_streaming),
+                ),
+                (true, true) => (
+                    quote!(StreamingService),
+                    quote!(#autoproto::grpc::Streaming<#request>),
+                    Some(quote!(type ResponseStream = #autoproto::grpc::BoxStream<#response>;)),
+                    quote!(streaming),
+                ),
+            };
+        let response_message = if self.server_streaming {
+            quote!(Self::ResponseStream)
+        } else {
+            quote!(Self::Response)
+        };
+
+        quote!(
+            #path => {
+                #[allow(non_camel_case_types)]
+                struct #svc<T: ?::core::marker::Sized>(::std::sync::Arc<T>);
+
+                impl<T> #autoproto::tonic::server::#service_trait<#request> for #svc<T>
+                where
+                    T: #trait_ident + ?::core::marker::Sized,
+                {
+                    type Response = #response;
+                    #response_stream
+                    type Future = #autoproto::tonic::codegen::BoxFuture<
+                        #autoproto::tonic::Response<#response_message>,
+                        #autoproto::tonic::Status,
+                    >;
+
+                    fn call(
+                        &mut self,
+                        request: #autoproto::tonic::Request<#request_ty>,
+                    ) -> Self::Future {
+                        let inner = self.0.clone();
+
+                        ::std::boxed::Box::pin(async move {
+                            inner
+                                .#ident(request.into_inner())
+                                .await
+                                .map(#autoproto::tonic::Response::new)
+                        })
+                    }
+                }
+
+                ::std::boxed::Box::pin(async move {
+                    let mut grpc = #autoproto::tonic::server::Grpc::new(
+                        #autoproto::grpc::AutoprotoCodec::default(),
+                    );
+
+                    ::core::result::Result::Ok(grpc.#call(#svc(inner), req).await)
+                })
+            }
+        )
+    }
+
+    /// The method of the client which calls this method.
+    fn client_method(&self, autoproto: &Path, path: &str) -> TokenStream2 {
+        let Self {
+            ident,
+            doc,
+            request,
+            response,
+            ..
+        } = self;
+
+        let (request_ty, into_request) = if self.client_streaming {
+            (
+                quote!(impl #autoproto::tonic::IntoStreamingRequest<Message = #request>),
+                quote!(into_streaming_request),
+            )
+        } else {
+            (
+                quote!(impl #autoproto::tonic::IntoRequest<#request>),
+                quote!(into_request),
+            )
+        };
+        let response_ty = if self.server_streaming {
+            quote!(#autoproto::grpc::Streaming<#response>)
+        } else {
+            quote!(#response)
+        };
+        let call = match (self.client_streaming, self.server_streaming) {
+            (false, false) => quote!(unary),
+            (false, true) => quote!(server_streaming),
+            (true, false) => quote!(client_streaming),
+            (true, true) => quote!(streaming),
+        };
+
+        quote!(
+            #(#doc)*
+            pub async fn #ident(
+                &mut self,
+                request: #request_ty,
+            ) -> ::core::result::Result<
+                #autoproto::tonic::Response<#response_ty>,
+                #autoproto::tonic::Status,
+            > {
+                self.inner.ready().await.map_err(|e| {
+                    #autoproto::tonic::Status::new(
+                        #autoproto::tonic::Code::Unknown,
+                        ::std::format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+
+                self.inner
+                    .#call(
+                        request.#into_request(),
+                        #autoproto::tonic::codegen::http::uri::PathAndQuery::from_static(#path),
+                        #autoproto::grpc::AutoprotoCodec::default(),
+                    )
+                    .await
+            }
+        )
+    }
+
+    /// The line declaring this method in the `service` block of a `.proto` file.
+    fn fmt_rpc(&self, autoproto: &Path) -> TokenStream2 {
+        let Self {
+            name,
+            request,
+            response,
+            ..
+        } = self;
+        let request_stream = if self.client_streaming { "stream " } else { "" };
+        let response_stream = if self.server_streaming { "stream " } else { "" };
+
+        quote!(
+            ::core::writeln!(
+                f,
+                "    rpc {} ({}{}) returns ({}{});",
+                #name,
+                #request_stream,
+                <#request as #autoproto::ToProtoSpec>::proto_name(),
+                #response_stream,
+                <#response as #autoproto::ToProtoSpec>::proto_name(),
+            )?;
+        )
+    }
+}
+
+pub fn service(attr: TokenStream2, item: TokenStream2) -> Result<TokenStream2> {
+    let attrs = ServiceAttributes::new(attr)?;
+    let mut item: ItemTrait = syn::parse2(item)?;
+
+    if !item.generics.params.is_empty() {
+        bail!("Services can't be generic");
+    }
+
+    let autoproto = &attrs.autoproto_path;
+    let trait_ident = &item.ident;
+    let vis = &item.vis;
+    let service_name = match &attrs.package {
+        Some(package) => format!("{}.{}", package.value(), trait_ident),
+        None => trait_ident.to_string(),
+    };
+
+    let methods = item
+        .items
+        .iter()
+        .map(|item| match item {
+            TraitItem::Method(method) => Method::new(method),
+            _ => bail!("Services can only contain methods"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let paths = methods
+        .iter()
+        .map(|method| format!("/{}/{}", service_name, method.name))
+        .collect::<Vec<_>>();
+
+    let server_arms = methods
+        .iter()
+        .zip(&paths)
+        .map(|(method, path)| method.server_arm(autoproto, trait_ident, path));
+    let client_methods = methods
+        .iter()
+        .zip(&paths)
+        .map(|(method, path)| method.client_method(autoproto, path));
+    let fmt_rpcs = methods.iter().map(|method| method.fmt_rpc(autoproto));
+    let registered_types = methods
+        .iter()
+        .flat_map(|method| vec![method.request, method.response]);
+
+    let server = format_ident!("{}Server", trait_ident);
+    let client = format_ident!("{}Client", trait_ident);
+    let trait_name = trait_ident.to_string();
+    let server_doc = format!(
+        "A `tonic` service which implements `{}` by calling an implementation of the trait.",
+        service_name
+    );
+    let client_doc = format!("A `tonic` client for `{}`.", service_name);
+
+    // The server calls the trait's methods from spawned tasks, and `async_trait` is used so that the
+    // futures they return are `Send`.
+    let supertraits: [TypeParamBound; 3] = [
+        syn::parse_quote!(::core::marker::Send),
+        syn::parse_quote!(::core::marker::Sync),
+        syn::parse_quote!('static),
+    ];
+
+    if item.colon_token.is_none() {
+        item.colon_token = Some(Token![:](Span::call_site()));
+    }
+
+    item.supertraits.extend(supertraits);
+
+    Ok(quote!(
+        #[#autoproto::grpc::async_trait]
+        #item
+
+        #[doc = #server_doc]
+        #vis struct #server<T: ?::core::marker::Sized> {
+            inner: ::std::sync::Arc<T>,
+        }
+
+        impl<T: #trait_ident> #server<T> {
+            #vis fn new(inner: T) -> Self {
+                Self::from_arc(::std::sync::Arc::new(inner))
+            }
+        }
+
+        impl<T: #trait_ident + ?::core::marker::Sized> #server<T> {
+            #vis fn from_arc(inner: ::std::sync::Arc<T>) -> Self {
+                Self { inner }
+            }
+        }
+
+        impl<T: ?::core::marker::Sized> ::core::clone::Clone for #server<T> {
+            fn clone(&self) -> Self {
+                Self {
+                    inner: self.inner.clone(),
+                }
+            }
+        }
+
+        impl<T: ?::core::marker::Sized> ::core::fmt::Debug for #server<T> {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                f.write_str(::core::stringify!(#server))
+            }
+        }
+
+        impl<T, B> #autoproto::tonic::codegen::Service<
+            #autoproto::tonic::codegen::http::Request<B>
+        > for #server<T>
+        where
+            T: #trait_ident + ?::core::marker::Sized,
+            B: #autoproto::tonic::codegen::Body + ::core::marker::Send + ::core::marker::Sync + 'static,
+            B::Error: ::core::convert::Into<#autoproto::tonic::codegen::StdError>
+                + ::core::marker::Send
+                + 'static,
+        {
+            type Response = #autoproto::tonic::codegen::http::Response<
+                #autoproto::tonic::body::BoxBody,
+            >;
+            type Error = #autoproto::tonic::codegen::Never;
+            type Future = #autoproto::tonic::codegen::BoxFuture<Self::Response, Self::Error>;
+
+            fn poll_ready(
+                &mut self,
+                _cx: &mut ::core::task::Context<'_>,
+            ) -> ::core::task::Poll<::core::result::Result<(), Self::Error>> {
+                ::core::task::Poll::Ready(::core::result::Result::Ok(()))
+            }
+
+            fn call(
+                &mut self,
+                req: #autoproto::tonic::codegen::http::Request<B>,
+            ) -> Self::Future {
+                let inner = self.inner.clone();
+
+                match req.uri().path() {
+                    #(#server_arms)*
+                    _ => ::std::boxed::Box::pin(async move {
+                        ::core::result::Result::Ok(
+                            #autoproto::tonic::codegen::http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(#autoproto::tonic::codegen::empty_body())
+                                .unwrap(),
+                        )
+                    }),
+                }
+            }
+        }
+
+        impl<T: #trait_ident + ?::core::marker::Sized> #autoproto::tonic::transport::NamedService
+            for #server<T>
+        {
+            const NAME: &'static str = #service_name;
+        }
+
+        #[doc = #client_doc]
+        #[derive(Debug, Clone)]
+        #vis struct #client<T = #autoproto::tonic::transport::Channel> {
+            inner: #autoproto::tonic::client::Grpc<T>,
+        }
+
+        impl #client<#autoproto::tonic::transport::Channel> {
+            /// Connect to a server at `dst`, which is anything that can be converted to an
+            /// `Endpoint` such as a URL.
+            #vis async fn connect<D>(
+                dst: D,
+            ) -> ::core::result::Result<Self, #autoproto::tonic::transport::Error>
+            where
+                D: ::core::convert::TryInto<#autoproto::tonic::transport::Endpoint>,
+                D::Error: ::core::convert::Into<#autoproto::tonic::codegen::StdError>,
+            {
+                let conn = #autoproto::tonic::transport::Endpoint::new(dst)?
+                    .connect()
+                    .await?;
+
+                ::core::result::Result::Ok(Self::new(conn))
+            }
+        }
+
+        impl<T> #client<T>
+        where
+            T: #autoproto::tonic::client::GrpcService<#autoproto::tonic::body::BoxBody>,
+            T::ResponseBody: #autoproto::tonic::codegen::Body
+                + ::core::marker::Send
+                + ::core::marker::Sync
+                + 'static,
+            T::Error: ::core::convert::Into<#autoproto::tonic::codegen::StdError>,
+            <T::ResponseBody as #autoproto::tonic::codegen::Body>::Error:
+                ::core::convert::Into<#autoproto::tonic::codegen::StdError> + ::core::marker::Send,
+        {
+            #vis fn new(inner: T) -> Self {
+                Self {
+                    inner: #autoproto::tonic::client::Grpc::new(inner),
+                }
+            }
+
+            #(#client_methods)*
+        }
+
+        impl #autoproto::ToProtoSpec for dyn #trait_ident {
+            const SHAPE: #autoproto::spec::FieldShape = #autoproto::spec::FieldShape::Skipped;
+
+            fn proto_name() -> ::std::string::String {
+                ::std::string::String::from(#trait_name)
+            }
+
+            fn fmt(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                ::core::writeln!(
+                    f,
+                    "service {} {{",
+                    <Self as #autoproto::ToProtoSpec>::proto_name(),
+                )?;
+                #(#fmt_rpcs)*
+                ::core::writeln!(f, "}}")
+            }
+
+            fn register(file: &mut #autoproto::ProtoFile) {
+                if file.define(
+                    <Self as #autoproto::ToProtoSpec>::proto_name(),
+                    <Self as #autoproto::ToProtoSpec>::fmt,
+                ) {
+                    #(<#registered_types as #autoproto::ToProtoSpec>::register(file);)*
+                }
+            }
+        }
+    ))
+}
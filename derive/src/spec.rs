@@ -0,0 +1,455 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    Expr, Fields, FieldsNamed, FieldsUnnamed, GenericParam, Generics, Ident, ItemImpl, Lit, LitInt,
+    LitStr, Member, Path, Type, Variant,
+};
+
+use crate::{
+    bounded_field_types, encoded_type, struct_members,
+    util::{snake_case, Result, WhereClauseBuilder},
+    FieldTags,
+};
+
+pub fn field_name(member: &Member) -> String {
+    match member {
+        Member::Named(ident) => ident.to_string().trim_start_matches("r#").to_owned(),
+        Member::Unnamed(index) => format!("field_{}", index.index),
+    }
+}
+
+/// The name of the type in the generated spec. As protobuf has no concept of generics, each
+/// instantiation of a generic type gets its own name, based on the names of its type parameters.
+fn proto_name(autoproto_path: &Path, ident: &Ident, generics: &Generics) -> Expr {
+    let name = ident.to_string();
+    let type_params = generics
+        .params
+        .iter()
+        .filter_map(|p| match p {
+            GenericParam::Type(t) => Some(&t.ident),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if type_params.is_empty() {
+        syn::parse_quote!(::std::string::String::from(#name))
+    } else {
+        syn::parse_quote!(
+            #autoproto_path::spec::generic_name(
+                #name,
+                &[#(<#type_params as #autoproto_path::ToProtoSpec>::proto_name()),*],
+            )
+        )
+    }
+}
+
+/// The name of a well-known type, which is always in the `google.protobuf` package.
+pub fn well_known_name(ident: &Ident) -> String {
+    format!("google.protobuf.{}", ident)
+}
+
+/// The body of `ToProtoSpec::register`, which defines the type with `define` and then registers
+/// the types it refers to with `register`. Well-known types are imported from their file instead.
+fn register_or_import(
+    autoproto_path: &Path,
+    well_known: Option<&LitStr>,
+    register: TokenStream2,
+) -> TokenStream2 {
+    match well_known {
+        Some(file_name) => quote!(
+            file.add_import(#file_name);
+        ),
+        None => quote!(
+            if file.define(
+                <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                <Self as #autoproto_path::ToProtoSpec>::fmt,
+            ) {
+                #register
+            }
+        ),
+    }
+}
+
+/// The name of a variant of an enumeration. Enumeration values are scoped to the enclosing package
+/// rather than the enumeration itself, so they are prefixed with the name of the enumeration. The
+/// values of well-known enumerations already have fixed names, and aren't prefixed.
+pub fn enum_value_name(ident: &Ident, variant: &Ident, well_known: Option<&LitStr>) -> String {
+    let variant = snake_case(&variant.to_string()).to_uppercase();
+
+    match well_known {
+        Some(_) => variant,
+        None => format!(
+            "{}_{}",
+            snake_case(&ident.to_string()).to_uppercase(),
+            variant
+        ),
+    }
+}
+
+/// The name, tag and type of each member as it appears in the spec. Oneof fields are described
+/// through `OneofField`, and the first of their tags is used. Fields with an overridden encoding
+/// are described through `WithEncoding`. Unknown fields aren't part of the
+/// spec.
+pub fn spec_fields(
+    autoproto_path: &Path,
+    members: &[(FieldTags, Member, &Type)],
+) -> Vec<(String, Lit, Type)> {
+    members
+        .iter()
+        .filter_map(|(tags, member, ty)| {
+            let name = field_name(member);
+            let (tag, ty): (_, Type) = match tags {
+                FieldTags::Unknown => return None,
+                FieldTags::Single(tag) => (*tag, (*ty).clone()),
+                FieldTags::Encoded(tag, encoding) => {
+                    (*tag, encoded_type(autoproto_path, ty, encoding))
+                }
+                FieldTags::Group(tag) => {
+                    let tag_lit: Lit =
+                        LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+
+                    (
+                        *tag,
+                        syn::parse_quote!(#autoproto_path::Group<#ty, #tag_lit>),
+                    )
+                }
+                FieldTags::Oneof(tags) => {
+                    let tag: Lit =
+                        LitInt::new(&tags[0].get().to_string(), Span::call_site()).into();
+
+                    (
+                        tags[0],
+                        syn::parse_quote!(#autoproto_path::OneofField<#ty, #tag>),
+                    )
+                }
+            };
+            let tag: Lit = LitInt::new(&tag.get().to_string(), Span::call_site()).into();
+
+            Some((name, tag, ty))
+        })
+        .collect()
+}
+
+/// Statements writing the fields of a message, and registering the types of those fields.
+fn fields_fmt_and_register(
+    autoproto_path: &Path,
+    members: &[(FieldTags, Member, &Type)],
+) -> (TokenStream2, TokenStream2) {
+    spec_fields(autoproto_path, members)
+        .into_iter()
+        .map(|(name, tag, ty)| {
+            (
+                quote!(
+                    <#ty as #autoproto_path::ToProtoSpec>::fmt_field(
+                        f,
+                        #name,
+                        unsafe { ::core::num::NonZeroU32::new_unchecked(#tag) },
+                    )?;
+                ),
+                quote!(<#ty as #autoproto_path::ToProtoSpec>::register(file);),
+            )
+        })
+        .unzip()
+}
+
+pub fn message(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    members: &[(FieldTags, Member, &Type)],
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let name = match well_known {
+        Some(_) => {
+            let name = well_known_name(ident);
+
+            syn::parse_quote!(::std::string::String::from(#name))
+        }
+        None => proto_name(autoproto_path, ident, generics),
+    };
+    let (fmt_fields, register_fields) = fields_fmt_and_register(autoproto_path, members);
+    let register = register_or_import(autoproto_path, well_known, register_fields);
+
+    let where_clause_builder = WhereClauseBuilder::new(generics)
+        .with_field_types(bounded_field_types(autoproto_path, members));
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::ToProtoSpec))
+        .with_bound(quote!(#autoproto_path::ToProtoSpec));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ToProtoSpec for #ident #ty_generics #where_clause {
+            const SHAPE: #autoproto_path::spec::FieldShape =
+                #autoproto_path::spec::FieldShape::Single(#autoproto_path::spec::FieldType::Message);
+
+            fn proto_name() -> ::std::string::String {
+                #name
+            }
+
+            fn fmt(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                ::core::writeln!(
+                    f,
+                    "message {} {{",
+                    <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                )?;
+                #fmt_fields
+                ::core::writeln!(f, "}}")
+            }
+
+            fn register(file: &mut #autoproto_path::ProtoFile) {
+                #register
+            }
+        }
+    )
+}
+
+pub fn unit(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    message(autoproto_path, ident, generics, &[], well_known)
+}
+
+pub fn newtype(
+    autoproto_path: &Path,
+    ident: &Ident,
+    field_ty: &Type,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> ItemImpl {
+    let where_clause = where_clause_builder.with_bound(quote!(#autoproto_path::ToProtoSpec));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ToProtoSpec for #ident #ty_generics #where_clause {
+            const SHAPE: #autoproto_path::spec::FieldShape =
+                <#field_ty as #autoproto_path::ToProtoSpec>::SHAPE;
+
+            fn proto_name() -> ::std::string::String {
+                <#field_ty as #autoproto_path::ToProtoSpec>::proto_name()
+            }
+
+            fn fmt_field(
+                f: &mut ::core::fmt::Formatter,
+                name: &str,
+                tag: ::core::num::NonZeroU32,
+            ) -> ::core::fmt::Result {
+                <#field_ty as #autoproto_path::ToProtoSpec>::fmt_field(f, name, tag)
+            }
+
+            fn fmt(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                <#field_ty as #autoproto_path::ToProtoSpec>::fmt(f)
+            }
+
+            fn register(file: &mut #autoproto_path::ProtoFile) {
+                <#field_ty as #autoproto_path::ToProtoSpec>::register(file)
+            }
+        }
+    )
+}
+
+/// The spec of an enumeration, given each variant along with an expression for its value.
+pub fn scalar_enum(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(&Ident, TokenStream2)],
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let name = match well_known {
+        Some(_) => well_known_name(ident),
+        None => ident.to_string(),
+    };
+    let register = register_or_import(autoproto_path, well_known, quote!());
+
+    let fmt_variants = variants.iter().map(|(variant, value)| {
+        let name = enum_value_name(ident, variant, well_known);
+
+        quote!(
+            ::core::writeln!(f, "    {} = {};", #name, #value)?;
+        )
+    });
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::ToProtoSpec for #ident #ty_generics #where_clause {
+            const SHAPE: #autoproto_path::spec::FieldShape =
+                #autoproto_path::spec::FieldShape::Single(#autoproto_path::spec::FieldType::Enum);
+
+            fn proto_name() -> ::std::string::String {
+                ::std::string::String::from(#name)
+            }
+
+            fn fmt(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                ::core::writeln!(
+                    f,
+                    "enum {} {{",
+                    <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                )?;
+                #(#fmt_variants)*
+                ::core::writeln!(f, "}}")
+            }
+
+            fn register(file: &mut #autoproto_path::ProtoFile) {
+                #register
+            }
+        }
+    )
+}
+
+pub fn oneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(Lit, &Variant)],
+    well_known: Option<&LitStr>,
+) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let name = match well_known {
+        Some(_) => {
+            let name = well_known_name(ident);
+
+            syn::parse_quote!(::std::string::String::from(#name))
+        }
+        None => proto_name(autoproto_path, ident, generics),
+    };
+    let register = register_or_import(
+        autoproto_path,
+        well_known,
+        quote!(<Self as #autoproto_path::ProtoOneofSpec>::register_variants(file);),
+    );
+
+    let mut fmt_variants = vec![];
+    let mut register_variants = vec![];
+    let mut field_types = vec![];
+
+    for (tag, variant) in variants {
+        let variant_name = snake_case(&variant.ident.to_string());
+
+        let fields = match &variant.fields {
+            Fields::Named(FieldsNamed { named: fields, .. })
+            | Fields::Unnamed(FieldsUnnamed {
+                unnamed: fields, ..
+            }) => fields.iter().collect::<Vec<_>>(),
+            Fields::Unit => vec![],
+        };
+
+        match &fields[..] {
+            [] => {
+                fmt_variants.push(quote!(
+                    ::core::writeln!(
+                        f,
+                        "        {} {} = {};",
+                        <() as #autoproto_path::ToProtoSpec>::proto_name(),
+                        #variant_name,
+                        #tag,
+                    )?;
+                ));
+                register_variants.push(quote!(
+                    <() as #autoproto_path::ToProtoSpec>::register(file);
+                ));
+            }
+            [field] => {
+                let ty = &field.ty;
+
+                fmt_variants.push(quote!(
+                    ::core::writeln!(
+                        f,
+                        "        {} {} = {};",
+                        <#ty as #autoproto_path::ToProtoSpec>::proto_name(),
+                        #variant_name,
+                        #tag,
+                    )?;
+                ));
+                register_variants.push(quote!(
+                    <#ty as #autoproto_path::ToProtoSpec>::register(file);
+                ));
+                field_types.push((*ty).clone());
+            }
+            fields => {
+                // Variants with multiple fields are encoded as a message, which we name after the
+                // oneof and the variant.
+                let members = struct_members(fields.iter().copied())?;
+                let (fmt_fields, register_fields) =
+                    fields_fmt_and_register(autoproto_path, &members);
+                let variant_ident = variant.ident.to_string();
+                let message_name = quote!(::std::format!(
+                    "{}{}",
+                    <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                    #variant_ident,
+                ));
+
+                fmt_variants.push(quote!(
+                    ::core::writeln!(
+                        f,
+                        "        {} {} = {};",
+                        #message_name,
+                        #variant_name,
+                        #tag,
+                    )?;
+                ));
+                register_variants.push(quote!(
+                    let name = #message_name;
+
+                    if file.define(name.clone(), |f| {
+                        ::core::writeln!(f, "message {} {{", name)?;
+                        #fmt_fields
+                        ::core::writeln!(f, "}}")
+                    }) {
+                        #register_fields
+                    }
+                ));
+                field_types.extend(bounded_field_types(autoproto_path, &members));
+            }
+        }
+    }
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::ToProtoSpec))
+        .with_bound(quote!(#autoproto_path::ToProtoSpec));
+
+    Ok(quote!(
+        impl #impl_generics #autoproto_path::ToProtoSpec for #ident #ty_generics #where_clause {
+            const SHAPE: #autoproto_path::spec::FieldShape =
+                #autoproto_path::spec::FieldShape::Single(#autoproto_path::spec::FieldType::Message);
+
+            fn proto_name() -> ::std::string::String {
+                #name
+            }
+
+            fn fmt(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                ::core::writeln!(
+                    f,
+                    "message {} {{",
+                    <Self as #autoproto_path::ToProtoSpec>::proto_name(),
+                )?;
+                ::core::writeln!(f, "    oneof value {{")?;
+                <Self as #autoproto_path::ProtoOneofSpec>::fmt_variants(f)?;
+                ::core::writeln!(f, "    }}")?;
+                ::core::writeln!(f, "}}")
+            }
+
+            fn register(file: &mut #autoproto_path::ProtoFile) {
+                #register
+            }
+        }
+
+        impl #impl_generics #autoproto_path::ProtoOneofSpec for #ident #ty_generics #where_clause {
+            fn fmt_variants(f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                #(#fmt_variants)*
+
+                Ok(())
+            }
+
+            fn register_variants(file: &mut #autoproto_path::ProtoFile) {
+                #({ #register_variants })*
+            }
+        }
+    ))
+}
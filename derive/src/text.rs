@@ -0,0 +1,443 @@
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    Field, Fields, FieldsNamed, FieldsUnnamed, Generics, Ident, ItemImpl, Lit, LitInt, LitStr,
+    Member, Path, Type, Variant,
+};
+
+use crate::{
+    spec::{enum_value_name, field_name},
+    struct_members,
+    util::{snake_case, Result, WhereClauseBuilder},
+    FieldTags,
+};
+
+/// The code to write and merge the fields of a message.
+struct TextFields {
+    /// Statements adding each field to `fields`.
+    write: Vec<TokenStream2>,
+    /// Match arms merging `value` into the field named `name`.
+    merge_arms: Vec<TokenStream2>,
+    /// The types of the fields, other than `oneof`s.
+    field_types: Vec<Type>,
+    /// The `OneofField` types through which `oneof` fields are accessed.
+    oneof_types: Vec<Type>,
+}
+
+/// Generate the code for the fields of a message. `place` gives the expression to access a field
+/// from its index and member, such as `self.field`.
+fn text_fields<'a>(
+    autoproto_path: &Path,
+    fields: impl Iterator<Item = &'a Field>,
+    place: impl Fn(usize, &Member) -> TokenStream2,
+) -> Result<TextFields> {
+    let members = struct_members(fields)?;
+
+    let mut out = TextFields {
+        write: vec![],
+        merge_arms: vec![],
+        field_types: vec![],
+        oneof_types: vec![],
+    };
+    let mut oneof_arms = vec![];
+
+    for (i, (tags, member, ty)) in members.iter().enumerate() {
+        let place = place(i, member);
+
+        match tags {
+            FieldTags::Unknown => {}
+            FieldTags::Single(_) | FieldTags::Encoded(..) | FieldTags::Group(_) => {
+                let name = field_name(member);
+
+                out.write.push(quote!(
+                    #autoproto_path::text::write_field(&mut fields, #name, &#place);
+                ));
+                out.merge_arms.push(quote!(
+                    #name => #autoproto_path::TextFormat::merge_text_value(&mut #place, value),
+                ));
+                out.field_types.push((*ty).clone());
+            }
+            FieldTags::Oneof(tags) => {
+                // The variants of a `oneof` are written as fields of the containing message. The
+                // tag doesn't affect the text format, so the first one is used.
+                let tag: Lit = LitInt::new(&tags[0].get().to_string(), Span::call_site()).into();
+                let oneof_ty: Type = syn::parse_quote!(#autoproto_path::OneofField<#ty, #tag>);
+
+                out.write.push(quote!(
+                    <#oneof_ty as #autoproto_path::TextFormatOneof>::variants_to_text(
+                        <#oneof_ty>::from_ref(&#place),
+                        &mut fields,
+                    );
+                ));
+                oneof_arms.push(quote!(
+                    name if <#oneof_ty as #autoproto_path::TextFormatOneof>::has_variant(name) => {
+                        <#oneof_ty as #autoproto_path::TextFormatOneof>::merge_variant_text(
+                            <#oneof_ty>::from_mut(&mut #place),
+                            name,
+                            value,
+                        )
+                    }
+                ));
+                out.oneof_types.push(oneof_ty);
+            }
+        }
+    }
+
+    out.merge_arms.extend(oneof_arms);
+
+    Ok(out)
+}
+
+/// An expression converting the fields to a message.
+fn fields_to_text(autoproto_path: &Path, write: &[TokenStream2]) -> TokenStream2 {
+    if write.is_empty() {
+        quote!(#autoproto_path::text::TextValue::Message(::std::vec::Vec::new()))
+    } else {
+        quote!({
+            let mut fields = ::std::vec::Vec::new();
+            #(#write)*
+            #autoproto_path::text::TextValue::Message(fields)
+        })
+    }
+}
+
+/// An expression merging the message `value` into the fields.
+fn merge_fields(autoproto_path: &Path, merge_arms: &[TokenStream2]) -> TokenStream2 {
+    quote!(
+        #autoproto_path::text::merge_fields(value, |name, value| match name {
+            #(#merge_arms)*
+            name => ::core::result::Result::Err(
+                #autoproto_path::text::TextError::unknown_field(name),
+            ),
+        })
+    )
+}
+
+pub fn message<'a>(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    fields: impl Iterator<Item = &'a Field> + Clone,
+) -> Result<ItemImpl> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let TextFields {
+        write,
+        merge_arms,
+        field_types,
+        oneof_types,
+    } = text_fields(autoproto_path, fields, |_, member| quote!(self.#member))?;
+
+    let to_text = fields_to_text(autoproto_path, &write);
+    let merge = merge_fields(autoproto_path, &merge_arms);
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(#autoproto_path::TextFormat))
+        .with_types_bound(oneof_types, quote!(#autoproto_path::TextFormatOneof))
+        .with_bound(quote!(#autoproto_path::TextFormat));
+
+    Ok(syn::parse_quote!(
+        impl #impl_generics #autoproto_path::TextFormat for #ident #ty_generics #where_clause {
+            fn to_text_value(&self) -> #autoproto_path::text::TextValue {
+                #to_text
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                #merge
+            }
+        }
+    ))
+}
+
+/// Messages without fields are written in the same way as `google.protobuf.Empty`.
+pub fn unit(autoproto_path: &Path, ident: &Ident, generics: &Generics) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::TextFormat for #ident #ty_generics #where_clause {
+            fn to_text_value(&self) -> #autoproto_path::text::TextValue {
+                <() as #autoproto_path::TextFormat>::to_text_value(&())
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                <() as #autoproto_path::TextFormat>::merge_text_value(&mut (), value)
+            }
+        }
+    )
+}
+
+pub fn newtype(
+    autoproto_path: &Path,
+    ident: &Ident,
+    field: &Member,
+    impl_generics: &syn::ImplGenerics,
+    ty_generics: &syn::TypeGenerics,
+    where_clause_builder: &mut WhereClauseBuilder,
+) -> ItemImpl {
+    let where_clause = where_clause_builder.with_bound(quote!(#autoproto_path::TextFormat));
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::TextFormat for #ident #ty_generics #where_clause {
+            fn to_text_value(&self) -> #autoproto_path::text::TextValue {
+                #autoproto_path::TextFormat::to_text_value(&self.#field)
+            }
+
+            fn is_text_default(&self) -> bool {
+                #autoproto_path::TextFormat::is_text_default(&self.#field)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                #autoproto_path::TextFormat::merge_text_value(&mut self.#field, value)
+            }
+        }
+    )
+}
+
+/// Enumerations are written as the names of their values, and either the name or the number is
+/// accepted when parsing.
+pub fn scalar_enum<'a>(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: impl Iterator<Item = &'a Ident>,
+    unknown: Option<&Ident>,
+    well_known: Option<&LitStr>,
+) -> ItemImpl {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let (names, variants): (Vec<_>, Vec<_>) = variants
+        .map(|variant| (enum_value_name(ident, variant, well_known), variant))
+        .unzip();
+
+    // Values that an `open` enumeration doesn't recognise are written as numbers.
+    let unknown_arm = unknown.map(|unknown| {
+        quote!(
+            #ident::#unknown(value) => {
+                return #autoproto_path::text::TextValue::Scalar(::std::string::ToString::to_string(value));
+            }
+        )
+    });
+
+    syn::parse_quote!(
+        impl #impl_generics #autoproto_path::TextFormat for #ident #ty_generics #where_clause {
+            fn to_text_value(&self) -> #autoproto_path::text::TextValue {
+                let name = match self {
+                    #(#ident::#variants => #names,)*
+                    #unknown_arm
+                };
+
+                #autoproto_path::text::TextValue::Scalar(::std::string::String::from(name))
+            }
+
+            fn is_text_default(&self) -> bool {
+                #autoproto_path::IsDefault::is_default(self)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                let parsed = match &value {
+                    #autoproto_path::text::TextValue::Scalar(name) => match &name[..] {
+                        #(#names => ::core::option::Option::Some(#ident::#variants),)*
+                        number => number.parse::<i128>().ok().and_then(|number| {
+                            <Self as #autoproto_path::ProtoScalar>::from_value(
+                                #autoproto_path::Value::Int(number),
+                            )
+                        }),
+                    },
+                    other => {
+                        return ::core::result::Result::Err(
+                            #autoproto_path::text::TextError::expected("an enum value", other),
+                        )
+                    }
+                };
+
+                *self = parsed.ok_or_else(|| {
+                    #autoproto_path::text::TextError::new(::std::format!(
+                        "Unknown value {:?} for enum `{}`",
+                        value,
+                        ::core::stringify!(#ident),
+                    ))
+                })?;
+
+                ::core::result::Result::Ok(())
+            }
+        }
+    )
+}
+
+/// A `oneof` is written as a message with a single field for the current variant, or as fields of
+/// the containing message when it is used for an `#[autoproto(oneof)]` field.
+pub fn oneof(
+    autoproto_path: &Path,
+    ident: &Ident,
+    generics: &Generics,
+    variants: &[(Lit, &Variant)],
+) -> Result<TokenStream2> {
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+
+    let mut names = vec![];
+    let mut write_arms = vec![];
+    let mut merge_arms = vec![];
+    let mut single_types = vec![];
+    let mut field_types = vec![];
+    let mut oneof_types = vec![];
+
+    for (_, variant) in variants {
+        let variant_ident = &variant.ident;
+        let name = snake_case(&variant_ident.to_string());
+
+        let fields = match &variant.fields {
+            Fields::Named(FieldsNamed { named: fields, .. })
+            | Fields::Unnamed(FieldsUnnamed {
+                unnamed: fields, ..
+            }) => fields.iter().collect::<Vec<_>>(),
+            Fields::Unit => vec![],
+        };
+
+        let bindings = (0..fields.len())
+            .map(|i| Ident::new(&format!("__field_{}", i), Span::call_site()))
+            .collect::<Vec<_>>();
+        let defaults = fields
+            .iter()
+            .map(|_| quote!(::core::default::Default::default()))
+            .collect::<Vec<_>>();
+        let (pattern, default) = match &variant.fields {
+            Fields::Named(_) => {
+                let field_idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+
+                (
+                    quote!(#ident::#variant_ident { #(#field_idents: #bindings),* }),
+                    quote!(#ident::#variant_ident { #(#field_idents: #defaults),* }),
+                )
+            }
+            Fields::Unnamed(_) => (
+                quote!(#ident::#variant_ident(#(#bindings),*)),
+                quote!(#ident::#variant_ident(#(#defaults),*)),
+            ),
+            Fields::Unit => (
+                quote!(#ident::#variant_ident),
+                quote!(#ident::#variant_ident),
+            ),
+        };
+
+        let (to_text, merge) = match &fields[..] {
+            // Variants without fields are `google.protobuf.Empty`.
+            [] => (
+                quote!(<() as #autoproto_path::TextFormat>::to_text_value(&())),
+                quote!(<() as #autoproto_path::TextFormat>::merge_text_value(&mut (), value)),
+            ),
+            [field] => {
+                single_types.push(field.ty.clone());
+
+                (
+                    quote!(#autoproto_path::TextFormat::to_text_value(__field_0)),
+                    quote!(#autoproto_path::TextFormat::merge_text_value(__field_0, value)),
+                )
+            }
+            // Variants with multiple fields are written as a message.
+            fields => {
+                let variant_fields =
+                    text_fields(autoproto_path, fields.iter().copied(), |i, _| {
+                        let binding = &bindings[i];
+
+                        quote!((*#binding))
+                    })?;
+
+                field_types.extend(variant_fields.field_types);
+                oneof_types.extend(variant_fields.oneof_types);
+
+                (
+                    fields_to_text(autoproto_path, &variant_fields.write),
+                    merge_fields(autoproto_path, &variant_fields.merge_arms),
+                )
+            }
+        };
+
+        write_arms.push(quote!(
+            #pattern => {
+                fields.push((::std::string::String::from(#name), #to_text));
+            }
+        ));
+        merge_arms.push(quote!(
+            #name => match self {
+                #pattern => #merge,
+                _ => {
+                    *self = #default;
+
+                    <Self as #autoproto_path::TextFormatOneof>::merge_variant_text(self, name, value)
+                }
+            },
+        ));
+        names.push(name);
+    }
+
+    let where_clause_builder = WhereClauseBuilder::new(generics).with_field_types(field_types);
+    let where_clause = where_clause_builder
+        .with_field_bound(quote!(
+            #autoproto_path::TextFormat + ::core::default::Default
+        ))
+        .with_types_bound(
+            single_types,
+            quote!(#autoproto_path::TextFormat + ::core::default::Default),
+        )
+        .with_types_bound(oneof_types, quote!(#autoproto_path::TextFormatOneof))
+        .with_bound(quote!(#autoproto_path::TextFormat));
+
+    Ok(quote!(
+        impl #impl_generics #autoproto_path::TextFormat for #ident #ty_generics #where_clause {
+            fn to_text_value(&self) -> #autoproto_path::text::TextValue {
+                #autoproto_path::text::oneof_to_text(self)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                #autoproto_path::text::merge_oneof(self, value)
+            }
+        }
+
+        impl #impl_generics #autoproto_path::TextFormatOneof for #ident #ty_generics #where_clause {
+            fn has_variant(name: &str) -> bool {
+                ::core::matches!(name, #(#names)|*)
+            }
+
+            fn variants_to_text(
+                &self,
+                fields: &mut ::std::vec::Vec<(
+                    ::std::string::String,
+                    #autoproto_path::text::TextValue,
+                )>,
+            ) {
+                match self {
+                    #(#write_arms)*
+                }
+            }
+
+            fn merge_variant_text(
+                &mut self,
+                name: &str,
+                value: #autoproto_path::text::TextValue,
+            ) -> ::core::result::Result<(), #autoproto_path::text::TextError> {
+                match name {
+                    #(#merge_arms)*
+                    name => ::core::result::Result::Err(
+                        #autoproto_path::text::TextError::unknown_field(name),
+                    ),
+                }
+            }
+        }
+    ))
+}
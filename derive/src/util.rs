@@ -6,8 +6,8 @@ use std::{
     num::NonZeroU32,
 };
 use syn::{
-    punctuated::Punctuated, Attribute, GenericParam, Generics, Ident, Lit, LitBool, Meta, MetaList,
-    NestedMeta, Path, Type, WhereClause, WherePredicate,
+    punctuated::Punctuated, Attribute, GenericParam, Generics, Ident, Lit, LitBool, LitStr, Meta,
+    MetaList, NestedMeta, Path, Type, WhereClause, WherePredicate,
 };
 
 pub type Result<T> = std::result::Result<T, anyhow::Error>;
@@ -145,6 +145,28 @@ impl<Fields> AmendedWhereClause<'_, Fields> {
 
         self
     }
+
+    /// Bound some types other than the field types, for when different fields need different
+    /// bounds.
+    pub fn with_types_bound<I, T>(mut self, types: I, bound: T) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Type>,
+        T: ToTokens,
+    {
+        // We trigger the "trivial bounds" lint if we try to add a `Self`
+        // bound to a concrete type.
+        if !self.builder.type_params.is_empty() {
+            self.where_clause.to_mut().predicates.extend(
+                types.into_iter().map::<WherePredicate, _>(|ty| {
+                    let ty = ty.borrow();
+                    syn::parse_quote!(#ty: #bound)
+                }),
+            );
+        }
+
+        self
+    }
 }
 
 impl<Fields> AmendedWhereClause<'_, Fields>
@@ -172,14 +194,101 @@ where
     }
 }
 
+fn parse_tag(lit: &Lit) -> Result<NonZeroU32> {
+    NonZeroU32::new(match lit {
+        Lit::Str(lit) => lit.value().trim().parse()?,
+        Lit::Int(lit) => lit.base10_parse()?,
+        _ => bail!("Unknown tag type"),
+    })
+    .ok_or_else(|| anyhow!("Tag cannot be zero"))
+}
+
+/// Convert a `CamelCase` identifier into `snake_case`, as used for field names in `.proto` files.
+pub fn snake_case(ident: &str) -> String {
+    let mut out = String::with_capacity(ident.len());
+
+    for (i, c) in ident.trim_start_matches("r#").chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                out.push('_');
+            }
+
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+/// An overridden encoding for a scalar field, as the names of the marker types in
+/// `autoproto::encoding`.
+#[derive(Debug, Clone)]
+pub struct FieldEncoding {
+    pub kind: &'static str,
+    pub packing: &'static str,
+    /// The proto2 `default` of the field, which is wrapped around `kind` with `WithDefault`.
+    pub default: Option<i128>,
+    /// Where `default` was given, for errors about its value.
+    pub default_span: Span,
+}
+
+fn encoding_kind(name: &LitStr) -> Result<&'static str> {
+    Ok(match &name.value()[..] {
+        "double" => "Double",
+        "float" => "Float",
+        "fixed32" => "Fixed32",
+        "fixed64" => "Fixed64",
+        "sfixed32" => "SFixed32",
+        "sfixed64" => "SFixed64",
+        "bool" => "Bool",
+        "int32" => "Int32",
+        "int64" => "Int64",
+        "sint32" => "SInt32",
+        "sint64" => "SInt64",
+        "uint32" => "UInt32",
+        "uint64" => "UInt64",
+        other => bail!("Unknown encoding `{}`", other),
+    })
+}
+
 #[derive(Debug)]
 pub struct FieldAttributes {
     pub tag: Option<NonZeroU32>,
+    /// The tags of every variant, if this field is a `oneof` that should be flattened into the
+    /// containing message.
+    pub oneof: Option<Vec<NonZeroU32>>,
+    /// Whether this field stores the fields that aren't recognised when decoding.
+    pub unknown_fields: bool,
+    /// The encoding to use for this field, if it isn't the default for its type.
+    pub encoding: Option<FieldEncoding>,
+    /// The name of this field in JSON, if it isn't the `lowerCamelCase` version of its name.
+    pub json_name: Option<String>,
+    /// Whether decoding should fail if this field is missing, like a proto2 `required` field.
+    pub required: bool,
+    /// Whether this field is encoded as a proto2 group instead of a length-delimited message.
+    pub group: bool,
+    /// Whether this field is left out when it has its default value, as `prost` does for strings
+    /// and bytes. Scalars are already left out.
+    pub skip_default: bool,
 }
 
 impl FieldAttributes {
     pub fn new(attrs: &[Attribute]) -> Result<Self> {
         let mut tag = None::<NonZeroU32>;
+        let mut oneof = false;
+        let mut unknown_fields = false;
+        let mut kind = None::<&'static str>;
+        let mut fixed = false;
+        let mut packed = None::<bool>;
+        let mut tags = None::<Vec<NonZeroU32>>;
+        let mut json_name = None::<String>;
+        let mut default = None::<i128>;
+        let mut default_span = Span::call_site();
+        let mut required = false;
+        let mut group = false;
+        let mut skip_default = false;
 
         for meta in attrs
             .iter()
@@ -195,27 +304,178 @@ impl FieldAttributes {
             })
             .flatten()
         {
-            if let NestedMeta::Meta(Meta::NameValue(inner)) = meta {
-                let ident = if let Some(ident) = inner.path.get_ident() {
-                    ident
-                } else {
-                    continue;
-                };
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(inner)) => {
+                    let ident = if let Some(ident) = inner.path.get_ident() {
+                        ident
+                    } else {
+                        continue;
+                    };
+
+                    if ident == "tag" {
+                        tag = Some(parse_tag(&inner.lit)?);
+                    }
+
+                    if ident == "encoding" {
+                        kind = Some(match &inner.lit {
+                            Lit::Str(lit) => encoding_kind(lit)?,
+                            _ => bail!("`encoding` must be a string, such as `\"sint64\"`"),
+                        });
+                    }
+
+                    if ident == "packed" {
+                        packed = Some(match &inner.lit {
+                            Lit::Bool(LitBool { value, .. }) => *value,
+                            _ => bail!("Invalid value for `packed`"),
+                        });
+                    }
 
-                if ident == "tag" {
-                    tag = Some(
-                        NonZeroU32::new(match inner.lit {
-                            Lit::Str(lit) => lit.value().parse()?,
+                    if ident == "default" {
+                        default_span = inner.lit.span();
+                        default = Some(match &inner.lit {
                             Lit::Int(lit) => lit.base10_parse()?,
-                            _ => bail!("Unknown tag type"),
-                        })
-                        .ok_or_else(|| anyhow!("Tag cannot be zero"))?,
-                    );
+                            Lit::Bool(LitBool { value, .. }) => *value as i128,
+                            // Negative numbers aren't literals as far as attributes are concerned.
+                            Lit::Str(lit) => lit.value().trim().parse().map_err(|_| {
+                                anyhow!("`default` must be an integer, such as `42` or `\"-1\"`")
+                            })?,
+                            _ => bail!("`default` must be an integer or a bool"),
+                        });
+                    }
+
+                    if ident == "json_name" {
+                        json_name = Some(match &inner.lit {
+                            Lit::Str(lit) => lit.value(),
+                            _ => bail!("`json_name` must be a string"),
+                        });
+                    }
+
+                    if ident == "tags" {
+                        tags = Some(match &inner.lit {
+                            Lit::Str(lit) => lit
+                                .value()
+                                .split(',')
+                                .map(|tag| {
+                                    NonZeroU32::new(tag.trim().parse()?)
+                                        .ok_or_else(|| anyhow!("Tag cannot be zero"))
+                                })
+                                .collect::<Result<_>>()?,
+                            _ => bail!("`tags` must be a comma-separated string of tags"),
+                        });
+                    }
                 }
+                NestedMeta::Meta(Meta::Path(inner)) => {
+                    if inner.is_ident("oneof") {
+                        oneof = true;
+                    }
+
+                    if inner.is_ident("unknown_fields") {
+                        unknown_fields = true;
+                    }
+
+                    if inner.is_ident("fixed") {
+                        fixed = true;
+                    }
+
+                    if inner.is_ident("required") {
+                        required = true;
+                    }
+
+                    if inner.is_ident("group") {
+                        group = true;
+                    }
+
+                    if inner.is_ident("skip_default") {
+                        skip_default = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let oneof = match (oneof, tags) {
+            (true, Some(tags)) => {
+                if tag.is_some() {
+                    bail!("`oneof` fields take `tags`, not `tag`");
+                }
+
+                if tags.is_empty() {
+                    bail!("`oneof` fields must have at least one tag");
+                }
+
+                Some(tags)
             }
+            (true, None) => {
+                bail!("`oneof` fields must specify the tags of their variants with `tags`")
+            }
+            (false, Some(_)) => bail!("`tags` can only be specified on `oneof` fields"),
+            (false, None) => None,
+        };
+
+        if unknown_fields && (tag.is_some() || oneof.is_some()) {
+            bail!("`unknown_fields` fields cannot have a tag");
+        }
+
+        let kind = match (kind, fixed) {
+            (Some(_), true) => bail!("`fixed` cannot be specified along with `encoding`"),
+            (Some(kind), false) => Some(kind),
+            (None, true) => Some("DefaultFixed"),
+            (None, false) => None,
+        };
+
+        let encoding = match (kind, packed, default) {
+            (None, None, None) => None,
+            (kind, packed, default) => Some(FieldEncoding {
+                kind: kind.unwrap_or("DefaultKind"),
+                packing: match packed {
+                    Some(true) => "Packed",
+                    Some(false) => "Unpacked",
+                    None => "DefaultPacking",
+                },
+                default,
+                default_span,
+            }),
+        };
+
+        if encoding.is_some() && (unknown_fields || oneof.is_some()) {
+            bail!("The encoding and `default` can only be specified for scalar fields");
+        }
+
+        if required && (unknown_fields || oneof.is_some()) {
+            bail!("`unknown_fields` and `oneof` fields cannot be `required`");
+        }
+
+        if group && (encoding.is_some() || unknown_fields || oneof.is_some()) {
+            bail!("`group` can only be specified for message fields");
+        }
+
+        if skip_default
+            && (required || group || encoding.is_some() || unknown_fields || oneof.is_some())
+        {
+            bail!("`skip_default` can't be used with `required`, `group`, `oneof` or scalar encodings");
+        }
+
+        if json_name.is_some() && (unknown_fields || oneof.is_some()) {
+            bail!(
+                "`json_name` cannot be specified for `unknown_fields` or `oneof` fields, the \
+                 variants of a `oneof` are named individually"
+            );
         }
 
-        Ok(Self { tag })
+        Ok(Self {
+            tag,
+            oneof,
+            unknown_fields,
+            encoding,
+            json_name,
+            required,
+            group,
+            skip_default,
+        })
+    }
+
+    pub fn is_explicitly_tagged(&self) -> bool {
+        self.tag.is_some() || self.oneof.is_some()
     }
 }
 
@@ -223,12 +483,30 @@ impl FieldAttributes {
 pub struct MessageAttributes {
     pub transparent: bool,
     pub autoproto_path: Path,
+    /// A `.proto` file to check the type against, relative to the crate root.
+    pub proto_file: Option<LitStr>,
+    /// The fully-qualified name of the message in `proto_file` that this type corresponds to.
+    pub proto_message: Option<LitStr>,
+    /// Whether an enumeration keeps values it doesn't recognise in its `#[autoproto(unknown)]`
+    /// variant, instead of failing to decode.
+    pub open: bool,
+    /// The file in the `google.protobuf` package that declares this type, if it is one of the
+    /// well-known types. These are imported by specs and descriptors rather than defined, and have
+    /// their own JSON mappings.
+    pub well_known: Option<LitStr>,
+    /// The URL identifying this type when it is packed in a `google.protobuf.Any`.
+    pub type_url: Option<LitStr>,
 }
 
 impl MessageAttributes {
     pub fn new(attrs: &[Attribute]) -> Result<Self> {
         let mut transparent = false;
         let mut autoproto_path = syn::parse_quote!(::autoproto);
+        let mut proto_file = None;
+        let mut proto_message = None;
+        let mut open = false;
+        let mut well_known = None;
+        let mut type_url = None;
 
         for meta in attrs
             .iter()
@@ -291,6 +569,41 @@ impl MessageAttributes {
                     };
                 }
 
+                if ident == "proto_file" {
+                    proto_file = match value {
+                        Ok(Lit::Str(lit)) => Some(lit.clone()),
+                        _ => bail!("Invalid value for `proto_file`"),
+                    };
+                }
+
+                if ident == "message" {
+                    proto_message = match value {
+                        Ok(Lit::Str(lit)) => Some(lit.clone()),
+                        _ => bail!("Invalid value for `message`"),
+                    };
+                }
+
+                if ident == "open" {
+                    open = match value {
+                        Ok(Lit::Bool(LitBool { value, .. })) => *value,
+                        _ => bail!("Invalid value for `open`"),
+                    };
+                }
+
+                if ident == "well_known" {
+                    well_known = match value {
+                        Ok(Lit::Str(lit)) => Some(lit.clone()),
+                        _ => bail!("Invalid value for `well_known`"),
+                    };
+                }
+
+                if ident == "type_url" {
+                    type_url = match value {
+                        Ok(Lit::Str(lit)) => Some(lit.clone()),
+                        _ => bail!("Invalid value for `type_url`"),
+                    };
+                }
+
                 if ident == "path" {
                     autoproto_path = match value {
                         Err(path) => path,
@@ -300,9 +613,32 @@ impl MessageAttributes {
             }
         }
 
+        if proto_message.is_some() && proto_file.is_none() {
+            bail!("`message` can only be specified along with `proto_file`");
+        }
+
         Ok(Self {
             transparent,
             autoproto_path,
+            proto_file,
+            proto_message,
+            open,
+            well_known,
+            type_url,
         })
     }
 }
+
+/// Whether an enum variant is marked `#[autoproto(unknown)]`, meaning that it stores the values of
+/// an `open` enumeration that don't match any other variant.
+pub fn is_unknown_variant(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("autoproto"))
+        .filter_map(|attr| match attr.parse_meta().ok()? {
+            Meta::List(MetaList { nested: inner, .. }) => Some(inner),
+            _ => None,
+        })
+        .flatten()
+        .any(|meta| matches!(meta, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("unknown")))
+}
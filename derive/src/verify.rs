@@ -0,0 +1,314 @@
+//! Checking derived types against a `.proto` file, for `#[autoproto(proto_file = "...")]`.
+//!
+//! Missing or mismatched tags are known while expanding the macro, so they are reported with
+//! `compile_error!`. Whether the type of a field matches is only known once the types are
+//! resolved, so for that we emit a constant which fails to evaluate if `ToProtoSpec::SHAPE` of the
+//! field's type doesn't match the shape declared in the `.proto` file.
+
+use anyhow::bail;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+use std::{collections::BTreeSet, path::PathBuf};
+use syn::{spanned::Spanned, GenericParam, Generics, Ident, Member, Path, Type};
+
+use crate::{
+    encoded_type,
+    proto_file::{FieldType, Label, ProtoField, ProtoFile},
+    util::{MessageAttributes, Result},
+    FieldTags,
+};
+
+fn error(span: Span, msg: &str) -> TokenStream2 {
+    quote_spanned!(span=> ::core::compile_error!(#msg);)
+}
+
+fn is_phantom_data(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "PhantomData")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn expected_type(autoproto_path: &Path, file: &ProtoFile, ty: &FieldType) -> Option<TokenStream2> {
+    let field_type = quote!(#autoproto_path::spec::FieldType);
+
+    Some(match ty {
+        FieldType::Builtin(name) => match &name[..] {
+            "double" => quote!(#field_type::Fixed(#autoproto_path::Fixed::Double)),
+            "float" => quote!(#field_type::Fixed(#autoproto_path::Fixed::Float)),
+            "fixed32" => quote!(#field_type::Fixed(#autoproto_path::Fixed::Fixed32)),
+            "fixed64" => quote!(#field_type::Fixed(#autoproto_path::Fixed::Fixed64)),
+            "sfixed32" => quote!(#field_type::Fixed(#autoproto_path::Fixed::SFixed32)),
+            "sfixed64" => quote!(#field_type::Fixed(#autoproto_path::Fixed::SFixed64)),
+            "bool" => quote!(#field_type::Varint(#autoproto_path::Varint::Bool)),
+            "int32" => quote!(#field_type::Varint(#autoproto_path::Varint::I32)),
+            "int64" => quote!(#field_type::Varint(#autoproto_path::Varint::I64)),
+            "sint32" => quote!(#field_type::Varint(#autoproto_path::Varint::SI32)),
+            "sint64" => quote!(#field_type::Varint(#autoproto_path::Varint::SI64)),
+            "uint32" => quote!(#field_type::Varint(#autoproto_path::Varint::U32)),
+            "uint64" => quote!(#field_type::Varint(#autoproto_path::Varint::U64)),
+            "string" => quote!(#field_type::String),
+            "bytes" => quote!(#field_type::Bytes),
+            _ => return None,
+        },
+        FieldType::Named(name) if file.is_enum(name) => quote!(#field_type::Enum),
+        FieldType::Named(name) if file.message(name).is_some() => quote!(#field_type::Message),
+        // Types from imported files can't be checked, since we don't know if they are messages or
+        // enumerations.
+        FieldType::Named(_) | FieldType::Group(_) | FieldType::Map(..) => return None,
+    })
+}
+
+fn expected_shape(
+    autoproto_path: &Path,
+    file: &ProtoFile,
+    field: &ProtoField,
+) -> Option<TokenStream2> {
+    let field_shape = quote!(#autoproto_path::spec::FieldShape);
+
+    Some(match (&field.label, &field.ty) {
+        (_, FieldType::Map(key, val)) => {
+            let key = expected_type(autoproto_path, file, key)?;
+            let val = expected_type(autoproto_path, file, val)?;
+
+            quote!(#field_shape::Map(#key, #val))
+        }
+        (Label::Repeated, ty) => {
+            let ty = expected_type(autoproto_path, file, ty)?;
+
+            quote!(#field_shape::Repeated(#ty))
+        }
+        (_, ty) => {
+            let ty = expected_type(autoproto_path, file, ty)?;
+
+            quote!(#field_shape::Single(#ty))
+        }
+    })
+}
+
+fn describe(field: &ProtoField) -> String {
+    let ty = |ty: &FieldType| match ty {
+        FieldType::Builtin(name) | FieldType::Named(name) | FieldType::Group(name) => name.clone(),
+        FieldType::Map(..) => unreachable!(),
+    };
+
+    let ty = match &field.ty {
+        FieldType::Map(key, val) => format!("map<{}, {}>", ty(key), ty(val)),
+        other => ty(other),
+    };
+
+    let label = match field.label {
+        Label::Single => "",
+        Label::Optional => "optional ",
+        Label::Required => "required ",
+        Label::Repeated => "repeated ",
+    };
+
+    format!("{}{} {} = {}", label, ty, field.name, field.tag)
+}
+
+/// Check the members of a struct against the message it corresponds to in the `.proto` file given
+/// in its attributes, returning a compile error for each mismatch.
+pub fn message(
+    attrs: &MessageAttributes,
+    ident: &Ident,
+    generics: &Generics,
+    members: &[(FieldTags, Member, &Type)],
+) -> Result<TokenStream2> {
+    let autoproto_path = &attrs.autoproto_path;
+
+    let proto_file = match &attrs.proto_file {
+        Some(proto_file) => proto_file,
+        None => return Ok(quote!()),
+    };
+
+    if generics
+        .params
+        .iter()
+        .any(|param| matches!(param, GenericParam::Type(_)))
+    {
+        bail!("`proto_file` cannot be used with generic types");
+    }
+
+    let path = std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(proto_file.value());
+
+    let src = match std::fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) => {
+            return Ok(error(
+                proto_file.span(),
+                &format!("Cannot read `{}`: {}", path.display(), e),
+            ))
+        }
+    };
+
+    let file = match ProtoFile::parse(&src) {
+        Ok(file) => file,
+        Err(e) => {
+            return Ok(error(
+                proto_file.span(),
+                &format!("Cannot parse `{}`: {}", path.display(), e),
+            ))
+        }
+    };
+
+    let (message_name, message_span) = match &attrs.proto_message {
+        Some(name) => (name.value(), name.span()),
+        None => match &file.package {
+            Some(package) => (format!("{}.{}", package, ident), ident.span()),
+            None => (ident.to_string(), ident.span()),
+        },
+    };
+
+    let message = match file.message(&message_name) {
+        Some(message) => message,
+        None => {
+            return Ok(error(
+                message_span,
+                &format!(
+                    "`{}` has no message named `{}`",
+                    proto_file.value(),
+                    message_name
+                ),
+            ))
+        }
+    };
+
+    let path = path.to_string_lossy();
+    // Make sure that the crate is rebuilt if the `.proto` file changes.
+    let mut out = quote!(
+        const _: &[u8] = ::core::include_bytes!(#path);
+    );
+    let mut seen_tags = BTreeSet::new();
+
+    for (tags, member, ty) in members {
+        let span = match member {
+            Member::Named(ident) => ident.span(),
+            Member::Unnamed(_) => ty.span(),
+        };
+
+        if is_phantom_data(ty) {
+            continue;
+        }
+
+        match tags {
+            FieldTags::Unknown => {}
+            FieldTags::Single(tag) | FieldTags::Encoded(tag, _) | FieldTags::Group(tag) => {
+                seen_tags.insert(tag.get());
+
+                let field = match message.fields.iter().find(|f| f.tag == tag.get()) {
+                    Some(field) => field,
+                    None => {
+                        out.extend(error(
+                            span,
+                            &format!("`{}` has no field with tag {}", message.name, tag),
+                        ));
+
+                        continue;
+                    }
+                };
+
+                if let Some(oneof) = &field.oneof {
+                    out.extend(error(
+                        span,
+                        &format!(
+                            "Tag {} is part of `oneof {}` in `{}`, so this field must be an \
+                             `#[autoproto(oneof)]` field",
+                            tag, oneof, message.name
+                        ),
+                    ));
+
+                    continue;
+                }
+
+                if let Some(expected) = expected_shape(autoproto_path, &file, field) {
+                    let msg = format!(
+                        "The type of this field does not match `{}` in `{}`",
+                        describe(field),
+                        message.name
+                    );
+
+                    let shape_ty = match tags {
+                        FieldTags::Encoded(_, encoding) => {
+                            encoded_type(autoproto_path, ty, encoding)
+                        }
+                        _ => (*ty).clone(),
+                    };
+
+                    out.extend(quote_spanned!(ty.span()=>
+                        const _: () = {
+                            if !#autoproto_path::spec::FieldShape::matches(
+                                <#shape_ty as #autoproto_path::ToProtoSpec>::SHAPE,
+                                #expected,
+                            ) {
+                                ::core::panic!(#msg);
+                            }
+                        };
+                    ));
+                }
+            }
+            FieldTags::Oneof(tags) => {
+                seen_tags.extend(tags.iter().map(|tag| tag.get()));
+
+                let oneof = message
+                    .fields
+                    .iter()
+                    .find(|f| f.tag == tags[0].get())
+                    .and_then(|f| f.oneof.as_ref());
+
+                let oneof = match oneof {
+                    Some(oneof) => oneof,
+                    None => {
+                        out.extend(error(
+                            span,
+                            &format!("`{}` has no oneof with tag {}", message.name, tags[0]),
+                        ));
+
+                        continue;
+                    }
+                };
+
+                let expected = message
+                    .fields
+                    .iter()
+                    .filter(|f| f.oneof.as_ref() == Some(oneof))
+                    .map(|f| f.tag)
+                    .collect::<BTreeSet<_>>();
+                let actual = tags.iter().map(|tag| tag.get()).collect::<BTreeSet<_>>();
+
+                if expected != actual {
+                    out.extend(error(
+                        span,
+                        &format!(
+                            "The tags of `oneof {}` in `{}` are {:?}, but this field has tags {:?}",
+                            oneof, message.name, expected, actual
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    for field in &message.fields {
+        if !seen_tags.contains(&field.tag) {
+            out.extend(error(
+                ident.span(),
+                &format!(
+                    "Missing field `{}` from `{}`",
+                    describe(field),
+                    message.name
+                ),
+            ));
+        }
+    }
+
+    Ok(out)
+}
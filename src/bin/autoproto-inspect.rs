@@ -0,0 +1,89 @@
+//! Print the fields of a protobuf message without its schema, like `protoc --decode_raw`.
+//!
+//! The message is read from stdin as hex, base64 or raw bytes. The format is detected
+//! automatically, or can be given with `--hex`, `--base64` or `--raw`.
+
+use std::{
+    io::{self, Read},
+    process,
+};
+
+const USAGE: &str = "Usage: autoproto-inspect [--hex | --base64 | --raw] < message";
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<Vec<_>>>()?;
+
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect(),
+    )
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let config = if text.contains(['-', '_']) {
+        base64::URL_SAFE
+    } else {
+        base64::STANDARD
+    };
+
+    base64::decode_config(&text, config).ok()
+}
+
+/// Hex is tried first, as any hex string is also valid base64.
+fn detect(input: Vec<u8>) -> Vec<u8> {
+    match std::str::from_utf8(&input) {
+        Ok(text) if !text.trim().is_empty() => decode_hex(text)
+            .or_else(|| decode_base64(text))
+            .unwrap_or(input),
+        _ => input,
+    }
+}
+
+fn main() {
+    let format = std::env::args().nth(1);
+    let mut input = vec![];
+
+    if let Err(e) = io::stdin().read_to_end(&mut input) {
+        eprintln!("Could not read stdin: {}", e);
+        process::exit(1);
+    }
+
+    let text = || String::from_utf8_lossy(&input).into_owned();
+    let bytes = match format.as_deref() {
+        None => Some(detect(input.clone())),
+        Some("--hex") => decode_hex(&text()),
+        Some("--base64") => decode_base64(&text()),
+        Some("--raw") => Some(input.clone()),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let bytes = bytes.unwrap_or_else(|| {
+        eprintln!("Input is not valid {}", &format.unwrap()[2..]);
+        process::exit(1);
+    });
+
+    match autoproto::raw::decode_raw(&bytes) {
+        Ok(message) => print!("{}", message),
+        Err(e) => {
+            eprintln!("Could not decode message: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Decoding messages which borrow from the buffer they're decoded from, so that strings and bytes
+//! don't need to be copied. A struct with a lifetime parameter can derive `ProtoBorrow`, and its
+//! `&'a str`, `&'a [u8]` and `beef::Cow<'a, str>` fields then point directly into the input. Strings
+//! are still checked to be valid UTF-8.
+//!
+//! ```rust
+//! use autoproto::prost::Message;
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Entry {
+//!     id: u64,
+//!     name: String,
+//!     data: Box<[u8]>,
+//! }
+//!
+//! #[derive(PartialEq, Default, Debug, autoproto::ProtoBorrow)]
+//! struct EntryRef<'a> {
+//!     id: u64,
+//!     name: &'a str,
+//!     data: &'a [u8],
+//! }
+//!
+//! let buf = Entry {
+//!     id: 1,
+//!     name: "foo".into(),
+//!     data: vec![1, 2, 3].into(),
+//! }
+//! .encode_to_vec();
+//!
+//! let entry: EntryRef = autoproto::borrow::decode(&buf).unwrap();
+//!
+//! assert_eq!(entry.name, "foo");
+//! assert_eq!(entry.data, [1, 2, 3]);
+//! assert!(buf.as_ptr_range().contains(&entry.name.as_ptr()));
+//! assert!(buf.as_ptr_range().contains(&entry.data.as_ptr()));
+//! ```
+//!
+//! Scalars, and types which own their data such as `String` and `bytes::Bytes`, can be used in
+//! borrowed messages too, and are decoded in the same way as usual. Other messages need to derive
+//! `ProtoBorrow` to be used as fields, even if they don't borrow anything. Deriving `ProtoEncode`
+//! as well allows borrowed messages to be encoded again.
+
+use crate::{
+    encoding::WithEncoding, generic, Group, Proto, ProtoMergeRepeated, ProtoScalar, UnknownFields,
+};
+use prost::{
+    bytes,
+    encoding::{DecodeContext, WireType},
+    DecodeError,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroU32,
+};
+
+/// A field which can be decoded from a buffer that outlives it, borrowing from that buffer instead
+/// of copying. This is the borrowed equivalent of `Proto`.
+pub trait ProtoBorrow<'a> {
+    /// Merge the next value in `buf` into `self`, in the same way as `Proto::merge_self`.
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+
+    /// Merge the next value in `buf` into a repeated field. Scalars override this to accept packed
+    /// fields.
+    fn merge_repeated_borrowed(
+        values: &mut Vec<Self>,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>
+    where
+        Self: Sized + Default,
+    {
+        let mut value = Self::default();
+        generic::required::decode(|| value.merge_borrowed(wire_type, buf, ctx))?;
+
+        values.push(value);
+
+        Ok(())
+    }
+}
+
+/// A message which can be decoded with `decode`, implemented by `#[derive(ProtoBorrow)]`. This is
+/// the borrowed equivalent of `ProtoStructMut`.
+pub trait ProtoStructBorrow<'a> {
+    /// The tags and names of fields which must be present when decoding.
+    const REQUIRED_FIELDS: &'static [(u32, &'static str)] = &[];
+
+    fn field_borrowed_mut(&mut self, tag: NonZeroU32) -> Option<&mut (dyn ProtoBorrow<'a> + '_)>;
+
+    /// Where to store fields with tags that `field_borrowed_mut` doesn't recognise. If this
+    /// returns `None`, unknown fields are skipped.
+    fn unknown_fields_mut(&mut self) -> Option<&mut UnknownFields> {
+        None
+    }
+}
+
+/// Decode a message which borrows from `buf`.
+pub fn decode<'a, T>(buf: &'a [u8]) -> Result<T, DecodeError>
+where
+    T: ProtoStructBorrow<'a> + Default,
+{
+    let mut message = T::default();
+
+    merge(&mut message, buf)?;
+
+    Ok(message)
+}
+
+/// Merge a message which borrows from `buf` into `message`, in the same way as `Message::merge`.
+pub fn merge<'a, T>(message: &mut T, buf: &'a [u8]) -> Result<(), DecodeError>
+where
+    T: ProtoStructBorrow<'a>,
+{
+    generic::protostructborrow::merge(message, buf)
+}
+
+/// Split the contents of a length-delimited field off the front of `buf`.
+fn split_length_delimited<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], DecodeError> {
+    let len = prost::encoding::decode_varint(buf)?;
+
+    if len > buf.len() as u64 {
+        return Err(DecodeError::new("buffer underflow"));
+    }
+
+    let (field, rest) = buf.split_at(len as usize);
+
+    *buf = rest;
+
+    Ok(field)
+}
+
+impl<'a> ProtoBorrow<'a> for &'a [u8] {
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        *self = split_length_delimited(buf)?;
+
+        Ok(())
+    }
+}
+
+impl<'a> ProtoBorrow<'a> for &'a str {
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        *self = std::str::from_utf8(split_length_delimited(buf)?)
+            .map_err(|_| DecodeError::new("invalid string value: data is not UTF-8 encoded"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "beef")]
+impl<'a, U> ProtoBorrow<'a> for beef::generic::Cow<'a, str, U>
+where
+    U: beef::traits::Capacity,
+{
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut string: &'a str = "";
+        string.merge_borrowed(wire_type, buf, ctx)?;
+
+        *self = beef::generic::Cow::borrowed(string);
+
+        Ok(())
+    }
+}
+
+impl<'a, T> ProtoBorrow<'a> for Option<T>
+where
+    T: ProtoBorrow<'a> + Default,
+{
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        self.get_or_insert_with(T::default)
+            .merge_borrowed(wire_type, buf, ctx)
+    }
+}
+
+impl<'a, T> ProtoBorrow<'a> for Vec<T>
+where
+    T: ProtoBorrow<'a> + Default,
+{
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        T::merge_repeated_borrowed(self, wire_type, buf, ctx)
+    }
+}
+
+impl<'a, T> ProtoBorrow<'a> for T
+where
+    T: ProtoScalar + ProtoMergeRepeated,
+{
+    fn merge_borrowed(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        self.merge_self(wire_type, buf, ctx)
+    }
+
+    fn merge_repeated_borrowed(
+        values: &mut Vec<Self>,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        T::merge_repeated(values, wire_type, buf, ctx)
+    }
+}
+
+/// Types which own their contents are decoded in the same way as usual, copying from the buffer.
+macro_rules! impl_protoborrow_for_proto {
+    ($(impl<$($pname:ident),*$(; $(const $cname:ident : $cty:ty),*)?> for $t:ty;)*) => {
+        $(
+            impl<'a, $($pname,)* $($(const $cname : $cty),*)?> ProtoBorrow<'a> for $t
+            where
+                $t: Proto,
+            {
+                fn merge_borrowed(
+                    &mut self,
+                    wire_type: WireType,
+                    buf: &mut &'a [u8],
+                    ctx: DecodeContext,
+                ) -> Result<(), DecodeError> {
+                    self.merge_self(wire_type, buf, ctx)
+                }
+            }
+        )*
+    };
+}
+
+impl_protoborrow_for_proto! {
+    impl<> for String;
+    impl<> for crate::BytesStr;
+    impl<> for Box<[u8]>;
+    impl<> for bytes::Bytes;
+    impl<> for bytes::BytesMut;
+    impl<; const LEN: usize> for [u8; LEN];
+    impl<K, V> for BTreeMap<K, V>;
+    impl<K, V> for HashMap<K, V>;
+    impl<T, K, P> for WithEncoding<T, K, P>;
+    impl<T; const TAG: u32> for Group<T, TAG>;
+}
@@ -0,0 +1,200 @@
+//! A string type which shares the buffer it was decoded from. When a message is decoded from a
+//! `bytes::Bytes` or `bytes::BytesMut`, its `Bytes` fields are slices of that buffer rather than
+//! copies, including fields of nested messages and `oneof` variants. `String` fields always need to
+//! be copied, so `BytesStr` is a string backed by `Bytes` which is decoded in the same way, after
+//! checking that it is valid UTF-8.
+//!
+//! ```rust
+//! use autoproto::{bytes::Bytes, prost::Message, BytesStr};
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Frame {
+//!     name: BytesStr,
+//!     payload: Bytes,
+//! }
+//!
+//! let buf = Bytes::from(
+//!     Frame {
+//!         name: "foo".into(),
+//!         payload: vec![0; 1024].into(),
+//!     }
+//!     .encode_to_vec(),
+//! );
+//!
+//! let frame = Frame::decode(buf.clone()).unwrap();
+//!
+//! assert_eq!(frame.name, "foo");
+//! assert!(buf.as_ptr_range().contains(&frame.name.as_ptr()));
+//! assert!(buf.as_ptr_range().contains(&frame.payload.as_ptr()));
+//! ```
+//!
+//! Decoding from a `&[u8]` copies each field into a new allocation instead.
+
+use crate::{decode_length_delimited, IsDefault, Proto, ProtoEncode};
+use prost::{
+    bytes::{Buf, BufMut, Bytes},
+    encoding::{DecodeContext, WireType},
+    DecodeError,
+};
+use std::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+    num::NonZeroU32,
+    ops::Deref,
+    str::Utf8Error,
+};
+
+/// An immutable string backed by `Bytes`, which is encoded in the same way as `String`.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BytesStr(Bytes);
+
+impl BytesStr {
+    pub const fn new() -> Self {
+        Self(Bytes::new())
+    }
+
+    pub const fn from_static(s: &'static str) -> Self {
+        Self(Bytes::from_static(s.as_bytes()))
+    }
+
+    /// Check that `bytes` is valid UTF-8 and wrap it, without copying.
+    pub fn from_utf8(bytes: Bytes) -> Result<Self, Utf8Error> {
+        std::str::from_utf8(&bytes)?;
+
+        Ok(Self(bytes))
+    }
+
+    /// # Safety
+    ///
+    /// `bytes` must be valid UTF-8.
+    pub unsafe fn from_utf8_unchecked(bytes: Bytes) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Safe because the contents are checked when this is created
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for BytesStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for BytesStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<[u8]> for BytesStr {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Borrow<str> for BytesStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// `Bytes` hashes differently from `str`, which would break lookups through `Borrow<str>`.
+impl Hash for BytesStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for BytesStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for BytesStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq<str> for BytesStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&'_ str> for BytesStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for BytesStr {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl From<String> for BytesStr {
+    fn from(other: String) -> Self {
+        Self(other.into_bytes().into())
+    }
+}
+
+impl From<&'_ str> for BytesStr {
+    fn from(other: &str) -> Self {
+        Self(Bytes::copy_from_slice(other.as_bytes()))
+    }
+}
+
+impl From<BytesStr> for Bytes {
+    fn from(other: BytesStr) -> Self {
+        other.0
+    }
+}
+
+impl ProtoEncode for BytesStr {
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        self.as_str().encode_as_field(tag, buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        self.as_str().encoded_len_as_field(tag)
+    }
+}
+
+impl Proto for BytesStr {
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        _ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        *self = Self::from_utf8(decode_length_delimited(buf)?)
+            .map_err(|e| DecodeError::new(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+impl IsDefault for BytesStr {
+    fn is_default(&self) -> bool {
+        self.0.is_empty()
+    }
+}
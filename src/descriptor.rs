@@ -0,0 +1,581 @@
+//! Generation of `prost_types` descriptors from Rust types, for use with gRPC server reflection and
+//! tools that consume `FileDescriptorSet`s. Every type deriving `Message` implements
+//! `ProtoDescriptor`, and `FileDescriptor` collects the descriptors of a type and everything it
+//! refers to into a complete file.
+//!
+//! ```rust
+//! # #![feature(generic_associated_types)]
+//! use autoproto::descriptor::{self, Type};
+//!
+//! #[derive(PartialEq, Default, Debug, autoproto::Message)]
+//! struct Person {
+//!     name: String,
+//!     scores: Vec<u32>,
+//! }
+//!
+//! let set = descriptor::file_descriptor_set::<Person>("person.proto", Some("example"));
+//! let person = &set.file[0].message_type[0];
+//!
+//! assert_eq!(person.name(), "Person");
+//! assert_eq!(person.field[0].name(), "name");
+//! assert_eq!(person.field[0].r#type(), Type::String);
+//! assert_eq!(person.field[1].number(), 2);
+//! assert_eq!(person.field[1].label(), descriptor::Label::Repeated);
+//! ```
+
+use crate::{
+    spec::{FieldShape, FieldType},
+    Encoding, Fixed, Group, MappedInt, OneofField, ProtoDescriptor, ProtoOneofDescriptor,
+    ProtoScalar, ToProtoSpec, Varint,
+};
+use prost_types::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    FileDescriptorSet, MessageOptions, OneofDescriptorProto,
+};
+use std::{
+    ffi::{OsStr, OsString},
+    marker::PhantomData,
+    num::NonZeroU32,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+};
+
+pub use prost_types::field_descriptor_proto::{Label, Type};
+
+/// A complete `.proto` file as a `FileDescriptorProto`, built up from the descriptors of Rust
+/// types, along with the files it depends on.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct FileDescriptor {
+    file: FileDescriptorProto,
+    dependencies: Vec<FileDescriptorProto>,
+}
+
+impl FileDescriptor {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            file: FileDescriptorProto {
+                name: Some(name.into()),
+                syntax: Some("proto3".into()),
+                ..Default::default()
+            },
+            dependencies: vec![],
+        }
+    }
+
+    pub fn with_package(name: impl Into<String>, package: impl Into<String>) -> Self {
+        let mut out = Self::new(name);
+
+        out.file.package = Some(package.into());
+
+        out
+    }
+
+    /// Add the descriptor of `T`, along with the descriptors of every type it refers to.
+    pub fn add<T: ProtoDescriptor + ?Sized>(&mut self) -> &mut Self {
+        T::register_descriptor(self);
+
+        self
+    }
+
+    /// The fully-qualified name of a type defined in this file, as used in `type_name`.
+    pub fn type_name(&self, name: &str) -> String {
+        match &self.file.package {
+            Some(package) => format!(".{}.{}", package, name),
+            None => format!(".{}", name),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.file.message_type.iter().any(|msg| msg.name() == name)
+            || self.file.enum_type.iter().any(|e| e.name() == name)
+    }
+
+    /// Add the descriptor of a message. Returns `false` without doing anything if a type with this
+    /// name already exists, which is how recursive types are handled.
+    pub fn define_message(&mut self, message: DescriptorProto) -> bool {
+        if self.contains(message.name()) {
+            false
+        } else {
+            self.file.message_type.push(message);
+
+            true
+        }
+    }
+
+    /// Add the descriptor of an enumeration. Returns `false` without doing anything if a type with
+    /// this name already exists.
+    pub fn define_enum(&mut self, enumeration: EnumDescriptorProto) -> bool {
+        if self.contains(enumeration.name()) {
+            false
+        } else {
+            self.file.enum_type.push(enumeration);
+
+            true
+        }
+    }
+
+    /// Add a file that this file imports, such as `google/protobuf/empty.proto`.
+    pub fn add_dependency(&mut self, dependency: FileDescriptorProto) -> &mut Self {
+        if !self.file.dependency.iter().any(|d| d == dependency.name()) {
+            self.file.dependency.push(dependency.name().to_owned());
+            self.dependencies.push(dependency);
+        }
+
+        self
+    }
+
+    /// Call `define` with the descriptor of the file `name` in `package`, adding it as a dependency
+    /// if it isn't one already. This is how the well-known types are described, since they are
+    /// always imported rather than defined in the file that uses them.
+    pub fn define_dependency<F>(&mut self, name: &str, package: &str, define: F)
+    where
+        F: FnOnce(&mut FileDescriptor),
+    {
+        if self.file.name() == name {
+            return define(self);
+        }
+
+        let index = match self.dependencies.iter().position(|d| d.name() == name) {
+            Some(index) => index,
+            None => {
+                self.add_dependency(FileDescriptorProto {
+                    name: Some(name.into()),
+                    package: Some(package.into()),
+                    syntax: Some("proto3".into()),
+                    ..Default::default()
+                });
+
+                self.dependencies.len() - 1
+            }
+        };
+
+        let mut dependency = FileDescriptor {
+            file: std::mem::take(&mut self.dependencies[index]),
+            dependencies: vec![],
+        };
+
+        define(&mut dependency);
+
+        self.dependencies[index] = dependency.file;
+
+        // Files that the dependency imports need to come before it in a `FileDescriptorSet`.
+        for file in dependency.dependencies {
+            if !self.dependencies.iter().any(|d| d.name() == file.name()) {
+                self.dependencies.insert(index, file);
+            }
+        }
+    }
+
+    pub fn file_descriptor_proto(&self) -> &FileDescriptorProto {
+        &self.file
+    }
+
+    /// Convert into a `FileDescriptorSet` containing this file, preceded by the files it depends
+    /// on.
+    pub fn into_file_descriptor_set(self) -> FileDescriptorSet {
+        let mut file = self.dependencies;
+
+        file.push(self.file);
+
+        FileDescriptorSet { file }
+    }
+}
+
+/// Build a `FileDescriptorSet` with a single file named `name` containing the descriptors of `T`
+/// and every type it refers to, along with any files that file depends on.
+pub fn file_descriptor_set<T: ProtoDescriptor + ?Sized>(
+    name: &str,
+    package: Option<&str>,
+) -> FileDescriptorSet {
+    let mut file = match package {
+        Some(package) => FileDescriptor::with_package(name, package),
+        None => FileDescriptor::new(name),
+    };
+
+    file.add::<T>();
+
+    file.into_file_descriptor_set()
+}
+
+/// Build the descriptor of a single field.
+pub fn field(
+    name: &str,
+    tag: NonZeroU32,
+    label: Label,
+    ty: Type,
+    type_name: Option<String>,
+) -> FieldDescriptorProto {
+    let mut field = FieldDescriptorProto {
+        name: Some(name.to_owned()),
+        number: Some(tag.get() as i32),
+        json_name: Some(camel_case(name, false)),
+        type_name,
+        ..Default::default()
+    };
+
+    field.set_label(label);
+    field.set_type(ty);
+
+    field
+}
+
+/// The type of a single value with the given shape, used as the default for
+/// `ProtoDescriptor::descriptor_type`.
+pub fn descriptor_type(
+    file: &FileDescriptor,
+    shape: FieldShape,
+    proto_name: &str,
+) -> (Type, Option<String>) {
+    let ty = match shape {
+        FieldShape::Single(ty) | FieldShape::Repeated(ty) => ty,
+        FieldShape::Skipped | FieldShape::Map(..) | FieldShape::Oneof => FieldType::Message,
+    };
+
+    let ty = match ty {
+        FieldType::Message => return (Type::Message, Some(file.type_name(proto_name))),
+        FieldType::Enum => return (Type::Enum, Some(file.type_name(proto_name))),
+        FieldType::String => Type::String,
+        FieldType::Bytes => Type::Bytes,
+        FieldType::Fixed(Fixed::Float) => Type::Float,
+        FieldType::Fixed(Fixed::Double) => Type::Double,
+        FieldType::Fixed(Fixed::Fixed32) => Type::Fixed32,
+        FieldType::Fixed(Fixed::Fixed64) => Type::Fixed64,
+        FieldType::Fixed(Fixed::SFixed32) => Type::Sfixed32,
+        FieldType::Fixed(Fixed::SFixed64) => Type::Sfixed64,
+        FieldType::Varint(Varint::Bool) => Type::Bool,
+        FieldType::Varint(Varint::I32) => Type::Int32,
+        FieldType::Varint(Varint::I64) => Type::Int64,
+        FieldType::Varint(Varint::SI32) => Type::Sint32,
+        FieldType::Varint(Varint::SI64) => Type::Sint64,
+        FieldType::Varint(Varint::U32) => Type::Uint32,
+        FieldType::Varint(Varint::U64) => Type::Uint64,
+    };
+
+    (ty, None)
+}
+
+/// Add the descriptor of a `map<K, V>` field to `message`. Maps are described as a repeated field
+/// of a nested `FieldNameEntry` message, as `protoc` does.
+pub fn describe_map_field<K, V>(
+    file: &FileDescriptor,
+    message: &mut DescriptorProto,
+    name: &str,
+    tag: NonZeroU32,
+) where
+    K: ProtoDescriptor + ?Sized,
+    V: ProtoDescriptor + ?Sized,
+{
+    let entry_name = format!("{}Entry", camel_case(name, true));
+
+    let mut entry = DescriptorProto {
+        name: Some(entry_name.clone()),
+        options: Some(MessageOptions {
+            map_entry: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    K::describe_field(file, &mut entry, "key", NonZeroU32::new(1).unwrap());
+    V::describe_field(file, &mut entry, "value", NonZeroU32::new(2).unwrap());
+
+    let type_name = format!("{}.{}", file.type_name(message.name()), entry_name);
+
+    message.nested_type.push(entry);
+    message.field.push(field(
+        name,
+        tag,
+        Label::Repeated,
+        Type::Message,
+        Some(type_name),
+    ));
+}
+
+/// Convert a `snake_case` field name into `camelCase` (or `CamelCase`, if `upper_first` is set),
+/// following the same rules as `protoc`.
+fn camel_case(name: &str, upper_first: bool) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = upper_first;
+
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+impl<T, E> ProtoDescriptor for MappedInt<T, E>
+where
+    T: ProtoScalar,
+    E: Encoding,
+{
+}
+
+impl<T> ProtoDescriptor for Option<T>
+where
+    T: ProtoDescriptor,
+{
+    fn descriptor_type(file: &FileDescriptor) -> (Type, Option<String>) {
+        T::descriptor_type(file)
+    }
+
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        T::describe_field(file, message, name, tag)
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_descriptor(file)
+    }
+}
+
+impl<T> ProtoDescriptor for &'_ T
+where
+    T: ProtoDescriptor + ?Sized,
+{
+    fn descriptor_type(file: &FileDescriptor) -> (Type, Option<String>) {
+        T::descriptor_type(file)
+    }
+
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        T::describe_field(file, message, name, tag)
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_descriptor(file)
+    }
+}
+
+impl<T> ProtoDescriptor for Box<T>
+where
+    T: ProtoDescriptor,
+{
+    fn descriptor_type(file: &FileDescriptor) -> (Type, Option<String>) {
+        T::descriptor_type(file)
+    }
+
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        T::describe_field(file, message, name, tag)
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_descriptor(file)
+    }
+}
+
+// `PhantomData` is never encoded, so it doesn't appear in the descriptor either.
+impl<T> ProtoDescriptor for PhantomData<T> {
+    fn describe_field(
+        _file: &FileDescriptor,
+        _message: &mut DescriptorProto,
+        _name: &str,
+        _tag: NonZeroU32,
+    ) {
+    }
+}
+
+impl ProtoDescriptor for () {
+    fn descriptor_type(_file: &FileDescriptor) -> (Type, Option<String>) {
+        (Type::Message, Some(".google.protobuf.Empty".into()))
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        file.add_dependency(FileDescriptorProto {
+            name: Some("google/protobuf/empty.proto".into()),
+            package: Some("google.protobuf".into()),
+            message_type: vec![DescriptorProto {
+                name: Some("Empty".into()),
+                ..Default::default()
+            }],
+            syntax: Some("proto3".into()),
+            ..Default::default()
+        });
+    }
+}
+
+// `Timestamp` and `Duration` have the same fields, a number of seconds and the nanoseconds on top.
+macro_rules! impl_seconds_and_nanos_descriptor {
+    ($($t:ty => $name:expr, $file_name:expr;)*) => {
+        $(
+            impl ProtoDescriptor for $t {
+                fn descriptor_type(_file: &FileDescriptor) -> (Type, Option<String>) {
+                    (Type::Message, Some(format!(".google.protobuf.{}", $name)))
+                }
+
+                fn register_descriptor(file: &mut FileDescriptor) {
+                    let fields = vec![
+                        field(
+                            "seconds",
+                            NonZeroU32::new(1).unwrap(),
+                            Label::Optional,
+                            Type::Int64,
+                            None,
+                        ),
+                        field(
+                            "nanos",
+                            NonZeroU32::new(2).unwrap(),
+                            Label::Optional,
+                            Type::Int32,
+                            None,
+                        ),
+                    ];
+
+                    file.add_dependency(FileDescriptorProto {
+                        name: Some($file_name.into()),
+                        package: Some("google.protobuf".into()),
+                        message_type: vec![DescriptorProto {
+                            name: Some($name.into()),
+                            field: fields,
+                            ..Default::default()
+                        }],
+                        syntax: Some("proto3".into()),
+                        ..Default::default()
+                    });
+                }
+            }
+        )*
+    };
+}
+
+impl_seconds_and_nanos_descriptor!(
+    prost_types::Timestamp => "Timestamp", "google/protobuf/timestamp.proto";
+    prost_types::Duration => "Duration", "google/protobuf/duration.proto";
+);
+
+impl<T, const TAG: u32> ProtoDescriptor for OneofField<T, TAG>
+where
+    T: ProtoOneofDescriptor,
+{
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        _tag: NonZeroU32,
+    ) {
+        message.oneof_decl.push(OneofDescriptorProto {
+            name: Some(name.to_owned()),
+            ..Default::default()
+        });
+
+        let oneof_index = message.oneof_decl.len() as i32 - 1;
+
+        T::describe_variants(file, message, oneof_index);
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_variant_descriptors(file)
+    }
+}
+
+impl<T, const TAG: u32> ProtoDescriptor for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneofDescriptor,
+{
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        OneofField::<T, TAG>::describe_field(file, message, name, tag)
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_variant_descriptors(file)
+    }
+}
+
+impl<T, const TAG: u32> ProtoDescriptor for Group<T, TAG>
+where
+    T: ProtoDescriptor,
+{
+    fn descriptor_type(file: &FileDescriptor) -> (Type, Option<String>) {
+        match T::descriptor_type(file) {
+            (Type::Message, type_name) => (Type::Group, type_name),
+            other => other,
+        }
+    }
+
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        T::describe_field(file, message, name, tag);
+
+        if let Some(field) = message.field.last_mut() {
+            if field.r#type() == Type::Message {
+                field.set_type(Type::Group);
+            }
+        }
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        T::register_descriptor(file)
+    }
+}
+
+impl ProtoDescriptor for str {}
+impl ProtoDescriptor for String {}
+impl ProtoDescriptor for crate::BytesStr {}
+impl ProtoDescriptor for OsStr {}
+impl ProtoDescriptor for OsString {}
+impl ProtoDescriptor for Path {}
+impl ProtoDescriptor for PathBuf {}
+impl ProtoDescriptor for crate::bytes::Bytes {}
+
+#[cfg(feature = "beef")]
+impl<U> ProtoDescriptor for beef::generic::Cow<'_, str, U> where U: beef::traits::Capacity {}
+
+#[cfg(feature = "uuid")]
+impl ProtoDescriptor for uuid::Uuid {}
+
+// Ranges are described as a message with `start` and `end` fields.
+macro_rules! impl_range_descriptor {
+    ($($t:ident),*) => {
+        $(
+            impl<T> ProtoDescriptor for $t<T>
+            where
+                T: ProtoDescriptor,
+            {
+                fn register_descriptor(file: &mut FileDescriptor) {
+                    let mut message = DescriptorProto {
+                        name: Some(Self::proto_name()),
+                        ..Default::default()
+                    };
+
+                    T::describe_field(file, &mut message, "start", NonZeroU32::new(1).unwrap());
+                    T::describe_field(file, &mut message, "end", NonZeroU32::new(2).unwrap());
+
+                    if file.define_message(message) {
+                        T::register_descriptor(file);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_range_descriptor!(Range, RangeInclusive);
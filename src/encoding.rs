@@ -0,0 +1,552 @@
+//! Choosing the encoding of scalar fields without changing their type, as is done by
+//! `#[autoproto(encoding = "..")]`, `#[autoproto(fixed)]` and `#[autoproto(packed = ..)]`.
+//!
+//! The derive macros access fields with these attributes through `WithEncoding`, with marker types
+//! from this module describing the encoding. This works for scalars, `Option`s of scalars,
+//! repeated scalars and maps with scalar values. For example:
+//!
+//! ```rust
+//! # #![feature(generic_associated_types)]
+//! use autoproto::prost::Message;
+//! use std::collections::BTreeMap;
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Point {
+//!     #[autoproto(encoding = "sint64")]
+//!     x: i64,
+//!     #[autoproto(fixed)]
+//!     y: u32,
+//!     #[autoproto(encoding = "sfixed32", packed = false)]
+//!     history: Vec<i32>,
+//!     #[autoproto(encoding = "sint32")]
+//!     offsets: BTreeMap<String, i32>,
+//! }
+//!
+//! let point = Point {
+//!     x: -1,
+//!     y: 2,
+//!     history: vec![3, 4],
+//!     offsets: Default::default(),
+//! };
+//!
+//! assert_eq!(
+//!     point.encode_to_vec(),
+//!     [0x08, 0x01, 0x15, 2, 0, 0, 0, 0x1d, 3, 0, 0, 0, 0x1d, 4, 0, 0, 0],
+//! );
+//! ```
+
+use crate::{
+    descriptor::{self, FileDescriptor},
+    generic, prost_types,
+    spec::{FieldShape, ProtoFile},
+    Encoding, Fixed, IsDefault, MapExtend, MappedInt, Proto, ProtoDescriptor, ProtoEncode,
+    ProtoMergeRepeated, ProtoScalar, ScalarEncoding, ScalarEncodingKind, ToProtoSpec, Varint,
+};
+use prost::{
+    bytes::{Buf, BufMut},
+    encoding::{DecodeContext, WireType},
+    DecodeError,
+};
+use std::{fmt, marker::PhantomData, num::NonZeroU32};
+
+/// Marker for the kind of encoding to use, overriding `ScalarEncoding::kind`.
+pub trait EncodingKind {
+    /// `None` uses the default for the type.
+    const KIND: Option<ScalarEncodingKind>;
+
+    /// Overrides `ScalarEncoding::default`, for fields with a proto2 `default`. `None` uses the
+    /// default for the type.
+    const DEFAULT: Option<i128> = None;
+}
+
+/// Marker for whether repeated fields should be packed, overriding `ScalarEncoding::packed`.
+pub trait Packing {
+    /// `None` uses the default for the type.
+    const PACKED: Option<bool>;
+}
+
+macro_rules! encoding_kinds {
+    ($($(#[$meta:meta])* $name:ident => $kind:expr,)*) => {
+        $(
+            $(#[$meta])*
+            pub struct $name;
+
+            impl EncodingKind for $name {
+                const KIND: Option<ScalarEncodingKind> = $kind;
+            }
+        )*
+    };
+}
+
+encoding_kinds! {
+    /// Use the default encoding for the type.
+    DefaultKind => None,
+    /// Use the default fixed-width encoding for the type.
+    DefaultFixed => Some(ScalarEncodingKind::Fixed(None)),
+    Double => Some(ScalarEncodingKind::Fixed(Some(Fixed::Double))),
+    Float => Some(ScalarEncodingKind::Fixed(Some(Fixed::Float))),
+    Fixed32 => Some(ScalarEncodingKind::Fixed(Some(Fixed::Fixed32))),
+    Fixed64 => Some(ScalarEncodingKind::Fixed(Some(Fixed::Fixed64))),
+    SFixed32 => Some(ScalarEncodingKind::Fixed(Some(Fixed::SFixed32))),
+    SFixed64 => Some(ScalarEncodingKind::Fixed(Some(Fixed::SFixed64))),
+    Bool => Some(ScalarEncodingKind::Varint(Some(Varint::Bool))),
+    Int32 => Some(ScalarEncodingKind::Varint(Some(Varint::I32))),
+    Int64 => Some(ScalarEncodingKind::Varint(Some(Varint::I64))),
+    SInt32 => Some(ScalarEncodingKind::Varint(Some(Varint::SI32))),
+    SInt64 => Some(ScalarEncodingKind::Varint(Some(Varint::SI64))),
+    UInt32 => Some(ScalarEncodingKind::Varint(Some(Varint::U32))),
+    UInt64 => Some(ScalarEncodingKind::Varint(Some(Varint::U64))),
+}
+
+/// The kind of encoding `K`, for a field whose default value is `VALUE` instead of zero, like
+/// `[default = 42]` in proto2. Fields with this value are not encoded.
+pub struct WithDefault<K, const VALUE: i128>(PhantomData<K>);
+
+impl<K, const VALUE: i128> EncodingKind for WithDefault<K, VALUE>
+where
+    K: EncodingKind,
+{
+    const KIND: Option<ScalarEncodingKind> = K::KIND;
+    const DEFAULT: Option<i128> = Some(VALUE);
+}
+
+/// Use the default packing for the type.
+pub struct DefaultPacking;
+
+impl Packing for DefaultPacking {
+    const PACKED: Option<bool> = None;
+}
+
+pub struct Packed;
+
+impl Packing for Packed {
+    const PACKED: Option<bool> = Some(true);
+}
+
+pub struct Unpacked;
+
+impl Packing for Unpacked {
+    const PACKED: Option<bool> = Some(false);
+}
+
+/// The default encoding of `T`, with the overrides given by `K` and `P`.
+pub struct FieldEncoding<T, K, P>(PhantomData<(T, K, P)>);
+
+impl<T, K, P> Default for FieldEncoding<T, K, P> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, K, P> PartialEq for FieldEncoding<T, K, P> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<T, K, P> Encoding for FieldEncoding<T, K, P>
+where
+    T: ProtoScalar,
+    K: EncodingKind,
+    P: Packing,
+{
+    const ENCODING: ScalarEncoding = ScalarEncoding {
+        default: match K::DEFAULT {
+            Some(default) => Some(default),
+            None => T::DEFAULT_ENCODING.default,
+        },
+        kind: match K::KIND {
+            Some(kind) => kind,
+            None => T::DEFAULT_ENCODING.kind,
+        },
+        packed: match P::PACKED {
+            Some(packed) => packed,
+            None => T::DEFAULT_ENCODING.packed,
+        },
+    };
+}
+
+/// Types containing scalars which can be encoded with an encoding other than their default.
+pub trait ProtoWithEncoding<K, P> {
+    /// A type with the same representation in a `.proto` file as this one with the encoding
+    /// overridden, used to implement `ToProtoSpec` and `ProtoDescriptor`.
+    type Described;
+
+    fn encode_with(&self, tag: NonZeroU32, buf: &mut dyn BufMut);
+
+    fn encoded_len_with(&self, tag: NonZeroU32) -> usize;
+
+    /// Encode even if this is the default value, see `ProtoEncode::encode_as_field_explicit`.
+    fn encode_with_explicit(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        self.encode_with(tag, buf)
+    }
+
+    fn encoded_len_with_explicit(&self, tag: NonZeroU32) -> usize {
+        self.encoded_len_with(tag)
+    }
+
+    fn merge_with(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError>;
+}
+
+impl<T, K, P> ProtoWithEncoding<K, P> for T
+where
+    T: ProtoScalar,
+    K: EncodingKind,
+    P: Packing,
+{
+    type Described = MappedInt<T, FieldEncoding<T, K, P>>;
+
+    fn encode_with(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        generic::protoscalar::protoencode_encode_as_field::<_, FieldEncoding<T, K, P>>(
+            self, tag, buf,
+        )
+    }
+
+    fn encoded_len_with(&self, tag: NonZeroU32) -> usize {
+        generic::protoscalar::protoencode_encoded_len_as_field::<_, FieldEncoding<T, K, P>>(
+            self, tag,
+        )
+    }
+
+    fn encode_with_explicit(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        MappedInt::<T, FieldEncoding<T, K, P>>(self.clone(), PhantomData)
+            .encode_as_field_explicit(tag, buf)
+    }
+
+    fn encoded_len_with_explicit(&self, tag: NonZeroU32) -> usize {
+        MappedInt::<T, FieldEncoding<T, K, P>>(self.clone(), PhantomData)
+            .encoded_len_as_field_explicit(tag)
+    }
+
+    fn merge_with(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        generic::protoscalar::proto_merge_self::<_, FieldEncoding<T, K, P>>(
+            self, wire_type, buf, ctx,
+        )
+    }
+}
+
+impl<T, K, P> ProtoWithEncoding<K, P> for Option<T>
+where
+    T: ProtoScalar,
+    K: EncodingKind,
+    P: Packing,
+{
+    type Described = Option<MappedInt<T, FieldEncoding<T, K, P>>>;
+
+    fn encode_with(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        if let Some(inner) = self {
+            MappedInt::<T, FieldEncoding<T, K, P>>(inner.clone(), PhantomData)
+                .encode_as_field_explicit(tag, buf)
+        }
+    }
+
+    fn encoded_len_with(&self, tag: NonZeroU32) -> usize {
+        match self {
+            Some(inner) => MappedInt::<T, FieldEncoding<T, K, P>>(inner.clone(), PhantomData)
+                .encoded_len_as_field_explicit(tag),
+            None => 0,
+        }
+    }
+
+    fn merge_with(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut cur = self.take().unwrap_or_default();
+
+        <T as ProtoWithEncoding<K, P>>::merge_with(&mut cur, wire_type, buf, ctx)?;
+
+        *self = Some(cur);
+
+        Ok(())
+    }
+}
+
+/// Implementation of `ProtoWithEncoding::encode_with` for repeated fields.
+#[doc(hidden)]
+pub fn encode_repeated_with<'a, T, K, P, I>(iter: I, tag: NonZeroU32, buf: &mut dyn BufMut)
+where
+    T: ProtoScalar + 'a,
+    K: EncodingKind,
+    P: Packing,
+    I: ExactSizeIterator<Item = &'a T> + Clone,
+{
+    if iter.len() != 0 {
+        MappedInt::<T, FieldEncoding<T, K, P>>::encode_as_field_repeated(iter, tag, buf)
+    }
+}
+
+/// Implementation of `ProtoWithEncoding::encoded_len_with` for repeated fields.
+#[doc(hidden)]
+pub fn encoded_len_repeated_with<'a, T, K, P, I>(iter: I, tag: NonZeroU32) -> usize
+where
+    T: ProtoScalar + 'a,
+    K: EncodingKind,
+    P: Packing,
+    I: ExactSizeIterator<Item = &'a T>,
+{
+    if iter.len() == 0 {
+        0
+    } else {
+        MappedInt::<T, FieldEncoding<T, K, P>>::encoded_len_as_field_repeated(iter, tag)
+    }
+}
+
+/// Implementation of `ProtoWithEncoding::merge_with` for repeated fields.
+#[doc(hidden)]
+pub fn merge_repeated_with<T, K, P, C>(
+    values: &mut C,
+    wire_type: WireType,
+    buf: &mut dyn Buf,
+    ctx: DecodeContext,
+) -> Result<(), DecodeError>
+where
+    T: ProtoScalar,
+    K: EncodingKind,
+    P: Packing,
+    C: Extend<T>,
+{
+    MappedInt::<T, FieldEncoding<T, K, P>>::merge_repeated(
+        &mut MapExtend::new(values, |MappedInt(inner, _)| inner),
+        wire_type,
+        buf,
+        ctx,
+    )
+}
+
+/// Implementation of `ProtoWithEncoding::encode_with` for maps, where only the values have their
+/// encoding overridden.
+#[doc(hidden)]
+pub fn encode_map_with<'a, Key, T, K, P, I>(iter: I, tag: NonZeroU32, mut buf: &mut dyn BufMut)
+where
+    Key: ProtoEncode + 'a,
+    T: ProtoScalar + 'a,
+    K: EncodingKind,
+    P: Packing,
+    I: Iterator<Item = (&'a Key, &'a T)>,
+{
+    let (key_tag, val_tag) = map_entry_tags();
+
+    for (key, val) in iter {
+        let val = MappedInt::<T, FieldEncoding<T, K, P>>::from_ref(val);
+        let len = key.encoded_len_as_field(key_tag) + val.encoded_len_as_field(val_tag);
+
+        prost::encoding::encode_key(tag.get(), WireType::LengthDelimited, &mut buf);
+        prost::encoding::encode_varint(len as u64, &mut buf);
+        key.encode_as_field(key_tag, buf);
+        val.encode_as_field(val_tag, buf);
+    }
+}
+
+/// Implementation of `ProtoWithEncoding::encoded_len_with` for maps.
+#[doc(hidden)]
+pub fn encoded_len_map_with<'a, Key, T, K, P, I>(iter: I, tag: NonZeroU32) -> usize
+where
+    Key: ProtoEncode + 'a,
+    T: ProtoScalar + 'a,
+    K: EncodingKind,
+    P: Packing,
+    I: Iterator<Item = (&'a Key, &'a T)>,
+{
+    let (key_tag, val_tag) = map_entry_tags();
+
+    iter.map(|(key, val)| {
+        let val = MappedInt::<T, FieldEncoding<T, K, P>>::from_ref(val);
+        let len = key.encoded_len_as_field(key_tag) + val.encoded_len_as_field(val_tag);
+
+        prost::encoding::key_len(tag.get()) + prost::encoding::encoded_len_varint(len as u64) + len
+    })
+    .sum()
+}
+
+/// Implementation of `ProtoWithEncoding::merge_with` for maps, returning the decoded entry.
+#[doc(hidden)]
+pub fn merge_map_entry_with<Key, T, K, P>(
+    wire_type: WireType,
+    mut buf: &mut dyn Buf,
+    ctx: DecodeContext,
+) -> Result<(Key, T), DecodeError>
+where
+    Key: Proto + Default,
+    T: ProtoScalar,
+    K: EncodingKind,
+    P: Packing,
+{
+    prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+    let mut key = Key::default();
+    let mut val = MappedInt::<T, FieldEncoding<T, K, P>>::default();
+
+    prost::encoding::merge_loop(
+        &mut (&mut key, &mut val),
+        &mut buf,
+        ctx,
+        |(key, val), buf, ctx| {
+            let (tag, wire_type) = prost::encoding::decode_key(buf)?;
+
+            match tag {
+                1 => key.merge_self(wire_type, buf, ctx),
+                2 => val.merge_self(wire_type, buf, ctx),
+                _ => prost::encoding::skip_field(wire_type, tag, buf, ctx),
+            }
+        },
+    )?;
+
+    Ok((key, val.0))
+}
+
+fn map_entry_tags() -> (NonZeroU32, NonZeroU32) {
+    (NonZeroU32::new(1).unwrap(), NonZeroU32::new(2).unwrap())
+}
+
+/// Adapter used to access fields with an overridden encoding. `K` and `P` are the overrides for
+/// the kind of encoding and for whether repeated fields are packed.
+#[repr(transparent)]
+pub struct WithEncoding<T, K, P>(pub T, PhantomData<(K, P)>);
+
+impl<T, K, P> WithEncoding<T, K, P> {
+    pub fn from_ref(v: &T) -> &Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+
+    pub fn from_mut(v: &mut T) -> &mut Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+}
+
+impl<T, K, P> IsDefault for WithEncoding<T, K, P>
+where
+    T: IsDefault,
+{
+    fn is_default(&self) -> bool {
+        self.0.is_default()
+    }
+}
+
+impl<T, K, P> ProtoEncode for WithEncoding<T, K, P>
+where
+    T: ProtoWithEncoding<K, P>,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        self.0.encode_with(tag, buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        self.0.encoded_len_with(tag)
+    }
+
+    fn encode_as_field_explicit(&self, tag: NonZeroU32, buf: &mut dyn BufMut) {
+        self.0.encode_with_explicit(tag, buf)
+    }
+
+    fn encoded_len_as_field_explicit(&self, tag: NonZeroU32) -> usize {
+        self.0.encoded_len_with_explicit(tag)
+    }
+}
+
+impl<T, K, P> Proto for WithEncoding<T, K, P>
+where
+    T: ProtoWithEncoding<K, P>,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        self.0.merge_with(wire_type, buf, ctx)
+    }
+}
+
+impl<T, K, P> WithEncoding<T, K, P>
+where
+    P: Packing,
+{
+    /// Whether this is a repeated field which is explicitly not packed, which needs to be
+    /// mentioned in the spec since proto3 packs repeated scalars by default.
+    const fn unpacked(shape: FieldShape) -> bool {
+        matches!(shape, FieldShape::Repeated(_)) && matches!(P::PACKED, Some(false))
+    }
+}
+
+impl<T, K, P> ToProtoSpec for WithEncoding<T, K, P>
+where
+    T: ProtoWithEncoding<K, P>,
+    T::Described: ToProtoSpec,
+    P: Packing,
+{
+    const SHAPE: FieldShape = <T::Described as ToProtoSpec>::SHAPE;
+
+    fn proto_name() -> String {
+        <T::Described as ToProtoSpec>::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        if Self::unpacked(Self::SHAPE) {
+            writeln!(
+                f,
+                "    {} {} = {} [packed = false];",
+                Self::proto_name(),
+                name,
+                tag
+            )
+        } else {
+            <T::Described as ToProtoSpec>::fmt_field(f, name, tag)
+        }
+    }
+
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        <T::Described as ToProtoSpec>::fmt(f)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        <T::Described as ToProtoSpec>::register(file)
+    }
+}
+
+impl<T, K, P> ProtoDescriptor for WithEncoding<T, K, P>
+where
+    T: ProtoWithEncoding<K, P>,
+    T::Described: ProtoDescriptor,
+    P: Packing,
+{
+    fn descriptor_type(file: &FileDescriptor) -> (descriptor::Type, Option<String>) {
+        <T::Described as ProtoDescriptor>::descriptor_type(file)
+    }
+
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut prost_types::DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        <T::Described as ProtoDescriptor>::describe_field(file, message, name, tag);
+
+        if Self::unpacked(Self::SHAPE) {
+            if let Some(field) = message.field.last_mut() {
+                field.options = Some(prost_types::FieldOptions {
+                    packed: Some(false),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    fn register_descriptor(file: &mut FileDescriptor) {
+        <T::Described as ProtoDescriptor>::register_descriptor(file)
+    }
+}
@@ -9,6 +9,122 @@
 
 pub use wrapper::Wrapper;
 
+/// Tracking of `#[autoproto(required)]` fields. A message field which appears more than once is
+/// merged, so a required field of a nested message only has to appear in one of the occurrences.
+/// Because of this, nested messages only record which required fields they saw, and these are
+/// checked once the message being decoded (or an element of a repeated field, which is never
+/// merged with another) is complete.
+pub mod required {
+    use prost::DecodeError;
+    use std::cell::RefCell;
+
+    struct Seen {
+        message: *const (),
+        type_name: &'static str,
+        required: &'static [(u32, &'static str)],
+        seen: Vec<bool>,
+    }
+
+    thread_local! {
+        static SEEN: RefCell<Option<Vec<Seen>>> = const { RefCell::new(None) };
+    }
+
+    /// Record that `tag` was seen, if it is one of `required`.
+    pub fn mark(required: &[(u32, &str)], seen: &mut [bool], tag: u32) {
+        if let Some(i) = required.iter().position(|(other, _)| *other == tag) {
+            seen[i] = true;
+        }
+    }
+
+    pub fn check(required: &[(u32, &str)], seen: &[bool]) -> Result<(), DecodeError> {
+        match seen.iter().position(|seen| !seen) {
+            Some(i) => {
+                let (tag, name) = required[i];
+
+                Err(DecodeError::new(format!(
+                    "Missing required field `{}` (tag {})",
+                    name, tag
+                )))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record the required fields seen in one occurrence of `message`. These are combined with
+    /// other occurrences of the same message and checked at the end of `decode`, or checked now if
+    /// nothing is being decoded with `decode`.
+    ///
+    /// Occurrences are matched up by the address of `message`, so messages have to be merged in
+    /// place. Anything decoded into a temporary which is then moved, such as an element of a
+    /// `Vec`, needs its own `decode`.
+    pub fn record<T>(
+        message: &T,
+        required: &'static [(u32, &'static str)],
+        seen: Vec<bool>,
+    ) -> Result<(), DecodeError> {
+        if required.is_empty() {
+            return Ok(());
+        }
+
+        // The first field of a struct has the same address as the struct, so the type is needed
+        // to tell them apart.
+        let message = message as *const T as *const ();
+        let type_name = std::any::type_name::<T>();
+
+        SEEN.with(|all| match &mut *all.borrow_mut() {
+            Some(all) => {
+                match all
+                    .iter_mut()
+                    .find(|other| other.message == message && other.type_name == type_name)
+                {
+                    Some(other) => {
+                        for (other, seen) in other.seen.iter_mut().zip(seen) {
+                            *other |= seen;
+                        }
+                    }
+                    None => all.push(Seen {
+                        message,
+                        type_name,
+                        required,
+                        seen,
+                    }),
+                }
+
+                Ok(())
+            }
+            None => check(required, &seen),
+        })
+    }
+
+    /// Decode a new message with `merge`, checking the required fields of it and every message
+    /// inside it afterwards.
+    pub fn decode<F>(merge: F) -> Result<(), DecodeError>
+    where
+        F: FnOnce() -> Result<(), DecodeError>,
+    {
+        struct Restore(Option<Vec<Seen>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer = self.0.take();
+
+                SEEN.with(|all| *all.borrow_mut() = outer);
+            }
+        }
+
+        let restore = Restore(SEEN.with(|all| all.borrow_mut().replace(Vec::new())));
+
+        merge()?;
+
+        let all = SEEN.with(|all| all.borrow_mut().take()).unwrap_or_default();
+
+        drop(restore);
+
+        all.iter()
+            .try_for_each(|message| check(message.required, &message.seen))
+    }
+}
+
 mod wrapper {
     use crate::{Clear, IsDefault, Proto, ProtoEncode};
     use std::ops::{Deref, DerefMut};
@@ -61,6 +177,18 @@ mod wrapper {
         fn encoded_len_as_field(&self, tag: std::num::NonZeroU32) -> usize {
             (**self).encoded_len_as_field(tag)
         }
+
+        fn encode_as_field_explicit(
+            &self,
+            tag: std::num::NonZeroU32,
+            buf: &mut dyn prost::bytes::BufMut,
+        ) {
+            (**self).encode_as_field_explicit(tag, buf)
+        }
+
+        fn encoded_len_as_field_explicit(&self, tag: std::num::NonZeroU32) -> usize {
+            (**self).encoded_len_as_field_explicit(tag)
+        }
     }
 
     impl<T> IsDefault for Wrapper<T>
@@ -91,6 +219,7 @@ mod wrapper {
 }
 
 pub mod protostruct {
+    use super::required;
     use crate::{ProtoStruct, ProtoStructMut};
     use prost::{
         bytes::{Buf, BufMut},
@@ -110,12 +239,30 @@ pub mod protostruct {
         this: &mut T,
         mut buf: B,
     ) -> Result<(), DecodeError> {
-        let ctx = DecodeContext::default();
-        while buf.has_remaining() {
-            let (tag, wire_type) = prost::encoding::decode_key(&mut buf)?;
-            message_merge_field(this, tag, wire_type, &mut buf, ctx.clone())?;
-        }
-        Ok(())
+        required::decode(|| {
+            let ctx = DecodeContext::default();
+            let mut seen = vec![false; T::REQUIRED_FIELDS.len()];
+            while buf.has_remaining() {
+                let (tag, wire_type) = prost::encoding::decode_key(&mut buf)?;
+                required::mark(T::REQUIRED_FIELDS, &mut seen, tag);
+                message_merge_field(this, tag, wire_type, &mut buf, ctx.clone())?;
+            }
+            required::record(this, T::REQUIRED_FIELDS, seen)
+        })
+    }
+
+    pub fn message_merge_length_delimited<T: ProtoStructMut, B: Buf>(
+        this: &mut T,
+        mut buf: B,
+    ) -> Result<(), DecodeError> {
+        required::decode(|| {
+            proto_merge_self(
+                this,
+                WireType::LengthDelimited,
+                &mut buf,
+                DecodeContext::default(),
+            )
+        })
     }
 
     pub fn message_encode_raw<T: ProtoStruct, B: BufMut>(this: &T, buf: &mut B) {
@@ -131,11 +278,15 @@ pub mod protostruct {
         buf: &mut B,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
-        if let Some(field) = NonZeroU32::new(tag).and_then(|tag| this.field_mut(tag)) {
-            field.merge_self(wire_type, buf, ctx)?;
+        let tag = NonZeroU32::new(tag).ok_or_else(|| DecodeError::new("Invalid tag: 0"))?;
+
+        if let Some(field) = this.field_mut(tag) {
+            field.merge_self(wire_type, buf, ctx)
+        } else if let Some(unknown) = this.unknown_fields_mut() {
+            unknown.merge_field(tag, wire_type, buf, ctx)
+        } else {
+            prost::encoding::skip_field(wire_type, tag.get(), buf, ctx)
         }
-
-        Ok(())
     }
 
     pub fn message_encoded_len<T: ProtoStruct>(this: &T) -> usize {
@@ -170,15 +321,80 @@ pub mod protostruct {
         ctx: DecodeContext,
     ) -> Result<(), prost::DecodeError> {
         prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        let mut seen = vec![false; T::REQUIRED_FIELDS.len()];
+
         prost::encoding::merge_loop(this, &mut buf, ctx, |this, buf, ctx| {
             let (tag, wire_type) = prost::encoding::decode_key(buf)?;
-            if let Some(field) = NonZeroU32::new(tag).and_then(|tag| this.field_mut(tag)) {
-                field.merge_self(wire_type, buf, ctx)
-            } else {
-                prost::encoding::skip_field(wire_type, tag, buf, ctx)
+            required::mark(T::REQUIRED_FIELDS, &mut seen, tag);
+            message_merge_field(this, tag, wire_type, buf, ctx)
+        })?;
+
+        required::record(this, T::REQUIRED_FIELDS, seen)
+    }
+}
+
+pub mod protostructborrow {
+    use super::required;
+    use crate::borrow::ProtoStructBorrow;
+    use prost::{
+        encoding::{DecodeContext, WireType},
+        DecodeError,
+    };
+    use std::num::NonZeroU32;
+
+    pub fn merge<'a, T: ProtoStructBorrow<'a>>(
+        this: &mut T,
+        mut buf: &'a [u8],
+    ) -> Result<(), DecodeError> {
+        required::decode(|| {
+            let ctx = DecodeContext::default();
+            let mut seen = vec![false; T::REQUIRED_FIELDS.len()];
+            while !buf.is_empty() {
+                let (tag, wire_type) = prost::encoding::decode_key(&mut buf)?;
+                required::mark(T::REQUIRED_FIELDS, &mut seen, tag);
+                merge_field(this, tag, wire_type, &mut buf, ctx.clone())?;
             }
+            required::record(this, T::REQUIRED_FIELDS, seen)
         })
     }
+
+    pub fn merge_field<'a, T: ProtoStructBorrow<'a>>(
+        this: &mut T,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let tag = NonZeroU32::new(tag).ok_or_else(|| DecodeError::new("Invalid tag: 0"))?;
+
+        if let Some(field) = this.field_borrowed_mut(tag) {
+            field.merge_borrowed(wire_type, buf, ctx)
+        } else if let Some(unknown) = this.unknown_fields_mut() {
+            unknown.merge_field(tag, wire_type, buf, ctx)
+        } else {
+            prost::encoding::skip_field(wire_type, tag.get(), buf, ctx)
+        }
+    }
+
+    pub fn protoborrow_merge_borrowed<'a, T: ProtoStructBorrow<'a>>(
+        this: &mut T,
+        wire_type: WireType,
+        buf: &mut &'a [u8],
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        let mut seen = vec![false; T::REQUIRED_FIELDS.len()];
+
+        prost::encoding::merge_loop(this, buf, ctx, |this, buf, ctx| {
+            let (tag, wire_type) = prost::encoding::decode_key(buf)?;
+            required::mark(T::REQUIRED_FIELDS, &mut seen, tag);
+            merge_field(this, tag, wire_type, buf, ctx)
+        })?;
+
+        required::record(this, T::REQUIRED_FIELDS, seen)
+    }
 }
 
 pub mod clear {
@@ -214,7 +430,7 @@ pub mod proto {
         This: Proto + Default,
     {
         let mut inner = This::default();
-        inner.merge_self(wire_type, buf, ctx)?;
+        super::required::decode(|| inner.merge_self(wire_type, buf, ctx))?;
 
         values.extend(std::iter::once(inner));
 
@@ -223,7 +439,7 @@ pub mod proto {
 }
 
 pub mod protooneof {
-    use crate::ProtoOneof;
+    use crate::{ProtoOneof, ProtoOneofMut};
     use prost::{
         bytes::{Buf, BufMut},
         encoding::{DecodeContext, WireType},
@@ -232,26 +448,74 @@ pub mod protooneof {
     use std::num::NonZeroU32;
 
     pub fn message_encode_raw<T: ProtoOneof, B: BufMut>(this: &T, buf: &mut B) {
-        this.variant(|inner, tag| inner.encode_as_field(tag, buf))
+        this.variant(|inner, tag| inner.encode_as_field_explicit(tag, buf))
     }
 
-    pub fn message_merge_field<T: ProtoOneof, B: Buf>(
+    pub fn message_merge_field<T: ProtoOneofMut, B: Buf>(
         this: &mut T,
         tag: u32,
         wire_type: WireType,
         buf: &mut B,
         ctx: DecodeContext,
     ) -> Result<(), DecodeError> {
-        this.exec_merge(
-            NonZeroU32::new(tag).ok_or_else(|| DecodeError::new("Invalid tag: 0"))?,
-            |val| val.merge_self(wire_type, buf, ctx.clone()),
-        );
+        let tag = NonZeroU32::new(tag).ok_or_else(|| DecodeError::new("Invalid tag: 0"))?;
 
-        Ok(())
+        let mut known = true;
+
+        // A new variant is decoded into a temporary, so its required fields are checked by itself.
+        super::required::decode(|| {
+            match this.exec_merge(tag, |val| val.merge_self(wire_type, buf, ctx.clone())) {
+                Some(result) => result,
+                None => {
+                    known = false;
+
+                    Ok(())
+                }
+            }
+        })?;
+
+        if known {
+            return Ok(());
+        }
+
+        // A oneof has nowhere to keep fields it doesn't recognise, which are most likely variants
+        // added since this was built, so they are skipped.
+        prost::encoding::skip_field(wire_type, tag.get(), buf, ctx)
     }
 
     pub fn message_encoded_len<T: ProtoOneof>(this: &T) -> usize {
-        this.variant(|inner, tag| inner.encoded_len_as_field(tag))
+        this.variant(|inner, tag| inner.encoded_len_as_field_explicit(tag))
+    }
+
+    pub fn protoencode_encode_as_field<T: ProtoOneof>(
+        this: &T,
+        tag: NonZeroU32,
+        mut buf: &mut dyn prost::bytes::BufMut,
+    ) {
+        let len = message_encoded_len(this);
+        let buf = &mut buf;
+
+        prost::encoding::encode_key(tag.get(), WireType::LengthDelimited, buf);
+        prost::encoding::encode_varint(len as u64, buf);
+        message_encode_raw(this, buf)
+    }
+
+    pub fn protoencode_encoded_len_as_field<T: ProtoOneof>(this: &T, tag: NonZeroU32) -> usize {
+        let len = message_encoded_len(this);
+        prost::encoding::key_len(tag.get()) + prost::encoding::encoded_len_varint(len as u64) + len
+    }
+
+    pub fn proto_merge_self<T: ProtoOneofMut>(
+        this: &mut T,
+        wire_type: WireType,
+        mut buf: &mut dyn prost::bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+        prost::encoding::merge_loop(this, &mut buf, ctx, |this, buf, ctx| {
+            let (tag, wire_type) = prost::encoding::decode_key(buf)?;
+            message_merge_field(this, tag, wire_type, buf, ctx)
+        })
     }
 }
 
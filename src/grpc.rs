@@ -0,0 +1,133 @@
+//! A `tonic` codec for messages deriving `Message`, so they can be used in gRPC services and clients
+//! without generating code from `.proto` files with `prost-build`. Use `AutoprotoCodec` wherever the
+//! code generated by `tonic-build` would use `tonic::codec::ProstCodec`:
+//!
+//! ```rust
+//! use autoproto::grpc::AutoprotoCodec;
+//! use tonic::codec::Codec;
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct HelloRequest {
+//!     name: String,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct HelloReply {
+//!     message: String,
+//! }
+//!
+//! let mut codec = AutoprotoCodec::<HelloReply, HelloRequest>::default();
+//! let (_encoder, _decoder) = (codec.encoder(), codec.decoder());
+//! ```
+
+use prost::{DecodeError, Message};
+use std::{fmt, marker::PhantomData, pin::Pin};
+use tonic::{
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    codegen::futures_core::Stream,
+    Code, Status,
+};
+
+/// Used on implementations of `#[autoproto::service]` traits, as their methods are `async`.
+pub use tonic::codegen::async_trait;
+/// The requests of a client-streaming method in a `#[autoproto::service]` trait, and the responses
+/// of a server-streaming method on the generated client.
+pub use tonic::Streaming;
+
+/// The responses of a server-streaming method in a `#[autoproto::service]` trait.
+pub type BoxStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send + Sync + 'static>>;
+
+/// A `tonic::codec::Codec` which encodes `Req` and decodes `Resp`. On a server `Req` is the
+/// response type of a method and `Resp` the request type, and on a client it is the other way
+/// around.
+pub struct AutoprotoCodec<Req, Resp> {
+    _marker: PhantomData<fn(Req) -> Resp>,
+}
+
+impl<Req, Resp> Default for AutoprotoCodec<Req, Resp> {
+    fn default() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp> Clone for AutoprotoCodec<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<Req, Resp> fmt::Debug for AutoprotoCodec<Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoprotoCodec").finish()
+    }
+}
+
+impl<Req, Resp> Codec for AutoprotoCodec<Req, Resp>
+where
+    Req: Message + Send + 'static,
+    Resp: Message + Default + Send + 'static,
+{
+    type Encode = Req;
+    type Decode = Resp;
+
+    type Encoder = AutoprotoEncoder<Req>;
+    type Decoder = AutoprotoDecoder<Resp>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        AutoprotoEncoder(PhantomData)
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        AutoprotoDecoder(PhantomData)
+    }
+}
+
+/// The `tonic::codec::Encoder` of an `AutoprotoCodec`.
+pub struct AutoprotoEncoder<T>(PhantomData<fn(T)>);
+
+impl<T> fmt::Debug for AutoprotoEncoder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoprotoEncoder").finish()
+    }
+}
+
+impl<T: Message> Encoder for AutoprotoEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        // `EncodeBuf` grows as needed, so this can't run out of space.
+        item.encode_raw(buf);
+
+        Ok(())
+    }
+}
+
+/// The `tonic::codec::Decoder` of an `AutoprotoCodec`.
+pub struct AutoprotoDecoder<T>(PhantomData<fn() -> T>);
+
+impl<T> fmt::Debug for AutoprotoDecoder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoprotoDecoder").finish()
+    }
+}
+
+impl<T: Message + Default> Decoder for AutoprotoDecoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        let mut item = T::default();
+
+        item.merge(buf).map_err(from_decode_error)?;
+
+        Ok(Some(item))
+    }
+}
+
+/// Decoding errors are reported as `INTERNAL`, the same as `tonic`'s own codec.
+fn from_decode_error(error: DecodeError) -> Status {
+    Status::new(Code::Internal, error.to_string())
+}
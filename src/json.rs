@@ -0,0 +1,1007 @@
+//! The [proto3 JSON mapping](https://developers.google.com/protocol-buffers/docs/proto3#json),
+//! for exposing messages through JSON APIs such as REST gateways. `ProtoJson` is implemented by
+//! the derive macros alongside the binary encoding, and `to_json`/`from_json` convert a message to
+//! and from a JSON string.
+//!
+//! ```rust
+//! #[derive(Copy, Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Position {
+//!     line_number: u64,
+//!     #[autoproto(json_name = "col")]
+//!     column: u32,
+//! }
+//!
+//! let position = Position {
+//!     line_number: 10,
+//!     column: 0,
+//! };
+//! let json = autoproto::json::to_json(&position);
+//!
+//! // 64-bit integers are written as strings, and fields with the default value are omitted.
+//! assert_eq!(json, r#"{"lineNumber":"10"}"#);
+//! assert_eq!(autoproto::json::from_json::<Position>(&json).unwrap(), position);
+//!
+//! // Both the JSON name and the original name of a field are accepted.
+//! let parsed: Position =
+//!     autoproto::json::from_json(r#"{"line_number": 10, "col": 4}"#).unwrap();
+//! assert_eq!(parsed, Position { line_number: 10, column: 4 });
+//! ```
+
+use crate::{
+    bytes::{Bytes, BytesMut},
+    spec::FieldType,
+    BytesStr, Clear, Encoding, Fixed, IsDefault, MappedInt, OneofField, ProtoJson, ProtoMap,
+    ProtoOneofJson, ProtoScalar, ToProtoSpec, Value, Varint,
+};
+use serde_json::{Map, Number, Value as JsonValue};
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt,
+    marker::PhantomData,
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+};
+
+/// An error converting a value from JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    message: String,
+}
+
+impl JsonError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// The JSON value didn't have the type that was expected for the field, e.g. a string was
+    /// given for a boolean.
+    pub fn expected(expected: &str, found: &JsonValue) -> Self {
+        let found = match found {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "a boolean",
+            JsonValue::Number(_) => "a number",
+            JsonValue::String(_) => "a string",
+            JsonValue::Array(_) => "an array",
+            JsonValue::Object(_) => "an object",
+        };
+
+        Self::new(format!("Expected {}, found {}", expected, found))
+    }
+
+    /// An object had a key which isn't the name of any of the fields of the message.
+    pub fn unknown_field(name: &str) -> Self {
+        Self::new(format!("Unknown field `{}`", name))
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(other: serde_json::Error) -> Self {
+        Self::new(other.to_string())
+    }
+}
+
+/// Convert `value` to a JSON string.
+pub fn to_json<T: ProtoJson + ?Sized>(value: &T) -> String {
+    value.to_json_value().to_string()
+}
+
+/// Parse a value from a JSON string, starting from the default value.
+pub fn from_json<T: ProtoJson + Default>(json: &str) -> Result<T, JsonError> {
+    let mut out = T::default();
+
+    out.merge_json_value(serde_json::from_str(json)?)?;
+
+    Ok(out)
+}
+
+/// Insert `value` into `object` as the field `name`, unless it's the default. This is used by the
+/// derive macros to write the fields of a message.
+pub fn write_field<T>(object: &mut Map<String, JsonValue>, name: &str, value: &T)
+where
+    T: ProtoJson + ?Sized,
+{
+    if !value.is_json_default() {
+        object.insert(name.to_owned(), value.to_json_value());
+    }
+}
+
+/// Merge `value` into the field `field`. As in the proto3 JSON mapping, `null` resets the field to
+/// its default value.
+pub fn merge_field<T>(field: &mut T, value: JsonValue) -> Result<(), JsonError>
+where
+    T: ProtoJson + Clear,
+{
+    merge_field_with(field, value, Clear::clear)
+}
+
+/// Merge `value` into the field `field`, calling `reset` for `null`. The derive macros use this to
+/// reset the fields of a message from the message's default value, so that fields don't need to
+/// implement `Default` themselves.
+pub fn merge_field_with<T, F>(field: &mut T, value: JsonValue, reset: F) -> Result<(), JsonError>
+where
+    T: ProtoJson,
+    F: FnOnce(&mut T),
+{
+    if value.is_null() {
+        reset(field);
+
+        Ok(())
+    } else {
+        field.merge_json_value(value)
+    }
+}
+
+/// Call `merge` with the name and value of each field in `value`, which must be an object. This is
+/// used by the derive macros to merge into the fields of a message.
+pub fn merge_object<F>(value: JsonValue, mut merge: F) -> Result<(), JsonError>
+where
+    F: FnMut(&str, JsonValue) -> Result<(), JsonError>,
+{
+    match value {
+        JsonValue::Object(object) => {
+            for (name, value) in object {
+                merge(&name, value)?;
+            }
+
+            Ok(())
+        }
+        other => Err(JsonError::expected("an object", &other)),
+    }
+}
+
+/// Merge `value` into the variant named `name` of a `oneof`. A `null` value leaves the `oneof`
+/// untouched.
+pub fn merge_variant<T>(oneof: &mut T, name: &str, value: JsonValue) -> Result<(), JsonError>
+where
+    T: ProtoOneofJson + ?Sized,
+{
+    if value.is_null() {
+        Ok(())
+    } else {
+        oneof.merge_variant_json(name, value)
+    }
+}
+
+/// Convert a `oneof` that is used as a message on its own, which is an object with a single field
+/// for the current variant.
+pub fn oneof_to_json<T>(oneof: &T) -> JsonValue
+where
+    T: ProtoOneofJson + ?Sized,
+{
+    let mut object = Map::new();
+
+    oneof.variants_to_json(&mut object);
+
+    JsonValue::Object(object)
+}
+
+/// Merge into a `oneof` that is used as a message on its own.
+pub fn merge_oneof<T>(oneof: &mut T, value: JsonValue) -> Result<(), JsonError>
+where
+    T: ProtoOneofJson + ?Sized,
+{
+    merge_object(value, |name, value| {
+        if T::has_variant(name) {
+            merge_variant(oneof, name, value)
+        } else {
+            Err(JsonError::unknown_field(name))
+        }
+    })
+}
+
+/// Convert the elements of a repeated field to a JSON array.
+pub fn repeated_to_json<'a, T, I>(iter: I) -> JsonValue
+where
+    T: ProtoJson + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    JsonValue::Array(iter.into_iter().map(ProtoJson::to_json_value).collect())
+}
+
+/// Extend a repeated field with the elements of a JSON array.
+pub fn merge_repeated<T, C>(collection: &mut C, value: JsonValue) -> Result<(), JsonError>
+where
+    T: ProtoJson + Default,
+    C: Extend<T> + ?Sized,
+{
+    match value {
+        JsonValue::Array(values) => {
+            for value in values {
+                let mut item = T::default();
+
+                item.merge_json_value(value)?;
+                collection.extend(std::iter::once(item));
+            }
+
+            Ok(())
+        }
+        other => Err(JsonError::expected("an array", &other)),
+    }
+}
+
+/// Convert the entries of a map to a JSON object. Keys are always strings in JSON, so integer and
+/// boolean keys are converted to strings.
+pub fn map_to_json<'a, K, V, I>(iter: I) -> JsonValue
+where
+    K: ProtoJson + 'a,
+    V: ProtoJson + 'a,
+    I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+    JsonValue::Object(
+        iter.into_iter()
+            .map(|(k, v)| {
+                let key = match k.to_json_value() {
+                    JsonValue::String(s) => s,
+                    other => other.to_string(),
+                };
+
+                (key, v.to_json_value())
+            })
+            .collect(),
+    )
+}
+
+/// Insert the entries of a JSON object into a map.
+pub fn merge_map<M>(map: &mut M, value: JsonValue) -> Result<(), JsonError>
+where
+    M: ProtoMap + ?Sized,
+    M::Key: ProtoJson + Default,
+    M::Value: ProtoJson + Default,
+{
+    merge_object(value, |name, value| {
+        let mut key = M::Key::default();
+
+        if let Err(e) = key.merge_json_value(JsonValue::String(name.to_owned())) {
+            // Boolean keys are the only ones that don't accept strings as values.
+            match name {
+                "true" => key.merge_json_value(JsonValue::Bool(true))?,
+                "false" => key.merge_json_value(JsonValue::Bool(false))?,
+                _ => return Err(e),
+            }
+        }
+
+        let mut val = M::Value::default();
+
+        val.merge_json_value(value)?;
+        map.insert(key, val);
+
+        Ok(())
+    })
+}
+
+fn float_to_json(f: f64) -> JsonValue {
+    if f.is_nan() {
+        JsonValue::String("NaN".into())
+    } else if f.is_infinite() {
+        JsonValue::String(if f > 0. { "Infinity" } else { "-Infinity" }.into())
+    } else {
+        Number::from_f64(f)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null)
+    }
+}
+
+fn float_from_json(value: &JsonValue) -> Result<f64, JsonError> {
+    match value {
+        JsonValue::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| JsonError::expected("a float", value)),
+        JsonValue::String(s) => match &s[..] {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            s => s
+                .parse()
+                .map_err(|_| JsonError::new(format!("Invalid float `{}`", s))),
+        },
+        other => Err(JsonError::expected("a float", other)),
+    }
+}
+
+fn int_from_json(value: &JsonValue) -> Result<i128, JsonError> {
+    match value {
+        JsonValue::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from))
+            .or_else(|| {
+                // Integers may be written with an exponent or a fractional part of zero.
+                n.as_f64()
+                    .filter(|f| f.fract() == 0. && f.abs() < 2f64.powi(64))
+                    .map(|f| f as i128)
+            })
+            .ok_or_else(|| JsonError::new(format!("Invalid integer `{}`", n))),
+        JsonValue::String(s) => s
+            .parse()
+            .map_err(|_| JsonError::new(format!("Invalid integer `{}`", s))),
+        other => Err(JsonError::expected("an integer", other)),
+    }
+}
+
+impl<T, E> ProtoJson for MappedInt<T, E>
+where
+    T: ProtoScalar,
+    E: Encoding,
+{
+    fn to_json_value(&self) -> JsonValue {
+        match (<Self as ToProtoSpec>::SHAPE.item(), self.0.to_value()) {
+            (FieldType::Varint(Varint::Bool), value) => JsonValue::Bool(value.bool() == Some(true)),
+            // Go through `f32` to get the shortest representation that round-trips.
+            (FieldType::Fixed(Fixed::Float), Value::Float(f)) => {
+                float_to_json((f as f32).to_string().parse().unwrap_or(f))
+            }
+            (_, Value::Float(f)) => float_to_json(f),
+            // 64-bit integers are written as strings, as they can't be represented exactly by a
+            // JavaScript number.
+            (
+                FieldType::Varint(Varint::I64 | Varint::SI64 | Varint::U64)
+                | FieldType::Fixed(Fixed::Fixed64 | Fixed::SFixed64),
+                Value::Int(i),
+            ) => JsonValue::String(i.to_string()),
+            (_, Value::Int(i)) => JsonValue::Number((i as i64).into()),
+        }
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let parsed = match <Self as ToProtoSpec>::SHAPE.item() {
+            FieldType::Varint(Varint::Bool) => match value {
+                JsonValue::Bool(b) => Value::Int(b as i128),
+                other => return Err(JsonError::expected("a boolean", &other)),
+            },
+            FieldType::Fixed(Fixed::Float | Fixed::Double) => {
+                Value::Float(float_from_json(&value)?)
+            }
+            _ => Value::Int(int_from_json(&value)?),
+        };
+
+        self.0 = T::from_value(parsed)
+            .ok_or_else(|| JsonError::new(format!("Value `{}` is out of range", value)))?;
+
+        Ok(())
+    }
+}
+
+impl<T> ProtoJson for Option<T>
+where
+    T: ProtoJson + Default,
+{
+    fn to_json_value(&self) -> JsonValue {
+        match self {
+            None => JsonValue::Null,
+            Some(v) => v.to_json_value(),
+        }
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        if value.is_null() {
+            *self = None;
+
+            Ok(())
+        } else {
+            self.get_or_insert_with(Default::default)
+                .merge_json_value(value)
+        }
+    }
+}
+
+impl<T> ProtoJson for Box<T>
+where
+    T: ProtoJson + ?Sized,
+{
+    fn to_json_value(&self) -> JsonValue {
+        T::to_json_value(self)
+    }
+
+    fn is_json_default(&self) -> bool {
+        T::is_json_default(self)
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        T::merge_json_value(self, value)
+    }
+}
+
+// `PhantomData` is never encoded, so it doesn't appear in the JSON either.
+impl<T> ProtoJson for PhantomData<T> {
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::Null
+    }
+
+    fn is_json_default(&self) -> bool {
+        true
+    }
+
+    fn merge_json_value(&mut self, _value: JsonValue) -> Result<(), JsonError> {
+        Ok(())
+    }
+}
+
+/// `()` is `google.protobuf.Empty`, which is an empty object.
+impl ProtoJson for () {
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::Object(Map::new())
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        merge_object(value, |name, _| Err(JsonError::unknown_field(name)))
+    }
+}
+
+/// The fractional part of a timestamp or duration, with 0, 3, 6 or 9 digits as the JSON mapping
+/// requires.
+fn fmt_nanos(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos.is_multiple_of(1_000_000) {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos.is_multiple_of(1_000) {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+/// Parse the digits of a fractional part following a `.` as nanoseconds, returning them along with
+/// the rest of the string.
+fn parse_nanos(s: &str) -> Option<(u32, &str)> {
+    let fraction = match s.strip_prefix('.') {
+        Some(fraction) => fraction,
+        None => return Some((0, s)),
+    };
+    let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+
+    let nanos = fraction[..digits].parse::<u32>().ok()? * 10u32.pow(9 - digits as u32);
+
+    Some((nanos, &fraction[digits..]))
+}
+
+// These convert between a number of days since the Unix epoch and a date in the proleptic
+// Gregorian calendar, using the algorithms from http://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Parse an RFC 3339 timestamp such as `1972-01-01T10:00:20.021Z`, with any UTC offset.
+fn parse_timestamp(s: &str) -> Option<prost_types::Timestamp> {
+    let number = |start: usize, len: usize| -> Option<i64> {
+        let digits = s.get(start..start + len)?;
+
+        if digits.bytes().all(|c| c.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let separator =
+        |i: usize, expected: &[u8]| s.as_bytes().get(i).is_some_and(|c| expected.contains(c));
+
+    if !(separator(4, b"-")
+        && separator(7, b"-")
+        && separator(10, b"Tt")
+        && separator(13, b":")
+        && separator(16, b":"))
+    {
+        return None;
+    }
+
+    let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+    let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+    let days = days_from_civil(year, month, day);
+
+    if civil_from_days(days) != (year, month, day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let (nanos, offset) = parse_nanos(&s[19..])?;
+    let offset = match offset {
+        "Z" | "z" => 0,
+        offset => {
+            let sign = match offset.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+
+            if offset.len() != 6 || offset.as_bytes()[3] != b':' {
+                return None;
+            }
+
+            let offset_number = |start: usize| -> Option<i64> {
+                let digits = &offset[start..start + 2];
+
+                if digits.bytes().all(|c| c.is_ascii_digit()) {
+                    digits.parse().ok()
+                } else {
+                    None
+                }
+            };
+
+            sign * (offset_number(1)? * 3600 + offset_number(4)? * 60)
+        }
+    };
+
+    Some(prost_types::Timestamp {
+        seconds: days * 86_400 + hour * 3600 + minute * 60 + second - offset,
+        nanos: nanos as i32,
+    })
+}
+
+/// Timestamps are written in RFC 3339 format, always in UTC.
+impl ProtoJson for prost_types::Timestamp {
+    fn to_json_value(&self) -> JsonValue {
+        let mut timestamp = self.clone();
+        timestamp.normalize();
+
+        let (year, month, day) = civil_from_days(timestamp.seconds.div_euclid(86_400));
+        let seconds_of_day = timestamp.seconds.rem_euclid(86_400);
+
+        JsonValue::String(format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+            year,
+            month,
+            day,
+            seconds_of_day / 3600,
+            seconds_of_day / 60 % 60,
+            seconds_of_day % 60,
+            fmt_nanos(timestamp.nanos as u32),
+        ))
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        match &value {
+            JsonValue::String(s) => {
+                *self = parse_timestamp(s)
+                    .ok_or_else(|| JsonError::new(format!("Invalid timestamp {:?}", s)))?;
+
+                Ok(())
+            }
+            other => Err(JsonError::expected("a timestamp", other)),
+        }
+    }
+}
+
+/// Durations are written as a number of seconds with an `s` suffix, such as `"-1.5s"`.
+impl ProtoJson for prost_types::Duration {
+    fn to_json_value(&self) -> JsonValue {
+        let mut duration = self.clone();
+        duration.normalize();
+
+        let sign = if duration.seconds < 0 || duration.nanos < 0 {
+            "-"
+        } else {
+            ""
+        };
+
+        JsonValue::String(format!(
+            "{}{}{}s",
+            sign,
+            duration.seconds.unsigned_abs(),
+            fmt_nanos(duration.nanos.unsigned_abs()),
+        ))
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let s = match &value {
+            JsonValue::String(s) => s,
+            other => return Err(JsonError::expected("a duration", other)),
+        };
+        let invalid = || JsonError::new(format!("Invalid duration {:?}", s));
+
+        let (sign, unsigned) = match s.strip_prefix('-') {
+            Some(unsigned) => (-1, unsigned),
+            None => (1, &s[..]),
+        };
+        let unsigned = unsigned.strip_suffix('s').ok_or_else(invalid)?;
+        let digits = unsigned.bytes().take_while(u8::is_ascii_digit).count();
+
+        if digits == 0 {
+            return Err(invalid());
+        }
+
+        let seconds = unsigned[..digits].parse::<i64>().map_err(|_| invalid())?;
+        let (nanos, rest) = parse_nanos(&unsigned[digits..]).ok_or_else(invalid)?;
+
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+
+        *self = prost_types::Duration {
+            seconds: sign * seconds,
+            nanos: sign as i32 * nanos as i32,
+        };
+
+        Ok(())
+    }
+}
+
+impl<T, const TAG: u32> ProtoJson for OneofField<T, TAG>
+where
+    T: ProtoOneofJson,
+{
+    fn to_json_value(&self) -> JsonValue {
+        self.0.to_json_value()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        self.0.merge_json_value(value)
+    }
+}
+
+impl<T, const TAG: u32> ProtoOneofJson for OneofField<T, TAG>
+where
+    T: ProtoOneofJson,
+{
+    fn has_variant(name: &str) -> bool {
+        T::has_variant(name)
+    }
+
+    fn variants_to_json(&self, object: &mut Map<String, JsonValue>) {
+        self.0.variants_to_json(object)
+    }
+
+    fn merge_variant_json(&mut self, name: &str, value: JsonValue) -> Result<(), JsonError> {
+        self.0.merge_variant_json(name, value)
+    }
+}
+
+impl<T, const TAG: u32> ProtoJson for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneofJson + Default,
+{
+    fn to_json_value(&self) -> JsonValue {
+        oneof_to_json(self)
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        merge_oneof(self, value)
+    }
+}
+
+impl<T, const TAG: u32> ProtoOneofJson for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneofJson + Default,
+{
+    fn has_variant(name: &str) -> bool {
+        T::has_variant(name)
+    }
+
+    fn variants_to_json(&self, object: &mut Map<String, JsonValue>) {
+        if let Some(inner) = &self.0 {
+            inner.variants_to_json(object)
+        }
+    }
+
+    fn merge_variant_json(&mut self, name: &str, value: JsonValue) -> Result<(), JsonError> {
+        self.0
+            .get_or_insert_with(Default::default)
+            .merge_variant_json(name, value)
+    }
+}
+
+impl ProtoJson for String {
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::String(self.clone())
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        match value {
+            JsonValue::String(s) => {
+                *self = s;
+
+                Ok(())
+            }
+            other => Err(JsonError::expected("a string", &other)),
+        }
+    }
+}
+
+impl ProtoJson for BytesStr {
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::String(self.to_string())
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        match value {
+            JsonValue::String(s) => {
+                *self = s.into();
+
+                Ok(())
+            }
+            other => Err(JsonError::expected("a string", &other)),
+        }
+    }
+}
+
+#[cfg(feature = "beef")]
+impl<U> ProtoJson for beef::generic::Cow<'_, str, U>
+where
+    U: beef::traits::Capacity,
+{
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::String(self.to_string())
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let mut string = String::new();
+
+        string.merge_json_value(value)?;
+        *self = Self::owned(string);
+
+        Ok(())
+    }
+}
+
+/// Bytes are written as base64. Both the standard and URL-safe alphabets are accepted when
+/// parsing.
+fn bytes_to_json(bytes: &[u8]) -> JsonValue {
+    JsonValue::String(base64::encode(bytes))
+}
+
+fn bytes_from_json(value: JsonValue) -> Result<Vec<u8>, JsonError> {
+    match value {
+        JsonValue::String(s) => {
+            let config = if s.contains(['-', '_']) {
+                base64::URL_SAFE
+            } else {
+                base64::STANDARD
+            };
+
+            base64::decode_config(&s, config)
+                .map_err(|e| JsonError::new(format!("Invalid base64: {}", e)))
+        }
+        other => Err(JsonError::expected("a base64 string", &other)),
+    }
+}
+
+impl<const LEN: usize> ProtoJson for [u8; LEN] {
+    fn to_json_value(&self) -> JsonValue {
+        bytes_to_json(self)
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let bytes = bytes_from_json(value)?;
+
+        if bytes.len() != LEN {
+            return Err(JsonError::new(format!(
+                "Expected {} bytes, found {}",
+                LEN,
+                bytes.len()
+            )));
+        }
+
+        self.copy_from_slice(&bytes);
+
+        Ok(())
+    }
+}
+
+macro_rules! impl_bytes_json {
+    ($($t:ty),*) => {
+        $(
+            impl ProtoJson for $t {
+                fn to_json_value(&self) -> JsonValue {
+                    bytes_to_json(self)
+                }
+
+                fn is_json_default(&self) -> bool {
+                    self.is_empty()
+                }
+
+                fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+                    *self = bytes_from_json(value)?.into();
+
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_bytes_json!(Box<[u8]>, Bytes);
+
+impl ProtoJson for BytesMut {
+    fn to_json_value(&self) -> JsonValue {
+        bytes_to_json(self)
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        *self = bytes_from_json(value)?[..].into();
+
+        Ok(())
+    }
+}
+
+impl ProtoJson for OsString {
+    #[cfg(unix)]
+    fn to_json_value(&self) -> JsonValue {
+        use std::os::unix::ffi::OsStrExt;
+
+        bytes_to_json(self.as_bytes())
+    }
+
+    // Other platforms can only represent strings which are valid Unicode.
+    #[cfg(not(unix))]
+    fn to_json_value(&self) -> JsonValue {
+        bytes_to_json(self.to_string_lossy().as_bytes())
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[cfg(unix)]
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        use std::os::unix::ffi::OsStringExt;
+
+        *self = OsString::from_vec(bytes_from_json(value)?);
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        *self = String::from_utf8(bytes_from_json(value)?)
+            .map_err(|e| JsonError::new(e.to_string()))?
+            .into();
+
+        Ok(())
+    }
+}
+
+impl ProtoJson for PathBuf {
+    fn to_json_value(&self) -> JsonValue {
+        self.as_os_str().to_owned().to_json_value()
+    }
+
+    fn is_json_default(&self) -> bool {
+        self.as_os_str().is_empty()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let mut os_string = OsString::new();
+
+        os_string.merge_json_value(value)?;
+        *self = os_string.into();
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl ProtoJson for uuid::Uuid {
+    fn to_json_value(&self) -> JsonValue {
+        bytes_to_json(self.as_bytes())
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let mut bytes = *self.as_bytes();
+
+        bytes.merge_json_value(value)?;
+        *self = uuid::Uuid::from_bytes(bytes);
+
+        Ok(())
+    }
+}
+
+// Ranges are written as a message with `start` and `end` fields.
+fn range_to_json<T>(start: &T, end: &T) -> JsonValue
+where
+    T: ProtoJson,
+{
+    let mut object = Map::new();
+
+    write_field(&mut object, "start", start);
+    write_field(&mut object, "end", end);
+
+    JsonValue::Object(object)
+}
+
+fn merge_range<T>(start: &mut T, end: &mut T, value: JsonValue) -> Result<(), JsonError>
+where
+    T: ProtoJson + Clear,
+{
+    merge_object(value, |name, value| match name {
+        "start" => merge_field(start, value),
+        "end" => merge_field(end, value),
+        name => Err(JsonError::unknown_field(name)),
+    })
+}
+
+impl<T> ProtoJson for Range<T>
+where
+    T: ProtoJson + Clear,
+{
+    fn to_json_value(&self) -> JsonValue {
+        range_to_json(&self.start, &self.end)
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        merge_range(&mut self.start, &mut self.end, value)
+    }
+}
+
+impl<T> ProtoJson for RangeInclusive<T>
+where
+    T: ProtoJson + Clear + Clone,
+{
+    fn to_json_value(&self) -> JsonValue {
+        range_to_json(self.start(), self.end())
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let (mut start, mut end) = self.clone().into_inner();
+
+        merge_range(&mut start, &mut end, value)?;
+        *self = start..=end;
+
+        Ok(())
+    }
+}
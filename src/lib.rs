@@ -1,13 +1,42 @@
 #![feature(generic_associated_types)]
 #![doc = include_str!("../README.md")]
 
-pub use autoproto_derive::{IsDefault, Message, Proto, ProtoEncode, ProtoScalar};
+#[cfg(feature = "tonic")]
+pub use autoproto_derive::service;
+pub use autoproto_derive::{IsDefault, Message, Proto, ProtoBorrow, ProtoEncode, ProtoScalar};
 
 pub use prost;
 pub use prost::bytes;
-
+pub use prost_types;
+pub use serde_json;
+
+#[cfg(feature = "inventory")]
+#[doc(hidden)]
+pub use inventory;
+#[cfg(feature = "tonic")]
+pub use tonic;
+
+pub mod borrow;
+mod bytes_str;
+pub mod descriptor;
+pub mod encoding;
 pub mod generic;
+#[cfg(feature = "tonic")]
+pub mod grpc;
+pub mod json;
 pub mod macros;
+pub mod raw;
+pub mod registry;
+pub mod spec;
+pub mod stream;
+pub mod text;
+pub mod unknown;
+pub mod wkt;
+
+pub use bytes_str::BytesStr;
+pub use descriptor::FileDescriptor;
+pub use spec::ProtoFile;
+pub use unknown::UnknownFields;
 
 use prost::encoding::{DecodeContext, WireType};
 use std::{
@@ -33,8 +62,226 @@ impl<T: Default> Clear for T {
     }
 }
 
+/// Types which are encoded as `bytes` with `impl_proto_for_bytes!`. When a field appears more than
+/// once while decoding, the last value wins, so decoding replaces the contents instead of appending
+/// to them.
+pub trait ReplaceBytes {
+    fn replace_bytes(&mut self, bytes: &[u8]) -> Result<(), prost::DecodeError>;
+}
+
+impl<const LEN: usize> ReplaceBytes for [u8; LEN] {
+    fn replace_bytes(&mut self, bytes: &[u8]) -> Result<(), prost::DecodeError> {
+        self[..].replace_bytes(bytes)
+    }
+}
+
+/// Slices can't change their length, so the field must have exactly as many bytes as the slice.
+impl ReplaceBytes for [u8] {
+    fn replace_bytes(&mut self, bytes: &[u8]) -> Result<(), prost::DecodeError> {
+        if bytes.len() != self.len() {
+            return Err(prost::DecodeError::new(format!(
+                "Expected {} bytes, found {}",
+                self.len(),
+                bytes.len()
+            )));
+        }
+
+        self.copy_from_slice(bytes);
+
+        Ok(())
+    }
+}
+
+impl ReplaceBytes for Box<[u8]> {
+    fn replace_bytes(&mut self, bytes: &[u8]) -> Result<(), prost::DecodeError> {
+        *self = bytes.into();
+
+        Ok(())
+    }
+}
+
+impl ReplaceBytes for bytes::BytesMut {
+    fn replace_bytes(&mut self, bytes: &[u8]) -> Result<(), prost::DecodeError> {
+        self.clear();
+        self.extend_from_slice(bytes);
+
+        Ok(())
+    }
+}
+
+/// Read the contents of a length-delimited field from `buf`, which should be positioned immediately
+/// after the field's key. If `buf` is a `Bytes` or `BytesMut`, this is a slice of it rather than a
+/// copy.
+pub fn decode_length_delimited(
+    mut buf: &mut dyn bytes::Buf,
+) -> Result<bytes::Bytes, prost::DecodeError> {
+    let len = prost::encoding::decode_varint(&mut buf)?;
+
+    if len > buf.remaining() as u64 {
+        return Err(prost::DecodeError::new("buffer underflow"));
+    }
+
+    Ok(buf.copy_to_bytes(len as usize))
+}
+
+/// Describes how a type is written in a `.proto` file. This is implemented by the derive macros,
+/// and `ProtoFile` can be used to generate a complete file from it.
 pub trait ToProtoSpec {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result;
+    /// How a field of this type is declared, used to check types against a `.proto` file with
+    /// `#[autoproto(proto_file = "...")]`.
+    const SHAPE: spec::FieldShape;
+
+    /// The name used to refer to this type in a field declaration, e.g. `uint32`, `string` or the
+    /// name of a message.
+    fn proto_name() -> String;
+
+    /// Write the declaration of a field of this type as a line in the body of a message, including
+    /// the indentation and trailing newline.
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        writeln!(f, "    {} {} = {};", Self::proto_name(), name, tag)
+    }
+
+    /// Write the definition of this type. This writes nothing for types built in to protobuf.
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        let _ = f;
+
+        Ok(())
+    }
+
+    /// Add the definition of this type, and of every type it refers to, to `file`.
+    fn register(file: &mut ProtoFile) {
+        let _ = file;
+    }
+}
+
+/// Implemented by `oneof`s so that they can be written inline in the body of another message, as
+/// is done for `#[autoproto(oneof)]` fields.
+pub trait ProtoOneofSpec: ToProtoSpec {
+    /// Write the declaration of every variant, as lines in the body of a `oneof`.
+    fn fmt_variants(f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Add the definitions of the types of each variant to `file`.
+    fn register_variants(file: &mut ProtoFile);
+}
+
+/// Describes a type as a `prost_types` descriptor, the machine-readable counterpart to
+/// `ToProtoSpec`. This is implemented by the derive macros, and `FileDescriptor` can be used to
+/// build a complete `FileDescriptorSet` from it.
+pub trait ProtoDescriptor: ToProtoSpec {
+    /// The type of a single value of this type, along with the fully-qualified name of the message
+    /// or enumeration if it isn't built in to protobuf. By default this is worked out from
+    /// `ToProtoSpec::SHAPE` and `ToProtoSpec::proto_name`.
+    fn descriptor_type(file: &FileDescriptor) -> (descriptor::Type, Option<String>) {
+        descriptor::descriptor_type(file, Self::SHAPE, &Self::proto_name())
+    }
+
+    /// Add the descriptor of a field of this type to `message`.
+    fn describe_field(
+        file: &FileDescriptor,
+        message: &mut prost_types::DescriptorProto,
+        name: &str,
+        tag: NonZeroU32,
+    ) {
+        let (ty, type_name) = Self::descriptor_type(file);
+
+        message.field.push(descriptor::field(
+            name,
+            tag,
+            descriptor::Label::Optional,
+            ty,
+            type_name,
+        ));
+    }
+
+    /// Add the descriptor of this type, and of every type it refers to, to `file`.
+    fn register_descriptor(file: &mut FileDescriptor) {
+        let _ = file;
+    }
+}
+
+/// Implemented by `oneof`s so that their variants can be described as part of another message, as
+/// is done for `#[autoproto(oneof)]` fields.
+pub trait ProtoOneofDescriptor: ProtoDescriptor + ProtoOneofSpec {
+    /// Add a field to `message` for each variant, as part of the oneof at `oneof_index` in
+    /// `message.oneof_decl`.
+    fn describe_variants(
+        file: &FileDescriptor,
+        message: &mut prost_types::DescriptorProto,
+        oneof_index: i32,
+    );
+
+    /// Add the descriptors of the types of each variant to `file`.
+    fn register_variant_descriptors(file: &mut FileDescriptor);
+}
+
+/// Converts a type to and from the proto3 JSON mapping. This is implemented by the derive macros,
+/// and `json::to_json` and `json::from_json` can be used to convert to and from a JSON string.
+pub trait ProtoJson {
+    /// Convert this value to JSON.
+    fn to_json_value(&self) -> serde_json::Value;
+
+    /// Whether this is left out when it is a field of a message. Scalars, strings, bytes and
+    /// collections are left out when they are empty or zero, as determined by `IsDefault`, while
+    /// messages are always written.
+    fn is_json_default(&self) -> bool {
+        false
+    }
+
+    /// Merge a JSON value into this value. As with the binary encoding, fields that are present
+    /// overwrite the current value, and repeated fields are extended.
+    fn merge_json_value(&mut self, value: serde_json::Value) -> Result<(), json::JsonError>;
+}
+
+/// Implemented by `oneof`s so that their variants can be written as fields of another message, as
+/// is done for `#[autoproto(oneof)]` fields.
+pub trait ProtoOneofJson: ProtoJson {
+    /// Whether `name` is the JSON name or the original name of one of the variants.
+    fn has_variant(name: &str) -> bool;
+
+    /// Insert the current variant into `object`.
+    fn variants_to_json(&self, object: &mut serde_json::Map<String, serde_json::Value>);
+
+    /// Merge `value` into the variant named `name`, switching to that variant if it isn't the
+    /// current one.
+    fn merge_variant_json(
+        &mut self,
+        name: &str,
+        value: serde_json::Value,
+    ) -> Result<(), json::JsonError>;
+}
+
+/// Converts a type to and from the protobuf text format. This is implemented by the derive
+/// macros, and `text::to_text` and `text::from_text` can be used to convert to and from a string.
+pub trait TextFormat {
+    /// Convert this value to the text format.
+    fn to_text_value(&self) -> text::TextValue;
+
+    /// Whether this is left out when it is a field of a message, as with `ProtoJson`.
+    fn is_text_default(&self) -> bool {
+        false
+    }
+
+    /// Merge a parsed value into this value. Each occurrence of a field is merged separately, so
+    /// scalars take the last value, messages are merged and repeated fields are extended.
+    fn merge_text_value(&mut self, value: text::TextValue) -> Result<(), text::TextError>;
+}
+
+/// Implemented by `oneof`s so that their variants can be written as fields of another message, as
+/// is done for `#[autoproto(oneof)]` fields.
+pub trait TextFormatOneof: TextFormat {
+    /// Whether `name` is the name of one of the variants.
+    fn has_variant(name: &str) -> bool;
+
+    /// Add the current variant to `fields`.
+    fn variants_to_text(&self, fields: &mut Vec<(String, text::TextValue)>);
+
+    /// Merge `value` into the variant named `name`, switching to that variant if it isn't the
+    /// current one.
+    fn merge_variant_text(
+        &mut self,
+        name: &str,
+        value: text::TextValue,
+    ) -> Result<(), text::TextError>;
 }
 
 impl<T> IsDefault for PhantomData<T> {
@@ -82,6 +329,18 @@ pub trait ProtoEncode {
 
     /// Get the length if this type is encoded with its field tag.
     fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize;
+
+    /// Encode this type as a field even if it is the default value, which is how fields with
+    /// explicit presence such as `Option<u32>` (proto3 `optional uint32`) are encoded. This only
+    /// needs to be overridden by types whose `encode_as_field` skips the default value.
+    fn encode_as_field_explicit(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        self.encode_as_field(tag, buf)
+    }
+
+    /// Get the length if this type is encoded with `encode_as_field_explicit`.
+    fn encoded_len_as_field_explicit(&self, tag: NonZeroU32) -> usize {
+        self.encoded_len_as_field(tag)
+    }
 }
 
 pub trait ProtoEncodeRepeated: ProtoEncode {
@@ -159,8 +418,9 @@ where
         T: Extend<Self>,
         Self: Sized,
     {
+        // Each element is a separate message, so its required fields are checked on their own.
         let mut inner = Self::default();
-        inner.merge_self(wire_type, buf, ctx)?;
+        generic::required::decode(|| inner.merge_self(wire_type, buf, ctx))?;
 
         values.extend(std::iter::once(inner));
 
@@ -195,32 +455,395 @@ pub trait IsDefault {
     }
 }
 
-pub trait IsMessage {}
+pub trait IsMessage {
+    /// The tags and names of the `#[autoproto(required)]` fields. Decoding fails if any of these
+    /// never appear.
+    const REQUIRED_FIELDS: &'static [(u32, &'static str)] = &[];
+}
 
 pub trait ProtoOneof: IsMessage {
     fn variant<F, T>(&self, func: F) -> T
     where
         F: FnOnce(&(dyn ProtoEncode + '_), NonZeroU32) -> T;
+}
+
+pub trait ProtoOneofMut: ProtoOneof {
     fn exec_merge<F, T>(&mut self, tag: NonZeroU32, func: F) -> Option<T>
     where
         F: FnOnce(&mut (dyn Proto + '_)) -> T;
 }
 
+/// Adapter used to flatten a `ProtoOneof` into the tag space of the struct containing it, which is
+/// how `#[autoproto(oneof, tags = "..")]` fields are encoded. The tag passed to `encode_as_field`
+/// is ignored, since the active variant is always encoded with its own tag. As `Proto::merge_self`
+/// does not receive the tag, `TAG` specifies which variant this wrapper merges into.
+///
+/// This is also implemented for `Option<T>`, where `None` encodes nothing, to match `prost`'s
+/// representation of `oneof` fields.
+#[repr(transparent)]
+pub struct OneofField<T, const TAG: u32>(pub T);
+
+impl<T, const TAG: u32> OneofField<T, TAG> {
+    pub fn from_ref(v: &T) -> &Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+
+    pub fn from_mut(v: &mut T) -> &mut Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+
+    fn tag() -> Result<NonZeroU32, prost::DecodeError> {
+        NonZeroU32::new(TAG).ok_or_else(|| prost::DecodeError::new("Invalid tag: 0"))
+    }
+}
+
+impl<T, const TAG: u32> ProtoEncode for OneofField<T, TAG>
+where
+    T: ProtoOneof,
+{
+    // The current variant is always encoded, even if its value is the default, so that it decodes
+    // as the same variant again.
+    fn encode_as_field(&self, _tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        self.0
+            .variant(|inner, tag| inner.encode_as_field_explicit(tag, buf))
+    }
+
+    fn encoded_len_as_field(&self, _tag: NonZeroU32) -> usize {
+        self.0
+            .variant(|inner, tag| inner.encoded_len_as_field_explicit(tag))
+    }
+}
+
+impl<T, const TAG: u32> Proto for OneofField<T, TAG>
+where
+    T: ProtoOneofMut,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        let tag = Self::tag()?;
+
+        // A new variant is decoded into a temporary, so its required fields are checked by itself.
+        generic::required::decode(|| {
+            self.0
+                .exec_merge(tag, |val| val.merge_self(wire_type, buf, ctx))
+                .unwrap_or_else(|| {
+                    Err(prost::DecodeError::new(format!(
+                        "Oneof has no variant with tag {}",
+                        TAG
+                    )))
+                })
+        })
+    }
+}
+
+impl<T, const TAG: u32> ProtoEncode for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneof,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        if let Some(inner) = &self.0 {
+            OneofField::<T, TAG>::from_ref(inner).encode_as_field(tag, buf)
+        }
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        match &self.0 {
+            None => 0,
+            Some(inner) => OneofField::<T, TAG>::from_ref(inner).encoded_len_as_field(tag),
+        }
+    }
+}
+
+impl<T, const TAG: u32> Proto for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneofMut + Default,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        OneofField::<T, TAG>::from_mut(self.0.get_or_insert_with(Default::default))
+            .merge_self(wire_type, buf, ctx)
+    }
+}
+
+/// Adapter used to encode `#[autoproto(group)]` fields as proto2 groups, which are delimited by
+/// start and end group keys rather than a length prefix. As with `OneofField`, `TAG` is needed
+/// because `Proto::merge_self` doesn't receive the tag, which must match the end group key.
+///
+/// This is implemented for messages, for `Option`s of messages and for `Vec`s of messages, which
+/// are `repeated` groups.
+#[repr(transparent)]
+pub struct Group<T, const TAG: u32>(pub T);
+
+impl<T, const TAG: u32> Group<T, TAG> {
+    pub fn from_ref(v: &T) -> &Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+
+    pub fn from_mut(v: &mut T) -> &mut Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+}
+
+impl<T, const TAG: u32> ProtoEncode for Group<T, TAG>
+where
+    T: prost::Message + IsMessage,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut) {
+        prost::encoding::group::encode(tag.get(), &self.0, &mut buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        prost::encoding::group::encoded_len(tag.get(), &self.0)
+    }
+}
+
+/// Wraps the message in a group while it is merged by `prost::encoding::group::merge`, to record
+/// which of its required fields appear.
+#[derive(Debug)]
+struct GroupRequired<'a, T> {
+    message: &'a mut T,
+    seen: Vec<bool>,
+}
+
+impl<T> prost::Message for GroupRequired<'_, T>
+where
+    T: prost::Message + IsMessage,
+{
+    fn encode_raw<B>(&self, buf: &mut B)
+    where
+        B: bytes::BufMut,
+    {
+        self.message.encode_raw(buf)
+    }
+
+    fn merge_field<B>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError>
+    where
+        B: bytes::Buf,
+    {
+        generic::required::mark(T::REQUIRED_FIELDS, &mut self.seen, tag);
+        self.message.merge_field(tag, wire_type, buf, ctx)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.message.encoded_len()
+    }
+
+    fn clear(&mut self) {
+        self.message.clear()
+    }
+}
+
+impl<T, const TAG: u32> Proto for Group<T, TAG>
+where
+    T: prost::Message + IsMessage,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        mut buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        let mut group = GroupRequired {
+            message: &mut self.0,
+            seen: vec![false; T::REQUIRED_FIELDS.len()],
+        };
+
+        prost::encoding::group::merge(TAG, wire_type, &mut group, &mut buf, ctx)?;
+
+        let seen = group.seen;
+
+        generic::required::record(&self.0, T::REQUIRED_FIELDS, seen)
+    }
+}
+
+impl<T, const TAG: u32> ProtoEncode for Group<Option<T>, TAG>
+where
+    T: prost::Message + IsMessage,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        if let Some(inner) = &self.0 {
+            Group::<T, TAG>::from_ref(inner).encode_as_field(tag, buf)
+        }
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        match &self.0 {
+            None => 0,
+            Some(inner) => Group::<T, TAG>::from_ref(inner).encoded_len_as_field(tag),
+        }
+    }
+}
+
+impl<T, const TAG: u32> Proto for Group<Option<T>, TAG>
+where
+    T: prost::Message + IsMessage + Default,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        // Merged in place, so that the required fields of each occurrence are combined.
+        Group::<T, TAG>::from_mut(self.0.get_or_insert_with(T::default))
+            .merge_self(wire_type, buf, ctx)
+    }
+}
+
+impl<T, const TAG: u32> ProtoEncode for Group<Vec<T>, TAG>
+where
+    T: prost::Message + IsMessage,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut) {
+        prost::encoding::group::encode_repeated(tag.get(), &self.0, &mut buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        prost::encoding::group::encoded_len_repeated(tag.get(), &self.0)
+    }
+}
+
+impl<T, const TAG: u32> Proto for Group<Vec<T>, TAG>
+where
+    T: prost::Message + IsMessage + Default,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        let mut inner = T::default();
+
+        generic::required::decode(|| {
+            Group::<T, TAG>::from_mut(&mut inner).merge_self(wire_type, buf, ctx)
+        })?;
+
+        self.0.push(inner);
+
+        Ok(())
+    }
+}
+
+/// Adapter used to access `#[autoproto(required)]` fields. Unlike other fields, these are encoded
+/// even if they have the default value, since a proto2 message without one of its `required`
+/// fields fails to decode.
+#[repr(transparent)]
+pub struct Required<T>(pub T);
+
+impl<T> Required<T> {
+    pub fn from_ref(v: &T) -> &Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+
+    pub fn from_mut(v: &mut T) -> &mut Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+}
+
+impl<T> ProtoEncode for Required<T>
+where
+    T: ProtoEncode,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        self.0.encode_as_field_explicit(tag, buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        self.0.encoded_len_as_field_explicit(tag)
+    }
+}
+
+impl<T> Proto for Required<T>
+where
+    T: Proto,
+{
+    fn merge_self(
+        &mut self,
+        wire_type: WireType,
+        buf: &mut dyn bytes::Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), prost::DecodeError> {
+        self.0.merge_self(wire_type, buf, ctx)
+    }
+}
+
+/// Adapter used to access `#[autoproto(skip_default)]` fields, which aren't encoded if they have
+/// the default value.
+#[repr(transparent)]
+pub struct SkipDefault<T>(pub T);
+
+impl<T> SkipDefault<T> {
+    pub fn from_ref(v: &T) -> &Self {
+        // Safe due to `repr(transparent)`
+        unsafe { std::mem::transmute(v) }
+    }
+}
+
+impl<T> ProtoEncode for SkipDefault<T>
+where
+    T: ProtoEncode + IsDefault,
+{
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        if !self.0.is_default() {
+            self.0.encode_as_field(tag, buf)
+        }
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        if self.0.is_default() {
+            0
+        } else {
+            self.0.encoded_len_as_field(tag)
+        }
+    }
+
+    fn encode_as_field_explicit(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        self.0.encode_as_field_explicit(tag, buf)
+    }
+
+    fn encoded_len_as_field_explicit(&self, tag: NonZeroU32) -> usize {
+        self.0.encoded_len_as_field_explicit(tag)
+    }
+}
+
 impl<T> ProtoEncode for Option<T>
 where
     T: ProtoEncode,
 {
+    // `Some` is always encoded, even if the inner value is the default, so that it decodes as
+    // `Some` again.
     fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
         match self {
             None => {}
-            Some(v) => v.encode_as_field(tag, buf),
+            Some(v) => v.encode_as_field_explicit(tag, buf),
         }
     }
 
     fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
         match self {
             None => 0,
-            Some(v) => v.encoded_len_as_field(tag),
+            Some(v) => v.encoded_len_as_field_explicit(tag),
         }
     }
 }
@@ -235,13 +858,9 @@ where
         buf: &mut dyn bytes::Buf,
         ctx: DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        let mut cur = self.take().unwrap_or_default();
-
-        cur.merge_self(wire_type, buf, ctx)?;
-
-        *self = Some(cur);
-
-        Ok(())
+        // Merged in place, since required fields are tracked by the address of the message.
+        self.get_or_insert_with(Default::default)
+            .merge_self(wire_type, buf, ctx)
     }
 }
 
@@ -443,6 +1062,12 @@ pub trait ProtoStruct: IsMessage {
 
 pub trait ProtoStructMut: ProtoStruct {
     fn field_mut(&mut self, tag: NonZeroU32) -> Option<&mut (dyn Proto + '_)>;
+
+    /// Where to store fields with tags that `field_mut` doesn't recognise. If this returns `None`,
+    /// unknown fields are skipped.
+    fn unknown_fields_mut(&mut self) -> Option<&mut UnknownFields> {
+        None
+    }
 }
 
 pub trait ProtoScalar: IsDefault + Proto + Clone + Default + Sized {
@@ -478,7 +1103,21 @@ where
         mut buf: &mut dyn bytes::Buf,
         ctx: DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        prost::encoding::message::merge(wire_type, self, &mut buf, ctx)
+        if T::REQUIRED_FIELDS.is_empty() {
+            return prost::encoding::message::merge(wire_type, self, &mut buf, ctx);
+        }
+
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        let mut seen = vec![false; T::REQUIRED_FIELDS.len()];
+
+        prost::encoding::merge_loop(self, &mut buf, ctx, |this, buf, ctx| {
+            let (tag, wire_type) = prost::encoding::decode_key(buf)?;
+            generic::required::mark(T::REQUIRED_FIELDS, &mut seen, tag);
+            this.merge_field(tag, wire_type, buf, ctx)
+        })?;
+
+        generic::required::record(self, T::REQUIRED_FIELDS, seen)
     }
 }
 
@@ -576,6 +1215,17 @@ impl Fixed {
         }
     }
 
+    pub fn proto_name(&self) -> &'static str {
+        match self {
+            Self::Float => "float",
+            Self::Double => "double",
+            Self::Fixed32 => "fixed32",
+            Self::Fixed64 => "fixed64",
+            Self::SFixed32 => "sfixed32",
+            Self::SFixed64 => "sfixed64",
+        }
+    }
+
     fn width(&self) -> usize {
         match self {
             Self::Float | Self::Fixed32 | Self::SFixed32 => 4,
@@ -643,6 +1293,18 @@ impl Varint {
         }
     }
 
+    pub fn proto_name(&self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::I32 => "int32",
+            Self::I64 => "int64",
+            Self::SI32 => "sint32",
+            Self::SI64 => "sint64",
+            Self::U32 => "uint32",
+            Self::U64 => "uint64",
+        }
+    }
+
     fn width(&self, value: Value) -> usize {
         prost::encoding::encoded_len_varint(self.make_u64_varint(value))
     }
@@ -760,6 +1422,38 @@ where
         }
     }
 
+    /// Encode the key and value, even if the value is the default. Elements of unpacked repeated
+    /// fields must always be encoded.
+    fn encode_value(&self, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut) {
+        match E::ENCODING.kind {
+            ScalarEncodingKind::Varint(varint) => {
+                prost::encoding::encode_key(tag.get(), WireType::Varint, &mut buf);
+                prost::encoding::encode_varint(
+                    varint
+                        .unwrap_or(T::DEFAULT_VARINT)
+                        .make_u64_varint(self.0.to_value()),
+                    &mut buf,
+                )
+            }
+            ScalarEncodingKind::Fixed(fixed) => {
+                let fixed = fixed.unwrap_or(T::DEFAULT_FIXED);
+
+                prost::encoding::encode_key(tag.get(), fixed.into(), &mut buf);
+
+                fixed.write(self.0.to_value(), buf)
+            }
+        }
+    }
+
+    fn encoded_len_value(&self, tag: NonZeroU32) -> usize {
+        match E::ENCODING.kind {
+            ScalarEncodingKind::Varint(varint) => varint
+                .unwrap_or(T::DEFAULT_VARINT)
+                .encoded_len(tag, self.0.to_value()),
+            ScalarEncodingKind::Fixed(fixed) => fixed.unwrap_or(T::DEFAULT_FIXED).encoded_len(tag),
+        }
+    }
+
     pub fn encode_as_field_repeated<I, B>(iter: I, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut)
     where
         I: ExactSizeIterator<Item = B> + Clone,
@@ -792,7 +1486,7 @@ where
             }
         } else {
             for i in iter {
-                i.borrow().encode_as_field(tag, buf);
+                Self::from_ref(i.borrow()).encode_value(tag, buf);
             }
         }
     }
@@ -809,7 +1503,8 @@ where
                 + prost::encoding::encoded_len_varint(len as u64)
                 + len
         } else {
-            iter.map(|i| i.borrow().encoded_len_as_field(tag)).sum()
+            iter.map(|i| Self::from_ref(i.borrow()).encoded_len_value(tag))
+                .sum()
         }
     }
 }
@@ -902,7 +1597,7 @@ where
             }
             _ => {
                 let mut inner = Self::default();
-                inner.merge_self(expected_wire_type, buf, ctx)?;
+                inner.merge_self(wire_type, buf, ctx)?;
 
                 values.extend(iter::once(inner));
 
@@ -930,43 +1625,27 @@ where
     T: ProtoScalar,
     E: Encoding,
 {
-    fn encode_as_field(&self, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut) {
-        if !self.0.is_default() {
-            match E::ENCODING.kind {
-                ScalarEncodingKind::Varint(varint) => {
-                    prost::encoding::encode_key(tag.get(), WireType::Varint, &mut buf);
-                    prost::encoding::encode_varint(
-                        varint
-                            .unwrap_or(T::DEFAULT_VARINT)
-                            .make_u64_varint(self.0.to_value()),
-                        &mut buf,
-                    )
-                }
-                ScalarEncodingKind::Fixed(fixed) => {
-                    let fixed = fixed.unwrap_or(T::DEFAULT_FIXED);
-
-                    prost::encoding::encode_key(tag.get(), fixed.into(), &mut buf);
-
-                    fixed.write(self.0.to_value(), buf)
-                }
-            }
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        if !self.is_default() {
+            self.encode_value(tag, buf)
         }
     }
 
     fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
-        if self.0.is_default() {
+        if self.is_default() {
             0
         } else {
-            match E::ENCODING.kind {
-                ScalarEncodingKind::Varint(varint) => varint
-                    .unwrap_or(T::DEFAULT_VARINT)
-                    .encoded_len(tag, self.0.to_value()),
-                ScalarEncodingKind::Fixed(fixed) => {
-                    fixed.unwrap_or(T::DEFAULT_FIXED).encoded_len(tag)
-                }
-            }
+            self.encoded_len_value(tag)
         }
     }
+
+    fn encode_as_field_explicit(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        self.encode_value(tag, buf)
+    }
+
+    fn encoded_len_as_field_explicit(&self, tag: NonZeroU32) -> usize {
+        self.encoded_len_value(tag)
+    }
 }
 
 impl<T, E> Proto for MappedInt<T, E>
@@ -995,6 +1674,10 @@ where
                 let fixed = fixed.unwrap_or(T::DEFAULT_FIXED);
                 prost::encoding::check_wire_type(fixed.into(), wire_type)?;
 
+                if buf.remaining() < fixed.width() {
+                    return Err(prost::DecodeError::new("buffer underflow"));
+                }
+
                 T::from_value(fixed.read(&mut buf))
                     .ok_or_else(|| prost::DecodeError::new("Type mismatch"))?
             }
@@ -1022,7 +1705,7 @@ impl_protoscalar!(
     bool,
     (|v: Value| v.bool(), |v: bool| Value::Int((v as u64).into())),
     Fixed::Fixed32,
-    Varint::U32
+    Varint::Bool
 );
 impl_protoscalar!(u8, Fixed::Fixed32, Varint::U32);
 impl_protoscalar!(u16, Fixed::Fixed32, Varint::U32);
@@ -1150,7 +1833,12 @@ impl_proto_for_bytes!(impl Proto for [u8]);
 impl_proto_for_bytes!(impl Proto for Box<[u8]>);
 impl_proto_for_bytes!(impl Proto for bytes::BytesMut);
 
-impl<T> IsMessage for Box<T> where T: IsMessage {}
+impl<T> IsMessage for Box<T>
+where
+    T: IsMessage,
+{
+    const REQUIRED_FIELDS: &'static [(u32, &'static str)] = T::REQUIRED_FIELDS;
+}
 
 impl ProtoEncode for bytes::Bytes {
     fn encode_as_field(&self, tag: NonZeroU32, mut buf: &mut dyn bytes::BufMut) {
@@ -1166,18 +1854,22 @@ impl ProtoEncode for bytes::Bytes {
     }
 }
 
+impl IsDefault for bytes::Bytes {
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
 impl Proto for bytes::Bytes {
     fn merge_self(
         &mut self,
         wire_type: WireType,
         buf: &mut dyn bytes::Buf,
-        ctx: DecodeContext,
+        _ctx: DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        let mut bytes = bytes::BytesMut::from(&**self);
-
-        bytes.merge_self(wire_type, buf, ctx)?;
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
 
-        *self = bytes.freeze();
+        *self = decode_length_delimited(buf)?;
 
         Ok(())
     }
@@ -1207,6 +1899,28 @@ impl ProtoEncode for str {
     }
 }
 
+/// Borrowed strings and bytes, as decoded by `borrow::decode`, are encoded in the same way as
+/// their owned equivalents.
+impl ProtoEncode for &'_ str {
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        str::encode_as_field(self, tag, buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        str::encoded_len_as_field(self, tag)
+    }
+}
+
+impl ProtoEncode for &'_ [u8] {
+    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+        <[u8]>::encode_as_field(self, tag, buf)
+    }
+
+    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+        <[u8]>::encoded_len_as_field(self, tag)
+    }
+}
+
 #[cfg(feature = "beef")]
 impl<U> ProtoEncode for beef::generic::Cow<'_, str, U>
 where
@@ -1230,18 +1944,17 @@ where
 {
     fn merge_self(
         &mut self,
-        _wire_type: prost::encoding::WireType,
-        mut buf: &mut dyn prost::bytes::Buf,
+        wire_type: prost::encoding::WireType,
+        buf: &mut dyn prost::bytes::Buf,
         _ctx: prost::encoding::DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        let len = prost::encoding::decode_varint(&mut buf)? as usize;
-        // There is currently no good way to extend a string from a UTF8 byte iterator,
-        // so we must allocate.
-        let bytes = buf.copy_to_bytes(len);
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        let bytes = decode_length_delimited(buf)?;
         let validated =
             std::str::from_utf8(&bytes).map_err(|e| prost::DecodeError::new(e.to_string()))?;
 
-        self.push_str(validated);
+        *self = Self::owned(validated.to_owned());
 
         Ok(())
     }
@@ -1250,17 +1963,18 @@ where
 impl Proto for String {
     fn merge_self(
         &mut self,
-        _wire_type: prost::encoding::WireType,
-        mut buf: &mut dyn prost::bytes::Buf,
+        wire_type: prost::encoding::WireType,
+        buf: &mut dyn prost::bytes::Buf,
         _ctx: prost::encoding::DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        let len = prost::encoding::decode_varint(&mut buf)? as usize;
-        // There is currently no good way to extend a string from a UTF8 byte iterator,
-        // so we must allocate.
-        let bytes = buf.copy_to_bytes(len);
+        prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
+
+        let bytes = decode_length_delimited(buf)?;
         let validated =
             std::str::from_utf8(&bytes).map_err(|e| prost::DecodeError::new(e.to_string()))?;
 
+        // The last value wins when a field appears more than once.
+        self.clear();
         self.push_str(validated);
 
         Ok(())
@@ -1319,7 +2033,7 @@ impl Proto for OsString {
     ) -> Result<(), prost::DecodeError> {
         use std::os::unix::ffi::OsStrExt;
 
-        let mut bytes = self.as_bytes().to_owned();
+        let mut bytes = Box::<[u8]>::default();
 
         bytes.merge_self(wire_type, buf, ctx)?;
 
@@ -1374,11 +2088,11 @@ impl Proto for PathBuf {
         buf: &mut dyn bytes::Buf,
         ctx: DecodeContext,
     ) -> Result<(), prost::DecodeError> {
-        let mut bytes = self.as_os_str().to_owned();
+        let mut os_string = OsString::new();
 
-        bytes.merge_self(wire_type, buf, ctx)?;
+        os_string.merge_self(wire_type, buf, ctx)?;
 
-        *self = bytes.into();
+        *self = os_string.into();
 
         Ok(())
     }
@@ -1420,3 +2134,256 @@ mod uuid_impl {
         }
     }
 }
+
+// Times and durations are encoded as the `google.protobuf.Timestamp` and
+// `google.protobuf.Duration` messages, by converting to and from their definitions in
+// `prost_types`.
+mod time_impl {
+    use crate::{
+        descriptor::Type, json::JsonError, text::TextError, text::TextValue, FileDescriptor,
+        ProtoFile,
+    };
+    use prost::{
+        bytes,
+        encoding::{DecodeContext, WireType},
+    };
+    use prost_types::{Duration, Timestamp};
+    use std::{convert::TryFrom, num::NonZeroU32, ops::RangeInclusive, time::SystemTime};
+
+    trait WellKnownType: Sized {
+        type Message: crate::Proto
+            + crate::ToProtoSpec
+            + crate::ProtoDescriptor
+            + crate::ProtoJson
+            + crate::TextFormat
+            + Default
+            + PartialEq;
+
+        fn to_message(&self) -> Self::Message;
+
+        /// Convert back from the message, or return a description of why it can't be represented
+        /// by this type.
+        fn from_message(message: Self::Message) -> Result<Self, String>;
+    }
+
+    macro_rules! impl_well_known_type {
+        ($($t:ty),*) => {
+            $(
+                impl crate::ProtoEncode for $t {
+                    fn encode_as_field(&self, tag: NonZeroU32, buf: &mut dyn bytes::BufMut) {
+                        self.to_message().encode_as_field(tag, buf)
+                    }
+
+                    fn encoded_len_as_field(&self, tag: NonZeroU32) -> usize {
+                        self.to_message().encoded_len_as_field(tag)
+                    }
+                }
+
+                impl crate::Proto for $t {
+                    fn merge_self(
+                        &mut self,
+                        wire_type: WireType,
+                        buf: &mut dyn bytes::Buf,
+                        ctx: DecodeContext,
+                    ) -> Result<(), prost::DecodeError> {
+                        let mut message = self.to_message();
+
+                        message.merge_self(wire_type, buf, ctx)?;
+
+                        *self = <$t>::from_message(message).map_err(prost::DecodeError::new)?;
+
+                        Ok(())
+                    }
+                }
+
+                impl crate::IsDefault for $t {
+                    fn is_default(&self) -> bool {
+                        self.to_message() == Default::default()
+                    }
+                }
+
+                impl crate::ToProtoSpec for $t {
+                    const SHAPE: crate::spec::FieldShape =
+                        <<$t as WellKnownType>::Message as crate::ToProtoSpec>::SHAPE;
+
+                    fn proto_name() -> String {
+                        <<$t as WellKnownType>::Message as crate::ToProtoSpec>::proto_name()
+                    }
+
+                    fn register(file: &mut ProtoFile) {
+                        <<$t as WellKnownType>::Message as crate::ToProtoSpec>::register(file)
+                    }
+                }
+
+                impl crate::ProtoDescriptor for $t {
+                    fn descriptor_type(file: &FileDescriptor) -> (Type, Option<String>) {
+                        <<$t as WellKnownType>::Message as crate::ProtoDescriptor>::descriptor_type(
+                            file,
+                        )
+                    }
+
+                    fn register_descriptor(file: &mut FileDescriptor) {
+                        <<$t as WellKnownType>::Message as crate::ProtoDescriptor>::register_descriptor(
+                            file,
+                        )
+                    }
+                }
+
+                impl crate::ProtoJson for $t {
+                    fn to_json_value(&self) -> serde_json::Value {
+                        self.to_message().to_json_value()
+                    }
+
+                    fn merge_json_value(&mut self, value: serde_json::Value) -> Result<(), JsonError> {
+                        let mut message = self.to_message();
+
+                        message.merge_json_value(value)?;
+
+                        *self = <$t>::from_message(message).map_err(JsonError::new)?;
+
+                        Ok(())
+                    }
+                }
+
+                impl crate::TextFormat for $t {
+                    fn to_text_value(&self) -> TextValue {
+                        self.to_message().to_text_value()
+                    }
+
+                    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+                        let mut message = self.to_message();
+
+                        message.merge_text_value(value)?;
+
+                        *self = <$t>::from_message(message).map_err(TextError::new)?;
+
+                        Ok(())
+                    }
+                }
+            )*
+        };
+    }
+
+    /// The seconds of a valid `Timestamp`, from 0001-01-01T00:00:00Z to 9999-12-31T23:59:59Z.
+    const TIMESTAMP_SECONDS: RangeInclusive<i64> = -62_135_596_800..=253_402_300_799;
+    /// The seconds of a valid `Duration`, which is at most about 10,000 years either way.
+    const DURATION_SECONDS: RangeInclusive<i64> = -315_576_000_000..=315_576_000_000;
+    const NANOS_PER_SECOND: i32 = 1_000_000_000;
+
+    /// Check that `message` is a valid `Timestamp` before it's converted, since the conversions in
+    /// `prost-types` can overflow on extreme values.
+    fn check_timestamp(message: &Timestamp) -> Result<(), String> {
+        if TIMESTAMP_SECONDS.contains(&message.seconds)
+            && (0..NANOS_PER_SECOND).contains(&message.nanos)
+        {
+            Ok(())
+        } else {
+            Err(format!("{:?} is out of the range of `Timestamp`", message))
+        }
+    }
+
+    /// Check that `message` is a valid `Duration`, whose `nanos` has the same sign as `seconds`.
+    fn check_duration(message: &Duration) -> Result<(), String> {
+        let same_sign = message.seconds == 0
+            || message.nanos == 0
+            || (message.seconds < 0) == (message.nanos < 0);
+
+        if DURATION_SECONDS.contains(&message.seconds)
+            && message.nanos.abs() < NANOS_PER_SECOND
+            && same_sign
+        {
+            Ok(())
+        } else {
+            Err(format!("{:?} is out of the range of `Duration`", message))
+        }
+    }
+
+    impl WellKnownType for SystemTime {
+        type Message = Timestamp;
+
+        fn to_message(&self) -> Timestamp {
+            Timestamp::from(*self)
+        }
+
+        fn from_message(message: Timestamp) -> Result<Self, String> {
+            check_timestamp(&message)?;
+
+            SystemTime::try_from(message).map_err(|e| e.to_string())
+        }
+    }
+
+    impl WellKnownType for std::time::Duration {
+        type Message = Duration;
+
+        fn to_message(&self) -> Duration {
+            Duration::from(*self)
+        }
+
+        fn from_message(message: Duration) -> Result<Self, String> {
+            check_duration(&message)?;
+
+            std::time::Duration::try_from(message).map_err(|_| {
+                "A negative `Duration` can't be represented by `std::time::Duration`".into()
+            })
+        }
+    }
+
+    impl crate::IsMessage for Timestamp {}
+    impl crate::IsMessage for Duration {}
+
+    impl_well_known_type!(SystemTime, std::time::Duration);
+
+    #[cfg(feature = "chrono")]
+    impl WellKnownType for chrono::DateTime<chrono::Utc> {
+        type Message = Timestamp;
+
+        fn to_message(&self) -> Timestamp {
+            Timestamp {
+                seconds: self.timestamp(),
+                // Leap seconds are represented by `chrono` as nanoseconds past the end of the second
+                // before them, which `Timestamp` can't represent.
+                nanos: self.timestamp_subsec_nanos().min(999_999_999) as i32,
+            }
+        }
+
+        fn from_message(message: Timestamp) -> Result<Self, String> {
+            check_timestamp(&message)?;
+
+            chrono::TimeZone::timestamp_opt(&chrono::Utc, message.seconds, message.nanos as u32)
+                .single()
+                .ok_or_else(|| format!("{:?} is out of the range of `chrono::DateTime`", message))
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    impl_well_known_type!(chrono::DateTime<chrono::Utc>);
+
+    #[cfg(feature = "time")]
+    impl WellKnownType for time::OffsetDateTime {
+        type Message = Timestamp;
+
+        fn to_message(&self) -> Timestamp {
+            Timestamp {
+                seconds: self.unix_timestamp(),
+                nanos: self.nanosecond() as i32,
+            }
+        }
+
+        fn from_message(message: Timestamp) -> Result<Self, String> {
+            check_timestamp(&message)?;
+
+            time::OffsetDateTime::from_unix_timestamp_nanos(
+                i128::from(message.seconds) * 1_000_000_000 + i128::from(message.nanos),
+            )
+            .map_err(|_| {
+                format!(
+                    "{:?} is out of the range of `time::OffsetDateTime`",
+                    message
+                )
+            })
+        }
+    }
+
+    #[cfg(feature = "time")]
+    impl_well_known_type!(time::OffsetDateTime);
+}
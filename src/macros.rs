@@ -30,22 +30,13 @@ macro_rules! impl_proto_for_bytes {
             fn merge_self(
                 &mut self,
                 wire_type: $crate::prost::encoding::WireType,
-                mut buf: &mut dyn $crate::prost::bytes::Buf,
+                buf: &mut dyn $crate::prost::bytes::Buf,
                 _ctx: $crate::prost::encoding::DecodeContext,
             ) -> Result<(), $crate::prost::DecodeError> {
-                use crate::bytes::Buf as _;
-
                 $crate::prost::encoding::check_wire_type(WireType::LengthDelimited, wire_type)?;
-                let len = $crate::prost::encoding::decode_varint(&mut buf)?;
-                if len > buf.remaining() as u64 {
-                    return Err($crate::prost::DecodeError::new("buffer underflow"));
-                }
+                let bytes = $crate::decode_length_delimited(buf)?;
 
-                <&mut &mut dyn $crate::bytes::Buf as $crate::bytes::Buf>::take(
-                    &mut buf,
-                    len as usize,
-                ).copy_to_slice(<Self as ::core::convert::AsMut<[u8]>>::as_mut(self));
-                Ok(())
+                <Self as $crate::ReplaceBytes>::replace_bytes(self, &bytes)
             }
         }
 
@@ -56,6 +47,22 @@ macro_rules! impl_proto_for_bytes {
                 <Self as ::core::convert::AsRef<[u8]>>::as_ref(self).is_empty()
             }
         }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ToProtoSpec for $t
+        $(where $($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            const SHAPE: $crate::spec::FieldShape =
+                $crate::spec::FieldShape::Single($crate::spec::FieldType::Bytes);
+
+            fn proto_name() -> ::std::string::String {
+                "bytes".into()
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ProtoDescriptor for $t
+        $(where $($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+        }
     }
 }
 
@@ -135,6 +142,14 @@ macro_rules! impl_protoscalar {
             fn encoded_len_as_field(&self, tag: ::core::num::NonZeroU32) -> usize {
                 MappedInt::<Self>(*self, ::core::default::Default::default()).encoded_len_as_field(tag)
             }
+
+            fn encode_as_field_explicit(&self, tag: ::core::num::NonZeroU32, buf: &mut dyn $crate::prost::bytes::BufMut) {
+                MappedInt::<Self>(*self, ::core::default::Default::default()).encode_as_field_explicit(tag, buf)
+            }
+
+            fn encoded_len_as_field_explicit(&self, tag: ::core::num::NonZeroU32) -> usize {
+                MappedInt::<Self>(*self, ::core::default::Default::default()).encoded_len_as_field_explicit(tag)
+            }
         }
 
         #[allow(clippy::all)]
@@ -187,6 +202,65 @@ macro_rules! impl_protoscalar {
             }
         }
 
+        #[allow(clippy::all)]
+        impl $crate::ToProtoSpec for $t {
+            const SHAPE: $crate::spec::FieldShape =
+                <MappedInt::<Self> as $crate::ToProtoSpec>::SHAPE;
+
+            fn proto_name() -> ::std::string::String {
+                <MappedInt::<Self> as $crate::ToProtoSpec>::proto_name()
+            }
+        }
+
+        #[allow(clippy::all)]
+        impl $crate::ProtoDescriptor for $t {}
+
+        #[allow(clippy::all)]
+        impl $crate::ProtoJson for $t {
+            fn to_json_value(&self) -> $crate::serde_json::Value {
+                MappedInt::<Self>(*self, ::core::default::Default::default()).to_json_value()
+            }
+
+            fn is_json_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: $crate::serde_json::Value,
+            ) -> Result<(), $crate::json::JsonError> {
+                let mut mapped = MappedInt::<Self>(*self, ::core::default::Default::default());
+                mapped.merge_json_value(value)?;
+
+                *self = mapped.0;
+
+                Ok(())
+            }
+        }
+
+        #[allow(clippy::all)]
+        impl $crate::TextFormat for $t {
+            fn to_text_value(&self) -> $crate::text::TextValue {
+                MappedInt::<Self>(*self, ::core::default::Default::default()).to_text_value()
+            }
+
+            fn is_text_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: $crate::text::TextValue,
+            ) -> Result<(), $crate::text::TextError> {
+                let mut mapped = MappedInt::<Self>(*self, ::core::default::Default::default());
+                mapped.merge_text_value(value)?;
+
+                *self = mapped.0;
+
+                Ok(())
+            }
+        }
+
         #[allow(clippy::all)]
         impl $crate::Proto for $t {
             fn merge_self(
@@ -361,6 +435,150 @@ macro_rules! impl_proto_for_protorepeated {
                 )
             }
         }
+
+        impl<__K, __P $($(, $pname)* $($(, const $cname : $cty)*)?)?>
+            $crate::encoding::ProtoWithEncoding<__K, __P> for $t
+        where
+            <Self as $crate::ProtoRepeated>::Item: $crate::ProtoScalar,
+            __K: $crate::encoding::EncodingKind,
+            __P: $crate::encoding::Packing,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            type Described = ::std::vec::Vec<
+                $crate::MappedInt<
+                    <Self as $crate::ProtoRepeated>::Item,
+                    $crate::encoding::FieldEncoding<<Self as $crate::ProtoRepeated>::Item, __K, __P>,
+                >,
+            >;
+
+            fn encode_with(&self, tag: ::core::num::NonZeroU32, buf: &mut dyn $crate::prost::bytes::BufMut) {
+                $crate::encoding::encode_repeated_with::<_, __K, __P, _>(
+                    <Self as $crate::ProtoRepeated>::iter(self),
+                    tag,
+                    buf,
+                )
+            }
+
+            fn encoded_len_with(&self, tag: ::core::num::NonZeroU32) -> usize {
+                $crate::encoding::encoded_len_repeated_with::<_, __K, __P, _>(
+                    <Self as $crate::ProtoRepeated>::iter(self),
+                    tag,
+                )
+            }
+
+            fn merge_with(
+                &mut self,
+                wire_type: WireType,
+                buf: &mut dyn $crate::prost::bytes::Buf,
+                ctx: DecodeContext,
+            ) -> Result<(), $crate::prost::DecodeError> {
+                $crate::encoding::merge_repeated_with::<_, __K, __P, _>(self, wire_type, buf, ctx)
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ToProtoSpec for $t
+        where
+            Self: $crate::ProtoRepeated,
+            <Self as $crate::ProtoRepeated>::Item: $crate::ToProtoSpec,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            const SHAPE: $crate::spec::FieldShape = $crate::spec::FieldShape::Repeated(
+                <<Self as $crate::ProtoRepeated>::Item as $crate::ToProtoSpec>::SHAPE.item(),
+            );
+
+            fn proto_name() -> ::std::string::String {
+                ::std::format!(
+                    "repeated {}",
+                    <<Self as $crate::ProtoRepeated>::Item as $crate::ToProtoSpec>::proto_name(),
+                )
+            }
+
+            fn register(file: &mut $crate::ProtoFile) {
+                <<Self as $crate::ProtoRepeated>::Item as $crate::ToProtoSpec>::register(file)
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ProtoDescriptor for $t
+        where
+            Self: $crate::ProtoRepeated,
+            <Self as $crate::ProtoRepeated>::Item: $crate::ProtoDescriptor,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn descriptor_type(
+                file: &$crate::FileDescriptor,
+            ) -> ($crate::descriptor::Type, ::core::option::Option<::std::string::String>) {
+                <<Self as $crate::ProtoRepeated>::Item as $crate::ProtoDescriptor>::descriptor_type(
+                    file,
+                )
+            }
+
+            fn describe_field(
+                file: &$crate::FileDescriptor,
+                message: &mut $crate::prost_types::DescriptorProto,
+                name: &str,
+                tag: ::core::num::NonZeroU32,
+            ) {
+                let (ty, type_name) = <Self as $crate::ProtoDescriptor>::descriptor_type(file);
+
+                message.field.push($crate::descriptor::field(
+                    name,
+                    tag,
+                    $crate::descriptor::Label::Repeated,
+                    ty,
+                    type_name,
+                ));
+            }
+
+            fn register_descriptor(file: &mut $crate::FileDescriptor) {
+                <<Self as $crate::ProtoRepeated>::Item as $crate::ProtoDescriptor>::register_descriptor(
+                    file,
+                )
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ProtoJson for $t
+        where
+            Self: $crate::ProtoRepeated,
+            <Self as $crate::ProtoRepeated>::Item: $crate::ProtoJson + ::core::default::Default,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn to_json_value(&self) -> $crate::serde_json::Value {
+                $crate::json::repeated_to_json(<Self as $crate::ProtoRepeated>::iter(self))
+            }
+
+            fn is_json_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: $crate::serde_json::Value,
+            ) -> Result<(), $crate::json::JsonError> {
+                $crate::json::merge_repeated(self, value)
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::TextFormat for $t
+        where
+            Self: $crate::ProtoRepeated,
+            <Self as $crate::ProtoRepeated>::Item: $crate::TextFormat + ::core::default::Default,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn to_text_value(&self) -> $crate::text::TextValue {
+                $crate::text::repeated_to_text(<Self as $crate::ProtoRepeated>::iter(self))
+            }
+
+            fn is_text_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: $crate::text::TextValue,
+            ) -> Result<(), $crate::text::TextError> {
+                $crate::text::merge_repeated(self, value)
+            }
+        }
     };
 }
 
@@ -426,35 +644,186 @@ macro_rules! impl_proto_for_protomap {
         {
             fn merge_self(
                 &mut self,
-                _wire_type: $crate::prost::encoding::WireType,
+                wire_type: $crate::prost::encoding::WireType,
                 mut buf: &mut dyn $crate::prost::bytes::Buf,
                 ctx: $crate::prost::encoding::DecodeContext,
             ) -> Result<(), $crate::prost::DecodeError> {
+                $crate::prost::encoding::check_wire_type(
+                    $crate::prost::encoding::WireType::LengthDelimited,
+                    wire_type,
+                )?;
+
                 let mut key = Default::default();
                 let mut val = Default::default();
 
-                $crate::prost::encoding::merge_loop(
-                    &mut (&mut key, &mut val),
-                    &mut buf,
-                    ctx,
-                    |(key, val), buf, ctx| {
-                        let (tag, wire_type) = $crate::prost::encoding::decode_key(buf)?;
-                        match tag {
-                            1 => <
-                                <Self as $crate::ProtoMap>::Key as $crate::Proto
-                            >::merge_self(key, wire_type, buf, ctx),
-                            2 => <
-                                <Self as $crate::ProtoMap>::Value as $crate::Proto
-                            >::merge_self(val, wire_type, buf, ctx),
-                            _ => $crate::prost::encoding::skip_field(wire_type, tag, buf, ctx),
-                        }
-                    },
-                )?;
+                $crate::generic::required::decode(|| {
+                    $crate::prost::encoding::merge_loop(
+                        &mut (&mut key, &mut val),
+                        &mut buf,
+                        ctx,
+                        |(key, val), buf, ctx| {
+                            let (tag, wire_type) = $crate::prost::encoding::decode_key(buf)?;
+                            match tag {
+                                1 => <
+                                    <Self as $crate::ProtoMap>::Key as $crate::Proto
+                                >::merge_self(key, wire_type, buf, ctx),
+                                2 => <
+                                    <Self as $crate::ProtoMap>::Value as $crate::Proto
+                                >::merge_self(val, wire_type, buf, ctx),
+                                _ => $crate::prost::encoding::skip_field(wire_type, tag, buf, ctx),
+                            }
+                        },
+                    )
+                })?;
+
+                <Self as $crate::ProtoMap>::insert(self, key, val);
+
+                Ok(())
+            }
+        }
+
+        impl<__K, __P $($(, $pname)* $($(, const $cname : $cty)*)?)?>
+            $crate::encoding::ProtoWithEncoding<__K, __P> for $t
+        where
+            <Self as $crate::ProtoMap>::Key: ::core::default::Default + ::core::cmp::Ord,
+            <Self as $crate::ProtoMap>::Value: $crate::ProtoScalar,
+            __K: $crate::encoding::EncodingKind,
+            __P: $crate::encoding::Packing,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            type Described = ::std::collections::BTreeMap<
+                <Self as $crate::ProtoMap>::Key,
+                $crate::MappedInt<
+                    <Self as $crate::ProtoMap>::Value,
+                    $crate::encoding::FieldEncoding<<Self as $crate::ProtoMap>::Value, __K, __P>,
+                >,
+            >;
+
+            fn encode_with(&self, tag: ::core::num::NonZeroU32, buf: &mut dyn $crate::prost::bytes::BufMut) {
+                $crate::encoding::encode_map_with::<_, _, __K, __P, _>(
+                    <Self as $crate::ProtoMap>::iter(self),
+                    tag,
+                    buf,
+                )
+            }
+
+            fn encoded_len_with(&self, tag: ::core::num::NonZeroU32) -> usize {
+                $crate::encoding::encoded_len_map_with::<_, _, __K, __P, _>(
+                    <Self as $crate::ProtoMap>::iter(self),
+                    tag,
+                )
+            }
+
+            fn merge_with(
+                &mut self,
+                wire_type: $crate::prost::encoding::WireType,
+                buf: &mut dyn $crate::prost::bytes::Buf,
+                ctx: $crate::prost::encoding::DecodeContext,
+            ) -> Result<(), $crate::prost::DecodeError> {
+                let (key, val) =
+                    $crate::encoding::merge_map_entry_with::<_, _, __K, __P>(wire_type, buf, ctx)?;
 
                 <Self as $crate::ProtoMap>::insert(self, key, val);
 
                 Ok(())
             }
         }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ToProtoSpec for $t
+        where
+            Self: $crate::ProtoMap,
+            <Self as $crate::ProtoMap>::Key: $crate::ToProtoSpec,
+            <Self as $crate::ProtoMap>::Value: $crate::ToProtoSpec,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            const SHAPE: $crate::spec::FieldShape = $crate::spec::FieldShape::Map(
+                <<Self as $crate::ProtoMap>::Key as $crate::ToProtoSpec>::SHAPE.item(),
+                <<Self as $crate::ProtoMap>::Value as $crate::ToProtoSpec>::SHAPE.item(),
+            );
+
+            fn proto_name() -> ::std::string::String {
+                ::std::format!(
+                    "map<{}, {}>",
+                    <<Self as $crate::ProtoMap>::Key as $crate::ToProtoSpec>::proto_name(),
+                    <<Self as $crate::ProtoMap>::Value as $crate::ToProtoSpec>::proto_name(),
+                )
+            }
+
+            fn register(file: &mut $crate::ProtoFile) {
+                <<Self as $crate::ProtoMap>::Key as $crate::ToProtoSpec>::register(file);
+                <<Self as $crate::ProtoMap>::Value as $crate::ToProtoSpec>::register(file);
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ProtoDescriptor for $t
+        where
+            Self: $crate::ProtoMap,
+            <Self as $crate::ProtoMap>::Key: $crate::ProtoDescriptor,
+            <Self as $crate::ProtoMap>::Value: $crate::ProtoDescriptor,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn describe_field(
+                file: &$crate::FileDescriptor,
+                message: &mut $crate::prost_types::DescriptorProto,
+                name: &str,
+                tag: ::core::num::NonZeroU32,
+            ) {
+                $crate::descriptor::describe_map_field::<
+                    <Self as $crate::ProtoMap>::Key,
+                    <Self as $crate::ProtoMap>::Value,
+                >(file, message, name, tag)
+            }
+
+            fn register_descriptor(file: &mut $crate::FileDescriptor) {
+                <<Self as $crate::ProtoMap>::Key as $crate::ProtoDescriptor>::register_descriptor(file);
+                <<Self as $crate::ProtoMap>::Value as $crate::ProtoDescriptor>::register_descriptor(file);
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::ProtoJson for $t
+        where
+            Self: $crate::ProtoMap,
+            <Self as $crate::ProtoMap>::Key: $crate::ProtoJson + ::core::default::Default,
+            <Self as $crate::ProtoMap>::Value: $crate::ProtoJson + ::core::default::Default,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn to_json_value(&self) -> $crate::serde_json::Value {
+                $crate::json::map_to_json(<Self as $crate::ProtoMap>::iter(self))
+            }
+
+            fn is_json_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_json_value(
+                &mut self,
+                value: $crate::serde_json::Value,
+            ) -> Result<(), $crate::json::JsonError> {
+                $crate::json::merge_map(self, value)
+            }
+        }
+
+        impl$(<$($pname,)* $($(const $cname : $cty),*)?>)? $crate::TextFormat for $t
+        where
+            Self: $crate::ProtoMap,
+            <Self as $crate::ProtoMap>::Key: $crate::TextFormat + ::core::default::Default,
+            <Self as $crate::ProtoMap>::Value: $crate::TextFormat + ::core::default::Default,
+            $($($bounded : $bound,)* $($($lbounded : $lbound),*)?)?
+        {
+            fn to_text_value(&self) -> $crate::text::TextValue {
+                $crate::text::map_to_text(<Self as $crate::ProtoMap>::iter(self))
+            }
+
+            fn is_text_default(&self) -> bool {
+                $crate::IsDefault::is_default(self)
+            }
+
+            fn merge_text_value(
+                &mut self,
+                value: $crate::text::TextValue,
+            ) -> Result<(), $crate::text::TextError> {
+                $crate::text::merge_map(self, value)
+            }
+        }
     };
 }
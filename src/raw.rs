@@ -0,0 +1,364 @@
+//! Decoding of messages without a schema, in the same way as `protoc --decode_raw`. This is useful
+//! for looking inside opaque buffers, such as ones attached to bug reports. The field numbers and
+//! wire types are all that's known, so for length-delimited fields the possible nested message and
+//! packed repeated fields are worked out as well, and the `Display` implementation picks the most
+//! likely one. The `autoproto-inspect` binary does the same for a buffer read from stdin.
+//!
+//! ```rust
+//! use autoproto::{
+//!     prost::{encoding::WireType, Message},
+//!     raw::{decode_raw, RawValue},
+//! };
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Inner {
+//!     value: f32,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Outer {
+//!     count: i32,
+//!     name: String,
+//!     inner: Inner,
+//! }
+//!
+//! let outer = Outer {
+//!     count: 150,
+//!     name: "hi".into(),
+//!     inner: Inner { value: 1.0 },
+//! };
+//! let raw = decode_raw(&outer.encode_to_vec()).unwrap();
+//!
+//! assert_eq!(raw.fields[0].tag.get(), 1);
+//! assert_eq!(raw.fields[0].wire_type, WireType::Varint);
+//! assert!(matches!(raw.fields[0].value, RawValue::Varint(150)));
+//!
+//! assert_eq!(
+//!     raw.to_string(),
+//!     r#"1: 150  # int32/int64/uint32/uint64: 150, sint32/sint64: 75
+//! 2: "hi"
+//! 3 {
+//!   1: 0x3f800000  # fixed32/sfixed32: 1065353216, float: 1.0
+//! }
+//! "#,
+//! );
+//! ```
+
+use crate::{
+    text::{write_bytes, write_string},
+    Fixed, Value, Varint,
+};
+use prost::{
+    bytes::Buf,
+    encoding::{decode_key, decode_varint, WireType},
+    DecodeError,
+};
+use std::{fmt, num::NonZeroU32};
+
+/// The same limit on nesting as is used by `prost`.
+const RECURSION_LIMIT: u32 = 100;
+
+/// The varint encodings, in the order their interpretations are shown.
+const VARINTS: [Varint; 7] = [
+    Varint::I32,
+    Varint::I64,
+    Varint::U32,
+    Varint::U64,
+    Varint::SI32,
+    Varint::SI64,
+    Varint::Bool,
+];
+
+/// The fields of a message, in the order they were encoded.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct RawMessage {
+    pub fields: Vec<RawField>,
+}
+
+/// A single field, as it appears on the wire.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RawField {
+    pub tag: NonZeroU32,
+    pub wire_type: WireType,
+    pub value: RawValue,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RawValue {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    LengthDelimited(RawBytes),
+    Group(RawMessage),
+}
+
+/// The contents of a length-delimited field, which could be a string, bytes, a nested message or a
+/// packed repeated field.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RawBytes {
+    pub bytes: Vec<u8>,
+    /// The contents parsed as a message, if they are a valid message.
+    pub message: Option<RawMessage>,
+    /// The contents parsed as each kind of packed repeated field that they could be.
+    pub packed: Vec<Packed>,
+}
+
+/// The elements of a packed repeated field.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Packed {
+    Varint(Vec<u64>),
+    Fixed32(Vec<u32>),
+    Fixed64(Vec<u64>),
+}
+
+/// Decode a message without knowing its type.
+pub fn decode_raw(buf: &[u8]) -> Result<RawMessage, DecodeError> {
+    decode_fields(&mut &buf[..], None, 0)
+}
+
+fn decode_fields(
+    buf: &mut &[u8],
+    group: Option<u32>,
+    depth: u32,
+) -> Result<RawMessage, DecodeError> {
+    let mut fields = vec![];
+
+    while buf.has_remaining() {
+        let (tag, wire_type) = decode_key(buf)?;
+
+        let value = match wire_type {
+            WireType::Varint => RawValue::Varint(decode_varint(buf)?),
+            WireType::SixtyFourBit => {
+                if buf.remaining() < 8 {
+                    return Err(DecodeError::new("Buffer underflow"));
+                }
+
+                RawValue::Fixed64(buf.get_u64_le())
+            }
+            WireType::ThirtyTwoBit => {
+                if buf.remaining() < 4 {
+                    return Err(DecodeError::new("Buffer underflow"));
+                }
+
+                RawValue::Fixed32(buf.get_u32_le())
+            }
+            WireType::LengthDelimited => {
+                let len = decode_varint(buf)?;
+
+                if len > buf.remaining() as u64 {
+                    return Err(DecodeError::new("Buffer underflow"));
+                }
+
+                let (bytes, rest) = buf.split_at(len as usize);
+
+                *buf = rest;
+
+                RawValue::LengthDelimited(RawBytes::new(bytes, depth))
+            }
+            WireType::StartGroup => {
+                if depth >= RECURSION_LIMIT {
+                    return Err(DecodeError::new("Recursion limit reached"));
+                }
+
+                RawValue::Group(decode_fields(buf, Some(tag), depth + 1)?)
+            }
+            WireType::EndGroup => {
+                return if group == Some(tag) {
+                    Ok(RawMessage { fields })
+                } else {
+                    Err(DecodeError::new("Unexpected end group tag"))
+                };
+            }
+        };
+
+        fields.push(RawField {
+            tag: NonZeroU32::new(tag).ok_or_else(|| DecodeError::new("Invalid tag: 0"))?,
+            wire_type,
+            value,
+        });
+    }
+
+    match group {
+        Some(_) => Err(DecodeError::new("Missing end group tag")),
+        None => Ok(RawMessage { fields }),
+    }
+}
+
+impl RawBytes {
+    fn new(bytes: &[u8], depth: u32) -> Self {
+        let message = if bytes.is_empty() || depth >= RECURSION_LIMIT {
+            None
+        } else {
+            decode_fields(&mut &bytes[..], None, depth + 1).ok()
+        };
+
+        let mut packed = vec![];
+
+        if !bytes.is_empty() {
+            let mut buf = bytes;
+            let varints =
+                std::iter::from_fn(|| buf.has_remaining().then(|| decode_varint(&mut buf)))
+                    .collect::<Result<Vec<_>, _>>();
+
+            if let Ok(varints) = varints {
+                packed.push(Packed::Varint(varints));
+            }
+
+            let fixed32 = bytes.chunks_exact(4);
+
+            if fixed32.remainder().is_empty() {
+                packed.push(Packed::Fixed32(
+                    fixed32.map(|mut chunk| chunk.get_u32_le()).collect(),
+                ));
+            }
+
+            let fixed64 = bytes.chunks_exact(8);
+
+            if fixed64.remainder().is_empty() {
+                packed.push(Packed::Fixed64(
+                    fixed64.map(|mut chunk| chunk.get_u64_le()).collect(),
+                ));
+            }
+        }
+
+        Self {
+            bytes: bytes.to_vec(),
+            message,
+            packed,
+        }
+    }
+
+    /// The contents as a string, if they are UTF-8 without any unusual control characters.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.bytes)
+            .ok()
+            .filter(|s| !s.contains(|c: char| c.is_control() && !"\n\r\t".contains(c)))
+    }
+}
+
+impl RawValue {
+    /// Every scalar type that this value could be, along with the value it would have. Varints
+    /// only have the interpretations which would be encoded as the same varint, so e.g. a varint
+    /// that is too big for 32 bits is not shown as an `int32`.
+    pub fn interpretations(&self) -> Vec<(&'static str, Value)> {
+        match *self {
+            RawValue::Varint(varint) => VARINTS
+                .iter()
+                .map(|encoding| (encoding, encoding.parse_u64_varint(varint)))
+                .filter(|(encoding, value)| encoding.make_u64_varint(*value) == varint)
+                .map(|(encoding, value)| (encoding.proto_name(), value))
+                .collect(),
+            RawValue::Fixed32(fixed) => [Fixed::Fixed32, Fixed::SFixed32, Fixed::Float]
+                .iter()
+                .map(|encoding| {
+                    let value = encoding.read(&mut &fixed.to_le_bytes()[..]);
+
+                    (encoding.proto_name(), value)
+                })
+                .collect(),
+            RawValue::Fixed64(fixed) => [Fixed::Fixed64, Fixed::SFixed64, Fixed::Double]
+                .iter()
+                .map(|encoding| {
+                    let value = encoding.read(&mut &fixed.to_le_bytes()[..]);
+
+                    (encoding.proto_name(), value)
+                })
+                .collect(),
+            RawValue::LengthDelimited(_) | RawValue::Group(_) => vec![],
+        }
+    }
+}
+
+/// Write the interpretations of a value as a comment, with names that have the same value grouped
+/// together.
+fn write_interpretations(f: &mut fmt::Formatter, value: &RawValue) -> fmt::Result {
+    let mut groups: Vec<(Vec<&str>, Value)> = vec![];
+
+    for (name, value) in value.interpretations() {
+        match groups.iter_mut().find(|(_, other)| *other == value) {
+            Some((names, _)) => names.push(name),
+            None => groups.push((vec![name], value)),
+        }
+    }
+
+    f.write_str("  #")?;
+
+    for (i, (names, value)) in groups.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+
+        match value {
+            // Show the shortest representation that reads back as the same `f32`.
+            Value::Float(float) if names == &["float"] => {
+                write!(f, "{}{}: {:?}", separator, names.join("/"), *float as f32)?
+            }
+            Value::Float(float) => write!(f, "{}{}: {:?}", separator, names.join("/"), float)?,
+            Value::Int(int) => write!(f, "{}{}: {}", separator, names.join("/"), int)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn write_fields(f: &mut fmt::Formatter, message: &RawMessage, depth: usize) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+
+    for field in &message.fields {
+        let tag = field.tag;
+
+        match &field.value {
+            value @ RawValue::Varint(varint) => {
+                write!(f, "{}{}: {}", indent, tag, varint)?;
+                write_interpretations(f, value)?;
+            }
+            value @ RawValue::Fixed32(fixed) => {
+                write!(f, "{}{}: {:#010x}", indent, tag, fixed)?;
+                write_interpretations(f, value)?;
+            }
+            value @ RawValue::Fixed64(fixed) => {
+                write!(f, "{}{}: {:#018x}", indent, tag, fixed)?;
+                write_interpretations(f, value)?;
+            }
+            RawValue::Group(group) => {
+                writeln!(f, "{}{} {{  # group", indent, tag)?;
+                write_fields(f, group, depth + 1)?;
+                write!(f, "{}}}", indent)?;
+            }
+            RawValue::LengthDelimited(bytes) => {
+                // Text is the most likely, as it is unusual for a message to be printable, and a
+                // message is more likely than a packed field.
+                if let Some(s) = bytes.as_str() {
+                    write!(f, "{}{}: ", indent, tag)?;
+                    write_string(f, s)?;
+                } else if let Some(message) = &bytes.message {
+                    writeln!(f, "{}{} {{", indent, tag)?;
+                    write_fields(f, message, depth + 1)?;
+                    write!(f, "{}}}", indent)?;
+                } else if let Some(packed) = bytes.packed.first() {
+                    write!(f, "{}{}: ", indent, tag)?;
+
+                    let (values, kind) = match packed {
+                        Packed::Varint(values) => (format!("{:?}", values), "varint"),
+                        Packed::Fixed32(values) => (format!("{:?}", values), "fixed32"),
+                        Packed::Fixed64(values) => (format!("{:?}", values), "fixed64"),
+                    };
+
+                    write!(f, "{}  # packed {}", values, kind)?;
+                } else {
+                    write!(f, "{}{}: ", indent, tag)?;
+                    write_bytes(f, &bytes.bytes)?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+    }
+
+    Ok(())
+}
+
+/// Messages are written in the text format, with the interpretations of each number in a comment.
+impl fmt::Display for RawMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_fields(f, self, 0)
+    }
+}
@@ -0,0 +1,275 @@
+//! Packing messages into `google.protobuf.Any`, and decoding them again without knowing their type
+//! ahead of time. A derived message gets a type URL with `#[autoproto(type_url = "...")]`, and can
+//! then be packed with `Any::pack` and unpacked with `Any::unpack`. A `TypeRegistry` maps type URLs
+//! to the types they belong to, so that `TypeRegistry::decode_dyn` can decode an `Any` holding any
+//! registered message.
+//!
+//! ```rust
+//! use autoproto::{registry::TypeRegistry, wkt::Any};
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! #[autoproto(type_url = "type.googleapis.com/events.UserCreated")]
+//! struct UserCreated {
+//!     name: String,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! #[autoproto(type_url = "type.googleapis.com/events.UserDeleted")]
+//! struct UserDeleted {
+//!     id: u64,
+//! }
+//!
+//! let any = Any::pack(&UserCreated { name: "foo".into() });
+//!
+//! assert_eq!(any.type_url, "type.googleapis.com/events.UserCreated");
+//! assert_eq!(any.unpack::<UserCreated>().unwrap().name, "foo");
+//! assert!(any.unpack::<UserDeleted>().is_err());
+//!
+//! let mut registry = TypeRegistry::new();
+//!
+//! registry.register::<UserCreated>().register::<UserDeleted>();
+//!
+//! let message = registry.decode_dyn(&any).unwrap();
+//!
+//! assert_eq!(format!("{:?}", message), r#"UserCreated { name: "foo" }"#);
+//! assert_eq!(message.to_any(), any);
+//! assert_eq!(message.downcast_ref::<UserCreated>().unwrap().name, "foo");
+//! ```
+
+use crate::{prost::Message, wkt::Any};
+use prost::{bytes::BufMut, DecodeError};
+use std::{collections::HashMap, fmt::Debug};
+
+/// A message which can be packed in an `Any`. This is implemented by deriving `Message` with
+/// `#[autoproto(type_url = "...")]`, and by the well-known types.
+pub trait TypeUrl {
+    /// The URL identifying this type, such as `type.googleapis.com/pkg.Foo`. Only the part after
+    /// the last `/` is used to find the type when decoding.
+    fn type_url() -> &'static str;
+}
+
+/// The fully-qualified name of a type, which is the last segment of its type URL.
+pub(crate) fn type_name(type_url: &str) -> &str {
+    type_url.rsplit('/').next().unwrap_or_default()
+}
+
+/// A message of a type which is only known at runtime, as returned by
+/// `TypeRegistry::decode_dyn`. It can be printed with `Debug`, encoded again, or downcast to its
+/// concrete type.
+pub trait DynMessage: Debug + Send + Sync + 'static {
+    /// The type URL of this message's type.
+    fn message_type_url(&self) -> &'static str;
+
+    /// Encode this message, without a length delimiter.
+    fn encode_dyn(&self, buf: &mut dyn BufMut);
+
+    /// The length of this message when encoded with `encode_dyn`.
+    fn encoded_len_dyn(&self) -> usize;
+
+    /// Pack this message in an `Any` again.
+    fn to_any(&self) -> Any;
+
+    #[doc(hidden)]
+    fn as_std_any(&self) -> &dyn std::any::Any;
+
+    #[doc(hidden)]
+    fn into_std_any(self: Box<Self>) -> Box<dyn std::any::Any>;
+}
+
+impl<T> DynMessage for T
+where
+    T: Message + TypeUrl + 'static,
+{
+    fn message_type_url(&self) -> &'static str {
+        T::type_url()
+    }
+
+    fn encode_dyn(&self, mut buf: &mut dyn BufMut) {
+        self.encode_raw(&mut buf)
+    }
+
+    fn encoded_len_dyn(&self) -> usize {
+        self.encoded_len()
+    }
+
+    fn to_any(&self) -> Any {
+        Any::pack(self)
+    }
+
+    fn as_std_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn into_std_any(self: Box<Self>) -> Box<dyn std::any::Any> {
+        self
+    }
+}
+
+impl dyn DynMessage {
+    /// Whether this message is of type `T`.
+    pub fn is<T: DynMessage>(&self) -> bool {
+        self.as_std_any().is::<T>()
+    }
+
+    /// Get a reference to this message as its concrete type, if it is of type `T`.
+    pub fn downcast_ref<T: DynMessage>(&self) -> Option<&T> {
+        self.as_std_any().downcast_ref()
+    }
+
+    /// Convert this message to its concrete type, or give it back if it isn't of type `T`.
+    pub fn downcast<T: DynMessage>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
+        if self.is::<T>() {
+            Ok(self.into_std_any().downcast().unwrap())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Any {
+    /// Encode a message and pack it along with its type URL.
+    pub fn pack<T: Message + TypeUrl>(message: &T) -> Self {
+        Self {
+            type_url: T::type_url().into(),
+            value: message.encode_to_vec().into(),
+        }
+    }
+
+    /// Whether this holds a message of type `T`, going by its type URL.
+    pub fn is<T: TypeUrl>(&self) -> bool {
+        self.type_name() == type_name(T::type_url())
+    }
+
+    /// Decode the message this holds, failing if it holds a different type of message.
+    pub fn unpack<T: Message + TypeUrl + Default>(&self) -> Result<T, DecodeError> {
+        if self.is::<T>() {
+            T::decode(&self.value[..])
+        } else {
+            Err(DecodeError::new(format!(
+                "Expected `Any` to hold `{}`, but it holds `{}`",
+                type_name(T::type_url()),
+                self.type_name()
+            )))
+        }
+    }
+}
+
+type DecodeFn = fn(&[u8]) -> Result<Box<dyn DynMessage>, DecodeError>;
+
+fn decode<T: Message + TypeUrl + Default + 'static>(
+    buf: &[u8],
+) -> Result<Box<dyn DynMessage>, DecodeError> {
+    Ok(Box::new(T::decode(buf)?))
+}
+
+/// A type which can be added to a `TypeRegistry`. With the `inventory` feature, these can be
+/// submitted from anywhere in a program with `register_type!`, and are collected by
+/// `TypeRegistry::registered`.
+#[derive(Copy, Clone, Debug)]
+pub struct Registration {
+    type_url: fn() -> &'static str,
+    decode: DecodeFn,
+}
+
+impl Registration {
+    pub const fn of<T: Message + TypeUrl + Default + 'static>() -> Self {
+        Self {
+            type_url: T::type_url,
+            decode: decode::<T>,
+        }
+    }
+
+    pub fn type_url(&self) -> &'static str {
+        (self.type_url)()
+    }
+}
+
+#[cfg(feature = "inventory")]
+inventory::collect!(Registration);
+
+/// Register message types with the `TypeRegistry` returned by `TypeRegistry::registered`. This can
+/// be used anywhere in the program, including in other crates.
+///
+/// ```rust
+/// #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+/// #[autoproto(type_url = "type.googleapis.com/events.Ping")]
+/// struct Ping {}
+///
+/// autoproto::register_type!(Ping);
+///
+/// assert!(autoproto::registry::TypeRegistry::registered().contains("type.googleapis.com/events.Ping"));
+/// ```
+#[cfg(feature = "inventory")]
+#[macro_export]
+macro_rules! register_type {
+    ($($t:ty),* $(,)?) => {
+        $(
+            $crate::inventory::submit! {
+                $crate::registry::Registration::of::<$t>()
+            }
+        )*
+    };
+}
+
+/// A collection of message types, which can decode an `Any` holding any of them. Every registry
+/// starts out with the well-known types.
+#[derive(Clone, Debug)]
+pub struct TypeRegistry {
+    types: HashMap<String, Registration>,
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            types: HashMap::new(),
+        };
+
+        crate::wkt::register(&mut registry);
+
+        registry
+    }
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry of every type submitted with `register_type!`, along with the well-known types.
+    #[cfg(feature = "inventory")]
+    pub fn registered() -> Self {
+        let mut registry = Self::new();
+
+        for registration in inventory::iter::<Registration> {
+            registry.add(*registration);
+        }
+
+        registry
+    }
+
+    pub fn register<T: Message + TypeUrl + Default + 'static>(&mut self) -> &mut Self {
+        self.add(Registration::of::<T>())
+    }
+
+    /// Add a type to this registry, replacing any type with the same name.
+    pub fn add(&mut self, registration: Registration) -> &mut Self {
+        self.types
+            .insert(type_name(registration.type_url()).into(), registration);
+
+        self
+    }
+
+    /// Whether a type with this type URL has been registered.
+    pub fn contains(&self, type_url: &str) -> bool {
+        self.types.contains_key(type_name(type_url))
+    }
+
+    /// Decode the message held in an `Any`, failing if its type hasn't been registered.
+    pub fn decode_dyn(&self, any: &Any) -> Result<Box<dyn DynMessage>, DecodeError> {
+        let registration = self.types.get(any.type_name()).ok_or_else(|| {
+            DecodeError::new(format!("Unknown type URL `{}` in `Any`", any.type_url))
+        })?;
+
+        (registration.decode)(&any.value[..])
+    }
+}
@@ -0,0 +1,510 @@
+//! Generation of `.proto` schemas from Rust types. Every type deriving `Message` implements
+//! `ToProtoSpec`, and `ProtoFile` collects the definitions of a type and everything it refers to
+//! into a complete proto3 file.
+//!
+//! ```rust
+//! # #![feature(generic_associated_types)]
+//! #[derive(PartialEq, Default, Debug, autoproto::Message)]
+//! struct Person {
+//!     name: String,
+//!     scores: Vec<u32>,
+//! }
+//!
+//! let file = autoproto::spec::ProtoFile::with_package("example")
+//!     .add::<Person>()
+//!     .to_string();
+//!
+//! assert_eq!(
+//!     file,
+//!     "syntax = \"proto3\";\n\
+//!      \n\
+//!      package example;\n\
+//!      \n\
+//!      message Person {\n    \
+//!          string name = 1;\n    \
+//!          repeated uint32 scores = 2;\n\
+//!      }\n"
+//! );
+//! ```
+
+use crate::{
+    Encoding, Fixed, Group, MappedInt, OneofField, ProtoOneofSpec, ProtoScalar, ScalarEncodingKind,
+    ToProtoSpec, Varint,
+};
+use std::{
+    collections::BTreeSet,
+    ffi::{OsStr, OsString},
+    fmt,
+    marker::PhantomData,
+    num::NonZeroU32,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+};
+
+/// A complete `.proto` file, built up from the definitions of Rust types.
+#[derive(Default, Debug, Clone)]
+pub struct ProtoFile {
+    package: Option<String>,
+    imports: BTreeSet<String>,
+    definitions: Vec<(String, String)>,
+}
+
+impl ProtoFile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_package(package: impl Into<String>) -> Self {
+        Self {
+            package: Some(package.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Add the definition of `T`, along with the definitions of every type it refers to.
+    pub fn add<T: ToProtoSpec + ?Sized>(&mut self) -> &mut Self {
+        T::register(self);
+
+        self
+    }
+
+    pub fn add_import(&mut self, path: impl Into<String>) -> &mut Self {
+        self.imports.insert(path.into());
+
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions
+            .iter()
+            .any(|(existing, _)| existing == name)
+    }
+
+    /// Add a definition named `name`, which is written by `func`. Returns `false` without doing
+    /// anything if a definition with this name already exists, which is how recursive types are
+    /// handled.
+    pub fn define<F>(&mut self, name: String, func: F) -> bool
+    where
+        F: Fn(&mut fmt::Formatter) -> fmt::Result,
+    {
+        if self.contains(&name) {
+            false
+        } else {
+            let definition = FmtFn(func).to_string();
+
+            self.definitions.push((name, definition));
+
+            true
+        }
+    }
+}
+
+impl fmt::Display for ProtoFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "syntax = \"proto3\";")?;
+
+        if let Some(package) = &self.package {
+            writeln!(f)?;
+            writeln!(f, "package {};", package)?;
+        }
+
+        if !self.imports.is_empty() {
+            writeln!(f)?;
+
+            for import in &self.imports {
+                writeln!(f, "import \"{}\";", import)?;
+            }
+        }
+
+        for (_, definition) in &self.definitions {
+            writeln!(f)?;
+            f.write_str(definition)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The type of a single value in a field, as far as the wire format is concerned.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+pub enum FieldType {
+    Fixed(Fixed),
+    Varint(Varint),
+    String,
+    Bytes,
+    Message,
+    Enum,
+}
+
+impl FieldType {
+    /// Whether a value of this type can be used for a field declared with type `expected`. This is
+    /// a `const fn` so that it can be used to check fields at compile time.
+    pub const fn matches(self, expected: Self) -> bool {
+        match (self, expected) {
+            (Self::Fixed(a), Self::Fixed(b)) => a as u8 == b as u8,
+            (Self::Varint(a), Self::Varint(b)) => a as u8 == b as u8,
+            // Enumerations are always encoded as `int32`.
+            (Self::Varint(Varint::I32), Self::Enum) => true,
+            (Self::String, Self::String)
+            | (Self::Bytes, Self::Bytes)
+            | (Self::Message, Self::Message)
+            | (Self::Enum, Self::Enum) => true,
+            _ => false,
+        }
+    }
+}
+
+/// How a field is declared in a `.proto` file.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+pub enum FieldShape {
+    /// The field is never encoded, like `PhantomData`.
+    Skipped,
+    Single(FieldType),
+    Repeated(FieldType),
+    Map(FieldType, FieldType),
+    /// A `oneof`, which has one field for each variant.
+    Oneof,
+}
+
+impl FieldShape {
+    /// The type of a non-repeated field, used when this type is the element of a collection.
+    pub const fn item(self) -> FieldType {
+        match self {
+            Self::Single(ty) => ty,
+            _ => panic!("Only non-repeated types can be used as elements of a collection"),
+        }
+    }
+
+    /// Whether a field of this shape can be used for a field declared with shape `expected`.
+    pub const fn matches(self, expected: Self) -> bool {
+        match (self, expected) {
+            (Self::Skipped, _) => true,
+            (Self::Single(a), Self::Single(b)) | (Self::Repeated(a), Self::Repeated(b)) => {
+                a.matches(b)
+            }
+            (Self::Map(a_key, a_val), Self::Map(b_key, b_val)) => {
+                a_key.matches(b_key) && a_val.matches(b_val)
+            }
+            (Self::Oneof, Self::Oneof) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Get the definition of `T` on its own, without any of the types it refers to.
+pub fn definition<T: ToProtoSpec + ?Sized>() -> String {
+    FmtFn(T::fmt).to_string()
+}
+
+/// The name of an instantiation of a generic type, since protobuf has no concept of generics. For
+/// example, `SomeStruct<u32, Foo>` becomes `SomeStructUint32Foo`.
+#[doc(hidden)]
+pub fn generic_name(base: &str, params: &[String]) -> String {
+    let mut out = base.to_owned();
+
+    for param in params {
+        let mut upper = true;
+
+        for c in param.chars() {
+            if c.is_ascii_alphanumeric() {
+                if upper {
+                    out.extend(c.to_uppercase());
+                } else {
+                    out.push(c);
+                }
+
+                upper = false;
+            } else {
+                upper = true;
+            }
+        }
+    }
+
+    out
+}
+
+struct FmtFn<F>(F);
+
+impl<F> fmt::Display for FmtFn<F>
+where
+    F: Fn(&mut fmt::Formatter) -> fmt::Result,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+impl<T, E> ToProtoSpec for MappedInt<T, E>
+where
+    T: ProtoScalar,
+    E: Encoding,
+{
+    const SHAPE: FieldShape = FieldShape::Single(match E::ENCODING.kind {
+        ScalarEncodingKind::Varint(Some(varint)) => FieldType::Varint(varint),
+        ScalarEncodingKind::Varint(None) => FieldType::Varint(T::DEFAULT_VARINT),
+        ScalarEncodingKind::Fixed(Some(fixed)) => FieldType::Fixed(fixed),
+        ScalarEncodingKind::Fixed(None) => FieldType::Fixed(T::DEFAULT_FIXED),
+    });
+
+    fn proto_name() -> String {
+        match E::ENCODING.kind {
+            ScalarEncodingKind::Varint(varint) => varint.unwrap_or(T::DEFAULT_VARINT).proto_name(),
+            ScalarEncodingKind::Fixed(fixed) => fixed.unwrap_or(T::DEFAULT_FIXED).proto_name(),
+        }
+        .to_owned()
+    }
+}
+
+impl<T> ToProtoSpec for Option<T>
+where
+    T: ToProtoSpec,
+{
+    const SHAPE: FieldShape = T::SHAPE;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    // Messages always have explicit presence, but other types need to be marked as `optional`.
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        match T::SHAPE {
+            FieldShape::Single(ty) if !ty.matches(FieldType::Message) => {
+                writeln!(f, "    optional {} {} = {};", T::proto_name(), name, tag)
+            }
+            _ => T::fmt_field(f, name, tag),
+        }
+    }
+
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        T::fmt(f)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register(file)
+    }
+}
+
+impl<T> ToProtoSpec for &'_ T
+where
+    T: ToProtoSpec + ?Sized,
+{
+    const SHAPE: FieldShape = T::SHAPE;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        T::fmt_field(f, name, tag)
+    }
+
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        T::fmt(f)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register(file)
+    }
+}
+
+impl<T> ToProtoSpec for Box<T>
+where
+    T: ToProtoSpec,
+{
+    const SHAPE: FieldShape = T::SHAPE;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        T::fmt_field(f, name, tag)
+    }
+
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        T::fmt(f)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register(file)
+    }
+}
+
+// `PhantomData` is never encoded, so it doesn't appear in the spec either.
+impl<T> ToProtoSpec for PhantomData<T> {
+    const SHAPE: FieldShape = FieldShape::Skipped;
+
+    fn proto_name() -> String {
+        String::new()
+    }
+
+    fn fmt_field(_f: &mut fmt::Formatter, _name: &str, _tag: NonZeroU32) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl ToProtoSpec for () {
+    const SHAPE: FieldShape = FieldShape::Single(FieldType::Message);
+
+    fn proto_name() -> String {
+        "google.protobuf.Empty".into()
+    }
+
+    fn register(file: &mut ProtoFile) {
+        file.add_import("google/protobuf/empty.proto");
+    }
+}
+
+// Like `google.protobuf.Empty`, these are imported from the files that come with `protoc`.
+macro_rules! impl_well_known_spec {
+    ($($t:ty => $name:expr, $import:expr;)*) => {
+        $(
+            impl ToProtoSpec for $t {
+                const SHAPE: FieldShape = FieldShape::Single(FieldType::Message);
+
+                fn proto_name() -> String {
+                    $name.into()
+                }
+
+                fn register(file: &mut ProtoFile) {
+                    file.add_import($import);
+                }
+            }
+        )*
+    };
+}
+
+impl_well_known_spec!(
+    prost_types::Timestamp => "google.protobuf.Timestamp", "google/protobuf/timestamp.proto";
+    prost_types::Duration => "google.protobuf.Duration", "google/protobuf/duration.proto";
+);
+
+impl<T, const TAG: u32> ToProtoSpec for OneofField<T, TAG>
+where
+    T: ProtoOneofSpec,
+{
+    const SHAPE: FieldShape = FieldShape::Oneof;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, _tag: NonZeroU32) -> fmt::Result {
+        writeln!(f, "    oneof {} {{", name)?;
+        T::fmt_variants(f)?;
+        writeln!(f, "    }}")
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register_variants(file)
+    }
+}
+
+impl<T, const TAG: u32> ToProtoSpec for OneofField<Option<T>, TAG>
+where
+    T: ProtoOneofSpec,
+{
+    const SHAPE: FieldShape = FieldShape::Oneof;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        OneofField::<T, TAG>::fmt_field(f, name, tag)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register_variants(file)
+    }
+}
+
+// Groups can't be declared in proto3, so they are written as the message that they contain, which
+// is the closest equivalent.
+impl<T, const TAG: u32> ToProtoSpec for Group<T, TAG>
+where
+    T: ToProtoSpec,
+{
+    const SHAPE: FieldShape = T::SHAPE;
+
+    fn proto_name() -> String {
+        T::proto_name()
+    }
+
+    fn fmt_field(f: &mut fmt::Formatter, name: &str, tag: NonZeroU32) -> fmt::Result {
+        T::fmt_field(f, name, tag)
+    }
+
+    fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+        T::fmt(f)
+    }
+
+    fn register(file: &mut ProtoFile) {
+        T::register(file)
+    }
+}
+
+macro_rules! impl_builtin_spec {
+    ($name:expr, $ty:ident => $($t:ty),*) => {
+        $(
+            impl ToProtoSpec for $t {
+                const SHAPE: FieldShape = FieldShape::Single(FieldType::$ty);
+
+                fn proto_name() -> String {
+                    $name.into()
+                }
+            }
+        )*
+    };
+}
+
+impl_builtin_spec!("string", String => str, String, crate::BytesStr);
+impl_builtin_spec!("bytes", Bytes => OsStr, OsString, Path, PathBuf, crate::bytes::Bytes);
+
+#[cfg(feature = "beef")]
+impl<U> ToProtoSpec for beef::generic::Cow<'_, str, U>
+where
+    U: beef::traits::Capacity,
+{
+    const SHAPE: FieldShape = FieldShape::Single(FieldType::String);
+
+    fn proto_name() -> String {
+        "string".into()
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl_builtin_spec!("bytes", Bytes => uuid::Uuid);
+
+// Ranges are encoded as a message with `start` and `end` fields.
+macro_rules! impl_range_spec {
+    ($($t:ident),*) => {
+        $(
+            impl<T> ToProtoSpec for $t<T>
+            where
+                T: ToProtoSpec,
+            {
+                const SHAPE: FieldShape = FieldShape::Single(FieldType::Message);
+
+                fn proto_name() -> String {
+                    generic_name("Range", &[T::proto_name()])
+                }
+
+                fn fmt(f: &mut fmt::Formatter) -> fmt::Result {
+                    writeln!(f, "message {} {{", Self::proto_name())?;
+                    T::fmt_field(f, "start", NonZeroU32::new(1).unwrap())?;
+                    T::fmt_field(f, "end", NonZeroU32::new(2).unwrap())?;
+                    writeln!(f, "}}")
+                }
+
+                fn register(file: &mut ProtoFile) {
+                    if file.define(Self::proto_name(), Self::fmt) {
+                        T::register(file);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_range_spec!(Range, RangeInclusive);
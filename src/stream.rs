@@ -0,0 +1,493 @@
+//! Reading and writing sequences of messages, each prefixed by its length as a varint, which is the
+//! same format as `prost::Message::encode_length_delimited`. This can be used for logs of messages
+//! in files or for messages sent over a socket.
+//!
+//! ```rust
+//! use autoproto::stream::{DelimitedReader, DelimitedWriter};
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Entry {
+//!     id: u64,
+//!     text: String,
+//! }
+//!
+//! let mut writer = DelimitedWriter::new(Vec::new());
+//!
+//! writer.write(&Entry { id: 1, text: "foo".into() }).unwrap();
+//! writer.write(&Entry { id: 2, text: "bar".into() }).unwrap();
+//!
+//! let log = writer.into_inner();
+//! let mut reader = DelimitedReader::new(&log[..]);
+//!
+//! assert_eq!(reader.read::<Entry>().unwrap().unwrap().id, 1);
+//! assert_eq!(reader.read::<Entry>().unwrap().unwrap().id, 2);
+//! assert!(reader.read::<Entry>().unwrap().is_none());
+//! ```
+//!
+//! Errors in a single frame never leave the stream out of sync, since the length of every frame is
+//! known before it is read. A frame which fails to decode or which is larger than the maximum frame
+//! size is skipped, and the next read starts at the following frame. The only exception is a
+//! length prefix which isn't a valid varint, after which the stream can't be read any further.
+//!
+//! With the `tokio` feature, `DelimitedCodec` reads and writes the same format with
+//! `tokio_util::codec`.
+
+use prost::{bytes::Buf, encoding::encode_varint, DecodeError, Message};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+};
+
+/// The largest frame that is read or written by default, 64MiB.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// The longest that a varint can be.
+const MAX_VARINT_LEN: usize = 10;
+
+/// An error reading or writing a stream of length-delimited messages.
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    /// A frame was read but didn't contain a valid message. The frame has been skipped.
+    Decode(DecodeError),
+    /// The length of a frame was greater than the maximum frame size. When reading, the frame
+    /// has been skipped, and when writing, nothing was written.
+    FrameTooLarge {
+        len: u64,
+        max: usize,
+    },
+    /// The length prefix of a frame wasn't a valid varint. The stream can't be read any further.
+    InvalidLength,
+    /// The stream ended partway through a frame. The partial frame is kept, so reading again
+    /// continues it if more data is written to the stream, as is the case when following a log.
+    Truncated {
+        buffered: usize,
+    },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Decode(error) => write!(f, "Invalid message in frame: {}", error),
+            Self::FrameTooLarge { len, max } => write!(
+                f,
+                "Frame of {} bytes is larger than the maximum of {} bytes",
+                len, max
+            ),
+            Self::InvalidLength => f.write_str("Invalid length prefix of frame"),
+            Self::Truncated { buffered } => write!(
+                f,
+                "Stream ended partway through a frame, after {} bytes",
+                buffered
+            ),
+        }
+    }
+}
+
+impl Error for StreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Decode(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StreamError {
+    fn from(other: io::Error) -> Self {
+        Self::Io(other)
+    }
+}
+
+impl From<DecodeError> for StreamError {
+    fn from(other: DecodeError) -> Self {
+        Self::Decode(other)
+    }
+}
+
+/// Parse the length prefix at the start of `buf`, returning the length of the frame and of the
+/// prefix itself, or `None` if `buf` ends partway through the prefix.
+fn frame_header(buf: &[u8]) -> Result<Option<(u64, usize)>, StreamError> {
+    let mut len = 0u64;
+
+    for (i, &byte) in buf.iter().enumerate().take(MAX_VARINT_LEN) {
+        len |= u64::from(byte & 0x7f) << (i * 7);
+
+        if byte < 0x80 {
+            return Ok(Some((len, i + 1)));
+        }
+    }
+
+    if buf.len() >= MAX_VARINT_LEN {
+        Err(StreamError::InvalidLength)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The state shared by `DelimitedReader` and `DelimitedCodec`, which finds frames in the data read
+/// so far.
+#[derive(Debug, Clone)]
+struct Frames {
+    max_frame_size: usize,
+    /// The number of bytes of a frame which was too large that are still to be discarded.
+    skip: u64,
+    invalid: bool,
+}
+
+impl Frames {
+    fn new(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size,
+            skip: 0,
+            invalid: false,
+        }
+    }
+
+    /// Decode the next message in `buf`, removing it from the buffer. This returns `Ok(None)` if
+    /// there isn't a complete frame in `buf` yet, along with the number of bytes needed to finish
+    /// the frame if it's known.
+    fn decode<T, B>(&mut self, buf: &mut B) -> Result<Result<T, Option<usize>>, StreamError>
+    where
+        T: Message + Default,
+        B: Buf,
+    {
+        if self.invalid {
+            return Err(StreamError::InvalidLength);
+        }
+
+        if self.skip > 0 {
+            let skipped = self.skip.min(buf.remaining() as u64);
+
+            buf.advance(skipped as usize);
+            self.skip -= skipped;
+
+            if self.skip > 0 {
+                return Ok(Err(None));
+            }
+        }
+
+        let (len, prefix_len) = match frame_header(buf.chunk()) {
+            Ok(Some(header)) => header,
+            Ok(None) => return Ok(Err(None)),
+            Err(error) => {
+                self.invalid = true;
+
+                return Err(error);
+            }
+        };
+
+        if len > self.max_frame_size as u64 {
+            buf.advance(prefix_len);
+            self.skip = len;
+
+            let skipped = self.skip.min(buf.remaining() as u64);
+
+            buf.advance(skipped as usize);
+            self.skip -= skipped;
+
+            return Err(StreamError::FrameTooLarge {
+                len,
+                max: self.max_frame_size,
+            });
+        }
+
+        let len = len as usize;
+
+        if buf.remaining() < prefix_len + len {
+            return Ok(Err(Some(prefix_len + len - buf.remaining())));
+        }
+
+        buf.advance(prefix_len);
+
+        let mut frame = buf.take(len);
+        let message = T::decode(&mut frame);
+
+        // `prost` stops at the first error, so skip whatever is left of the frame.
+        let unread = frame.remaining();
+        frame.advance(unread);
+
+        Ok(Ok(message?))
+    }
+}
+
+/// Writes messages to an `io::Write`, each prefixed with its length.
+#[derive(Debug)]
+pub struct DelimitedWriter<W> {
+    inner: W,
+    max_frame_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DelimitedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Set the size of the largest message that can be written, not including the length prefix.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+
+        self
+    }
+
+    /// Write a single message. The frame is written with a single call to `write_all`, and
+    /// nothing is written if the message is larger than the maximum frame size.
+    pub fn write<T: Message>(&mut self, message: &T) -> Result<(), StreamError> {
+        let len = message.encoded_len();
+
+        if len > self.max_frame_size {
+            return Err(StreamError::FrameTooLarge {
+                len: len as u64,
+                max: self.max_frame_size,
+            });
+        }
+
+        self.buf.clear();
+        // A `Vec` grows as needed, so this can't run out of space.
+        encode_varint(len as u64, &mut self.buf);
+        message.encode_raw(&mut self.buf);
+        self.inner.write_all(&self.buf)?;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), StreamError> {
+        Ok(self.inner.flush()?)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads messages written by `DelimitedWriter` from an `io::Read`. Data is read in chunks and
+/// buffered, so if the reader returns an error such as `WouldBlock` partway through a frame,
+/// reading again continues where it left off.
+#[derive(Debug)]
+pub struct DelimitedReader<R> {
+    inner: R,
+    frames: Frames,
+    buf: Vec<u8>,
+    /// The start of the data in `buf` which hasn't been read yet.
+    pos: usize,
+}
+
+impl<R: Read> DelimitedReader<R> {
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            frames: Frames::new(DEFAULT_MAX_FRAME_SIZE),
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Set the size of the largest message that can be read, not including the length prefix.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.frames.max_frame_size = max_frame_size;
+
+        self
+    }
+
+    /// Read the next message, or `None` if the stream has ended at the end of a frame.
+    pub fn read<T: Message + Default>(&mut self) -> Result<Option<T>, StreamError> {
+        loop {
+            let mut unread = &self.buf[self.pos..];
+            let result = self.frames.decode(&mut unread);
+
+            self.pos = self.buf.len() - unread.len();
+
+            let needed = match result? {
+                Ok(message) => return Ok(Some(message)),
+                Err(needed) => needed.unwrap_or(1),
+            };
+
+            // Move the partial frame to the start of the buffer before reading more.
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+
+            let len = self.buf.len();
+
+            self.buf.resize(len + needed.max(Self::CHUNK_SIZE), 0);
+
+            let read = loop {
+                match self.inner.read(&mut self.buf[len..]) {
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    other => break other,
+                }
+            };
+
+            self.buf.truncate(len + *read.as_ref().unwrap_or(&0));
+
+            match read? {
+                0 if self.buf.is_empty() && self.frames.skip == 0 => return Ok(None),
+                0 => {
+                    return Err(StreamError::Truncated {
+                        buffered: self.buf.len(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// An iterator over the messages in the stream, which ends after the first error.
+    pub fn messages<T: Message + Default>(
+        &mut self,
+    ) -> impl Iterator<Item = Result<T, StreamError>> + '_ {
+        let mut done = false;
+
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+
+            let next = self.read().transpose();
+
+            done = !matches!(next, Some(Ok(_)));
+
+            next
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Get back the underlying reader. Any data which has been read from it but not yet decoded
+    /// is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::codec::DelimitedCodec;
+
+#[cfg(feature = "tokio")]
+mod codec {
+    use super::{Frames, StreamError, DEFAULT_MAX_FRAME_SIZE};
+    use prost::{
+        bytes::BytesMut,
+        encoding::{encode_varint, encoded_len_varint},
+        Message,
+    };
+    use std::{fmt, marker::PhantomData};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    /// A `tokio_util::codec` encoder and decoder of messages prefixed with their length, for use
+    /// with `Framed`, `FramedRead` and `FramedWrite`.
+    pub struct DelimitedCodec<T> {
+        frames: Frames,
+        _marker: PhantomData<fn(T) -> T>,
+    }
+
+    impl<T> DelimitedCodec<T> {
+        pub fn new() -> Self {
+            Self {
+                frames: Frames::new(DEFAULT_MAX_FRAME_SIZE),
+                _marker: PhantomData,
+            }
+        }
+
+        /// Set the size of the largest message that can be read or written, not including the
+        /// length prefix.
+        pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+            self.frames.max_frame_size = max_frame_size;
+
+            self
+        }
+    }
+
+    impl<T> Default for DelimitedCodec<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T> Clone for DelimitedCodec<T> {
+        fn clone(&self) -> Self {
+            Self {
+                frames: self.frames.clone(),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl<T> fmt::Debug for DelimitedCodec<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("DelimitedCodec")
+                .field("max_frame_size", &self.frames.max_frame_size)
+                .finish()
+        }
+    }
+
+    impl<T: Message + Default> Decoder for DelimitedCodec<T> {
+        type Item = T;
+        type Error = StreamError;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, StreamError> {
+            match self.frames.decode(src)? {
+                Ok(message) => Ok(Some(message)),
+                Err(needed) => {
+                    src.reserve(needed.unwrap_or(1));
+
+                    Ok(None)
+                }
+            }
+        }
+
+        fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, StreamError> {
+            match self.decode(src)? {
+                Some(message) => Ok(Some(message)),
+                None if src.is_empty() && self.frames.skip == 0 => Ok(None),
+                None => Err(StreamError::Truncated {
+                    buffered: src.len(),
+                }),
+            }
+        }
+    }
+
+    impl<T: Message> Encoder<T> for DelimitedCodec<T> {
+        type Error = StreamError;
+
+        fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), StreamError> {
+            let len = item.encoded_len();
+
+            if len > self.frames.max_frame_size {
+                return Err(StreamError::FrameTooLarge {
+                    len: len as u64,
+                    max: self.frames.max_frame_size,
+                });
+            }
+
+            dst.reserve(len + encoded_len_varint(len as u64));
+            encode_varint(len as u64, dst);
+            item.encode_raw(dst);
+
+            Ok(())
+        }
+    }
+}
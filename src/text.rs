@@ -0,0 +1,1145 @@
+//! The protobuf text format, as printed by `protoc --decode`. This is mostly useful for test
+//! fixtures and configuration files, which can be parsed straight into the derived types with
+//! `from_text`, and for readable debugging output with `to_text`.
+//!
+//! ```rust
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Point {
+//!     x: i32,
+//!     y: i32,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Shape {
+//!     name: String,
+//!     points: Vec<Point>,
+//! }
+//!
+//! let shape = Shape {
+//!     name: "line".into(),
+//!     points: vec![Point { x: 0, y: 0 }, Point { x: 1, y: -2 }],
+//! };
+//! let text = autoproto::text::to_text(&shape);
+//!
+//! assert_eq!(
+//!     text,
+//!     "name: \"line\"\npoints {}\npoints {\n  x: 1\n  y: -2\n}\n",
+//! );
+//! assert_eq!(autoproto::text::from_text::<Shape>(&text).unwrap(), shape);
+//!
+//! // Comments, `<>` delimiters, list syntax and separators are accepted when parsing.
+//! let parsed: Shape = autoproto::text::from_text(
+//!     "# A shape
+//!      name: 'line'; points: [<>, { x: 1, y: -2 }]",
+//! )
+//! .unwrap();
+//! assert_eq!(parsed, shape);
+//! ```
+
+use crate::{
+    bytes::{Bytes, BytesMut},
+    spec::FieldType,
+    BytesStr, Encoding, Fixed, IsDefault, MappedInt, OneofField, ProtoMap, ProtoScalar, TextFormat,
+    TextFormatOneof, ToProtoSpec, Value, Varint,
+};
+use std::{
+    error::Error,
+    ffi::OsString,
+    fmt::{self, Write as _},
+    marker::PhantomData,
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+};
+
+/// A value in the text format, which is parsed before being merged into a message and built before
+/// being printed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextValue {
+    /// A number, a boolean or the name of an enumeration value, as it is written.
+    Scalar(String),
+    /// A string, which is printed with its UTF-8 characters intact.
+    String(String),
+    /// A quoted string of bytes, as parsed. Parsed strings are always bytes, as they may contain
+    /// escape sequences for arbitrary bytes.
+    Bytes(Vec<u8>),
+    /// A message, as the name and value of each field. Repeated fields appear once for each
+    /// element.
+    Message(Vec<(String, TextValue)>),
+    /// The elements of a repeated field, which are printed as one field per element and parsed
+    /// from the `[a, b]` syntax.
+    List(Vec<TextValue>),
+}
+
+/// An error parsing the text format or converting it to a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextError {
+    message: String,
+}
+
+impl TextError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// The value didn't have the kind that was expected for the field, e.g. a message was given
+    /// for an integer.
+    pub fn expected(expected: &str, found: &TextValue) -> Self {
+        let found = match found {
+            TextValue::Scalar(s) => {
+                return Self::new(format!("Expected {}, found `{}`", expected, s))
+            }
+            TextValue::String(_) | TextValue::Bytes(_) => "a string",
+            TextValue::Message(_) => "a message",
+            TextValue::List(_) => "a list",
+        };
+
+        Self::new(format!("Expected {}, found {}", expected, found))
+    }
+
+    /// A message had a field with a name that isn't one of the fields of the message.
+    pub fn unknown_field(name: &str) -> Self {
+        Self::new(format!("Unknown field `{}`", name))
+    }
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for TextError {}
+
+/// Convert `value` to the text format. Messages are written as their fields, one per line.
+pub fn to_text<T: TextFormat + ?Sized>(value: &T) -> String {
+    let mut out = String::new();
+
+    match value.to_text_value() {
+        TextValue::Message(fields) => print_fields(&mut out, &fields, 0),
+        other => print_value(&mut out, &other, 0),
+    }
+    .expect("Writing to a `String` cannot fail");
+
+    out
+}
+
+/// Parse a message from the text format, starting from the default value.
+pub fn from_text<T: TextFormat + Default>(text: &str) -> Result<T, TextError> {
+    let mut out = T::default();
+
+    out.merge_text_value(parse(text)?)?;
+
+    Ok(out)
+}
+
+/// Parse the fields of a message from the text format, without converting it to a Rust type.
+pub fn parse(text: &str) -> Result<TextValue, TextError> {
+    let mut parser = Parser { text, pos: 0 };
+    let fields = parser.parse_fields(None)?;
+
+    Ok(TextValue::Message(fields))
+}
+
+/// Add `value` to `fields` as the field `name`, unless it's the default. This is used by the
+/// derive macros to build the fields of a message.
+pub fn write_field<T>(fields: &mut Vec<(String, TextValue)>, name: &str, value: &T)
+where
+    T: TextFormat + ?Sized,
+{
+    if !value.is_text_default() {
+        fields.push((name.to_owned(), value.to_text_value()));
+    }
+}
+
+/// Call `merge` with the name and value of each field in `value`, which must be a message. This is
+/// used by the derive macros to merge into the fields of a message.
+pub fn merge_fields<F>(value: TextValue, mut merge: F) -> Result<(), TextError>
+where
+    F: FnMut(&str, TextValue) -> Result<(), TextError>,
+{
+    match value {
+        TextValue::Message(fields) => {
+            for (name, value) in fields {
+                merge(&name, value)
+                    .map_err(|e| TextError::new(format!("In field `{}`: {}", name, e)))?;
+            }
+
+            Ok(())
+        }
+        other => Err(TextError::expected("a message", &other)),
+    }
+}
+
+/// Convert a `oneof` that is used as a message on its own, which is a message with a single field
+/// for the current variant.
+pub fn oneof_to_text<T>(oneof: &T) -> TextValue
+where
+    T: TextFormatOneof + ?Sized,
+{
+    let mut fields = vec![];
+
+    oneof.variants_to_text(&mut fields);
+
+    TextValue::Message(fields)
+}
+
+/// Merge into a `oneof` that is used as a message on its own.
+pub fn merge_oneof<T>(oneof: &mut T, value: TextValue) -> Result<(), TextError>
+where
+    T: TextFormatOneof + ?Sized,
+{
+    merge_fields(value, |name, value| {
+        if T::has_variant(name) {
+            oneof.merge_variant_text(name, value)
+        } else {
+            Err(TextError::unknown_field(name))
+        }
+    })
+}
+
+/// Convert the elements of a repeated field.
+pub fn repeated_to_text<'a, T, I>(iter: I) -> TextValue
+where
+    T: TextFormat + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    TextValue::List(iter.into_iter().map(TextFormat::to_text_value).collect())
+}
+
+/// Extend a repeated field with either a single element, as each element is written as a separate
+/// field, or a list of elements.
+pub fn merge_repeated<T, C>(collection: &mut C, value: TextValue) -> Result<(), TextError>
+where
+    T: TextFormat + Default,
+    C: Extend<T> + ?Sized,
+{
+    let values = match value {
+        TextValue::List(values) => values,
+        other => vec![other],
+    };
+
+    for value in values {
+        let mut item = T::default();
+
+        item.merge_text_value(value)?;
+        collection.extend(std::iter::once(item));
+    }
+
+    Ok(())
+}
+
+/// Convert the entries of a map, which are written as a repeated message with `key` and `value`
+/// fields.
+pub fn map_to_text<'a, K, V, I>(iter: I) -> TextValue
+where
+    K: TextFormat + 'a,
+    V: TextFormat + 'a,
+    I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+    TextValue::List(
+        iter.into_iter()
+            .map(|(k, v)| {
+                let mut fields = vec![];
+
+                write_field(&mut fields, "key", k);
+                write_field(&mut fields, "value", v);
+
+                TextValue::Message(fields)
+            })
+            .collect(),
+    )
+}
+
+/// Insert a single entry or a list of entries into a map.
+pub fn merge_map<M>(map: &mut M, value: TextValue) -> Result<(), TextError>
+where
+    M: ProtoMap + ?Sized,
+    M::Key: TextFormat + Default,
+    M::Value: TextFormat + Default,
+{
+    let entries = match value {
+        TextValue::List(entries) => entries,
+        other => vec![other],
+    };
+
+    for entry in entries {
+        let mut key = M::Key::default();
+        let mut val = M::Value::default();
+
+        merge_fields(entry, |name, value| match name {
+            "key" => key.merge_text_value(value),
+            "value" => val.merge_text_value(value),
+            name => Err(TextError::unknown_field(name)),
+        })?;
+        map.insert(key, val);
+    }
+
+    Ok(())
+}
+
+fn indent(out: &mut String, depth: usize) {
+    out.push_str(&"  ".repeat(depth));
+}
+
+fn print_fields(out: &mut String, fields: &[(String, TextValue)], depth: usize) -> fmt::Result {
+    for (name, value) in fields {
+        print_field(out, name, value, depth)?;
+    }
+
+    Ok(())
+}
+
+fn print_field(out: &mut String, name: &str, value: &TextValue, depth: usize) -> fmt::Result {
+    match value {
+        TextValue::List(values) => {
+            for value in values {
+                print_field(out, name, value, depth)?;
+            }
+
+            Ok(())
+        }
+        TextValue::Message(_) => {
+            indent(out, depth);
+            write!(out, "{} ", name)?;
+            print_value(out, value, depth)
+        }
+        _ => {
+            indent(out, depth);
+            write!(out, "{}: ", name)?;
+            print_value(out, value, depth)
+        }
+    }
+}
+
+fn print_value(out: &mut String, value: &TextValue, depth: usize) -> fmt::Result {
+    match value {
+        TextValue::Scalar(s) => writeln!(out, "{}", s),
+        TextValue::String(s) => {
+            write_string(out, s)?;
+            out.write_char('\n')
+        }
+        TextValue::Bytes(bytes) => {
+            write_bytes(out, bytes)?;
+            out.write_char('\n')
+        }
+        TextValue::Message(fields) if fields.is_empty() => out.write_str("{}\n"),
+        TextValue::Message(fields) => {
+            out.push_str("{\n");
+            print_fields(out, fields, depth + 1)?;
+            indent(out, depth);
+            out.write_str("}\n")
+        }
+        TextValue::List(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+
+                let mut element = String::new();
+
+                print_value(&mut element, value, depth)?;
+                out.push_str(element.trim_end());
+            }
+            out.write_str("]\n")
+        }
+    }
+}
+
+/// Write `s` as a quoted string, keeping its characters intact other than control characters.
+pub(crate) fn write_string<W>(out: &mut W, s: &str) -> fmt::Result
+where
+    W: fmt::Write + ?Sized,
+{
+    out.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            c if c.is_control() => {
+                let mut buf = [0; 4];
+
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    write!(out, "\\{:03o}", b)?;
+                }
+            }
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+/// Write `bytes` as a quoted string, escaping everything other than printable ASCII.
+pub(crate) fn write_bytes<W>(out: &mut W, bytes: &[u8]) -> fmt::Result
+where
+    W: fmt::Write + ?Sized,
+{
+    out.write_char('"')?;
+    for &b in bytes {
+        match b {
+            b'\n' => out.write_str("\\n")?,
+            b'\r' => out.write_str("\\r")?,
+            b'\t' => out.write_str("\\t")?,
+            b'"' => out.write_str("\\\"")?,
+            b'\\' => out.write_str("\\\\")?,
+            b' '..=b'~' => out.write_char(b as char)?,
+            b => write!(out, "\\{:03o}", b)?,
+        }
+    }
+    out.write_char('"')
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl fmt::Display) -> TextError {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        let column = self.pos - self.text[..self.pos].rfind('\n').map_or(0, |i| i + 1) + 1;
+
+        TextError::new(format!("{} at line {}, column {}", message, line, column))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Skip whitespace and comments, and return the next character.
+    fn peek(&mut self) -> Option<char> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with('#') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                return trimmed.chars().next();
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), TextError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected `{}`", c)))
+        }
+    }
+
+    /// A name, number or identifier.
+    fn token(&mut self) -> Result<&'a str, TextError> {
+        self.peek();
+
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_.-+".contains(c)))
+            .unwrap_or(self.rest().len());
+
+        if len == 0 {
+            return Err(self.error("Expected a value"));
+        }
+
+        self.pos += len;
+
+        Ok(&self.text[start..self.pos])
+    }
+
+    /// The fields of a message, until `close` or the end of the input if this is the top level.
+    fn parse_fields(&mut self, close: Option<char>) -> Result<Vec<(String, TextValue)>, TextError> {
+        let mut fields = vec![];
+
+        loop {
+            match (self.peek(), close) {
+                (None, None) => return Ok(fields),
+                (None, Some(close)) => return Err(self.error(format!("Expected `{}`", close))),
+                (Some(c), Some(close)) if c == close => {
+                    self.pos += c.len_utf8();
+
+                    return Ok(fields);
+                }
+                _ => {}
+            }
+
+            let name = self.token()?.to_owned();
+
+            let value = if self.eat(':') {
+                self.parse_value(true)?
+            } else {
+                match self.peek() {
+                    Some('{') | Some('<') => self.parse_value(false)?,
+                    _ => return Err(self.error("Expected `:`")),
+                }
+            };
+
+            fields.push((name, value));
+
+            if !self.eat(',') {
+                self.eat(';');
+            }
+        }
+    }
+
+    fn parse_value(&mut self, allow_list: bool) -> Result<TextValue, TextError> {
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                Ok(TextValue::Message(self.parse_fields(Some('}'))?))
+            }
+            Some('<') => {
+                self.pos += 1;
+                Ok(TextValue::Message(self.parse_fields(Some('>'))?))
+            }
+            Some('[') if allow_list => {
+                self.pos += 1;
+
+                let mut values = vec![];
+
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.parse_value(false)?);
+
+                        if self.eat(']') {
+                            break;
+                        }
+
+                        self.expect(',')?;
+                    }
+                }
+
+                Ok(TextValue::List(values))
+            }
+            Some('"') | Some('\'') => {
+                let mut bytes = vec![];
+
+                // Adjacent strings are concatenated.
+                while let Some(quote @ '"') | Some(quote @ '\'') = self.peek() {
+                    self.pos += 1;
+                    self.parse_string(quote, &mut bytes)?;
+                }
+
+                Ok(TextValue::Bytes(bytes))
+            }
+            _ => Ok(TextValue::Scalar(self.token()?.to_owned())),
+        }
+    }
+
+    fn parse_string(&mut self, quote: char, out: &mut Vec<u8>) -> Result<(), TextError> {
+        let mut chars = self.rest().char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + 1;
+
+                    return Ok(());
+                }
+                '\n' => break,
+                '\\' => {
+                    let (_, escaped) = chars
+                        .next()
+                        .ok_or_else(|| self.error("Unterminated string"))?;
+
+                    match escaped {
+                        'n' => out.push(b'\n'),
+                        'r' => out.push(b'\r'),
+                        't' => out.push(b'\t'),
+                        'a' => out.push(0x07),
+                        'b' => out.push(0x08),
+                        'f' => out.push(0x0c),
+                        'v' => out.push(0x0b),
+                        '0'..='7' => {
+                            let mut value = escaped.to_digit(8).unwrap();
+
+                            for _ in 0..2 {
+                                match chars.clone().next().and_then(|(_, c)| c.to_digit(8)) {
+                                    Some(digit) => {
+                                        chars.next();
+                                        value = value * 8 + digit;
+                                    }
+                                    None => break,
+                                }
+                            }
+
+                            out.push(value as u8);
+                        }
+                        'x' => {
+                            let mut value = 0;
+
+                            for _ in 0..2 {
+                                match chars.clone().next().and_then(|(_, c)| c.to_digit(16)) {
+                                    Some(digit) => {
+                                        chars.next();
+                                        value = value * 16 + digit;
+                                    }
+                                    None => break,
+                                }
+                            }
+
+                            out.push(value as u8);
+                        }
+                        'u' | 'U' => {
+                            let len = if escaped == 'u' { 4 } else { 8 };
+                            let digits =
+                                chars.clone().take(len).map(|(_, c)| c).collect::<String>();
+                            let c = u32::from_str_radix(&digits, 16)
+                                .ok()
+                                .filter(|_| digits.len() == len)
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("Invalid unicode escape"))?;
+
+                            for _ in 0..len {
+                                chars.next();
+                            }
+
+                            out.extend(c.encode_utf8(&mut [0; 4]).bytes());
+                        }
+                        other => out.extend(other.encode_utf8(&mut [0; 4]).bytes()),
+                    }
+                }
+                c => out.extend(c.encode_utf8(&mut [0; 4]).bytes()),
+            }
+        }
+
+        Err(self.error("Unterminated string"))
+    }
+}
+
+fn float_to_text(f: f64, shortest: impl fmt::Debug) -> TextValue {
+    TextValue::Scalar(if f.is_nan() {
+        "nan".into()
+    } else if f.is_infinite() {
+        if f > 0. { "inf" } else { "-inf" }.into()
+    } else {
+        format!("{:?}", shortest)
+    })
+}
+
+fn float_from_text(value: &TextValue) -> Result<f64, TextError> {
+    match value {
+        TextValue::Scalar(s) => {
+            let lower = s.to_lowercase();
+
+            match &lower[..] {
+                "nan" | "-nan" => Ok(f64::NAN),
+                "inf" | "infinity" => Ok(f64::INFINITY),
+                "-inf" | "-infinity" => Ok(f64::NEG_INFINITY),
+                // Floats can have an `f` suffix, as in C.
+                _ => lower
+                    .strip_suffix('f')
+                    .unwrap_or(&lower)
+                    .parse()
+                    .map_err(|_| TextError::new(format!("Invalid float `{}`", s))),
+            }
+        }
+        other => Err(TextError::expected("a float", other)),
+    }
+}
+
+/// Integers can be written in decimal, hexadecimal with `0x` or octal with a leading `0`.
+fn int_from_text(value: &TextValue) -> Result<i128, TextError> {
+    match value {
+        TextValue::Scalar(s) => {
+            let (negative, digits) = match s.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, &s[..]),
+            };
+            let parsed = if let Some(hex) = digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                i128::from_str_radix(hex, 16)
+            } else if digits.len() > 1 && digits.starts_with('0') {
+                i128::from_str_radix(&digits[1..], 8)
+            } else {
+                digits.parse()
+            };
+
+            parsed
+                .map(|i| if negative { -i } else { i })
+                .map_err(|_| TextError::new(format!("Invalid integer `{}`", s)))
+        }
+        other => Err(TextError::expected("an integer", other)),
+    }
+}
+
+impl<T, E> TextFormat for MappedInt<T, E>
+where
+    T: ProtoScalar,
+    E: Encoding,
+{
+    fn to_text_value(&self) -> TextValue {
+        match (<Self as ToProtoSpec>::SHAPE.item(), self.0.to_value()) {
+            (FieldType::Varint(Varint::Bool), value) => {
+                TextValue::Scalar((value.bool() == Some(true)).to_string())
+            }
+            (FieldType::Fixed(Fixed::Float), Value::Float(f)) => float_to_text(f, f as f32),
+            (_, Value::Float(f)) => float_to_text(f, f),
+            (_, Value::Int(i)) => TextValue::Scalar(i.to_string()),
+        }
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        let parsed = match <Self as ToProtoSpec>::SHAPE.item() {
+            FieldType::Varint(Varint::Bool) => match &value {
+                TextValue::Scalar(s) => match &s[..] {
+                    "true" | "True" | "t" | "1" => Value::Int(1),
+                    "false" | "False" | "f" | "0" => Value::Int(0),
+                    _ => return Err(TextError::new(format!("Invalid boolean `{}`", s))),
+                },
+                other => return Err(TextError::expected("a boolean", other)),
+            },
+            FieldType::Fixed(Fixed::Float | Fixed::Double) => {
+                Value::Float(float_from_text(&value)?)
+            }
+            _ => Value::Int(int_from_text(&value)?),
+        };
+
+        self.0 = T::from_value(parsed)
+            .ok_or_else(|| TextError::new(format!("Value {:?} is out of range", value)))?;
+
+        Ok(())
+    }
+}
+
+impl<T> TextFormat for Option<T>
+where
+    T: TextFormat + Default,
+{
+    fn to_text_value(&self) -> TextValue {
+        match self {
+            None => TextValue::Message(vec![]),
+            Some(v) => v.to_text_value(),
+        }
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        self.get_or_insert_with(Default::default)
+            .merge_text_value(value)
+    }
+}
+
+impl<T> TextFormat for Box<T>
+where
+    T: TextFormat + ?Sized,
+{
+    fn to_text_value(&self) -> TextValue {
+        T::to_text_value(self)
+    }
+
+    fn is_text_default(&self) -> bool {
+        T::is_text_default(self)
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        T::merge_text_value(self, value)
+    }
+}
+
+// `PhantomData` is never encoded, so it doesn't appear in the text format either.
+impl<T> TextFormat for PhantomData<T> {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Message(vec![])
+    }
+
+    fn is_text_default(&self) -> bool {
+        true
+    }
+
+    fn merge_text_value(&mut self, _value: TextValue) -> Result<(), TextError> {
+        Ok(())
+    }
+}
+
+/// `()` is `google.protobuf.Empty`, which is a message without fields.
+impl TextFormat for () {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Message(vec![])
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        merge_fields(value, |name, _| Err(TextError::unknown_field(name)))
+    }
+}
+
+// Unlike JSON, the text format has no special representation for `Timestamp` and `Duration`.
+macro_rules! impl_seconds_and_nanos_text {
+    ($($t:ty),*) => {
+        $(
+            impl TextFormat for $t {
+                fn to_text_value(&self) -> TextValue {
+                    let mut fields = vec![];
+
+                    write_field(&mut fields, "seconds", &self.seconds);
+                    write_field(&mut fields, "nanos", &self.nanos);
+
+                    TextValue::Message(fields)
+                }
+
+                fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+                    merge_fields(value, |name, value| match name {
+                        "seconds" => self.seconds.merge_text_value(value),
+                        "nanos" => self.nanos.merge_text_value(value),
+                        name => Err(TextError::unknown_field(name)),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_seconds_and_nanos_text!(prost_types::Timestamp, prost_types::Duration);
+
+impl<T, const TAG: u32> TextFormat for OneofField<T, TAG>
+where
+    T: TextFormatOneof,
+{
+    fn to_text_value(&self) -> TextValue {
+        self.0.to_text_value()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        self.0.merge_text_value(value)
+    }
+}
+
+impl<T, const TAG: u32> TextFormatOneof for OneofField<T, TAG>
+where
+    T: TextFormatOneof,
+{
+    fn has_variant(name: &str) -> bool {
+        T::has_variant(name)
+    }
+
+    fn variants_to_text(&self, fields: &mut Vec<(String, TextValue)>) {
+        self.0.variants_to_text(fields)
+    }
+
+    fn merge_variant_text(&mut self, name: &str, value: TextValue) -> Result<(), TextError> {
+        self.0.merge_variant_text(name, value)
+    }
+}
+
+impl<T, const TAG: u32> TextFormat for OneofField<Option<T>, TAG>
+where
+    T: TextFormatOneof + Default,
+{
+    fn to_text_value(&self) -> TextValue {
+        oneof_to_text(self)
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        merge_oneof(self, value)
+    }
+}
+
+impl<T, const TAG: u32> TextFormatOneof for OneofField<Option<T>, TAG>
+where
+    T: TextFormatOneof + Default,
+{
+    fn has_variant(name: &str) -> bool {
+        T::has_variant(name)
+    }
+
+    fn variants_to_text(&self, fields: &mut Vec<(String, TextValue)>) {
+        if let Some(inner) = &self.0 {
+            inner.variants_to_text(fields)
+        }
+    }
+
+    fn merge_variant_text(&mut self, name: &str, value: TextValue) -> Result<(), TextError> {
+        self.0
+            .get_or_insert_with(Default::default)
+            .merge_variant_text(name, value)
+    }
+}
+
+fn string_from_text(value: TextValue) -> Result<String, TextError> {
+    match value {
+        TextValue::String(s) => Ok(s),
+        TextValue::Bytes(bytes) => {
+            String::from_utf8(bytes).map_err(|e| TextError::new(format!("Invalid string: {}", e)))
+        }
+        other => Err(TextError::expected("a string", &other)),
+    }
+}
+
+fn bytes_from_text(value: TextValue) -> Result<Vec<u8>, TextError> {
+    match value {
+        TextValue::String(s) => Ok(s.into_bytes()),
+        TextValue::Bytes(bytes) => Ok(bytes),
+        other => Err(TextError::expected("a string", &other)),
+    }
+}
+
+impl TextFormat for String {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::String(self.clone())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        *self = string_from_text(value)?;
+
+        Ok(())
+    }
+}
+
+impl TextFormat for BytesStr {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::String(self.to_string())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        *self = string_from_text(value)?.into();
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "beef")]
+impl<U> TextFormat for beef::generic::Cow<'_, str, U>
+where
+    U: beef::traits::Capacity,
+{
+    fn to_text_value(&self) -> TextValue {
+        TextValue::String(self.to_string())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        *self = Self::owned(string_from_text(value)?);
+
+        Ok(())
+    }
+}
+
+impl<const LEN: usize> TextFormat for [u8; LEN] {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Bytes(self.to_vec())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_default()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        let bytes = bytes_from_text(value)?;
+
+        if bytes.len() != LEN {
+            return Err(TextError::new(format!(
+                "Expected {} bytes, found {}",
+                LEN,
+                bytes.len()
+            )));
+        }
+
+        self.copy_from_slice(&bytes);
+
+        Ok(())
+    }
+}
+
+macro_rules! impl_bytes_text {
+    ($($t:ty),*) => {
+        $(
+            impl TextFormat for $t {
+                fn to_text_value(&self) -> TextValue {
+                    TextValue::Bytes(self.to_vec())
+                }
+
+                fn is_text_default(&self) -> bool {
+                    self.is_empty()
+                }
+
+                fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+                    *self = bytes_from_text(value)?.into();
+
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_bytes_text!(Box<[u8]>, Bytes);
+
+impl TextFormat for BytesMut {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Bytes(self.to_vec())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        *self = bytes_from_text(value)?[..].into();
+
+        Ok(())
+    }
+}
+
+impl TextFormat for OsString {
+    #[cfg(unix)]
+    fn to_text_value(&self) -> TextValue {
+        use std::os::unix::ffi::OsStrExt;
+
+        TextValue::Bytes(self.as_bytes().to_vec())
+    }
+
+    // Other platforms can only represent strings which are valid Unicode.
+    #[cfg(not(unix))]
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Bytes(self.to_string_lossy().as_bytes().to_vec())
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[cfg(unix)]
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        use std::os::unix::ffi::OsStringExt;
+
+        *self = OsString::from_vec(bytes_from_text(value)?);
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        *self = String::from_utf8(bytes_from_text(value)?)
+            .map_err(|e| TextError::new(e.to_string()))?
+            .into();
+
+        Ok(())
+    }
+}
+
+impl TextFormat for PathBuf {
+    fn to_text_value(&self) -> TextValue {
+        self.as_os_str().to_owned().to_text_value()
+    }
+
+    fn is_text_default(&self) -> bool {
+        self.as_os_str().is_empty()
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        let mut os_string = OsString::new();
+
+        os_string.merge_text_value(value)?;
+        *self = os_string.into();
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl TextFormat for uuid::Uuid {
+    fn to_text_value(&self) -> TextValue {
+        TextValue::Bytes(self.as_bytes().to_vec())
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        let mut bytes = *self.as_bytes();
+
+        bytes.merge_text_value(value)?;
+        *self = uuid::Uuid::from_bytes(bytes);
+
+        Ok(())
+    }
+}
+
+// Ranges are written as a message with `start` and `end` fields.
+fn range_to_text<T>(start: &T, end: &T) -> TextValue
+where
+    T: TextFormat,
+{
+    let mut fields = vec![];
+
+    write_field(&mut fields, "start", start);
+    write_field(&mut fields, "end", end);
+
+    TextValue::Message(fields)
+}
+
+fn merge_range<T>(start: &mut T, end: &mut T, value: TextValue) -> Result<(), TextError>
+where
+    T: TextFormat,
+{
+    merge_fields(value, |name, value| match name {
+        "start" => start.merge_text_value(value),
+        "end" => end.merge_text_value(value),
+        name => Err(TextError::unknown_field(name)),
+    })
+}
+
+impl<T> TextFormat for Range<T>
+where
+    T: TextFormat,
+{
+    fn to_text_value(&self) -> TextValue {
+        range_to_text(&self.start, &self.end)
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        merge_range(&mut self.start, &mut self.end, value)
+    }
+}
+
+impl<T> TextFormat for RangeInclusive<T>
+where
+    T: TextFormat + Clone,
+{
+    fn to_text_value(&self) -> TextValue {
+        range_to_text(self.start(), self.end())
+    }
+
+    fn merge_text_value(&mut self, value: TextValue) -> Result<(), TextError> {
+        let (mut start, mut end) = self.clone().into_inner();
+
+        merge_range(&mut start, &mut end, value)?;
+        *self = start..=end;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Storage for fields which aren't known to a message, so that they survive being decoded and
+//! re-encoded. Add a field of type `UnknownFields` marked with `#[autoproto(unknown_fields)]` to a
+//! derived message to opt in:
+//!
+//! ```rust
+//! use autoproto::{prost::Message, UnknownFields};
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Old {
+//!     name: String,
+//!     #[autoproto(unknown_fields)]
+//!     unknown: UnknownFields,
+//! }
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct New {
+//!     name: String,
+//!     id: u64,
+//! }
+//!
+//! let new = New { name: "foo".into(), id: 5 };
+//! let mut old = Old::decode(&new.encode_to_vec()[..]).unwrap();
+//!
+//! old.name = "bar".into();
+//!
+//! assert_eq!(old.unknown.len(), 1);
+//! assert_eq!(
+//!     New::decode(&old.encode_to_vec()[..]).unwrap(),
+//!     New { name: "bar".into(), id: 5 },
+//! );
+//! ```
+
+use crate::{IsDefault, ProtoEncode};
+use prost::{
+    bytes::{Buf, BufMut},
+    encoding::{DecodeContext, WireType},
+    DecodeError,
+};
+use std::num::NonZeroU32;
+
+/// A single field which was not recognised while decoding.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnknownField {
+    tag: NonZeroU32,
+    wire_type: WireType,
+    value: Vec<u8>,
+}
+
+impl UnknownField {
+    pub fn tag(&self) -> NonZeroU32 {
+        self.tag
+    }
+
+    pub fn wire_type(&self) -> WireType {
+        self.wire_type
+    }
+
+    /// The encoded value of this field, exactly as it was received, not including the key. For
+    /// length-delimited fields this includes the length, and for groups this includes the
+    /// end-group key.
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// Every field which was not recognised while decoding a message, in the order they were received.
+/// These are encoded after the known fields of the message.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct UnknownFields {
+    fields: Vec<UnknownField>,
+}
+
+impl UnknownFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, UnknownField> {
+        self.fields.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.clear()
+    }
+
+    /// Read the value of a field with the given tag and wire type from `buf`, which should be
+    /// positioned immediately after the field's key.
+    pub fn merge_field(
+        &mut self,
+        tag: NonZeroU32,
+        wire_type: WireType,
+        buf: &mut dyn Buf,
+        ctx: DecodeContext,
+    ) -> Result<(), DecodeError> {
+        let mut recorder = Recorder {
+            inner: buf,
+            out: Vec::new(),
+        };
+
+        // `skip_field` already knows how to find the end of any field, including nested groups,
+        // so we just keep a copy of everything it skips over.
+        prost::encoding::skip_field(wire_type, tag.get(), &mut recorder, ctx)?;
+
+        self.fields.push(UnknownField {
+            tag,
+            wire_type,
+            value: recorder.out,
+        });
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a UnknownFields {
+    type Item = &'a UnknownField;
+    type IntoIter = std::slice::Iter<'a, UnknownField>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IsDefault for UnknownFields {
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+/// Each field is encoded with its own tag, so the tag passed here is ignored.
+impl ProtoEncode for UnknownFields {
+    fn encode_as_field(&self, _tag: NonZeroU32, mut buf: &mut dyn BufMut) {
+        for field in &self.fields {
+            prost::encoding::encode_key(field.tag.get(), field.wire_type, &mut buf);
+            buf.put_slice(&field.value);
+        }
+    }
+
+    fn encoded_len_as_field(&self, _tag: NonZeroU32) -> usize {
+        self.fields
+            .iter()
+            .map(|field| prost::encoding::key_len(field.tag.get()) + field.value.len())
+            .sum()
+    }
+}
+
+/// A `Buf` which copies every byte that is consumed from it.
+struct Recorder<'a> {
+    inner: &'a mut dyn Buf,
+    out: Vec<u8>,
+}
+
+impl Buf for Recorder<'_> {
+    fn remaining(&self) -> usize {
+        self.inner.remaining()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.inner.chunk()
+    }
+
+    fn advance(&mut self, mut cnt: usize) {
+        while cnt > 0 {
+            let chunk = self.inner.chunk();
+            let len = chunk.len().min(cnt);
+
+            assert!(len > 0, "advance past the end of the buffer");
+
+            self.out.extend_from_slice(&chunk[..len]);
+            self.inner.advance(len);
+            cnt -= len;
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! The well-known types from the `google.protobuf` package, for talking to APIs that use them
+//! without generating code from their `.proto` files. These are derived like any other message,
+//! and are encoded the same as the types in `prost_types`, but specs and descriptors import them
+//! from their files in `google/protobuf` instead of defining them, and they use the special
+//! representations that the proto3 JSON mapping gives them.
+//!
+//! `Timestamp` and `Duration` aren't defined here, as `std::time::SystemTime` and
+//! `std::time::Duration` (or `prost_types::Timestamp` and `prost_types::Duration`) can be used
+//! directly.
+//!
+//! ```rust
+//! use autoproto::wkt::{Int32Value, ListValue, Struct, Value};
+//!
+//! #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+//! struct Query {
+//!     limit: Option<Int32Value>,
+//!     filter: Struct,
+//! }
+//!
+//! let mut query = Query {
+//!     limit: Some(10.into()),
+//!     ..Default::default()
+//! };
+//!
+//! query.filter.fields.insert("name".into(), "foo".into());
+//! query.filter.fields.insert(
+//!     "tags".into(),
+//!     ListValue { values: vec![1.0.into(), true.into()] }.into(),
+//! );
+//!
+//! // Wrappers are written as their value, and `Struct` as an arbitrary JSON object.
+//! assert_eq!(
+//!     autoproto::json::to_json(&query),
+//!     r#"{"filter":{"name":"foo","tags":[1.0,true]},"limit":10}"#,
+//! );
+//! assert_eq!(
+//!     autoproto::json::from_json::<Query>(&autoproto::json::to_json(&query)).unwrap(),
+//!     query,
+//! );
+//! ```
+
+use crate::{
+    bytes::Bytes,
+    json::JsonError,
+    prost::Message,
+    registry::{type_name, TypeRegistry, TypeUrl},
+    ProtoJson, ToProtoSpec,
+};
+use serde_json::{Map, Value as JsonValue};
+use std::collections::BTreeMap;
+
+macro_rules! wrappers {
+    ($($(#[$attr:meta])* $name:ident($ty:ty);)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, PartialEq, Default, Debug, crate::Message)]
+            #[autoproto(path(crate), well_known = "google/protobuf/wrappers.proto")]
+            pub struct $name {
+                // Like scalars, empty strings and bytes are left out, as `prost-types` does.
+                #[autoproto(skip_default)]
+                pub value: $ty,
+            }
+
+            impl From<$ty> for $name {
+                fn from(value: $ty) -> Self {
+                    Self { value }
+                }
+            }
+
+            impl From<$name> for $ty {
+                fn from(wrapper: $name) -> Self {
+                    wrapper.value
+                }
+            }
+
+            // Wrappers are written as the value they wrap, so that an `Option` of a wrapper is
+            // written the same as a nullable scalar.
+            impl ProtoJson for $name {
+                fn to_json_value(&self) -> JsonValue {
+                    self.value.to_json_value()
+                }
+
+                fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+                    self.value.merge_json_value(value)
+                }
+            }
+        )*
+    };
+}
+
+wrappers! {
+    /// `google.protobuf.DoubleValue`, a `double` which can be distinguished from zero when wrapped
+    /// in an `Option`.
+    DoubleValue(f64);
+    /// `google.protobuf.FloatValue`, a `float` which can be distinguished from zero when wrapped in
+    /// an `Option`.
+    FloatValue(f32);
+    /// `google.protobuf.Int64Value`, an `int64` which can be distinguished from zero when wrapped in
+    /// an `Option`.
+    Int64Value(i64);
+    /// `google.protobuf.UInt64Value`, a `uint64` which can be distinguished from zero when wrapped
+    /// in an `Option`.
+    UInt64Value(u64);
+    /// `google.protobuf.Int32Value`, an `int32` which can be distinguished from zero when wrapped in
+    /// an `Option`.
+    Int32Value(i32);
+    /// `google.protobuf.UInt32Value`, a `uint32` which can be distinguished from zero when wrapped
+    /// in an `Option`.
+    UInt32Value(u32);
+    /// `google.protobuf.BoolValue`, a `bool` which can be distinguished from `false` when wrapped in
+    /// an `Option`.
+    BoolValue(bool);
+    /// `google.protobuf.StringValue`, a `string` which can be distinguished from the empty string
+    /// when wrapped in an `Option`.
+    StringValue(String);
+    /// `google.protobuf.BytesValue`, a `bytes` which can be distinguished from empty bytes when
+    /// wrapped in an `Option`.
+    BytesValue(Bytes);
+}
+
+/// `google.protobuf.Empty`, for methods which take or return nothing. This is encoded the same as
+/// `()`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug, crate::Message)]
+#[autoproto(path(crate), well_known = "google/protobuf/empty.proto")]
+pub struct Empty {}
+
+impl ProtoJson for Empty {
+    fn to_json_value(&self) -> JsonValue {
+        ().to_json_value()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        ().merge_json_value(value)
+    }
+}
+
+/// `google.protobuf.NullValue`, the only value of which is `null`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug, crate::ProtoScalar)]
+#[autoproto(path(crate), well_known = "google/protobuf/struct.proto")]
+pub enum NullValue {
+    #[default]
+    NullValue = 0,
+}
+
+impl ProtoJson for NullValue {
+    fn to_json_value(&self) -> JsonValue {
+        JsonValue::Null
+    }
+
+    fn is_json_default(&self) -> bool {
+        true
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        match value {
+            JsonValue::Null => Ok(()),
+            // Although it is written as `null`, the name of the value is also accepted.
+            JsonValue::String(s) if s == "NULL_VALUE" => Ok(()),
+            other => Err(JsonError::expected("null", &other)),
+        }
+    }
+}
+
+/// `google.protobuf.Struct`, an arbitrary JSON object.
+#[derive(Clone, PartialEq, Default, Debug, crate::Message)]
+#[autoproto(path(crate), well_known = "google/protobuf/struct.proto")]
+pub struct Struct {
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl ProtoJson for Struct {
+    fn to_json_value(&self) -> JsonValue {
+        self.fields.to_json_value()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        self.fields.merge_json_value(value)
+    }
+}
+
+/// `google.protobuf.Value`, an arbitrary JSON value. The variants are the same as the `kind`
+/// oneof, so this encodes the same as `prost_types::Value` with `kind` set.
+#[derive(Clone, PartialEq, Debug, crate::Message)]
+#[autoproto(path(crate), well_known = "google/protobuf/struct.proto")]
+pub enum Value {
+    NullValue(NullValue),
+    NumberValue(f64),
+    StringValue(String),
+    BoolValue(bool),
+    StructValue(Struct),
+    ListValue(ListValue),
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::NullValue(NullValue::NullValue)
+    }
+}
+
+impl ProtoJson for Value {
+    fn to_json_value(&self) -> JsonValue {
+        match self {
+            Self::NullValue(_) => JsonValue::Null,
+            Self::NumberValue(number) => number.to_json_value(),
+            Self::StringValue(string) => JsonValue::String(string.clone()),
+            Self::BoolValue(boolean) => JsonValue::Bool(*boolean),
+            Self::StructValue(object) => object.to_json_value(),
+            Self::ListValue(list) => list.to_json_value(),
+        }
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        *self = match value {
+            JsonValue::Null => Self::NullValue(NullValue::NullValue),
+            JsonValue::Bool(boolean) => Self::BoolValue(boolean),
+            JsonValue::Number(number) => Self::NumberValue(
+                number
+                    .as_f64()
+                    .ok_or_else(|| JsonError::new(format!("Invalid number `{}`", number)))?,
+            ),
+            JsonValue::String(string) => Self::StringValue(string),
+            value @ JsonValue::Array(_) => {
+                let mut list = ListValue::default();
+
+                list.merge_json_value(value)?;
+
+                Self::ListValue(list)
+            }
+            value @ JsonValue::Object(_) => {
+                let mut object = Struct::default();
+
+                object.merge_json_value(value)?;
+
+                Self::StructValue(object)
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl From<NullValue> for Value {
+    fn from(null: NullValue) -> Self {
+        Self::NullValue(null)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Self::NumberValue(number)
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Self::StringValue(string)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Self::StringValue(string.to_owned())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Self::BoolValue(boolean)
+    }
+}
+
+impl From<Struct> for Value {
+    fn from(object: Struct) -> Self {
+        Self::StructValue(object)
+    }
+}
+
+impl From<ListValue> for Value {
+    fn from(list: ListValue) -> Self {
+        Self::ListValue(list)
+    }
+}
+
+/// `google.protobuf.ListValue`, an arbitrary JSON array.
+#[derive(Clone, PartialEq, Default, Debug, crate::Message)]
+#[autoproto(path(crate), well_known = "google/protobuf/struct.proto")]
+pub struct ListValue {
+    pub values: Vec<Value>,
+}
+
+impl ProtoJson for ListValue {
+    fn to_json_value(&self) -> JsonValue {
+        self.values.to_json_value()
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        self.values.merge_json_value(value)
+    }
+}
+
+/// `google.protobuf.Any`, a message of any type along with a URL identifying the type, such as
+/// `type.googleapis.com/google.protobuf.Int32Value`.
+///
+/// In JSON, well-known types are written in the `value` field in their own JSON representation.
+/// Other messages can't be converted without knowing their type, so their encoded bytes are
+/// written in `value` as base64 instead.
+#[derive(Clone, PartialEq, Default, Debug, crate::Message)]
+#[autoproto(path(crate), well_known = "google/protobuf/any.proto")]
+pub struct Any {
+    #[autoproto(skip_default)]
+    pub type_url: String,
+    #[autoproto(skip_default)]
+    pub value: Bytes,
+}
+
+impl Any {
+    /// The fully-qualified name of the type of the message, which is the last segment of
+    /// `type_url`.
+    pub fn type_name(&self) -> &str {
+        type_name(&self.type_url)
+    }
+}
+
+// Calls `$mac!` with every well-known type that can be packed in an `Any`.
+macro_rules! for_each_well_known {
+    ($mac:ident) => {
+        $mac!(
+            DoubleValue,
+            FloatValue,
+            Int64Value,
+            UInt64Value,
+            Int32Value,
+            UInt32Value,
+            BoolValue,
+            StringValue,
+            BytesValue,
+            Empty,
+            Struct,
+            Value,
+            ListValue,
+            Any,
+            prost_types::Timestamp,
+            prost_types::Duration
+        )
+    };
+}
+
+macro_rules! type_urls {
+    ($($t:ty: $name:ident),*) => {
+        $(
+            impl TypeUrl for $t {
+                fn type_url() -> &'static str {
+                    concat!("type.googleapis.com/google.protobuf.", stringify!($name))
+                }
+            }
+        )*
+    };
+}
+
+type_urls!(
+    DoubleValue: DoubleValue,
+    FloatValue: FloatValue,
+    Int64Value: Int64Value,
+    UInt64Value: UInt64Value,
+    Int32Value: Int32Value,
+    UInt32Value: UInt32Value,
+    BoolValue: BoolValue,
+    StringValue: StringValue,
+    BytesValue: BytesValue,
+    Empty: Empty,
+    Struct: Struct,
+    Value: Value,
+    ListValue: ListValue,
+    Any: Any,
+    prost_types::Timestamp: Timestamp,
+    prost_types::Duration: Duration
+);
+
+/// Add every well-known type to a registry.
+pub(crate) fn register(registry: &mut TypeRegistry) {
+    macro_rules! register {
+        ($($t:ty),*) => {
+            $(
+                registry.register::<$t>();
+            )*
+        };
+    }
+
+    for_each_well_known!(register);
+}
+
+/// The JSON of the `value` of an `Any`, which is written in its own JSON representation if it's
+/// a well-known type. Otherwise the bytes are written as base64, unless they are empty.
+fn payload_to_json(type_name: &str, value: &Bytes) -> Option<JsonValue> {
+    macro_rules! to_json {
+        ($($t:ty),*) => {
+            $(
+                if type_name == <$t as ToProtoSpec>::proto_name() {
+                    if let Ok(message) = <$t as Message>::decode(&value[..]) {
+                        return Some(message.to_json_value());
+                    }
+                }
+            )*
+        };
+    }
+
+    for_each_well_known!(to_json);
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_json_value())
+    }
+}
+
+/// Encode the `value` of an `Any` from its JSON, the opposite of `payload_to_json`.
+fn payload_from_json(type_name: &str, value: JsonValue) -> Result<Bytes, JsonError> {
+    macro_rules! from_json {
+        ($($t:ty),*) => {
+            $(
+                if type_name == <$t as ToProtoSpec>::proto_name() {
+                    let mut message = <$t>::default();
+
+                    message.merge_json_value(value)?;
+
+                    return Ok(message.encode_to_vec().into());
+                }
+            )*
+        };
+    }
+
+    for_each_well_known!(from_json);
+
+    let mut bytes = Bytes::new();
+
+    if !value.is_null() {
+        bytes.merge_json_value(value)?;
+    }
+
+    Ok(bytes)
+}
+
+impl ProtoJson for Any {
+    fn to_json_value(&self) -> JsonValue {
+        let mut object = Map::new();
+
+        if !self.type_url.is_empty() {
+            object.insert("@type".into(), JsonValue::String(self.type_url.clone()));
+        }
+
+        if let Some(value) = payload_to_json(self.type_name(), &self.value) {
+            object.insert("value".into(), value);
+        }
+
+        JsonValue::Object(object)
+    }
+
+    fn merge_json_value(&mut self, value: JsonValue) -> Result<(), JsonError> {
+        let mut object = match value {
+            JsonValue::Object(object) => object,
+            other => return Err(JsonError::expected("an object", &other)),
+        };
+
+        let type_url = match object.remove("@type") {
+            Some(JsonValue::String(type_url)) => type_url,
+            Some(other) => return Err(JsonError::expected("a string", &other)),
+            None => return Err(JsonError::new("Expected an `@type` field")),
+        };
+
+        if let Some(name) = object.keys().find(|name| *name != "value") {
+            return Err(JsonError::unknown_field(name));
+        }
+
+        let value = object.remove("value").unwrap_or(JsonValue::Null);
+
+        self.value = payload_from_json(type_name(&type_url), value)?;
+        self.type_url = type_url;
+
+        Ok(())
+    }
+}
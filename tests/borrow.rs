@@ -0,0 +1,241 @@
+use autoproto::{
+    borrow::{self, ProtoBorrow},
+    prost::{bytes::Bytes, encoding, Message},
+    UnknownFields,
+};
+use std::ops::Range;
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Inner {
+    id: u32,
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Entry {
+    name: String,
+    data: Box<[u8]>,
+    count: u64,
+    values: Vec<u32>,
+    inner: Option<Inner>,
+    owned: String,
+}
+
+#[derive(PartialEq, Default, Debug, autoproto::ProtoEncode, autoproto::ProtoBorrow)]
+struct InnerRef<'a> {
+    id: u32,
+    name: &'a str,
+}
+
+#[derive(PartialEq, Default, Debug, autoproto::ProtoEncode, autoproto::ProtoBorrow)]
+struct EntryRef<'a> {
+    name: &'a str,
+    data: &'a [u8],
+    count: u64,
+    values: Vec<u32>,
+    inner: Option<InnerRef<'a>>,
+    owned: Bytes,
+}
+
+fn points_into(buf: &[u8], ptr: *const u8) -> bool {
+    let Range { start, end } = buf.as_ptr_range();
+
+    ptr >= start && ptr < end
+}
+
+fn entry() -> Entry {
+    Entry {
+        name: "foo".into(),
+        data: vec![1, 2, 3].into(),
+        count: 4,
+        values: vec![5, 6, 7],
+        inner: Some(Inner {
+            id: 8,
+            name: "bar".into(),
+        }),
+        owned: "baz".into(),
+    }
+}
+
+#[test]
+fn borrowed_fields() {
+    let entry = entry();
+    let buf = entry.encode_to_vec();
+
+    let borrowed: EntryRef = borrow::decode(&buf).unwrap();
+
+    assert_eq!(borrowed.name, entry.name);
+    assert_eq!(borrowed.data, &entry.data[..]);
+    assert_eq!(borrowed.count, entry.count);
+    assert_eq!(borrowed.values, entry.values);
+    assert_eq!(borrowed.inner, Some(InnerRef { id: 8, name: "bar" }));
+    assert_eq!(borrowed.owned, "baz");
+
+    assert!(points_into(&buf, borrowed.name.as_ptr()));
+    assert!(points_into(&buf, borrowed.data.as_ptr()));
+    assert!(points_into(
+        &buf,
+        borrowed.inner.as_ref().unwrap().name.as_ptr()
+    ));
+    assert!(!points_into(&buf, borrowed.owned.as_ptr()));
+
+    // Borrowed messages encode in the same way as the owned messages they were decoded from.
+    let mut reencoded = Vec::new();
+    autoproto::generic::protostruct::message_encode_raw(&borrowed, &mut reencoded);
+    assert_eq!(reencoded, buf);
+}
+
+#[derive(PartialEq, Default, Debug, autoproto::ProtoBorrow)]
+struct Repeated<'a> {
+    #[autoproto(tag = 1)]
+    names: Vec<&'a str>,
+    #[autoproto(tag = 2)]
+    chunks: Vec<&'a [u8]>,
+    #[autoproto(tag = 3)]
+    inners: Vec<InnerRef<'a>>,
+    #[autoproto(tag = 4, fixed)]
+    fixed: Vec<u64>,
+}
+
+#[test]
+fn repeated_fields() {
+    let names = vec!["foo".to_string(), String::new(), "bar".into()];
+    let chunks = vec![vec![1u8], vec![2, 3]];
+    let inners = vec![
+        Inner {
+            id: 1,
+            name: "a".into(),
+        },
+        Inner::default(),
+    ];
+
+    let mut buf = Vec::new();
+    encoding::string::encode_repeated(1, &names, &mut buf);
+    encoding::bytes::encode_repeated(2, &chunks, &mut buf);
+    encoding::message::encode_repeated(3, &inners, &mut buf);
+    encoding::fixed64::encode_packed(4, &[1, 2], &mut buf);
+
+    let repeated: Repeated = borrow::decode(&buf).unwrap();
+
+    assert_eq!(repeated.names, ["foo", "", "bar"]);
+    assert_eq!(repeated.chunks, [&[1][..], &[2, 3][..]]);
+    assert_eq!(
+        repeated.inners,
+        [InnerRef { id: 1, name: "a" }, InnerRef::default()]
+    );
+    assert_eq!(repeated.fixed, [1, 2]);
+    assert!(repeated
+        .names
+        .iter()
+        .all(|name| name.is_empty() || points_into(&buf, name.as_ptr())));
+}
+
+#[derive(PartialEq, Default, Debug, autoproto::ProtoBorrow)]
+struct Generic<'a, T> {
+    #[autoproto(required)]
+    value: T,
+    #[autoproto(unknown_fields)]
+    unknown: UnknownFields,
+    name: &'a str,
+}
+
+#[test]
+fn generic_and_unknown_fields() {
+    let mut buf = Vec::new();
+    encoding::uint32::encode(1, &5, &mut buf);
+    encoding::string::encode(2, &"foo".to_string(), &mut buf);
+    encoding::string::encode(3, &"unknown".to_string(), &mut buf);
+
+    let generic: Generic<u32> = borrow::decode(&buf).unwrap();
+
+    assert_eq!(generic.value, 5);
+    assert_eq!(generic.name, "foo");
+    assert_eq!(generic.unknown.len(), 1);
+
+    let mut buf = Vec::new();
+    encoding::message::encode(
+        1,
+        &Inner {
+            id: 6,
+            name: "bar".into(),
+        },
+        &mut buf,
+    );
+
+    let nested: Generic<InnerRef> = borrow::decode(&buf).unwrap();
+    assert_eq!(nested.value, InnerRef { id: 6, name: "bar" });
+    assert!(nested.name.is_empty());
+
+    // Merging into an existing message overwrites scalars and borrows the new strings.
+    let mut merged = generic;
+    let other = [0x08, 0x07, 0x12, 0x01, b'x'];
+    borrow::merge(&mut merged, &other).unwrap();
+    assert_eq!(merged.value, 7);
+    assert_eq!(merged.name, "x");
+}
+
+#[derive(PartialEq, Default, Debug, autoproto::ProtoBorrow)]
+struct Nested<'a> {
+    single: Option<Generic<'a, u32>>,
+    list: Vec<Generic<'a, u32>>,
+}
+
+#[test]
+fn required_fields_of_merged_messages() {
+    // The required field only needs to appear in one of the occurrences of `single`.
+    let nested: Nested =
+        borrow::decode(&[0x0a, 0x02, 0x08, 0x05, 0x0a, 0x03, 0x12, 0x01, b'x']).unwrap();
+    let single = nested.single.unwrap();
+    assert_eq!((single.value, single.name), (5, "x"));
+
+    // But each element of `list` is a separate message.
+    assert!(
+        borrow::decode::<Nested>(&[0x12, 0x02, 0x08, 0x05, 0x12, 0x03, 0x12, 0x01, b'x']).is_err()
+    );
+}
+
+#[test]
+fn errors() {
+    // A required field is missing.
+    let err = borrow::decode::<Generic<u32>>(&[]).unwrap_err();
+    assert!(err.to_string().contains("Missing required field `value`"));
+
+    // Strings are checked to be UTF-8.
+    let mut buf = Vec::new();
+    encoding::bytes::encode(2, &vec![0xff, 0xfe], &mut buf);
+    assert!(borrow::decode::<InnerRef>(&buf).is_err());
+
+    // Lengths past the end of the buffer.
+    assert!(borrow::decode::<InnerRef>(&[0x12, 0x05, b'a']).is_err());
+
+    // The wrong wire type.
+    let mut name = "";
+    assert!(name
+        .merge_borrowed(
+            encoding::WireType::Varint,
+            &mut &[0x01][..],
+            Default::default()
+        )
+        .is_err());
+}
+
+#[cfg(feature = "beef")]
+#[test]
+fn borrowed_cow() {
+    #[derive(PartialEq, Default, Debug, autoproto::ProtoBorrow)]
+    struct Named<'a> {
+        name: beef::Cow<'a, str>,
+        lean: beef::lean::Cow<'a, str>,
+    }
+
+    let mut buf = Vec::new();
+    encoding::string::encode(1, &"foo".to_string(), &mut buf);
+    encoding::string::encode(2, &"bar".to_string(), &mut buf);
+
+    let named: Named = borrow::decode(&buf).unwrap();
+
+    assert_eq!(named.name, "foo");
+    assert_eq!(named.lean, "bar");
+    assert!(named.name.is_borrowed() && named.lean.is_borrowed());
+    assert!(points_into(&buf, named.name.as_ptr()));
+}
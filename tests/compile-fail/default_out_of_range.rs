@@ -0,0 +1,13 @@
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+struct Settings {
+    #[autoproto(default = 300)]
+    retries: u8,
+    #[autoproto(default = "-1")]
+    timeout: u32,
+    #[autoproto(default = 2)]
+    enabled: bool,
+    #[autoproto(default = 42)]
+    count: i32,
+}
+
+fn main() {}
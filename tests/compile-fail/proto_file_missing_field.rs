@@ -0,0 +1,9 @@
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(proto_file = "../../../../tests/protos/compile_fail.proto")]
+struct Point {
+    x: u32,
+    #[autoproto(encoding = "sint64")]
+    y: i64,
+}
+
+fn main() {}
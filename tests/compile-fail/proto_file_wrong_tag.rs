@@ -0,0 +1,12 @@
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(proto_file = "../../../../tests/protos/compile_fail.proto")]
+struct Point {
+    #[autoproto(tag = 1)]
+    x: u32,
+    #[autoproto(tag = 2, encoding = "sint64")]
+    y: i64,
+    #[autoproto(tag = 4)]
+    label: String,
+}
+
+fn main() {}
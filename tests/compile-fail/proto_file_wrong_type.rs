@@ -0,0 +1,9 @@
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(proto_file = "../../../../tests/protos/compile_fail.proto")]
+struct Point {
+    x: String,
+    y: i64,
+    label: String,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+
+    // These are built from `target/tests/trybuild/autoproto`, so `proto_file` paths in them are
+    // relative to that directory.
+    t.compile_fail("tests/compile-fail/*.rs");
+}
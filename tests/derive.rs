@@ -2,7 +2,7 @@
 #![allow(dead_code)]
 
 use autoproto::prost::Message;
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use quickcheck::TestResult;
 use quickcheck_macros::quickcheck;
@@ -77,6 +77,29 @@ impl<A: DummyOne, B: DummyTwo, C: DummyThree> Default for Oneof<A, B, C> {
     }
 }
 
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+enum MessageOneof {
+    #[default]
+    Nothing,
+    One(u32),
+    Two(u32, u64),
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Proto)]
+enum ProtoOnlyOneof<A> {
+    #[default]
+    Nothing,
+    One(A),
+    Two(A, u64),
+}
+
+#[derive(autoproto::ProtoEncode)]
+enum EncodeOnlyOneof {
+    Nothing,
+    One(u32),
+    Two(u32, u64),
+}
+
 type WithOptionals = SomeStruct<Option<Foo<u32, u64>>, Option<SomeStruct<f32, Option<Unit>>>>;
 
 impl From<Wrapper> for WithOptionals {
@@ -108,6 +131,22 @@ static_assertions::assert_not_impl_any!(
 static_assertions::assert_impl_all!(Wrapper: Message, autoproto::Proto);
 static_assertions::assert_not_impl_any!(Wrapper: autoproto::ProtoStruct, autoproto::ProtoStructMut);
 
+static_assertions::assert_impl_all!(
+    ProtoOnlyOneof<u32>: autoproto::Proto,
+    autoproto::ProtoOneof,
+    autoproto::ProtoOneofMut
+);
+static_assertions::assert_not_impl_any!(ProtoOnlyOneof<u32>: Message);
+static_assertions::assert_impl_all!(
+    EncodeOnlyOneof: autoproto::ProtoEncode,
+    autoproto::ProtoOneof
+);
+static_assertions::assert_not_impl_any!(
+    EncodeOnlyOneof: Message,
+    autoproto::Proto,
+    autoproto::ProtoOneofMut
+);
+
 const _: fn() = || {
     fn assert_impl<T: Message + autoproto::Proto>() {}
 
@@ -458,6 +497,143 @@ fn repeated_messages_same_as_prost(a: Vec<(u32, u64)>, b: Vec<(u64, u32)>) {
     assert_eq!(round_trip(&prost_msg), round_trip(&autoproto_msg));
 }
 
+#[quickcheck]
+fn unknown_fields_round_trip(a: u32, b: u32, c: String, d: u64, e: (u32, u64), f: Vec<u64>) {
+    #[derive(PartialEq, ::prost::Message)]
+    struct ProstMsg {
+        #[prost(uint32, tag = 1)]
+        a: u32,
+        #[prost(fixed32, tag = 2)]
+        b: u32,
+        #[prost(string, tag = 3)]
+        c: String,
+        #[prost(fixed64, tag = 4)]
+        d: u64,
+        #[prost(message, required, tag = 5)]
+        e: Foo<u32, u64>,
+        #[prost(uint64, repeated, tag = 6)]
+        f: Vec<u64>,
+    }
+
+    #[derive(PartialEq, Default, Debug, autoproto::Message)]
+    struct AutoprotoMsg {
+        #[autoproto(unknown_fields)]
+        unknown: autoproto::UnknownFields,
+        a: u32,
+    }
+
+    let mut prost_msg = ProstMsg {
+        a,
+        b,
+        c,
+        d,
+        e: Foo(e.0, e.1),
+        f,
+    };
+    let encoded = prost_msg.encode_to_vec();
+    let mut autoproto_msg = AutoprotoMsg::decode(&encoded[..]).unwrap();
+
+    assert_eq!(autoproto_msg.a, a);
+    assert_eq!(autoproto_msg.encode_to_vec(), encoded);
+    assert!(autoproto_msg
+        .unknown
+        .iter()
+        .all(|field| field.tag().get() != 1));
+
+    autoproto_msg.a = a.wrapping_add(1);
+    prost_msg.a = a.wrapping_add(1);
+
+    assert_eq!(
+        ProstMsg::decode(&autoproto_msg.encode_to_vec()[..]).unwrap(),
+        prost_msg
+    );
+}
+
+#[test]
+fn unknown_fields_keep_groups() {
+    #[derive(PartialEq, Default, Debug, autoproto::Message)]
+    struct AutoprotoMsg {
+        #[autoproto(tag = 1)]
+        a: u32,
+        #[autoproto(unknown_fields)]
+        unknown: autoproto::UnknownFields,
+    }
+
+    // Field 1 = 5, then group 2 containing field 1 = 7 and an empty group 3, then field 4 = "hi".
+    let encoded = [
+        0x08, 0x05, 0x13, 0x08, 0x07, 0x1b, 0x1c, 0x14, 0x22, 0x02, b'h', b'i',
+    ];
+    let msg = AutoprotoMsg::decode(&encoded[..]).unwrap();
+
+    assert_eq!(msg.a, 5);
+    assert_eq!(
+        msg.unknown
+            .iter()
+            .map(|field| (field.tag().get(), field.value()))
+            .collect::<Vec<_>>(),
+        [
+            (2, &[0x08, 0x07, 0x1b, 0x1c, 0x14][..]),
+            (4, &[0x02, b'h', b'i'][..])
+        ]
+    );
+    assert_eq!(msg.encode_to_vec(), encoded);
+    assert_eq!(msg.encoded_len(), encoded.len());
+}
+
+#[quickcheck]
+fn encoding_attributes_same_as_prost(
+    a: i64,
+    b: u32,
+    c: i32,
+    d: Vec<i32>,
+    e: Vec<u64>,
+    f: BTreeMap<u32, i32>,
+) {
+    #[derive(PartialEq, ::prost::Message)]
+    struct ProstMsg {
+        #[prost(sint64, tag = 1)]
+        a: i64,
+        #[prost(fixed32, tag = 2)]
+        b: u32,
+        #[prost(sint32, tag = 3)]
+        c: i32,
+        #[prost(sfixed32, repeated, packed = "false", tag = 4)]
+        d: Vec<i32>,
+        #[prost(uint64, repeated, packed = "false", tag = 5)]
+        e: Vec<u64>,
+        #[prost(btree_map = "uint32, sint32", tag = 6)]
+        f: BTreeMap<u32, i32>,
+    }
+
+    #[derive(PartialEq, Default, Debug, autoproto::Message)]
+    struct AutoprotoMsg {
+        #[autoproto(encoding = "sint64")]
+        a: i64,
+        #[autoproto(fixed)]
+        b: u32,
+        #[autoproto(encoding = "sint32")]
+        c: i32,
+        #[autoproto(encoding = "sfixed32", packed = false)]
+        d: Vec<i32>,
+        #[autoproto(packed = false)]
+        e: Vec<u64>,
+        #[autoproto(encoding = "sint32")]
+        f: BTreeMap<u32, i32>,
+    }
+
+    let prost_msg = ProstMsg {
+        a,
+        b,
+        c,
+        d: d.clone(),
+        e: e.clone(),
+        f: f.clone(),
+    };
+    let autoproto_msg = AutoprotoMsg { a, b, c, d, e, f };
+
+    assert_eq!(round_trip(&prost_msg), round_trip(&autoproto_msg));
+}
+
 #[quickcheck]
 fn other_repeated_types(a: HashSet<(u32, u64)>) {
     let foos = a
@@ -542,3 +718,2441 @@ fn repeated_enumerations(vals: Vec<u8>) {
         round_trip(&RepeatedInts(ints))
     );
 }
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+enum NestedOneof {
+    #[autoproto(tag = 2)]
+    Text(String),
+    #[autoproto(tag = 3)]
+    Pair(Foo<u32, u64>),
+}
+
+impl Default for NestedOneof {
+    fn default() -> Self {
+        Self::Text(Default::default())
+    }
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(proto_file = "tests/protos/derive.proto")]
+struct WithNestedOneof {
+    #[autoproto(tag = 1)]
+    name: String,
+    #[autoproto(oneof, tags = "2, 3")]
+    choice: Option<NestedOneof>,
+    #[autoproto(tag = 4)]
+    count: u32,
+}
+
+fn make_oneofs(choice: Option<(u32, Option<u64>)>) -> (MessageOneof, ProtoOnlyOneof<u32>) {
+    match choice {
+        None => (MessageOneof::Nothing, ProtoOnlyOneof::Nothing),
+        Some((a, None)) => (MessageOneof::One(a), ProtoOnlyOneof::One(a)),
+        Some((a, Some(b))) => (MessageOneof::Two(a, b), ProtoOnlyOneof::Two(a, b)),
+    }
+}
+
+#[quickcheck]
+fn proto_enum_same_as_message_enum(choice: Option<(u32, Option<u64>)>, count: u32) -> TestResult {
+    // TODO: A newtype variant containing a default value encodes nothing, so it decodes as a
+    // different variant.
+    if let Some((0, None)) = choice {
+        return TestResult::discard();
+    }
+
+    let (message_oneof, proto_oneof) = make_oneofs(choice);
+
+    assert_eq!(
+        round_trip(&SomeStruct {
+            a: message_oneof,
+            b: count
+        }),
+        round_trip(&SomeStruct {
+            a: proto_oneof,
+            b: count
+        }),
+    );
+
+    TestResult::passed()
+}
+
+#[quickcheck]
+fn protoencode_enum_same_as_message_enum(choice: Option<(u32, Option<u64>)>) {
+    use autoproto::ProtoEncode;
+
+    let (message_oneof, _) = make_oneofs(choice);
+    let encode_oneof = match message_oneof {
+        MessageOneof::Nothing => EncodeOnlyOneof::Nothing,
+        MessageOneof::One(a) => EncodeOnlyOneof::One(a),
+        MessageOneof::Two(a, b) => EncodeOnlyOneof::Two(a, b),
+    };
+    let tag = std::num::NonZeroU32::new(1).unwrap();
+
+    let mut expected = vec![];
+    message_oneof.encode_as_field(tag, &mut expected);
+
+    let mut actual = vec![];
+    encode_oneof.encode_as_field(tag, &mut actual);
+
+    assert_eq!(actual, expected);
+    assert_eq!(encode_oneof.encoded_len_as_field(tag), expected.len());
+}
+
+#[quickcheck]
+fn nested_oneof_same_as_prost(
+    name: String,
+    choice: Option<Result<String, (u32, u64)>>,
+    count: u32,
+) -> TestResult {
+    // TODO: Empty strings are currently always encoded, whereas `prost` skips them.
+    if name.is_empty() {
+        return TestResult::discard();
+    }
+
+    #[derive(PartialEq, ::prost::Message)]
+    struct ProstMsg {
+        #[prost(string, tag = 1)]
+        name: String,
+        #[prost(oneof = "ProstOneof", tags = "2, 3")]
+        choice: Option<ProstOneof>,
+        #[prost(uint32, tag = 4)]
+        count: u32,
+    }
+
+    #[derive(PartialEq, ::prost::Oneof)]
+    enum ProstOneof {
+        #[prost(string, tag = 2)]
+        Text(String),
+        #[prost(message, tag = 3)]
+        Pair(Foo<u32, u64>),
+    }
+
+    let prost_msg = ProstMsg {
+        name: name.clone(),
+        choice: choice.clone().map(|choice| match choice {
+            Ok(text) => ProstOneof::Text(text),
+            Err((a, b)) => ProstOneof::Pair(Foo(a, b)),
+        }),
+        count,
+    };
+    let autoproto_msg = WithNestedOneof {
+        name,
+        choice: choice.map(|choice| match choice {
+            Ok(text) => NestedOneof::Text(text),
+            Err((a, b)) => NestedOneof::Pair(Foo(a, b)),
+        }),
+        count,
+    };
+
+    assert_eq!(round_trip(&prost_msg), round_trip(&autoproto_msg));
+
+    TestResult::passed()
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithEnumeration {
+    kind: SomeEnumeration,
+    values: Vec<u64>,
+}
+
+#[test]
+fn proto_spec() {
+    use autoproto::ProtoFile;
+
+    assert_eq!(
+        ProtoFile::with_package("test")
+            .add::<WithNestedOneof>()
+            .add::<WithEnumeration>()
+            .to_string(),
+        "\
+syntax = \"proto3\";
+
+package test;
+
+message WithNestedOneof {
+    string name = 1;
+    oneof choice {
+        string text = 2;
+        FooUint32Uint64 pair = 3;
+    }
+    uint32 count = 4;
+}
+
+message FooUint32Uint64 {
+    uint32 field_0 = 4;
+    uint64 field_1 = 5;
+}
+
+message WithEnumeration {
+    SomeEnumeration kind = 1;
+    repeated uint64 values = 2;
+}
+
+enum SomeEnumeration {
+    SOME_ENUMERATION_A = 0;
+    SOME_ENUMERATION_B = 1;
+    SOME_ENUMERATION_C = 2;
+    SOME_ENUMERATION_D = 3;
+    SOME_ENUMERATION_E = 4;
+    SOME_ENUMERATION_F = 5;
+    SOME_ENUMERATION_G = 6;
+    SOME_ENUMERATION_H = 7;
+    SOME_ENUMERATION_I = 8;
+    SOME_ENUMERATION_MAX = 9;
+}
+"
+    );
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithEncodings {
+    #[autoproto(encoding = "sint64")]
+    x: i64,
+    #[autoproto(fixed)]
+    y: u32,
+    #[autoproto(encoding = "sfixed32", packed = false)]
+    history: Vec<i32>,
+    #[autoproto(encoding = "sint32")]
+    offsets: BTreeMap<String, i32>,
+}
+
+#[test]
+fn encoding_attributes_spec() {
+    use autoproto::ProtoFile;
+
+    assert_eq!(
+        ProtoFile::new().add::<WithEncodings>().to_string(),
+        "\
+syntax = \"proto3\";
+
+message WithEncodings {
+    sint64 x = 1;
+    fixed32 y = 2;
+    repeated sfixed32 history = 3 [packed = false];
+    map<string, sint32> offsets = 4;
+}
+"
+    );
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(proto_file = "tests/protos/derive.proto", message = "test.Checked")]
+struct CheckedAgainstProtoFile {
+    kind: SomeEnumeration,
+    values: Vec<u64>,
+    counts: BTreeMap<String, i32>,
+    data: [u8; 4],
+    nested: WithNestedOneof,
+}
+
+#[test]
+fn checked_against_proto_file() {
+    let msg = CheckedAgainstProtoFile {
+        kind: SomeEnumeration::B,
+        values: vec![1, 2, 3],
+        counts: vec![("a".to_owned(), -1)].into_iter().collect(),
+        data: [1, 2, 3, 4],
+        nested: WithNestedOneof {
+            name: "nested".to_owned(),
+            choice: Some(NestedOneof::Pair(Foo(1, 2))),
+            count: 3,
+        },
+    };
+
+    round_trip(&msg);
+}
+
+#[test]
+fn proto_descriptor() {
+    use autoproto::descriptor::{self, Label, Type};
+
+    #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+    struct WithMap {
+        counts: BTreeMap<String, i32>,
+        nested: WithNestedOneof,
+        kind: SomeEnumeration,
+    }
+
+    let set = descriptor::file_descriptor_set::<WithMap>("test.proto", Some("test"));
+    let file = &set.file[0];
+
+    assert_eq!(set.file.len(), 1);
+    assert_eq!(file.name(), "test.proto");
+    assert_eq!(file.package(), "test");
+    assert_eq!(file.syntax(), "proto3");
+    assert_eq!(
+        file.message_type
+            .iter()
+            .map(|msg| msg.name())
+            .collect::<Vec<_>>(),
+        ["WithMap", "WithNestedOneof", "FooUint32Uint64"]
+    );
+    assert_eq!(file.enum_type[0].name(), "SomeEnumeration");
+    assert_eq!(file.enum_type[0].value[9].name(), "SOME_ENUMERATION_MAX");
+    assert_eq!(file.enum_type[0].value[9].number(), 9);
+
+    let with_map = &file.message_type[0];
+    let counts = &with_map.field[0];
+
+    assert_eq!(counts.label(), Label::Repeated);
+    assert_eq!(counts.r#type(), Type::Message);
+    assert_eq!(counts.type_name(), ".test.WithMap.CountsEntry");
+    assert_eq!(with_map.nested_type[0].name(), "CountsEntry");
+    assert!(with_map.nested_type[0]
+        .options
+        .as_ref()
+        .unwrap()
+        .map_entry());
+    assert_eq!(with_map.nested_type[0].field[1].r#type(), Type::Int32);
+    assert_eq!(with_map.field[1].type_name(), ".test.WithNestedOneof");
+    assert_eq!(with_map.field[2].r#type(), Type::Enum);
+    assert_eq!(with_map.field[2].type_name(), ".test.SomeEnumeration");
+
+    let nested = &file.message_type[1];
+
+    assert_eq!(nested.oneof_decl[0].name(), "choice");
+    assert_eq!(
+        nested
+            .field
+            .iter()
+            .map(|field| (field.name(), field.number(), field.oneof_index))
+            .collect::<Vec<_>>(),
+        [
+            ("name", 1, None),
+            ("text", 2, Some(0)),
+            ("pair", 3, Some(0)),
+            ("count", 4, None)
+        ]
+    );
+    assert_eq!(nested.field[2].type_name(), ".test.FooUint32Uint64");
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithJsonTypes {
+    #[autoproto(json_name = "id")]
+    identifier: u64,
+    small_signed: i32,
+    ratio: f64,
+    enabled: bool,
+    label: String,
+    payload: autoproto::bytes::Bytes,
+    kind: SomeEnumeration,
+    values: Vec<i64>,
+    counts: BTreeMap<u32, String>,
+    nested: Option<WithNestedOneof>,
+}
+
+type NestedArgs = Option<(String, Option<(bool, u32, u64)>, u32)>;
+
+fn make_nested(nested: NestedArgs) -> Option<WithNestedOneof> {
+    nested.map(|(name, choice, count)| WithNestedOneof {
+        name,
+        choice: choice.map(|(is_text, a, b)| {
+            if is_text {
+                NestedOneof::Text(a.to_string())
+            } else {
+                NestedOneof::Pair(Foo(a, b))
+            }
+        }),
+        count,
+    })
+}
+
+#[quickcheck]
+fn json_round_trip(
+    (identifier, small_signed, ratio, enabled): (u64, i32, f64, bool),
+    (label, payload, kind): (String, Vec<u8>, u8),
+    (values, counts): (Vec<i64>, BTreeMap<u32, String>),
+    nested: NestedArgs,
+) -> TestResult {
+    if ratio.is_nan() {
+        return TestResult::discard();
+    }
+
+    let message = WithJsonTypes {
+        identifier,
+        small_signed,
+        ratio,
+        enabled,
+        label,
+        payload: payload.into(),
+        kind: convert_scalar(kind % 10).unwrap(),
+        values,
+        counts,
+        nested: make_nested(nested),
+    };
+
+    let json = autoproto::json::to_json(&message);
+
+    TestResult::from_bool(autoproto::json::from_json::<WithJsonTypes>(&json).unwrap() == message)
+}
+
+#[test]
+fn json_canonical_form() {
+    let message = WithJsonTypes {
+        identifier: 1 << 60,
+        small_signed: -5,
+        ratio: 0.5,
+        enabled: false,
+        label: String::new(),
+        payload: vec![1, 2, 3, 255].into(),
+        kind: SomeEnumeration::C,
+        values: vec![0, -1],
+        counts: vec![(1, "one".to_owned())].into_iter().collect(),
+        nested: Some(WithNestedOneof {
+            name: "name".into(),
+            choice: Some(NestedOneof::Pair(Foo(0, 2))),
+            count: 0,
+        }),
+    };
+
+    assert_eq!(
+        autoproto::json::to_json(&message),
+        "{\
+            \"counts\":{\"1\":\"one\"},\
+            \"id\":\"1152921504606846976\",\
+            \"kind\":\"SOME_ENUMERATION_C\",\
+            \"nested\":{\"name\":\"name\",\"pair\":{\"field1\":\"2\"}},\
+            \"payload\":\"AQID/w==\",\
+            \"ratio\":0.5,\
+            \"smallSigned\":-5,\
+            \"values\":[\"0\",\"-1\"]\
+        }",
+    );
+
+    assert_eq!(autoproto::json::to_json(&WithJsonTypes::default()), "{}");
+    assert_eq!(autoproto::json::to_json(&Unit), "{}");
+    assert_eq!(
+        autoproto::json::to_json(&MessageOneof::Two(1, 2)),
+        r#"{"two":{"field0":1,"field1":"2"}}"#,
+    );
+}
+
+#[test]
+fn json_parsing() {
+    use autoproto::json::from_json;
+
+    let message = from_json::<WithJsonTypes>(
+        r#"{
+            "identifier": 10,
+            "small_signed": "-3",
+            "ratio": "Infinity",
+            "enabled": true,
+            "label": null,
+            "payload": "AQID_w",
+            "kind": 4,
+            "values": [1, "2", 3e2],
+            "counts": {"7": "seven"},
+            "nested": {"text": "hello", "count": 1}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        message,
+        WithJsonTypes {
+            identifier: 10,
+            small_signed: -3,
+            ratio: f64::INFINITY,
+            enabled: true,
+            label: String::new(),
+            payload: vec![1, 2, 3, 255].into(),
+            kind: SomeEnumeration::E,
+            values: vec![1, 2, 300],
+            counts: vec![(7, "seven".to_owned())].into_iter().collect(),
+            nested: Some(WithNestedOneof {
+                name: String::new(),
+                choice: Some(NestedOneof::Text("hello".into())),
+                count: 1,
+            }),
+        }
+    );
+
+    assert!(from_json::<WithJsonTypes>(r#"{"unknown": 1}"#).is_err());
+    assert!(from_json::<WithJsonTypes>(r#"{"smallSigned": 4294967296}"#).is_err());
+    assert!(from_json::<WithJsonTypes>(r#"{"kind": "NOT_A_VARIANT"}"#).is_err());
+    assert!(from_json::<WithJsonTypes>(r#"{"enabled": 1}"#).is_err());
+}
+
+#[quickcheck]
+fn text_round_trip(
+    (identifier, small_signed, ratio, enabled): (u64, i32, f64, bool),
+    (label, payload, kind): (String, Vec<u8>, u8),
+    (values, counts): (Vec<i64>, BTreeMap<u32, String>),
+    nested: NestedArgs,
+) -> TestResult {
+    if ratio.is_nan() {
+        return TestResult::discard();
+    }
+
+    let message = WithJsonTypes {
+        identifier,
+        small_signed,
+        ratio,
+        enabled,
+        label,
+        payload: payload.into(),
+        kind: convert_scalar(kind % 10).unwrap(),
+        values,
+        counts,
+        nested: make_nested(nested),
+    };
+
+    let text = autoproto::text::to_text(&message);
+
+    TestResult::from_bool(autoproto::text::from_text::<WithJsonTypes>(&text).unwrap() == message)
+}
+
+#[test]
+fn text_canonical_form() {
+    let message = WithJsonTypes {
+        identifier: 1 << 60,
+        small_signed: -5,
+        ratio: 0.5,
+        enabled: false,
+        label: "a \"quoted\"\nlabel ☃".into(),
+        payload: vec![1, 2, 3, 255].into(),
+        kind: SomeEnumeration::C,
+        values: vec![0, -1],
+        counts: vec![(1, "one".to_owned())].into_iter().collect(),
+        nested: Some(WithNestedOneof {
+            name: "name".into(),
+            choice: Some(NestedOneof::Pair(Foo(0, 2))),
+            count: 0,
+        }),
+    };
+
+    assert_eq!(
+        autoproto::text::to_text(&message),
+        r#"identifier: 1152921504606846976
+small_signed: -5
+ratio: 0.5
+label: "a \"quoted\"\nlabel ☃"
+payload: "\001\002\003\377"
+kind: SOME_ENUMERATION_C
+values: 0
+values: -1
+counts {
+  key: 1
+  value: "one"
+}
+nested {
+  name: "name"
+  pair {
+    field_1: 2
+  }
+}
+"#,
+    );
+
+    assert_eq!(autoproto::text::to_text(&WithJsonTypes::default()), "");
+    assert_eq!(autoproto::text::to_text(&Unit), "");
+    assert_eq!(
+        autoproto::text::to_text(&MessageOneof::Two(1, 2)),
+        "two {\n  field_0: 1\n  field_1: 2\n}\n",
+    );
+}
+
+#[test]
+fn text_parsing() {
+    use autoproto::text::from_text;
+
+    let message = from_text::<WithJsonTypes>(
+        r#"
+        # Scalars take the last value
+        identifier: 1
+        identifier: 0x10
+        small_signed: -3, ratio: -inf; enabled: t
+        label: "con" 'cat\x65nated'
+        payload: "\001\x02\003\377"
+        kind: 4
+        values: [1, 2]
+        values: 012
+        counts: { key: 7 value: "seven" }
+        counts < key: 8 >
+        nested {
+          text: "hello"
+        }
+        nested: { count: 1 }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        message,
+        WithJsonTypes {
+            identifier: 16,
+            small_signed: -3,
+            ratio: f64::NEG_INFINITY,
+            enabled: true,
+            label: "concatenated".into(),
+            payload: vec![1, 2, 3, 255].into(),
+            kind: SomeEnumeration::E,
+            values: vec![1, 2, 10],
+            counts: vec![(7, "seven".to_owned()), (8, String::new())]
+                .into_iter()
+                .collect(),
+            nested: Some(WithNestedOneof {
+                name: String::new(),
+                choice: Some(NestedOneof::Text("hello".into())),
+                count: 1,
+            }),
+        }
+    );
+
+    assert!(from_text::<WithJsonTypes>("unknown: 1").is_err());
+    assert!(from_text::<WithJsonTypes>("small_signed: 4294967296").is_err());
+    assert!(from_text::<WithJsonTypes>("kind: NOT_A_VARIANT").is_err());
+    assert!(from_text::<WithJsonTypes>("enabled: 2").is_err());
+    assert!(from_text::<WithJsonTypes>("label: \"unterminated").is_err());
+    assert!(from_text::<WithJsonTypes>("nested { name: \"\"").is_err());
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct RawInspected {
+    id: u64,
+    delta: i32,
+    values: Vec<i64>,
+    counts: BTreeMap<u32, String>,
+}
+
+#[test]
+fn raw_decoding() {
+    use autoproto::raw::{decode_raw, Packed, RawValue};
+
+    let message = RawInspected {
+        id: 1,
+        delta: -1,
+        values: vec![1, 300],
+        counts: vec![(2, "two".to_owned())].into_iter().collect(),
+    };
+    let encoded = message.encode_to_vec();
+    let raw = decode_raw(&encoded).unwrap();
+
+    let tags = raw
+        .fields
+        .iter()
+        .map(|field| field.tag.get())
+        .collect::<Vec<_>>();
+
+    assert_eq!(tags, [1, 2, 3, 4]);
+
+    match &raw.fields[2].value {
+        RawValue::LengthDelimited(bytes) => {
+            assert_eq!(bytes.packed[0], Packed::Varint(vec![1, 300]))
+        }
+        other => panic!("Expected a packed field, found {:?}", other),
+    }
+
+    assert_eq!(
+        raw.to_string(),
+        r#"1: 1  # int32/int64/uint32/uint64/bool: 1, sint32/sint64: -1
+2: 18446744073709551615  # int32/int64: -1, uint64: 18446744073709551615, sint64: -9223372036854775808
+3: [1, 300]  # packed varint
+4 {
+  1: 2  # int32/int64/uint32/uint64: 2, sint32/sint64: 1
+  2: "two"
+}
+"#,
+    );
+
+    assert!(decode_raw(&encoded[..encoded.len() - 1]).is_err());
+    assert!(decode_raw(&[0x0b, 0x08, 0x01]).is_err());
+    assert_eq!(
+        decode_raw(&[0x0b, 0x08, 0x01, 0x0c]).unwrap().to_string(),
+        "1 {  # group\n  1: 1  # int32/int64/uint32/uint64/bool: 1, sint32/sint64: -1\n}\n",
+    );
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Pair {
+    a: u32,
+    b: u32,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct EveryFieldKind {
+    string: String,
+    bytes: autoproto::bytes::Bytes,
+    boxed: Box<[u8]>,
+    array: [u8; 2],
+    int: i32,
+    float: f64,
+    flag: bool,
+    message: Pair,
+    optional: Option<Pair>,
+    repeated: Vec<u32>,
+    map: BTreeMap<u32, String>,
+    path: std::path::PathBuf,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstPair {
+    #[prost(uint32, tag = "1")]
+    a: u32,
+    #[prost(uint32, tag = "2")]
+    b: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstEveryFieldKind {
+    #[prost(string, tag = "1")]
+    string: String,
+    #[prost(bytes = "vec", tag = "2")]
+    bytes: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    boxed: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    array: Vec<u8>,
+    #[prost(int32, tag = "5")]
+    int: i32,
+    #[prost(double, tag = "6")]
+    float: f64,
+    #[prost(bool, tag = "7")]
+    flag: bool,
+    #[prost(message, optional, tag = "8")]
+    message: Option<ProstPair>,
+    #[prost(message, optional, tag = "9")]
+    optional: Option<ProstPair>,
+    #[prost(uint32, repeated, tag = "10")]
+    repeated: Vec<u32>,
+    #[prost(btree_map = "uint32, string", tag = "11")]
+    map: BTreeMap<u32, String>,
+    #[prost(bytes = "vec", tag = "12")]
+    path: Vec<u8>,
+}
+
+type EveryFieldKindArgs = (
+    (String, Vec<u8>, [u8; 2], i32, f64, bool),
+    (
+        (u32, u32),
+        Option<(u32, u32)>,
+        Vec<u32>,
+        BTreeMap<u32, String>,
+    ),
+    String,
+);
+
+fn make_every_field_kind(
+    ((string, bytes, array, int, float, flag), (message, optional, repeated, map), path): EveryFieldKindArgs,
+) -> EveryFieldKind {
+    EveryFieldKind {
+        string,
+        bytes: bytes.clone().into(),
+        boxed: bytes.into(),
+        array,
+        int,
+        float,
+        flag,
+        message: Pair {
+            a: message.0,
+            b: message.1,
+        },
+        optional: optional.map(|(a, b)| Pair { a, b }),
+        repeated,
+        map,
+        path: path.into(),
+    }
+}
+
+// Every field of `first` appears again in `second`, so the fields which aren't messages or repeated
+// should take the value from `second`, as they do with `prost`.
+#[quickcheck]
+fn last_value_wins(first: EveryFieldKindArgs, second: EveryFieldKindArgs) -> TestResult {
+    let (first, second) = (make_every_field_kind(first), make_every_field_kind(second));
+
+    if first.float.is_nan() || second.float.is_nan() {
+        return TestResult::discard();
+    }
+
+    let mut encoded = first.encode_to_vec();
+    encoded.extend(second.encode_to_vec());
+
+    let decoded = EveryFieldKind::decode(&encoded[..]).unwrap();
+    let expected = ProstEveryFieldKind::decode(&encoded[..]).unwrap();
+
+    let pair = |pair: &Pair| ProstPair {
+        a: pair.a,
+        b: pair.b,
+    };
+
+    // `prost` keeps empty messages, while they aren't encoded at all here.
+    let message = Some(pair(&decoded.message)).filter(|m| *m != ProstPair::default());
+    let decoded = ProstEveryFieldKind {
+        string: decoded.string.clone(),
+        bytes: decoded.bytes.to_vec(),
+        boxed: decoded.boxed.to_vec(),
+        array: decoded.array.to_vec(),
+        int: decoded.int,
+        float: decoded.float,
+        flag: decoded.flag,
+        message,
+        optional: decoded.optional.as_ref().map(pair),
+        repeated: decoded.repeated.clone(),
+        map: decoded.map.clone(),
+        path: decoded.path.to_str().unwrap().as_bytes().to_vec(),
+    };
+
+    TestResult::from_bool(
+        decoded
+            == ProstEveryFieldKind {
+                message: expected
+                    .message
+                    .clone()
+                    .filter(|m| *m != ProstPair::default()),
+                ..expected
+            },
+    )
+}
+
+#[test]
+fn bytes_length_mismatch() {
+    use autoproto::ProtoEncode;
+
+    let mut encoded = vec![];
+
+    "abc".encode_as_field(std::num::NonZeroU32::new(4).unwrap(), &mut encoded);
+
+    assert!(EveryFieldKind::decode(&encoded[..]).is_err());
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithEncodedCollections {
+    #[autoproto(encoding = "sint32")]
+    offsets: BTreeMap<u32, i32>,
+    #[autoproto(encoding = "sfixed32", packed = false)]
+    history: Vec<i32>,
+    name: std::ffi::OsString,
+}
+
+// Fields sent with the wrong wire type, as happens when the schemas don't match, must be rejected
+// rather than being parsed as something else.
+#[test]
+fn wire_type_mismatch() {
+    use autoproto::prost::encoding::{encode_key, encode_varint, WireType};
+
+    fn field(tag: u32, wire_type: WireType) -> Vec<u8> {
+        let mut buf = vec![];
+
+        encode_key(tag, wire_type, &mut buf);
+
+        match wire_type {
+            WireType::Varint => encode_varint(1, &mut buf),
+            WireType::SixtyFourBit => buf.extend([0; 8]),
+            WireType::ThirtyTwoBit => buf.extend([0; 4]),
+            // This is a valid string, message, map entry and packed field of any kind.
+            WireType::LengthDelimited => buf.extend([4, 0x08, 0x01, 0x10, 0x01]),
+            _ => unreachable!(),
+        }
+
+        buf
+    }
+
+    fn check<M: Message + Default>(accepted: &[(u32, &[WireType])]) {
+        for &(tag, accepted) in accepted {
+            for wire_type in [
+                WireType::Varint,
+                WireType::SixtyFourBit,
+                WireType::LengthDelimited,
+                WireType::ThirtyTwoBit,
+            ] {
+                let result = M::decode(&field(tag, wire_type)[..]);
+
+                if accepted.contains(&wire_type) {
+                    // This can still fail, e.g. for an array of the wrong length or a map entry
+                    // with a value of the wrong type, but not because of this wire type.
+                    if let Err(error) = result {
+                        let error = error.to_string();
+
+                        assert!(
+                            !error.contains(&format!("invalid wire type: {:?}", wire_type)),
+                            "{:?} for field {}: {}",
+                            wire_type,
+                            tag,
+                            error,
+                        );
+                    }
+                } else {
+                    let error = result.unwrap_err().to_string();
+
+                    assert!(
+                        error.contains(&format!(
+                            "invalid wire type: {:?} (expected {:?})",
+                            wire_type, accepted[0]
+                        )),
+                        "{:?} for field {}: {}",
+                        wire_type,
+                        tag,
+                        error,
+                    );
+                }
+            }
+        }
+    }
+
+    use WireType::*;
+
+    check::<EveryFieldKind>(&[
+        (1, &[LengthDelimited]),
+        (2, &[LengthDelimited]),
+        (3, &[LengthDelimited]),
+        (4, &[LengthDelimited]),
+        (5, &[Varint]),
+        (6, &[SixtyFourBit]),
+        (7, &[Varint]),
+        (8, &[LengthDelimited]),
+        (9, &[LengthDelimited]),
+        (10, &[Varint, LengthDelimited]),
+        (11, &[LengthDelimited]),
+        (12, &[LengthDelimited]),
+    ]);
+    check::<WithEncodedCollections>(&[
+        (1, &[LengthDelimited]),
+        (2, &[ThirtyTwoBit, LengthDelimited]),
+        (3, &[LengthDelimited]),
+    ]);
+}
+
+#[test]
+fn oneof_decode_errors() {
+    use prost::encoding::{encode_key, encode_varint, WireType};
+
+    fn field(tag: u32, wire_type: WireType, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+
+        encode_key(tag, wire_type, &mut out);
+
+        if wire_type == WireType::LengthDelimited {
+            encode_varint(payload.len() as u64, &mut out);
+        }
+
+        out.extend_from_slice(payload);
+        out
+    }
+
+    // Invalid UTF-8 in a string variant
+    let error = NestedOneof::decode(&field(2, WireType::LengthDelimited, b"\xff\xfe")[..]);
+    assert!(error.is_err());
+
+    // A message variant whose payload is cut off halfway through a field
+    let error = NestedOneof::decode(&field(3, WireType::LengthDelimited, &[0x08])[..]);
+    assert!(error.is_err());
+
+    // A variant sent with the wrong wire type
+    let error = MessageOneof::decode(&field(1, WireType::SixtyFourBit, &[0; 8])[..]);
+    assert!(error.is_err());
+
+    // Unknown fields of every wire type are skipped, including their payloads
+    let mut buf = vec![];
+    buf.extend(field(7, WireType::Varint, &[0x96, 0x01]));
+    buf.extend(field(8, WireType::LengthDelimited, &[0x10, 0x20, 0x30]));
+    buf.extend(field(9, WireType::ThirtyTwoBit, &[1, 2, 3, 4]));
+    buf.extend(field(10, WireType::SixtyFourBit, &[1, 2, 3, 4, 5, 6, 7, 8]));
+    buf.extend(field(2, WireType::LengthDelimited, b"hi"));
+    buf.extend(field(
+        11,
+        WireType::StartGroup,
+        &field(1, WireType::Varint, &[1]),
+    ));
+    buf.extend(field(11, WireType::EndGroup, &[]));
+
+    assert_eq!(
+        NestedOneof::decode(&buf[..]).unwrap(),
+        NestedOneof::Text("hi".into())
+    );
+
+    // Unknown fields whose payloads are truncated are still errors
+    let error = NestedOneof::decode(&field(8, WireType::LengthDelimited, &[0x10])[..2]);
+    assert!(error.is_err());
+
+    // Errors inside a oneof nested in a struct are propagated to the outer message
+    let mut buf = field(1, WireType::LengthDelimited, b"name");
+    buf.extend(field(2, WireType::LengthDelimited, b"\xff"));
+    assert!(WithNestedOneof::decode(&buf[..]).is_err());
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithPresence {
+    count: Option<u32>,
+    #[autoproto(encoding = "sint32")]
+    delta: Option<i32>,
+    ratio: Option<f64>,
+    flag: Option<bool>,
+    name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstWithPresence {
+    #[prost(uint32, optional, tag = "1")]
+    count: Option<u32>,
+    #[prost(sint32, optional, tag = "2")]
+    delta: Option<i32>,
+    #[prost(double, optional, tag = "3")]
+    ratio: Option<f64>,
+    #[prost(bool, optional, tag = "4")]
+    flag: Option<bool>,
+    #[prost(string, optional, tag = "5")]
+    name: Option<String>,
+}
+
+#[quickcheck]
+fn explicit_presence_same_as_prost(
+    count: Option<u32>,
+    delta: Option<i32>,
+    ratio: Option<f64>,
+    flag: Option<bool>,
+    name: Option<String>,
+) -> TestResult {
+    if ratio.is_some_and(f64::is_nan) {
+        return TestResult::discard();
+    }
+
+    let ours = WithPresence {
+        count,
+        delta,
+        ratio,
+        flag,
+        name: name.clone(),
+    };
+    let prost = ProstWithPresence {
+        count,
+        delta,
+        ratio,
+        flag,
+        name,
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(ours.encoded_len(), prost.encoded_len());
+    assert_eq!(
+        ProstWithPresence::decode(&ours.encode_to_vec()[..]).unwrap(),
+        prost
+    );
+    assert_eq!(
+        WithPresence::decode(&prost.encode_to_vec()[..]).unwrap(),
+        ours
+    );
+
+    TestResult::passed()
+}
+
+#[test]
+fn explicit_presence() {
+    use autoproto::ProtoFile;
+
+    let zeroes = WithPresence {
+        count: Some(0),
+        delta: Some(0),
+        ratio: Some(0.),
+        flag: Some(false),
+        name: Some(String::new()),
+    };
+    let encoded = zeroes.encode_to_vec();
+
+    assert_eq!(
+        encoded,
+        [8, 0, 16, 0, 25, 0, 0, 0, 0, 0, 0, 0, 0, 32, 0, 42, 0]
+    );
+    assert_eq!(WithPresence::decode(&encoded[..]).unwrap(), zeroes);
+    assert!(WithPresence::default().encode_to_vec().is_empty());
+
+    assert_eq!(
+        ProtoFile::new().add::<WithPresence>().to_string(),
+        "\
+syntax = \"proto3\";
+
+message WithPresence {
+    optional uint32 count = 1;
+    optional sint32 delta = 2;
+    optional double ratio = 3;
+    optional bool flag = 4;
+    optional string name = 5;
+}
+"
+    );
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+struct Proto2Fields {
+    #[autoproto(required)]
+    id: u32,
+    #[autoproto(required)]
+    name: String,
+    #[autoproto(default = 42)]
+    count: i32,
+    #[autoproto(default = "-1", encoding = "sint64")]
+    offset: i64,
+    #[autoproto(default = true)]
+    enabled: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstProto2Fields {
+    #[prost(uint32, required, tag = "1")]
+    id: u32,
+    #[prost(string, required, tag = "2")]
+    name: String,
+    #[prost(int32, tag = "3", default = "42")]
+    count: i32,
+    #[prost(sint64, tag = "4", default = "-1")]
+    offset: i64,
+    #[prost(bool, tag = "5", default = "true")]
+    enabled: bool,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct ContainsProto2 {
+    inner: Option<Proto2Fields>,
+}
+
+#[quickcheck]
+fn proto2_same_as_prost(id: u32, name: String, count: i32, offset: i64, enabled: bool) {
+    let ours = Proto2Fields {
+        id,
+        name: name.clone(),
+        count,
+        offset,
+        enabled,
+    };
+    let prost = ProstProto2Fields {
+        id,
+        name,
+        count,
+        offset,
+        enabled,
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(ours.encoded_len(), prost.encoded_len());
+    assert_eq!(
+        Proto2Fields::decode(&prost.encode_to_vec()[..]).unwrap(),
+        ours
+    );
+    assert_eq!(
+        ProstProto2Fields::decode(&ours.encode_to_vec()[..]).unwrap(),
+        prost
+    );
+}
+
+#[test]
+fn proto2_required_and_default() {
+    let default = Proto2Fields::default();
+
+    assert_eq!(default.count, 42);
+    assert_eq!(default.offset, -1);
+    assert!(default.enabled);
+
+    // Required fields are encoded even if they're the default, the others are skipped if they
+    // match their `default`.
+    assert_eq!(default.encode_to_vec(), [8, 0, 18, 0]);
+    assert_eq!(
+        Proto2Fields {
+            count: 0,
+            ..Proto2Fields::default()
+        }
+        .encode_to_vec(),
+        [8, 0, 18, 0, 24, 0]
+    );
+
+    // Fields which are missing keep their `default`.
+    assert_eq!(Proto2Fields::decode(&[8, 0, 18, 0][..]).unwrap(), default);
+
+    let error = Proto2Fields::decode(&[8, 1][..]).unwrap_err().to_string();
+    assert!(
+        error.contains("Missing required field `name` (tag 2)"),
+        "{}",
+        error
+    );
+
+    let error = Proto2Fields::decode_length_delimited(&[2, 18, 0][..])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("Missing required field `id` (tag 1)"),
+        "{}",
+        error
+    );
+
+    // The check also applies to nested messages.
+    assert!(ContainsProto2::decode(&[10, 2, 8, 1][..]).is_err());
+    assert_eq!(
+        ContainsProto2::decode(&[10, 4, 8, 1, 18, 0][..]).unwrap(),
+        ContainsProto2 {
+            inner: Some(Proto2Fields {
+                id: 1,
+                ..Proto2Fields::default()
+            }),
+        }
+    );
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct ManyProto2 {
+    single: Option<Proto2Fields>,
+    list: Vec<Proto2Fields>,
+    map: BTreeMap<u32, Proto2Fields>,
+}
+
+#[test]
+fn required_fields_of_merged_messages() {
+    // A message field which appears more than once is merged, so its required fields can be
+    // spread across the occurrences.
+    assert_eq!(
+        ManyProto2::decode(&[10, 2, 8, 1, 10, 4, 18, 2, b'h', b'i'][..]).unwrap(),
+        ManyProto2 {
+            single: Some(Proto2Fields {
+                id: 1,
+                name: "hi".into(),
+                ..Proto2Fields::default()
+            }),
+            ..ManyProto2::default()
+        }
+    );
+
+    let error = ManyProto2::decode(&[10, 2, 8, 1, 10, 2, 8, 2][..])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("Missing required field `name` (tag 2)"),
+        "{}",
+        error
+    );
+
+    // Elements of repeated fields and map values are never merged with each other.
+    assert!(ManyProto2::decode(&[18, 4, 8, 1, 18, 0, 18, 2, 8, 1][..]).is_err());
+    assert!(ManyProto2::decode(&[18, 2, 18, 0, 18, 2, 8, 1][..]).is_err());
+    assert!(ManyProto2::decode(&[26, 6, 8, 1, 18, 2, 8, 1][..]).is_err());
+    assert_eq!(
+        ManyProto2::decode(&[18, 4, 8, 1, 18, 0, 26, 8, 8, 1, 18, 4, 8, 2, 18, 0][..])
+            .unwrap()
+            .map[&1]
+            .id,
+        2
+    );
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+enum Proto2Choice {
+    #[autoproto(tag = 3)]
+    First(Proto2Fields),
+    #[autoproto(tag = 4)]
+    Second(Proto2Fields),
+}
+
+impl Default for Proto2Choice {
+    fn default() -> Self {
+        Self::First(Default::default())
+    }
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct SiblingProto2 {
+    #[autoproto(tag = 1)]
+    a: Option<Proto2Fields>,
+    #[autoproto(tag = 2)]
+    b: Option<Proto2Fields>,
+    #[autoproto(oneof, tags = "3, 4")]
+    choice: Option<Proto2Choice>,
+}
+
+#[test]
+fn required_fields_of_sibling_messages() {
+    let a = Proto2Fields {
+        id: 1,
+        ..Proto2Fields::default()
+    };
+
+    assert_eq!(
+        SiblingProto2::decode(&[10, 4, 8, 1, 18, 0, 18, 4, 8, 1, 18, 0][..]).unwrap(),
+        SiblingProto2 {
+            a: Some(a.clone()),
+            b: Some(a.clone()),
+            choice: None,
+        }
+    );
+
+    // Each field has its own required fields, even if it's decoded in the same way as another.
+    assert!(SiblingProto2::decode(&[10, 4, 8, 1, 18, 0, 18, 2, 8, 5][..]).is_err());
+    assert!(SiblingProto2::decode(&[18, 4, 8, 1, 18, 0, 10, 2, 8, 5][..]).is_err());
+
+    // A new oneof variant replaces the old one, so it has to be complete by itself.
+    assert_eq!(
+        SiblingProto2::decode(&[26, 4, 8, 1, 18, 0, 34, 4, 8, 1, 18, 0][..]).unwrap(),
+        SiblingProto2 {
+            choice: Some(Proto2Choice::Second(a)),
+            ..SiblingProto2::default()
+        }
+    );
+    assert!(SiblingProto2::decode(&[26, 4, 8, 1, 18, 0, 34, 2, 8, 1][..]).is_err());
+    assert!(SiblingProto2::decode(&[26, 4, 8, 1, 18, 0, 26, 2, 8, 1][..]).is_err());
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct GroupPoint {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstGroupPoint {
+    #[prost(int32, tag = "1")]
+    x: i32,
+    #[prost(int32, tag = "2")]
+    y: i32,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithGroups {
+    #[autoproto(tag = 1, group)]
+    single: Option<GroupPoint>,
+    #[autoproto(tag = 2, group)]
+    repeated: Vec<GroupPoint>,
+    #[autoproto(tag = 3)]
+    after: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstWithGroups {
+    #[prost(group, optional, tag = "1")]
+    single: Option<ProstGroupPoint>,
+    #[prost(group, repeated, tag = "2")]
+    repeated: Vec<ProstGroupPoint>,
+    #[prost(uint32, tag = "3")]
+    after: u32,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct RecursiveGroup {
+    #[autoproto(group)]
+    child: Option<Box<RecursiveGroup>>,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct RequiredGroupPoint {
+    #[autoproto(required)]
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithRequiredGroups {
+    #[autoproto(tag = 1, group)]
+    single: Option<RequiredGroupPoint>,
+    #[autoproto(tag = 2, group)]
+    repeated: Vec<RequiredGroupPoint>,
+}
+
+#[quickcheck]
+fn groups_same_as_prost(single: Option<(i32, i32)>, repeated: Vec<(i32, i32)>, after: u32) {
+    let ours = WithGroups {
+        single: single.map(|(x, y)| GroupPoint { x, y }),
+        repeated: repeated.iter().map(|&(x, y)| GroupPoint { x, y }).collect(),
+        after,
+    };
+    let prost = ProstWithGroups {
+        single: single.map(|(x, y)| ProstGroupPoint { x, y }),
+        repeated: repeated
+            .iter()
+            .map(|&(x, y)| ProstGroupPoint { x, y })
+            .collect(),
+        after,
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(ours.encoded_len(), prost.encoded_len());
+    assert_eq!(
+        WithGroups::decode(&prost.encode_to_vec()[..]).unwrap(),
+        ours
+    );
+    assert_eq!(
+        ProstWithGroups::decode(&ours.encode_to_vec()[..]).unwrap(),
+        prost
+    );
+}
+
+#[test]
+fn group_decoding() {
+    use autoproto::{
+        descriptor::{self, Label, Type},
+        ProtoFile,
+    };
+
+    let groups = WithGroups {
+        single: Some(GroupPoint { x: 1, y: 2 }),
+        repeated: vec![GroupPoint { x: 3, y: 0 }],
+        after: 4,
+    };
+
+    // Groups are written between a start group key and an end group key, with no length prefix.
+    assert_eq!(
+        groups.encode_to_vec(),
+        [11, 8, 1, 16, 2, 12, 19, 8, 3, 20, 24, 4]
+    );
+
+    // The end group key must have the same tag as the start group key.
+    assert!(WithGroups::decode(&[11, 8, 1, 20][..]).is_err());
+    // A group with no end group key is an error.
+    assert!(WithGroups::decode(&[11, 8, 1][..]).is_err());
+    // So is an end group key without a matching start group key.
+    assert!(WithGroups::decode(&[12][..]).is_err());
+    // Groups must use the start group wire type.
+    assert!(WithGroups::decode(&[10, 2, 8, 1][..]).is_err());
+
+    // Required fields of groups are checked in the same way as those of other messages.
+    let error = WithRequiredGroups::decode(&[11, 16, 2, 12][..])
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("Missing required field `x` (tag 1)"),
+        "{}",
+        error
+    );
+    assert!(WithRequiredGroups::decode(&[19, 8, 1, 20, 19, 16, 2, 20][..]).is_err());
+    assert_eq!(
+        WithRequiredGroups::decode(&[11, 8, 1, 12, 11, 16, 2, 12, 19, 8, 0, 20][..]).unwrap(),
+        WithRequiredGroups {
+            single: Some(RequiredGroupPoint { x: 1, y: 2 }),
+            repeated: vec![RequiredGroupPoint::default()],
+        }
+    );
+
+    let nested = |depth: usize| {
+        let mut bytes = vec![11; depth];
+        bytes.extend(vec![12; depth]);
+        bytes
+    };
+
+    let mut expected = RecursiveGroup::default();
+    for _ in 0..10 {
+        expected = RecursiveGroup {
+            child: Some(Box::new(expected)),
+        };
+    }
+
+    assert_eq!(expected.encode_to_vec(), nested(10));
+    assert_eq!(RecursiveGroup::decode(&nested(10)[..]).unwrap(), expected);
+    // Deeply-nested groups hit the recursion limit instead of overflowing the stack.
+    assert!(RecursiveGroup::decode(&nested(1000)[..]).is_err());
+
+    assert_eq!(
+        ProtoFile::new().add::<WithGroups>().to_string(),
+        "\
+syntax = \"proto3\";
+
+message WithGroups {
+    GroupPoint single = 1;
+    repeated GroupPoint repeated = 2;
+    uint32 after = 3;
+}
+
+message GroupPoint {
+    int32 x = 1;
+    int32 y = 2;
+}
+"
+    );
+
+    let set = descriptor::file_descriptor_set::<WithGroups>("test.proto", None);
+    let fields = &set.file[0].message_type[0].field;
+
+    assert_eq!(fields[0].r#type(), Type::Group);
+    assert_eq!(fields[0].type_name(), ".GroupPoint");
+    assert_eq!(fields[1].r#type(), Type::Group);
+    assert_eq!(fields[1].label(), Label::Repeated);
+    assert_eq!(fields[2].r#type(), Type::Uint32);
+}
+
+#[derive(Copy, Clone, PartialEq, Default, Debug, autoproto::ProtoScalar)]
+#[autoproto(open)]
+#[repr(i32)]
+enum OpenEnumeration {
+    #[default]
+    Zero = 0,
+    One,
+    Five = 5,
+    #[autoproto(unknown)]
+    Unknown(i32),
+    Seven = 7,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithOpenEnums {
+    single: OpenEnumeration,
+    repeated: Vec<OpenEnumeration>,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithOpenEnumInts {
+    single: i32,
+    repeated: Vec<i32>,
+}
+
+fn open_enumeration(value: i32) -> OpenEnumeration {
+    match value {
+        0 => OpenEnumeration::Zero,
+        1 => OpenEnumeration::One,
+        5 => OpenEnumeration::Five,
+        7 => OpenEnumeration::Seven,
+        other => OpenEnumeration::Unknown(other),
+    }
+}
+
+#[quickcheck]
+fn open_enumerations_same_as_ints(single: i32, repeated: Vec<i32>) {
+    let enums = WithOpenEnums {
+        single: open_enumeration(single),
+        repeated: repeated.iter().copied().map(open_enumeration).collect(),
+    };
+    let ints = WithOpenEnumInts { single, repeated };
+
+    assert_eq!(enums.encode_to_vec(), ints.encode_to_vec());
+    assert_eq!(enums.encoded_len(), ints.encoded_len());
+    assert_eq!(
+        WithOpenEnums::decode(&ints.encode_to_vec()[..]).unwrap(),
+        enums
+    );
+}
+
+#[test]
+fn open_enumerations() {
+    use autoproto::ProtoFile;
+
+    let enums = WithOpenEnums {
+        single: OpenEnumeration::Unknown(3),
+        repeated: vec![
+            OpenEnumeration::Seven,
+            OpenEnumeration::Unknown(-1),
+            OpenEnumeration::One,
+        ],
+    };
+
+    // Unknown values are kept as they are, including in packed repeated fields.
+    let encoded = enums.encode_to_vec();
+    assert_eq!(
+        encoded,
+        [8, 3, 18, 12, 7, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1, 1]
+    );
+    assert_eq!(WithOpenEnums::decode(&encoded[..]).unwrap(), enums);
+
+    // Closed enumerations still fail to decode values they don't recognise.
+    assert!(SomeStruct::<SomeEnumeration, u32>::decode(&[8, 100][..]).is_err());
+
+    assert_eq!(
+        autoproto::json::to_json(&enums),
+        r#"{"repeated":["OPEN_ENUMERATION_SEVEN",-1,"OPEN_ENUMERATION_ONE"],"single":3}"#
+    );
+    assert_eq!(
+        autoproto::json::from_json::<WithOpenEnums>(r#"{"single":3,"repeated":[7,-1,1]}"#).unwrap(),
+        enums
+    );
+    assert_eq!(
+        autoproto::text::to_text(&enums),
+        "single: 3\nrepeated: OPEN_ENUMERATION_SEVEN\nrepeated: -1\nrepeated: OPEN_ENUMERATION_ONE\n"
+    );
+
+    assert!(ProtoFile::new()
+        .add::<WithOpenEnums>()
+        .to_string()
+        .contains(
+            "enum OpenEnumeration {\n    \
+                OPEN_ENUMERATION_ZERO = 0;\n    \
+                OPEN_ENUMERATION_ONE = 1;\n    \
+                OPEN_ENUMERATION_FIVE = 5;\n    \
+                OPEN_ENUMERATION_SEVEN = 7;\n\
+            }\n"
+        ));
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+struct WithTimes {
+    created: std::time::SystemTime,
+    elapsed: std::time::Duration,
+    timeout: Option<std::time::Duration>,
+}
+
+impl Default for WithTimes {
+    fn default() -> Self {
+        Self {
+            created: std::time::UNIX_EPOCH,
+            elapsed: Default::default(),
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstWithTimes {
+    #[prost(message, optional, tag = "1")]
+    created: Option<prost_types::Timestamp>,
+    #[prost(message, optional, tag = "2")]
+    elapsed: Option<prost_types::Duration>,
+    #[prost(message, optional, tag = "3")]
+    timeout: Option<prost_types::Duration>,
+}
+
+#[quickcheck]
+fn times_same_as_prost(created: (i32, u32), elapsed: (u32, u32), timeout: Option<(u32, u32)>) {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let created_since_epoch =
+        Duration::new(created.0.unsigned_abs().into(), created.1 % 1_000_000_000);
+    let ours = WithTimes {
+        created: if created.0 < 0 {
+            UNIX_EPOCH - created_since_epoch
+        } else {
+            UNIX_EPOCH + created_since_epoch
+        },
+        elapsed: Duration::new(elapsed.0.into(), elapsed.1),
+        timeout: timeout.map(|(secs, nanos)| Duration::new(secs.into(), nanos)),
+    };
+    let prost = ProstWithTimes {
+        created: Some(ours.created.into()),
+        elapsed: Some(ours.elapsed.into()),
+        timeout: ours.timeout.map(Into::into),
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(ours.encoded_len(), prost.encoded_len());
+    assert_eq!(WithTimes::decode(&prost.encode_to_vec()[..]).unwrap(), ours);
+    assert_eq!(
+        ProstWithTimes::decode(&ours.encode_to_vec()[..]).unwrap(),
+        prost
+    );
+}
+
+#[test]
+fn times() {
+    use autoproto::ProtoFile;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let times = WithTimes {
+        created: UNIX_EPOCH + Duration::new(63_108_020, 21_000_000),
+        elapsed: Duration::from_millis(1500),
+        timeout: Some(Duration::from_secs(30)),
+    };
+    let json = autoproto::json::to_json(&times);
+
+    assert_eq!(
+        json,
+        r#"{"created":"1972-01-01T10:00:20.021Z","elapsed":"1.500s","timeout":"30s"}"#
+    );
+    assert_eq!(
+        autoproto::json::from_json::<WithTimes>(&json).unwrap(),
+        times
+    );
+    assert_eq!(
+        autoproto::json::from_json::<WithTimes>(
+            r#"{"created":"1972-01-01T11:30:20.021+01:30","elapsed":"1.5s","timeout":"30.000s"}"#
+        )
+        .unwrap(),
+        times
+    );
+    assert!(
+        autoproto::json::from_json::<WithTimes>(r#"{"created":"1972-02-30T00:00:00Z"}"#).is_err()
+    );
+    assert!(autoproto::json::from_json::<WithTimes>(r#"{"elapsed":"1.5"}"#).is_err());
+    // A negative duration is valid protobuf, but can't be represented by `std::time::Duration`.
+    assert!(autoproto::json::from_json::<WithTimes>(r#"{"elapsed":"-1.5s"}"#).is_err());
+    assert!(WithTimes::decode(
+        &ProstWithTimes {
+            elapsed: Some(prost_types::Duration {
+                seconds: -1,
+                nanos: 0
+            }),
+            ..Default::default()
+        }
+        .encode_to_vec()[..]
+    )
+    .is_err());
+
+    // Values outside the range of `Timestamp` and `Duration` are errors rather than overflowing.
+    let out_of_range = [
+        (i64::MIN, -5, 0, 0),
+        (i64::MIN, 0, 0, 0),
+        (i64::MAX, 999_999_999, 0, 0),
+        (-62_135_596_801, 0, 0, 0),
+        (253_402_300_800, 0, 0, 0),
+        (0, -1, 0, 0),
+        (0, 1_000_000_000, 0, 0),
+        (0, 0, i64::MIN, 0),
+        (0, 0, i64::MIN, -999_999_999),
+        (0, 0, i64::MAX, i32::MAX),
+        (0, 0, 315_576_000_001, 0),
+        (0, 0, 1, -1),
+    ];
+    for &(seconds, nanos, elapsed_seconds, elapsed_nanos) in &out_of_range {
+        let prost = ProstWithTimes {
+            created: Some(prost_types::Timestamp { seconds, nanos }),
+            elapsed: Some(prost_types::Duration {
+                seconds: elapsed_seconds,
+                nanos: elapsed_nanos,
+            }),
+            ..Default::default()
+        };
+
+        assert!(WithTimes::decode(&prost.encode_to_vec()[..]).is_err());
+    }
+    assert_eq!(
+        WithTimes::decode(
+            &ProstWithTimes {
+                created: Some(prost_types::Timestamp {
+                    seconds: 253_402_300_799,
+                    nanos: 999_999_999,
+                }),
+                elapsed: Some(prost_types::Duration {
+                    seconds: 315_576_000_000,
+                    nanos: 999_999_999,
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec()[..]
+        )
+        .unwrap(),
+        WithTimes {
+            created: UNIX_EPOCH + Duration::new(253_402_300_799, 999_999_999),
+            elapsed: Duration::new(315_576_000_000, 999_999_999),
+            timeout: None,
+        }
+    );
+
+    let before_epoch = WithTimes {
+        created: UNIX_EPOCH - Duration::from_millis(500),
+        ..WithTimes::default()
+    };
+    let json = autoproto::json::to_json(&before_epoch);
+    assert_eq!(
+        json,
+        r#"{"created":"1969-12-31T23:59:59.500Z","elapsed":"0s"}"#
+    );
+    assert_eq!(
+        autoproto::json::from_json::<WithTimes>(&json).unwrap(),
+        before_epoch
+    );
+
+    assert_eq!(
+        autoproto::text::to_text(&times),
+        "created {\n  seconds: 63108020\n  nanos: 21000000\n}\n\
+         elapsed {\n  seconds: 1\n  nanos: 500000000\n}\n\
+         timeout {\n  seconds: 30\n}\n"
+    );
+
+    assert_eq!(
+        ProtoFile::new().add::<WithTimes>().to_string(),
+        "\
+syntax = \"proto3\";
+
+import \"google/protobuf/duration.proto\";
+import \"google/protobuf/timestamp.proto\";
+
+message WithTimes {
+    google.protobuf.Timestamp created = 1;
+    google.protobuf.Duration elapsed = 2;
+    google.protobuf.Duration timeout = 3;
+}
+"
+    );
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_times() {
+    use chrono::{TimeZone, Utc};
+
+    #[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+    struct WithChrono {
+        created: chrono::DateTime<Utc>,
+    }
+
+    let ours = WithChrono {
+        created: Utc.timestamp_opt(63_108_020, 21_000_000).unwrap(),
+    };
+    let prost = ProstWithTimes {
+        created: Some(prost_types::Timestamp {
+            seconds: 63_108_020,
+            nanos: 21_000_000,
+        }),
+        ..Default::default()
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(
+        WithChrono::decode(&prost.encode_to_vec()[..]).unwrap(),
+        ours
+    );
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn time_times() {
+    #[derive(Clone, PartialEq, Debug, autoproto::Message)]
+    struct WithTime {
+        created: time::OffsetDateTime,
+    }
+
+    impl Default for WithTime {
+        fn default() -> Self {
+            Self {
+                created: time::OffsetDateTime::UNIX_EPOCH,
+            }
+        }
+    }
+
+    let ours = WithTime {
+        created: time::OffsetDateTime::from_unix_timestamp_nanos(63_108_020_021_000_000).unwrap(),
+    };
+    let prost = ProstWithTimes {
+        created: Some(prost_types::Timestamp {
+            seconds: 63_108_020,
+            nanos: 21_000_000,
+        }),
+        ..Default::default()
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(WithTime::decode(&prost.encode_to_vec()[..]).unwrap(), ours);
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct WithWellKnown {
+    count: Option<autoproto::wkt::Int32Value>,
+    name: Option<autoproto::wkt::StringValue>,
+    data: Option<autoproto::wkt::BytesValue>,
+    ratio: Option<autoproto::wkt::DoubleValue>,
+    nothing: Option<autoproto::wkt::Empty>,
+    object: Option<autoproto::wkt::Struct>,
+    any: Option<autoproto::wkt::Any>,
+}
+
+// `prost` represents the wrappers as `Option`s of the wrapped type, and `Empty` as `()`.
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProstWithWellKnown {
+    #[prost(message, optional, tag = "1")]
+    count: Option<i32>,
+    #[prost(message, optional, tag = "2")]
+    name: Option<String>,
+    #[prost(message, optional, tag = "3")]
+    data: Option<Vec<u8>>,
+    #[prost(message, optional, tag = "4")]
+    ratio: Option<f64>,
+    #[prost(message, optional, tag = "5")]
+    nothing: Option<()>,
+    #[prost(message, optional, tag = "6")]
+    object: Option<prost_types::Struct>,
+    #[prost(message, optional, tag = "7")]
+    any: Option<prost_types::Any>,
+}
+
+type StructArgs = BTreeMap<String, (u8, f64, String)>;
+type WrapperArgs = (
+    Option<i32>,
+    Option<String>,
+    Option<Vec<u8>>,
+    Option<f64>,
+    Option<()>,
+);
+
+/// Build the same `Struct` with each implementation, using every kind of value.
+fn make_structs(args: &StructArgs) -> (autoproto::wkt::Struct, prost_types::Struct) {
+    use autoproto::wkt::{ListValue, NullValue, Struct, Value};
+    use prost_types::value::Kind;
+
+    let (ours, prost) = args
+        .iter()
+        .map(|(key, (kind, number, string))| {
+            let (ours, prost) = match kind % 6 {
+                0 => (Value::NullValue(NullValue::NullValue), Kind::NullValue(0)),
+                1 => (Value::NumberValue(*number), Kind::NumberValue(*number)),
+                2 => (
+                    Value::StringValue(string.clone()),
+                    Kind::StringValue(string.clone()),
+                ),
+                3 => (
+                    Value::BoolValue(kind % 2 == 0),
+                    Kind::BoolValue(kind % 2 == 0),
+                ),
+                4 => (
+                    Value::StructValue(Struct {
+                        fields: vec![(string.clone(), Value::NumberValue(*number))]
+                            .into_iter()
+                            .collect(),
+                    }),
+                    Kind::StructValue(prost_types::Struct {
+                        fields: vec![(
+                            string.clone(),
+                            prost_types::Value {
+                                kind: Some(Kind::NumberValue(*number)),
+                            },
+                        )]
+                        .into_iter()
+                        .collect(),
+                    }),
+                ),
+                _ => (
+                    Value::ListValue(ListValue {
+                        values: vec![Value::StringValue(string.clone()), Value::BoolValue(true)],
+                    }),
+                    Kind::ListValue(prost_types::ListValue {
+                        values: vec![
+                            prost_types::Value {
+                                kind: Some(Kind::StringValue(string.clone())),
+                            },
+                            prost_types::Value {
+                                kind: Some(Kind::BoolValue(true)),
+                            },
+                        ],
+                    }),
+                ),
+            };
+
+            (
+                (key.clone(), ours),
+                (key.clone(), prost_types::Value { kind: Some(prost) }),
+            )
+        })
+        .unzip();
+
+    (
+        Struct { fields: ours },
+        prost_types::Struct { fields: prost },
+    )
+}
+
+#[quickcheck]
+fn well_known_types_same_as_prost(
+    (count, name, data, ratio, nothing): WrapperArgs,
+    object: Option<StructArgs>,
+    any: Option<(String, Vec<u8>)>,
+) -> TestResult {
+    use autoproto::wkt::{Any, BytesValue, Empty};
+
+    let has_nan = ratio.is_some_and(f64::is_nan)
+        || object
+            .iter()
+            .flat_map(|object| object.values())
+            .any(|(_, number, _)| number.is_nan());
+
+    if has_nan {
+        return TestResult::discard();
+    }
+
+    // Empty map keys are always written by this crate but skipped by `prost`, so only compare the
+    // encoded bytes if there are none.
+    let has_empty_key = object
+        .iter()
+        .flatten()
+        .any(|(key, (kind, _, string))| key.is_empty() || kind % 6 == 4 && string.is_empty());
+
+    let objects = object.as_ref().map(make_structs);
+    let ours = WithWellKnown {
+        count: count.map(Into::into),
+        name: name.clone().map(Into::into),
+        data: data.clone().map(|data| BytesValue { value: data.into() }),
+        ratio: ratio.map(Into::into),
+        nothing: nothing.map(|()| Empty {}),
+        object: objects.clone().map(|(ours, _)| ours),
+        any: any.clone().map(|(type_url, value)| Any {
+            type_url,
+            value: value.into(),
+        }),
+    };
+    let prost = ProstWithWellKnown {
+        count,
+        name,
+        data,
+        ratio,
+        nothing,
+        object: objects.map(|(_, prost)| prost),
+        any: any.map(|(type_url, value)| prost_types::Any { type_url, value }),
+    };
+
+    if !has_empty_key {
+        assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+        assert_eq!(ours.encoded_len(), prost.encoded_len());
+    }
+    assert_eq!(ours.encoded_len(), ours.encode_to_vec().len());
+    assert_eq!(
+        WithWellKnown::decode(&prost.encode_to_vec()[..]).unwrap(),
+        ours
+    );
+    assert_eq!(
+        ProstWithWellKnown::decode(&ours.encode_to_vec()[..]).unwrap(),
+        prost
+    );
+
+    TestResult::passed()
+}
+
+#[test]
+fn well_known_types() {
+    use autoproto::{
+        descriptor::{self, Type},
+        wkt::{Any, Int32Value, ListValue, Struct, Value},
+        ProtoFile,
+    };
+
+    let json = r#"{"a":null,"b":1.5,"c":"text","d":false,"e":{"f":[1.0,{},[]]}}"#;
+    let value: Value = autoproto::json::from_json(json).unwrap();
+
+    assert_eq!(
+        value,
+        Value::StructValue(Struct {
+            fields: vec![
+                ("a".to_owned(), Value::default()),
+                ("b".to_owned(), 1.5.into()),
+                ("c".to_owned(), "text".into()),
+                ("d".to_owned(), false.into()),
+                (
+                    "e".to_owned(),
+                    Struct {
+                        fields: vec![(
+                            "f".to_owned(),
+                            ListValue {
+                                values: vec![
+                                    1.0.into(),
+                                    Struct::default().into(),
+                                    ListValue::default().into()
+                                ],
+                            }
+                            .into()
+                        )]
+                        .into_iter()
+                        .collect()
+                    }
+                    .into()
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        })
+    );
+    assert_eq!(autoproto::json::to_json(&value), json);
+
+    // Wrappers are written as the value that they wrap, even when it is the default.
+    let message = WithWellKnown {
+        count: Some(Int32Value { value: 0 }),
+        nothing: Some(Default::default()),
+        ..Default::default()
+    };
+    assert_eq!(
+        autoproto::json::to_json(&message),
+        r#"{"count":0,"nothing":{}}"#
+    );
+    assert_eq!(
+        autoproto::json::from_json::<WithWellKnown>(r#"{"count":0,"nothing":{},"name":null}"#)
+            .unwrap(),
+        message
+    );
+
+    // Well-known types packed in an `Any` are written in the `value` field.
+    let any = Any {
+        type_url: "type.googleapis.com/google.protobuf.Int32Value".into(),
+        value: Int32Value { value: 5 }.encode_to_vec().into(),
+    };
+    let json = r#"{"@type":"type.googleapis.com/google.protobuf.Int32Value","value":5}"#;
+    assert_eq!(any.type_name(), "google.protobuf.Int32Value");
+    assert_eq!(autoproto::json::to_json(&any), json);
+    assert_eq!(autoproto::json::from_json::<Any>(json).unwrap(), any);
+
+    let any = Any {
+        type_url: "type.googleapis.com/google.protobuf.Duration".into(),
+        value: prost_types::Duration {
+            seconds: 1,
+            nanos: 500_000_000,
+        }
+        .encode_to_vec()
+        .into(),
+    };
+    let json = r#"{"@type":"type.googleapis.com/google.protobuf.Duration","value":"1.500s"}"#;
+    assert_eq!(autoproto::json::to_json(&any), json);
+    assert_eq!(autoproto::json::from_json::<Any>(json).unwrap(), any);
+
+    // Other types are written as their encoded bytes.
+    let any = Any {
+        type_url: "type.googleapis.com/test.Foo".into(),
+        value: vec![8, 1].into(),
+    };
+    let json = r#"{"@type":"type.googleapis.com/test.Foo","value":"CAE="}"#;
+    assert_eq!(autoproto::json::to_json(&any), json);
+    assert_eq!(autoproto::json::from_json::<Any>(json).unwrap(), any);
+    assert!(autoproto::json::from_json::<Any>(r#"{"value":"CAE="}"#).is_err());
+    assert!(autoproto::json::from_json::<Any>(
+        r#"{"@type":"type.googleapis.com/test.Foo","other":1}"#
+    )
+    .is_err());
+
+    // As in `prost-types`, empty fields of an `Any` are left out.
+    let any = Any {
+        type_url: "type.googleapis.com/google.protobuf.Empty".into(),
+        value: Default::default(),
+    };
+    let prost_any = prost_types::Any {
+        type_url: any.type_url.clone(),
+        value: Vec::new(),
+    };
+    assert_eq!(any.encode_to_vec(), prost_any.encode_to_vec());
+    assert!(Any::default().encode_to_vec().is_empty());
+
+    assert_eq!(
+        autoproto::text::to_text(&Value::ListValue(ListValue {
+            values: vec![Value::default(), "a".into()],
+        })),
+        "list_value {\n  values {\n    null_value: NULL_VALUE\n  }\n  values {\n    \
+         string_value: \"a\"\n  }\n}\n"
+    );
+
+    assert_eq!(
+        ProtoFile::new().add::<WithWellKnown>().to_string(),
+        "\
+syntax = \"proto3\";
+
+import \"google/protobuf/any.proto\";
+import \"google/protobuf/empty.proto\";
+import \"google/protobuf/struct.proto\";
+import \"google/protobuf/wrappers.proto\";
+
+message WithWellKnown {
+    google.protobuf.Int32Value count = 1;
+    google.protobuf.StringValue name = 2;
+    google.protobuf.BytesValue data = 3;
+    google.protobuf.DoubleValue ratio = 4;
+    google.protobuf.Empty nothing = 5;
+    google.protobuf.Struct object = 6;
+    google.protobuf.Any any = 7;
+}
+"
+    );
+
+    let set = descriptor::file_descriptor_set::<WithWellKnown>("test.proto", None);
+    let names = set.file.iter().map(|f| f.name()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "google/protobuf/wrappers.proto",
+            "google/protobuf/empty.proto",
+            "google/protobuf/struct.proto",
+            "google/protobuf/any.proto",
+            "test.proto"
+        ]
+    );
+
+    let file = set.file.last().unwrap();
+    assert_eq!(file.dependency, &names[..4]);
+    assert_eq!(file.message_type.len(), 1);
+    assert_eq!(file.message_type[0].field[5].r#type(), Type::Message);
+    assert_eq!(
+        file.message_type[0].field[5].type_name(),
+        ".google.protobuf.Struct"
+    );
+
+    let structs = &set.file[2];
+    assert_eq!(structs.package(), "google.protobuf");
+    assert_eq!(
+        structs
+            .message_type
+            .iter()
+            .map(|m| m.name())
+            .collect::<Vec<_>>(),
+        ["Struct", "Value", "ListValue"]
+    );
+    assert_eq!(
+        structs.message_type[0].field[0].type_name(),
+        ".google.protobuf.Struct.FieldsEntry"
+    );
+    assert_eq!(
+        structs.message_type[1].field[0].type_name(),
+        ".google.protobuf.NullValue"
+    );
+    assert_eq!(structs.enum_type[0].name(), "NullValue");
+    assert_eq!(structs.enum_type[0].value[0].name(), "NULL_VALUE");
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+#[autoproto(type_url = "type.googleapis.com/test.Created")]
+struct Created<T> {
+    id: u64,
+    item: T,
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+#[autoproto(type_url = "example.com/test.Event")]
+enum Event {
+    Created(Created<String>),
+    Deleted(u64),
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self::Deleted(0)
+    }
+}
+
+#[cfg(feature = "inventory")]
+autoproto::register_type!(Created<String>, Event);
+
+#[quickcheck]
+fn any_same_as_prost(id: u64, item: String) {
+    use autoproto::wkt::Any;
+
+    let created = Created { id, item };
+    let ours = Any::pack(&created);
+    let prost = prost_types::Any {
+        type_url: "type.googleapis.com/test.Created".into(),
+        value: created.encode_to_vec(),
+    };
+
+    assert_eq!(ours.encode_to_vec(), prost.encode_to_vec());
+    assert_eq!(ours.unpack::<Created<String>>().unwrap(), created);
+}
+
+#[test]
+fn type_registry() {
+    use autoproto::{
+        registry::TypeRegistry,
+        wkt::{Any, Int32Value},
+    };
+    use std::time::Duration;
+
+    let event = Event::Created(Created {
+        id: 5,
+        item: "foo".into(),
+    });
+    let any = Any::pack(&event);
+
+    assert_eq!(any.type_url, "example.com/test.Event");
+    assert!(any.is::<Event>());
+    assert!(!any.is::<Created<String>>());
+    assert_eq!(any.unpack::<Event>().unwrap(), event);
+    assert!(any
+        .unpack::<Created<String>>()
+        .unwrap_err()
+        .to_string()
+        .contains("Expected `Any` to hold `test.Created`, but it holds `test.Event`"));
+
+    let mut registry = TypeRegistry::new();
+
+    // The well-known types are always registered.
+    assert!(registry.contains("type.googleapis.com/google.protobuf.Duration"));
+    assert!(!registry.contains("example.com/test.Event"));
+    assert!(registry
+        .decode_dyn(&any)
+        .unwrap_err()
+        .to_string()
+        .contains("Unknown type URL `example.com/test.Event` in `Any`"));
+
+    registry.register::<Event>().register::<Created<String>>();
+
+    // Only the type name is used to look up the type, not the rest of the URL.
+    assert!(registry.contains("type.googleapis.com/test.Event"));
+
+    let message = registry.decode_dyn(&any).unwrap();
+
+    assert!(message.is::<Event>());
+    assert_eq!(message.message_type_url(), "example.com/test.Event");
+    assert_eq!(
+        format!("{:?}", message),
+        r#"Created(Created { id: 5, item: "foo" })"#
+    );
+    assert_eq!(message.encoded_len_dyn(), event.encoded_len());
+    assert_eq!(message.to_any(), any);
+
+    let mut buf = Vec::new();
+    message.encode_dyn(&mut buf);
+    assert_eq!(buf, event.encode_to_vec());
+
+    assert_eq!(message.downcast_ref::<Created<String>>(), None);
+    let message = message.downcast::<Created<String>>().unwrap_err();
+    assert_eq!(*message.downcast::<Event>().unwrap(), event);
+
+    let duration = registry
+        .decode_dyn(&Any::pack(&prost_types::Duration::from(
+            Duration::from_secs(3),
+        )))
+        .unwrap();
+    assert_eq!(
+        duration
+            .downcast_ref::<prost_types::Duration>()
+            .unwrap()
+            .seconds,
+        3
+    );
+
+    let wrapper = registry
+        .decode_dyn(&Any::pack(&Int32Value::from(7)))
+        .unwrap();
+    assert_eq!(
+        wrapper.message_type_url(),
+        "type.googleapis.com/google.protobuf.Int32Value"
+    );
+    assert_eq!(
+        *wrapper.downcast::<Int32Value>().unwrap(),
+        Int32Value::from(7)
+    );
+}
+
+#[cfg(feature = "inventory")]
+#[test]
+fn registered_types() {
+    use autoproto::{registry::TypeRegistry, wkt::Any};
+
+    let registry = TypeRegistry::registered();
+    let created = Created {
+        id: 1,
+        item: String::from("bar"),
+    };
+
+    assert!(registry.contains("type.googleapis.com/test.Event"));
+    assert!(registry.contains("type.googleapis.com/google.protobuf.Empty"));
+
+    let message = registry.decode_dyn(&Any::pack(&created)).unwrap();
+
+    assert_eq!(message.downcast_ref(), Some(&created));
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Chunk {
+    name: autoproto::BytesStr,
+    data: autoproto::bytes::Bytes,
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+enum Payload {
+    #[autoproto(tag = 4)]
+    Raw(autoproto::bytes::Bytes),
+    #[autoproto(tag = 5)]
+    Text(autoproto::BytesStr),
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Self::Raw(Default::default())
+    }
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Frame {
+    #[autoproto(tag = 1)]
+    header: Chunk,
+    #[autoproto(tag = 2)]
+    chunks: Vec<Chunk>,
+    #[autoproto(tag = 3)]
+    trailer: Option<autoproto::bytes::Bytes>,
+    #[autoproto(oneof, tags = "4, 5")]
+    payload: Payload,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct ProstChunk {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    data: Vec<u8>,
+}
+
+#[quickcheck]
+fn bytes_str_same_as_prost(name: String, data: Vec<u8>) -> TestResult {
+    // TODO: Empty strings are currently always encoded, whereas `prost` skips them.
+    if name.is_empty() || data.is_empty() {
+        return TestResult::discard();
+    }
+
+    let chunk = Chunk {
+        name: name.clone().into(),
+        data: data.clone().into(),
+    };
+    let prost_chunk = ProstChunk { name, data };
+
+    assert_eq!(chunk.encode_to_vec(), prost_chunk.encode_to_vec());
+    assert_eq!(
+        Chunk::decode(&prost_chunk.encode_to_vec()[..]).unwrap(),
+        chunk
+    );
+
+    TestResult::passed()
+}
+
+#[test]
+fn bytes_str_keys() {
+    use autoproto::BytesStr;
+    use std::collections::HashMap;
+
+    let mut map = HashMap::new();
+    map.insert(BytesStr::from_static("foo"), 1);
+    map.insert(BytesStr::from("bar".to_string()), 2);
+
+    assert_eq!(map.get("foo"), Some(&1));
+    assert_eq!(map.get("bar"), Some(&2));
+    assert_eq!(map.get("baz"), None);
+}
+
+#[test]
+fn decoding_from_bytes_shares_buffer() {
+    use autoproto::{bytes::Bytes, BytesStr};
+
+    let chunk = |name: &'static str, len: usize| Chunk {
+        name: BytesStr::from_static(name),
+        data: vec![len as u8; len].into(),
+    };
+    let frame = Frame {
+        header: chunk("header", 16),
+        chunks: vec![chunk("first", 32), chunk("second", 64)],
+        trailer: Some(vec![1; 8].into()),
+        payload: Payload::Text("text".into()),
+    };
+
+    let buf = Bytes::from(frame.encode_to_vec());
+    let decoded = Frame::decode(buf.clone()).unwrap();
+
+    assert_eq!(decoded, frame);
+    // The payload is a oneof, so its variant is encoded directly in the frame.
+    assert!(buf.ends_with(&[42, 4, b't', b'e', b'x', b't']));
+
+    let range = buf.as_ptr_range();
+    let Frame {
+        header,
+        chunks,
+        trailer,
+        payload,
+    } = &decoded;
+
+    for chunk in std::iter::once(header).chain(chunks) {
+        assert!(range.contains(&chunk.name.as_ptr()));
+        assert!(range.contains(&chunk.data.as_ptr()));
+    }
+    assert!(range.contains(&trailer.as_ref().unwrap().as_ptr()));
+    match payload {
+        Payload::Text(text) => assert!(range.contains(&text.as_ptr())),
+        other => panic!("Unexpected payload {:?}", other),
+    }
+
+    let raw = Bytes::from(
+        Frame {
+            payload: Payload::Raw(vec![2; 8].into()),
+            ..frame.clone()
+        }
+        .encode_to_vec(),
+    );
+    match Frame::decode(raw.clone()).unwrap().payload {
+        Payload::Raw(data) => assert!(raw.as_ptr_range().contains(&data.as_ptr())),
+        other => panic!("Unexpected payload {:?}", other),
+    }
+
+    // Decoding from a slice copies instead.
+    let copied = Frame::decode(&buf[..]).unwrap();
+    assert_eq!(copied, frame);
+    assert!(!range.contains(&copied.header.name.as_ptr()));
+
+    // Invalid UTF-8 is rejected, even though the bytes aren't copied.
+    let invalid = Bytes::from_static(&[0x0a, 0x01, 0xff]);
+    assert!(Chunk::decode(invalid).is_err());
+
+    assert_eq!(
+        autoproto::json::to_json(&frame.header),
+        r#"{"data":"EBAQEBAQEBAQEBAQEBAQEA==","name":"header"}"#
+    );
+    assert_eq!(
+        autoproto::text::from_text::<Chunk>(r#"name: "foo""#)
+            .unwrap()
+            .name,
+        "foo"
+    );
+}
@@ -0,0 +1,395 @@
+#![cfg(feature = "tonic")]
+
+use autoproto::grpc::AutoprotoCodec;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    body::BoxBody,
+    codegen::{http, Body, Never, StdError},
+    transport::{Channel, NamedService, Server},
+    Request, Response, Status,
+};
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Greeting<T> {
+    name: String,
+    extra: T,
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+enum Reply {
+    Message(String),
+    Refused(u32),
+}
+
+impl Default for Reply {
+    fn default() -> Self {
+        Self::Refused(0)
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// A service with a single unary method, written out in the same way as `tonic-build` would
+/// generate it but using `AutoprotoCodec`.
+#[derive(Clone)]
+struct Greeter;
+
+impl NamedService for Greeter {
+    const NAME: &'static str = "test.Greeter";
+}
+
+struct GreetSvc;
+
+impl tonic::server::UnaryService<Greeting<Vec<u32>>> for GreetSvc {
+    type Response = Reply;
+    type Future = BoxFuture<Result<Response<Reply>, Status>>;
+
+    fn call(&mut self, request: Request<Greeting<Vec<u32>>>) -> Self::Future {
+        Box::pin(async move {
+            let greeting = request.into_inner();
+
+            if greeting.name.is_empty() {
+                Ok(Response::new(Reply::Refused(greeting.extra.len() as u32)))
+            } else {
+                Ok(Response::new(Reply::Message(format!(
+                    "Hello, {}",
+                    greeting.name
+                ))))
+            }
+        })
+    }
+}
+
+impl<B> tonic::codegen::Service<http::Request<B>> for Greeter
+where
+    B: Body + Send + Sync + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Never;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        match req.uri().path() {
+            "/test.Greeter/Greet" => Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(AutoprotoCodec::default());
+
+                Ok(grpc.unary(GreetSvc, req).await)
+            }),
+            _ => Box::pin(async move {
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", "12")
+                    .header("content-type", "application/grpc")
+                    .body(tonic::codegen::empty_body())
+                    .unwrap())
+            }),
+        }
+    }
+}
+
+async fn greet(channel: Channel, greeting: Greeting<Vec<u32>>) -> Result<Response<Reply>, Status> {
+    let mut client = tonic::client::Grpc::new(channel);
+
+    client
+        .ready()
+        .await
+        .map_err(|e| Status::unknown(e.to_string()))?;
+    client
+        .unary(
+            Request::new(greeting),
+            http::uri::PathAndQuery::from_static("/test.Greeter/Greet"),
+            AutoprotoCodec::default(),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn unary_roundtrip() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(Greeter)
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let reply = greet(
+        channel.clone(),
+        Greeting {
+            name: "world".into(),
+            extra: vec![1, 2, 3],
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply.into_inner(), Reply::Message("Hello, world".into()));
+
+    // A variant with a default value is still sent, and a default request is still decoded.
+    let reply = greet(channel.clone(), Default::default()).await.unwrap();
+    assert_eq!(reply.into_inner(), Reply::Refused(0));
+
+    let reply = greet(
+        channel.clone(),
+        Greeting {
+            name: String::new(),
+            extra: vec![0; 4],
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(reply.into_inner(), Reply::Refused(4));
+
+    // `Refused` has the same tag as `extra` but a different wire type, so it fails to decode.
+    let mut client = tonic::client::Grpc::new(channel);
+    client.ready().await.unwrap();
+    let status = client
+        .unary::<_, Greeting<String>, _>(
+            Request::new(Greeting::<Vec<u32>>::default()),
+            http::uri::PathAndQuery::from_static("/test.Greeter/Greet"),
+            AutoprotoCodec::default(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Internal);
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct HelloRequest {
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct HelloReply {
+    message: String,
+}
+
+/// Used to check that generated services and clients work.
+#[autoproto::service(package = "test")]
+trait Hello {
+    /// Greet a single person.
+    async fn say_hello(&self, request: HelloRequest) -> Result<HelloReply, Status>;
+
+    async fn say_hello_many_times(
+        &self,
+        request: Greeting<u32>,
+    ) -> Result<autoproto::grpc::BoxStream<HelloReply>, Status>;
+
+    async fn say_hello_to_all(
+        &self,
+        request: autoproto::grpc::Streaming<HelloRequest>,
+    ) -> Result<HelloReply, Status>;
+
+    async fn echo(
+        &self,
+        request: autoproto::grpc::Streaming<Reply>,
+    ) -> Result<autoproto::grpc::BoxStream<Reply>, Status>;
+}
+
+struct HelloImpl;
+
+#[autoproto::grpc::async_trait]
+impl Hello for HelloImpl {
+    async fn say_hello(&self, request: HelloRequest) -> Result<HelloReply, Status> {
+        if request.name.is_empty() {
+            return Err(Status::invalid_argument("No name given"));
+        }
+
+        Ok(HelloReply {
+            message: format!("Hello, {}", request.name),
+        })
+    }
+
+    async fn say_hello_many_times(
+        &self,
+        request: Greeting<u32>,
+    ) -> Result<autoproto::grpc::BoxStream<HelloReply>, Status> {
+        let replies = (0..request.extra)
+            .map(|i| HelloReply {
+                message: format!("Hello #{}, {}", i, request.name),
+            })
+            .map(Ok)
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(tokio_stream::iter(replies)))
+    }
+
+    async fn say_hello_to_all(
+        &self,
+        mut request: autoproto::grpc::Streaming<HelloRequest>,
+    ) -> Result<HelloReply, Status> {
+        let mut names = Vec::new();
+
+        while let Some(request) = request.message().await? {
+            names.push(request.name);
+        }
+
+        Ok(HelloReply {
+            message: format!("Hello, {}", names.join(" and ")),
+        })
+    }
+
+    async fn echo(
+        &self,
+        mut request: autoproto::grpc::Streaming<Reply>,
+    ) -> Result<autoproto::grpc::BoxStream<Reply>, Status> {
+        let mut replies = Vec::new();
+
+        while let Some(reply) = request.message().await? {
+            replies.push(Ok(reply));
+        }
+
+        Ok(Box::pin(tokio_stream::iter(replies)))
+    }
+}
+
+#[tokio::test]
+async fn generated_service() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(HelloServer::new(HelloImpl))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let mut client = HelloClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    let reply = client
+        .say_hello(HelloRequest {
+            name: "world".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().message, "Hello, world");
+
+    let status = client.say_hello(HelloRequest::default()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    assert_eq!(status.message(), "No name given");
+
+    let mut replies = client
+        .say_hello_many_times(Greeting {
+            name: "world".into(),
+            extra: 2,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        replies.message().await.unwrap().unwrap().message,
+        "Hello #0, world"
+    );
+    assert_eq!(
+        replies.message().await.unwrap().unwrap().message,
+        "Hello #1, world"
+    );
+    assert_eq!(replies.message().await.unwrap(), None);
+
+    let reply = client
+        .say_hello_to_all(tokio_stream::iter(vec![
+            HelloRequest { name: "foo".into() },
+            HelloRequest { name: "bar".into() },
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().message, "Hello, foo and bar");
+
+    let messages = vec![Reply::Refused(0), Reply::Message("foo".into())];
+    let mut replies = client
+        .echo(tokio_stream::iter(messages.clone()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut echoed = Vec::new();
+    while let Some(reply) = replies.message().await.unwrap() {
+        echoed.push(reply);
+    }
+    assert_eq!(echoed, messages);
+}
+
+#[test]
+fn service_spec() {
+    let file = autoproto::ProtoFile::with_package("test")
+        .add::<dyn Hello>()
+        .to_string();
+
+    assert!(file.contains(
+        "service Hello {\n    \
+             rpc SayHello (HelloRequest) returns (HelloReply);\n    \
+             rpc SayHelloManyTimes (GreetingUint32) returns (stream HelloReply);\n    \
+             rpc SayHelloToAll (stream HelloRequest) returns (HelloReply);\n    \
+             rpc Echo (stream Reply) returns (stream Reply);\n\
+         }\n"
+    ));
+    assert!(file.contains("message HelloRequest {\n    string name = 1;\n}\n"));
+    assert!(file.contains("message GreetingUint32 {\n"));
+    assert!(file.contains("message Reply {\n"));
+}
+
+mod reexport {
+    pub use autoproto as proto;
+}
+
+/// Used to check that services can refer to `autoproto` through a re-export.
+#[autoproto::service(package = "test", path(crate::reexport::proto))]
+trait Renamed {
+    async fn say_hello(&self, request: HelloRequest) -> Result<HelloReply, Status>;
+}
+
+#[autoproto::grpc::async_trait]
+impl Renamed for HelloImpl {
+    async fn say_hello(&self, request: HelloRequest) -> Result<HelloReply, Status> {
+        Hello::say_hello(self, request).await
+    }
+}
+
+#[tokio::test]
+async fn service_path() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(RenamedServer::new(HelloImpl))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let mut client = RenamedClient::connect(format!("http://{}", addr))
+        .await
+        .unwrap();
+
+    let reply = client
+        .say_hello(HelloRequest {
+            name: "world".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(reply.into_inner().message, "Hello, world");
+
+    let file = autoproto::ProtoFile::with_package("test")
+        .add::<dyn Renamed>()
+        .to_string();
+
+    assert!(file
+        .contains("service Renamed {\n    rpc SayHello (HelloRequest) returns (HelloReply);\n}\n"));
+}
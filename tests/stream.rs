@@ -0,0 +1,234 @@
+use autoproto::prost::Message;
+use autoproto::stream::{DelimitedReader, DelimitedWriter, StreamError};
+use std::io::{self, Read};
+
+#[derive(Clone, PartialEq, Default, Debug, autoproto::Message)]
+struct Entry<T> {
+    id: u64,
+    value: T,
+}
+
+#[derive(Clone, PartialEq, Debug, autoproto::Message)]
+enum Event {
+    Started(Entry<String>),
+    Stopped(u32),
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self::Stopped(0)
+    }
+}
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::Stopped(0),
+        Event::Started(Entry {
+            id: 1,
+            value: "foo".into(),
+        }),
+        Event::Started(Entry {
+            id: 2,
+            value: "bar".repeat(100),
+        }),
+        Event::Stopped(3),
+    ]
+}
+
+fn write_all<T: autoproto::prost::Message>(messages: &[T]) -> Vec<u8> {
+    let mut writer = DelimitedWriter::new(Vec::new());
+
+    for message in messages {
+        writer.write(message).unwrap();
+    }
+
+    writer.into_inner()
+}
+
+/// Returns one byte at a time, with `WouldBlock` or `Interrupted` between each byte.
+struct Trickle<'a> {
+    data: &'a [u8],
+    calls: usize,
+}
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.calls += 1;
+
+        match self.calls % 3 {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            1 => Err(io::ErrorKind::Interrupted.into()),
+            _ => (&mut self.data).take(1).read(buf),
+        }
+    }
+}
+
+#[test]
+fn roundtrip() {
+    let events = events();
+    let log = write_all(&events);
+
+    let mut expected = Vec::new();
+    for event in &events {
+        event.encode_length_delimited(&mut expected).unwrap();
+    }
+    assert_eq!(log, expected);
+
+    let mut reader = DelimitedReader::new(&log[..]);
+    let read = reader.messages::<Event>().collect::<Result<Vec<_>, _>>();
+    assert_eq!(read.unwrap(), events);
+    assert!(reader.read::<Event>().unwrap().is_none());
+
+    assert!(DelimitedReader::new(io::empty())
+        .read::<Event>()
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn partial_frames() {
+    let events = events();
+    let log = write_all(&events);
+
+    let mut reader = DelimitedReader::new(Trickle {
+        data: &log,
+        calls: 0,
+    });
+    let mut read = Vec::new();
+
+    loop {
+        match reader.read::<Event>() {
+            Ok(Some(event)) => read.push(event),
+            Ok(None) => break,
+            Err(StreamError::Io(error)) if error.kind() == io::ErrorKind::WouldBlock => {}
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    assert_eq!(read, events);
+}
+
+#[test]
+fn truncated() {
+    let events = events();
+    let log = write_all(&events);
+    let cut = log.len() - 2;
+
+    let mut reader = DelimitedReader::new(io::Cursor::new(log[..cut].to_vec()));
+
+    for event in &events[..3] {
+        assert_eq!(&reader.read::<Event>().unwrap().unwrap(), event);
+    }
+    assert!(matches!(
+        reader.read::<Event>(),
+        Err(StreamError::Truncated { .. })
+    ));
+
+    // The rest of the frame arrives later, as when following a log that is being written.
+    reader.get_mut().get_mut().extend_from_slice(&log[cut..]);
+    assert_eq!(&reader.read::<Event>().unwrap().unwrap(), &events[3]);
+    assert!(reader.read::<Event>().unwrap().is_none());
+}
+
+#[test]
+fn invalid_frames_are_skipped() {
+    let small = Entry {
+        id: 1,
+        value: vec![1u32, 2],
+    };
+    let large = Entry {
+        id: 2,
+        value: vec![0xffff_ffffu32; 16],
+    };
+
+    let mut writer = DelimitedWriter::new(Vec::new()).with_max_frame_size(16);
+    writer.write(&small).unwrap();
+    assert!(matches!(
+        writer.write(&large),
+        Err(StreamError::FrameTooLarge { max: 16, .. })
+    ));
+    assert_eq!(writer.get_ref().len(), small.encoded_len() + 1);
+
+    let mut log = write_all(&[small.clone(), large, small.clone()]);
+    // A frame holding a truncated varint.
+    log.extend_from_slice(&[2, 0x08, 0x80]);
+    log.extend(write_all(std::slice::from_ref(&small)));
+    // A frame which fails to decode on its first byte, since field number 0 is invalid.
+    log.extend_from_slice(&[3, 0x00, 0x08, 0x01]);
+    log.extend(write_all(std::slice::from_ref(&small)));
+
+    let mut reader = DelimitedReader::new(&log[..]).with_max_frame_size(16);
+    assert_eq!(reader.read::<Entry<Vec<u32>>>().unwrap().unwrap(), small);
+    assert!(matches!(
+        reader.read::<Entry<Vec<u32>>>(),
+        Err(StreamError::FrameTooLarge { max: 16, .. })
+    ));
+    assert_eq!(reader.read::<Entry<Vec<u32>>>().unwrap().unwrap(), small);
+    assert!(matches!(
+        reader.read::<Entry<Vec<u32>>>(),
+        Err(StreamError::Decode(_))
+    ));
+    assert_eq!(reader.read::<Entry<Vec<u32>>>().unwrap().unwrap(), small);
+    assert!(matches!(
+        reader.read::<Entry<Vec<u32>>>(),
+        Err(StreamError::Decode(_))
+    ));
+    assert_eq!(reader.read::<Entry<Vec<u32>>>().unwrap().unwrap(), small);
+    assert!(reader.read::<Entry<Vec<u32>>>().unwrap().is_none());
+
+    // A length prefix which never ends can't be recovered from.
+    let log = [0xff; 12];
+    let mut reader = DelimitedReader::new(&log[..]);
+    for _ in 0..2 {
+        assert!(matches!(
+            reader.read::<Event>(),
+            Err(StreamError::InvalidLength)
+        ));
+    }
+    assert_eq!(reader.messages::<Event>().count(), 1);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn codec() {
+    use autoproto::prost::bytes::BytesMut;
+    use autoproto::stream::DelimitedCodec;
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{Decoder, FramedRead, FramedWrite};
+
+    let events = events();
+
+    let mut sink = FramedWrite::new(Vec::new(), DelimitedCodec::<Event>::new());
+    for event in &events {
+        sink.send(event.clone()).await.unwrap();
+    }
+    let log = sink.into_inner();
+    assert_eq!(log, write_all(&events));
+
+    let read = FramedRead::new(&log[..], DelimitedCodec::<Event>::new())
+        .map(Result::unwrap)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(read, events);
+
+    let mut stream = FramedRead::new(
+        &log[..log.len() - 1],
+        DelimitedCodec::<Event>::new().with_max_frame_size(8),
+    );
+    assert_eq!(stream.next().await.unwrap().unwrap(), events[0]);
+    assert!(matches!(
+        stream.next().await,
+        Some(Err(StreamError::FrameTooLarge { max: 8, .. }))
+    ));
+
+    // `FramedRead` ends the stream after an error, so the codec is used directly here.
+    let mut log = BytesMut::from(&[3, 0x00, 0x08, 0x01][..]);
+    log.extend(write_all(&events[..1]));
+    let mut codec = DelimitedCodec::<Event>::new();
+    assert!(matches!(
+        codec.decode(&mut log),
+        Err(StreamError::Decode(_))
+    ));
+    assert_eq!(codec.decode(&mut log).unwrap().unwrap(), events[0]);
+    assert!(codec.decode_eof(&mut log).unwrap().is_none());
+}